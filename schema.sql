@@ -9,6 +9,9 @@ CREATE TABLE actors (
     created_at timestamp not null default now(),
     updated_at timestamp not null default now(),
     resources varchar[] not null default array[]::varchar[],
+    also_known_as varchar[] not null default array[]::varchar[],
+    moved_to varchar,
+    deleted_at timestamp,
     PRIMARY KEY (ap_id)
 );
 
@@ -33,3 +36,172 @@ CREATE TABLE domains (
     created_at timestamp not null default now(),
     updated_at timestamp not null default now()
 );
+
+CREATE TABLE objects (
+    ap_id varchar not null,
+    kind varchar not null,
+    actor_ap_id varchar not null,
+    payload jsonb not null,
+    is_public bool not null default false,
+    created_at timestamp not null default now(),
+    updated_at timestamp not null default now(),
+    deleted_at timestamp,
+    PRIMARY KEY (ap_id)
+);
+
+create index objects_actor on public.objects (actor_ap_id);
+
+CREATE TABLE events (
+    actor_ap_id varchar not null,
+    title varchar not null,
+    summary varchar not null default '',
+    starts_at timestamptz not null,
+    ends_at timestamptz not null,
+    timezone varchar not null default 'UTC',
+    location varchar,
+    online_url varchar,
+    online_url_private boolean not null default false,
+    place_ap_id varchar,
+    calendar_ap_id varchar,
+    join_mode varchar not null default 'free',
+    capacity int,
+    rrule varchar,
+    exdates timestamptz[] not null default '{}',
+    sequence int not null default 0,
+    status varchar not null default 'published',
+    publish_at timestamptz,
+    owner_ap_id varchar,
+    import_uid varchar,
+    reminders int[],
+    hide_attendees boolean not null default false,
+    tags varchar[] not null default '{}',
+    created_at timestamp not null default now(),
+    updated_at timestamp not null default now(),
+    PRIMARY KEY (actor_ap_id)
+);
+
+create unique index events_import_uid on public.events (import_uid);
+create index events_place on public.events (place_ap_id);
+create index events_calendar on public.events (calendar_ap_id);
+create index events_tags on public.events using gin (tags);
+create index events_search on public.events using gin (to_tsvector('simple', title || ' ' || summary || ' ' || coalesce(location, '')));
+create index events_status on public.events (status);
+
+CREATE TABLE places (
+    actor_ap_id varchar not null,
+    name varchar not null,
+    address varchar,
+    latitude double precision,
+    longitude double precision,
+    accessibility varchar,
+    created_at timestamp not null default now(),
+    updated_at timestamp not null default now(),
+    PRIMARY KEY (actor_ap_id)
+);
+
+CREATE TABLE calendars (
+    actor_ap_id varchar not null,
+    name varchar not null,
+    summary varchar not null default '',
+    timezone varchar not null default 'UTC',
+    owner_ap_id varchar,
+    created_at timestamp not null default now(),
+    updated_at timestamp not null default now(),
+    PRIMARY KEY (actor_ap_id)
+);
+
+CREATE TABLE organizers (
+    event_ap_id varchar not null,
+    organizer_ap_id varchar not null,
+    role varchar not null,
+    created_at timestamp not null default now(),
+    PRIMARY KEY (event_ap_id, organizer_ap_id)
+);
+
+CREATE TABLE ownership_transfers (
+    event_ap_id varchar primary key,
+    nominee_ap_id varchar not null,
+    created_at timestamp not null default now()
+);
+
+CREATE TABLE rsvps (
+    actor_ap_id varchar not null,
+    occurrence varchar not null default '',
+    attendee_ap_id varchar not null,
+    status varchar not null,
+    activity_ap_id varchar,
+    hidden boolean not null default false,
+    created_at timestamp not null default now(),
+    updated_at timestamp not null default now(),
+    PRIMARY KEY (actor_ap_id, occurrence, attendee_ap_id)
+);
+
+create index rsvps_attendee on public.rsvps (attendee_ap_id);
+
+CREATE TABLE calendar_tokens (
+    token varchar not null,
+    attendee_ap_id varchar not null,
+    created_at timestamp not null default now(),
+    PRIMARY KEY (token)
+);
+
+create unique index calendar_tokens_attendee on public.calendar_tokens (attendee_ap_id);
+
+CREATE TABLE import_feeds (
+    url varchar not null,
+    last_synced_at timestamp,
+    created_at timestamp not null default now(),
+    PRIMARY KEY (url)
+);
+
+CREATE TABLE admin_tokens (
+    token varchar not null,
+    actor_ap_id varchar not null,
+    role varchar not null default 'owner',
+    expires_at timestamptz not null,
+    created_at timestamp not null default now(),
+    PRIMARY KEY (token)
+);
+
+CREATE TABLE event_archive (
+    actor_ap_id varchar not null,
+    title varchar not null,
+    starts_at timestamptz not null,
+    ends_at timestamptz not null,
+    timezone varchar not null,
+    status varchar not null,
+    follower_count bigint not null default 0,
+    attendee_count bigint not null default 0,
+    archived_at timestamp not null default now(),
+    PRIMARY KEY (actor_ap_id)
+);
+
+CREATE TABLE sent_reminders (
+    actor_ap_id varchar not null,
+    occurrence varchar not null,
+    attendee_ap_id varchar not null,
+    minutes_before int not null,
+    sent_at timestamp not null default now(),
+    PRIMARY KEY (actor_ap_id, occurrence, attendee_ap_id, minutes_before)
+);
+
+CREATE TABLE pending_updates (
+    actor_ap_id varchar not null,
+    previous jsonb not null,
+    notify_at timestamptz not null,
+    created_at timestamp not null default now(),
+    PRIMARY KEY (actor_ap_id)
+);
+
+CREATE TABLE deliveries (
+    id bigserial not null,
+    actor_ap_id varchar not null,
+    inbox varchar not null,
+    activity text not null,
+    collection_synchronization varchar,
+    attempts int not null default 0,
+    next_attempt_at timestamptz not null default now(),
+    created_at timestamp not null default now(),
+    PRIMARY KEY (id)
+);
+create index deliveries_next_attempt_at on public.deliveries (next_attempt_at);
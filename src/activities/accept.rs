@@ -1,4 +1,7 @@
-use crate::{activities::follow::Follow, objects::actor::EventActor, state::MyStateHandle};
+use crate::{
+    activities::follow::Follow, ap::object_or_id::ObjectOrId, objects::actor::EventActor,
+    state::MyStateHandle, storage_follows::accept_follow,
+};
 use activitypub_federation::{core::object_id::ObjectId, data::Data, traits::ActivityHandler};
 use activitystreams_kinds::activity::AcceptType;
 use serde::{Deserialize, Serialize};
@@ -8,14 +11,14 @@ use url::Url;
 #[serde(rename_all = "camelCase")]
 pub struct Accept {
     actor: ObjectId<EventActor>,
-    object: Follow,
+    object: ObjectOrId<Follow>,
     #[serde(rename = "type")]
     kind: AcceptType,
     id: Url,
 }
 
 impl Accept {
-    pub fn new(actor: ObjectId<EventActor>, object: Follow, id: Url) -> Accept {
+    pub fn new(actor: ObjectId<EventActor>, object: ObjectOrId<Follow>, id: Url) -> Accept {
         Accept {
             actor,
             object,
@@ -48,9 +51,9 @@ impl ActivityHandler for Accept {
 
     async fn receive(
         self,
-        _data: &Data<Self::DataType>,
+        app_state: &Data<Self::DataType>,
         _request_counter: &mut i32,
     ) -> Result<(), Self::Error> {
-        Ok(())
+        accept_follow(app_state, self.object.id().as_str(), self.id.as_str()).await
     }
 }
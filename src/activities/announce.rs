@@ -0,0 +1,65 @@
+use crate::{
+    ap::normalize::PUBLIC, error::ApEventsError, objects::actor::EventActor, state::MyStateHandle,
+};
+use activitypub_federation::{core::object_id::ObjectId, data::Data, traits::ActivityHandler};
+use activitystreams_kinds::activity::AnnounceType;
+use serde::{Deserialize, Serialize};
+use url::Url;
+
+/// Sent by places and calendars to share their events, and the activities about them, with their
+/// followers.
+#[derive(Deserialize, Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct Announce {
+    actor: ObjectId<EventActor>,
+    object: Url,
+    #[serde(rename = "type")]
+    kind: AnnounceType,
+    id: Url,
+    to: Vec<String>,
+    cc: Vec<String>,
+}
+
+impl Announce {
+    /// A public `Announce` of an object, copied to the actor's followers.
+    pub fn new(actor: &EventActor, object: Url, id: Url) -> Result<Announce, ApEventsError> {
+        Ok(Announce {
+            actor: actor.ap_id.clone(),
+            object,
+            kind: Default::default(),
+            id,
+            to: vec![PUBLIC.to_string()],
+            cc: vec![actor.followers_url()?.to_string()],
+        })
+    }
+}
+
+#[async_trait::async_trait(?Send)]
+impl ActivityHandler for Announce {
+    type DataType = MyStateHandle;
+    type Error = crate::error::ApEventsError;
+
+    fn id(&self) -> &Url {
+        &self.id
+    }
+
+    fn actor(&self) -> &Url {
+        self.actor.inner()
+    }
+
+    async fn verify(
+        &self,
+        _data: &Data<Self::DataType>,
+        _request_counter: &mut i32,
+    ) -> Result<(), Self::Error> {
+        Ok(())
+    }
+
+    async fn receive(
+        self,
+        _data: &Data<Self::DataType>,
+        _request_counter: &mut i32,
+    ) -> Result<(), Self::Error> {
+        Ok(())
+    }
+}
@@ -0,0 +1,92 @@
+use crate::{
+    ap::{event::Event as ApEvent, note::Note},
+    commands::handle_note,
+    error::ApEventsError,
+    objects::actor::EventActor,
+    state::MyStateHandle,
+};
+use activitypub_federation::{core::object_id::ObjectId, data::Data, traits::ActivityHandler};
+use activitystreams_kinds::activity::CreateType;
+use serde::{Deserialize, Serialize};
+use url::Url;
+
+#[derive(Deserialize, Serialize, Debug)]
+#[serde(untagged)]
+pub enum CreateObject {
+    Note(Note),
+    Event(Box<ApEvent>),
+}
+
+/// Sent by events when they are published and when they message followers or attendees, and
+/// received when someone messages an event.
+#[derive(Deserialize, Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct Create {
+    actor: ObjectId<EventActor>,
+    object: CreateObject,
+    #[serde(rename = "type")]
+    kind: CreateType,
+    id: Url,
+    #[serde(default)]
+    to: Vec<String>,
+    #[serde(default)]
+    cc: Vec<String>,
+}
+
+impl Create {
+    /// Wraps an object with the same addressing as the object.
+    pub fn new(actor: ObjectId<EventActor>, object: CreateObject, id: Url) -> Create {
+        let (to, cc) = match &object {
+            CreateObject::Note(note) => (note.to.clone(), note.cc.clone()),
+            CreateObject::Event(event) => (event.to.clone(), event.cc.clone()),
+        };
+        Create {
+            actor,
+            object,
+            kind: Default::default(),
+            id,
+            to,
+            cc,
+        }
+    }
+}
+
+#[async_trait::async_trait(?Send)]
+impl ActivityHandler for Create {
+    type DataType = MyStateHandle;
+    type Error = crate::error::ApEventsError;
+
+    fn id(&self) -> &Url {
+        &self.id
+    }
+
+    fn actor(&self) -> &Url {
+        self.actor.inner()
+    }
+
+    async fn verify(
+        &self,
+        _data: &Data<Self::DataType>,
+        _request_counter: &mut i32,
+    ) -> Result<(), Self::Error> {
+        match &self.object {
+            CreateObject::Note(note) if note.attributed_to != self.actor.inner().as_str() => Err(
+                ApEventsError::new("note is not attributed to the actor".to_string()),
+            ),
+            _ => Ok(()),
+        }
+    }
+
+    async fn receive(
+        self,
+        app_state: &Data<Self::DataType>,
+        request_counter: &mut i32,
+    ) -> Result<(), Self::Error> {
+        match self.object {
+            CreateObject::Note(note) => {
+                handle_note(app_state, self.actor, note, request_counter).await
+            }
+            CreateObject::Event(_) => Ok(()),
+        }
+    }
+}
@@ -0,0 +1,64 @@
+use crate::{
+    ap::normalize::PUBLIC, error::ApEventsError, objects::actor::EventActor, state::MyStateHandle,
+};
+use activitypub_federation::{core::object_id::ObjectId, data::Data, traits::ActivityHandler};
+use activitystreams_kinds::activity::DeleteType;
+use serde::{Deserialize, Serialize};
+use url::Url;
+
+/// Sent by events when they expire, deleting the event actor itself.
+#[derive(Deserialize, Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct Delete {
+    actor: ObjectId<EventActor>,
+    object: Url,
+    #[serde(rename = "type")]
+    kind: DeleteType,
+    id: Url,
+    to: Vec<String>,
+    cc: Vec<String>,
+}
+
+impl Delete {
+    /// A public `Delete` of the actor, copied to its followers.
+    pub fn new(actor: &EventActor, id: Url) -> Result<Delete, ApEventsError> {
+        Ok(Delete {
+            actor: actor.ap_id.clone(),
+            object: actor.ap_id.inner().clone(),
+            kind: Default::default(),
+            id,
+            to: vec![PUBLIC.to_string()],
+            cc: vec![actor.followers_url()?.to_string()],
+        })
+    }
+}
+
+#[async_trait::async_trait(?Send)]
+impl ActivityHandler for Delete {
+    type DataType = MyStateHandle;
+    type Error = crate::error::ApEventsError;
+
+    fn id(&self) -> &Url {
+        &self.id
+    }
+
+    fn actor(&self) -> &Url {
+        self.actor.inner()
+    }
+
+    async fn verify(
+        &self,
+        _data: &Data<Self::DataType>,
+        _request_counter: &mut i32,
+    ) -> Result<(), Self::Error> {
+        Ok(())
+    }
+
+    async fn receive(
+        self,
+        _data: &Data<Self::DataType>,
+        _request_counter: &mut i32,
+    ) -> Result<(), Self::Error> {
+        Ok(())
+    }
+}
@@ -1,11 +1,19 @@
-use crate::{objects::actor::EventActor, state::MyStateHandle};
+use crate::{
+    ap::{
+        ids::{generate_object_id, KindType},
+        object_or_id::ObjectOrId,
+    },
+    error::ApEventsError,
+    objects::actor::EventActor,
+    state::MyStateHandle,
+    storage_follows::{accept_follow, create_follow},
+};
 use activitypub_federation::{
     core::object_id::ObjectId,
     data::Data,
     traits::{ActivityHandler, Actor},
 };
 use activitystreams_kinds::activity::FollowType;
-use rand::{distributions::Alphanumeric, thread_rng, Rng};
 use serde::{Deserialize, Serialize};
 use url::Url;
 
@@ -55,8 +63,6 @@ impl ActivityHandler for Follow {
         Ok(())
     }
 
-    // Ignore clippy false positive: https://github.com/rust-lang/rust-clippy/issues/6446
-    #[allow(clippy::await_holding_lock)]
     async fn receive(
         self,
         app_state: &Data<Self::DataType>,
@@ -64,40 +70,37 @@ impl ActivityHandler for Follow {
     ) -> Result<(), Self::Error> {
         // TODO: Move all of this into a background job.
 
-        let found_actor: EventActor = sqlx::query_as("SELECT * FROM actors WHERE ap_id = $1")
-            .bind(self.id.to_string())
-            .fetch_one(&app_state.pool)
-            .await?;
+        // Expired events cannot be followed again.
+        let found_actor: EventActor =
+            sqlx::query_as("SELECT * FROM actors WHERE ap_id = $1 AND deleted_at IS NULL")
+                .bind(self.object.inner().to_string())
+                .fetch_optional(&app_state.pool)
+                .await?
+                .ok_or_else(|| ApEventsError::ObjectNotFound(self.object.to_string()))?;
 
         let follower = self
             .actor
             .dereference(app_state, &app_state.local_instance, request_counter)
             .await?;
 
-        let accept_partial: String = thread_rng()
-            .sample_iter(&Alphanumeric)
-            .take(7)
-            .map(char::from)
-            .collect();
-        let accept_ap_id = Url::parse(&format!(
-            "{}/object/{}",
-            app_state.external_base, accept_partial
-        ))?;
+        let accept_ap_id = generate_object_id(&app_state.external_base, KindType::Accept)?;
 
-        sqlx::query("insert into actor_followers (actor_ap_id, follower_ap_id, accept_ap_id) values ($1, $2, $3)")
-        .bind(found_actor.ap_id.to_string())
-        .bind(follower.ap_id.to_string())
-        .bind(accept_ap_id.path().to_string())
-        .execute(&app_state.pool)
+        create_follow(
+            app_state,
+            follower.ap_id.inner().as_str(),
+            found_actor.ap_id.inner().as_str(),
+            self.id.as_str(),
+        )
         .await?;
+        accept_follow(app_state, self.id.as_str(), accept_ap_id.as_str()).await?;
 
-        let accept = Accept::new(found_actor.ap_id.clone(), self, accept_ap_id.clone());
+        let accept = Accept::new(
+            found_actor.ap_id.clone(),
+            ObjectOrId::Object(self),
+            accept_ap_id.clone(),
+        );
         found_actor
-            .send(
-                accept,
-                vec![follower.shared_inbox_or_inbox()],
-                &app_state.local_instance,
-            )
+            .send(accept, vec![follower.shared_inbox_or_inbox()], app_state)
             .await?;
         Ok(())
     }
@@ -0,0 +1,103 @@
+use crate::{
+    ap::{
+        ids::{generate_object_id, KindType},
+        object_or_id::ObjectOrId,
+    },
+    messages::send_note,
+    objects::{actor::EventActor, event::local_event},
+    state::MyStateHandle,
+    storage_rsvps::RsvpStatus,
+    waitlist::{answer_event, waitlisted_notice},
+};
+use activitypub_federation::{
+    core::object_id::ObjectId,
+    data::Data,
+    traits::{ActivityHandler, Actor},
+};
+use activitystreams_kinds::activity::JoinType;
+use serde::{Deserialize, Serialize};
+use url::Url;
+
+use super::{accept::Accept, reject::Reject};
+
+#[derive(Deserialize, Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct Join {
+    actor: ObjectId<EventActor>,
+    object: Url,
+    #[serde(rename = "type")]
+    kind: JoinType,
+    id: Url,
+}
+
+#[async_trait::async_trait(?Send)]
+impl ActivityHandler for Join {
+    type DataType = MyStateHandle;
+    type Error = crate::error::ApEventsError;
+
+    fn id(&self) -> &Url {
+        &self.id
+    }
+
+    fn actor(&self) -> &Url {
+        self.actor.inner()
+    }
+
+    async fn verify(
+        &self,
+        _data: &Data<Self::DataType>,
+        _request_counter: &mut i32,
+    ) -> Result<(), Self::Error> {
+        Ok(())
+    }
+
+    async fn receive(
+        self,
+        app_state: &Data<Self::DataType>,
+        request_counter: &mut i32,
+    ) -> Result<(), Self::Error> {
+        let found_event = local_event(app_state, &self.object).await?;
+        let found_actor = &found_event.actor;
+
+        let attendee = self
+            .actor
+            .dereference(app_state, &app_state.local_instance, request_counter)
+            .await?;
+        let attendee_ap_id = attendee.ap_id.inner().as_str();
+
+        match answer_event(
+            app_state,
+            &found_event,
+            attendee_ap_id,
+            RsvpStatus::Going,
+            Some(self.id.as_str()),
+            None,
+        )
+        .await?
+        {
+            None => {
+                let reject_ap_id = generate_object_id(&app_state.external_base, KindType::Reject)?;
+                let reject = Reject::new(found_actor.ap_id.clone(), self.id, reject_ap_id);
+                found_actor
+                    .send(reject, vec![attendee.shared_inbox_or_inbox()], app_state)
+                    .await
+            }
+            // The Join is accepted once the attendee is promoted from the waitlist.
+            Some(RsvpStatus::Waitlisted) => {
+                let content = waitlisted_notice(app_state, &found_event, attendee_ap_id).await?;
+                send_note(app_state, found_actor, &[attendee], false, content, None).await
+            }
+            Some(_) => {
+                let accept_ap_id = generate_object_id(&app_state.external_base, KindType::Accept)?;
+                let accept = Accept::new(
+                    found_actor.ap_id.clone(),
+                    ObjectOrId::Id(self.id),
+                    accept_ap_id,
+                );
+                found_actor
+                    .send(accept, vec![attendee.shared_inbox_or_inbox()], app_state)
+                    .await
+            }
+        }
+    }
+}
@@ -0,0 +1,61 @@
+use crate::{
+    objects::{actor::EventActor, event::local_event},
+    state::MyStateHandle,
+    storage_rsvps::RsvpStatus,
+    waitlist::answer_event,
+};
+use activitypub_federation::{core::object_id::ObjectId, data::Data, traits::ActivityHandler};
+use activitystreams_kinds::activity::LeaveType;
+use serde::{Deserialize, Serialize};
+use url::Url;
+
+/// Sent by attendees that are no longer going to an event.
+#[derive(Deserialize, Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct Leave {
+    actor: ObjectId<EventActor>,
+    object: Url,
+    #[serde(rename = "type")]
+    kind: LeaveType,
+    id: Url,
+}
+
+#[async_trait::async_trait(?Send)]
+impl ActivityHandler for Leave {
+    type DataType = MyStateHandle;
+    type Error = crate::error::ApEventsError;
+
+    fn id(&self) -> &Url {
+        &self.id
+    }
+
+    fn actor(&self) -> &Url {
+        self.actor.inner()
+    }
+
+    async fn verify(
+        &self,
+        _data: &Data<Self::DataType>,
+        _request_counter: &mut i32,
+    ) -> Result<(), Self::Error> {
+        Ok(())
+    }
+
+    async fn receive(
+        self,
+        app_state: &Data<Self::DataType>,
+        _request_counter: &mut i32,
+    ) -> Result<(), Self::Error> {
+        let found_event = local_event(app_state, &self.object).await?;
+        answer_event(
+            app_state,
+            &found_event,
+            self.actor.inner().as_str(),
+            RsvpStatus::NotGoing,
+            Some(self.id.as_str()),
+            None,
+        )
+        .await?;
+        Ok(())
+    }
+}
@@ -1,2 +1,12 @@
 pub mod accept;
+pub mod announce;
+pub mod create;
+pub mod delete;
 pub mod follow;
+pub mod join;
+pub mod leave;
+pub mod move_actor;
+pub mod reject;
+pub mod tentative_accept;
+pub mod undo;
+pub mod update;
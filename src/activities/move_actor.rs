@@ -0,0 +1,133 @@
+use crate::{
+    error::ApEventsError,
+    migrate::{follow_moved, target_aliases},
+    objects::actor::EventActor,
+    state::MyStateHandle,
+    transfers::offer_moved_events,
+};
+use activitypub_federation::{core::object_id::ObjectId, data::Data, traits::ActivityHandler};
+use activitystreams_kinds::activity::MoveType;
+use serde::{Deserialize, Serialize};
+use url::Url;
+
+/// Sent by events when they move to another instance, and received when an account moves to
+/// another account.
+#[derive(Deserialize, Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct Move {
+    actor: ObjectId<EventActor>,
+    object: Url,
+    target: Url,
+    #[serde(rename = "type")]
+    kind: MoveType,
+    id: Url,
+    #[serde(default)]
+    to: Vec<String>,
+    #[serde(default)]
+    cc: Vec<String>,
+}
+
+impl Move {
+    /// Moves the actor to the target, addressed to its followers.
+    pub fn new(actor: &EventActor, target: Url, id: Url) -> Result<Move, ApEventsError> {
+        Ok(Move {
+            actor: actor.ap_id.clone(),
+            object: actor.ap_id.inner().clone(),
+            target,
+            kind: Default::default(),
+            id,
+            to: vec![actor.followers_url()?.to_string()],
+            cc: vec![],
+        })
+    }
+
+    /// Actors can only move themselves, and not someone else.
+    fn check_actor(&self) -> Result<(), ApEventsError> {
+        if self.object != *self.actor.inner() {
+            return Err(ApEventsError::new(
+                "actors can only move themselves".to_string(),
+            ));
+        }
+        Ok(())
+    }
+}
+
+#[async_trait::async_trait(?Send)]
+impl ActivityHandler for Move {
+    type DataType = MyStateHandle;
+    type Error = crate::error::ApEventsError;
+
+    fn id(&self) -> &Url {
+        &self.id
+    }
+
+    fn actor(&self) -> &Url {
+        self.actor.inner()
+    }
+
+    async fn verify(
+        &self,
+        _data: &Data<Self::DataType>,
+        _request_counter: &mut i32,
+    ) -> Result<(), Self::Error> {
+        self.check_actor()
+    }
+
+    async fn receive(
+        self,
+        app_state: &Data<Self::DataType>,
+        request_counter: &mut i32,
+    ) -> Result<(), Self::Error> {
+        if !target_aliases(app_state, &self.target)
+            .await?
+            .iter()
+            .any(|alias| alias == self.object.as_str())
+        {
+            return Err(ApEventsError::new(format!(
+                "{} does not list {} in alsoKnownAs",
+                self.target, self.object
+            )));
+        }
+        follow_moved(app_state, &self.object, &self.target).await?;
+        offer_moved_events(app_state, &self.object, &self.target, request_counter).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ap::normalize::normalize_activity;
+    use std::path::PathBuf;
+
+    fn load_move() -> serde_json::Value {
+        let mut path = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+        path.push("resources/test/activities/mastodon-move.json");
+        let data = std::fs::read_to_string(path).expect("fixture is readable");
+        normalize_activity(serde_json::from_str(&data).expect("fixture is json"))
+            .expect("fixture normalizes")
+    }
+
+    #[test]
+    fn mastodon_move() {
+        let activity: Move = serde_json::from_value(load_move()).unwrap();
+        assert_eq!(
+            activity.id.as_str(),
+            "https://thegem.city/users/nick#moves/1"
+        );
+        assert_eq!(
+            activity.actor.inner().as_str(),
+            "https://thegem.city/users/nick"
+        );
+        assert_eq!(activity.object.as_str(), "https://thegem.city/users/nick");
+        assert_eq!(
+            activity.target.as_str(),
+            "https://mastodon.social/users/ngerakines"
+        );
+        assert!(activity.check_actor().is_ok());
+
+        let mut someone_else = load_move();
+        someone_else["object"] = "https://thegem.city/users/dana".into();
+        let activity: Move = serde_json::from_value(someone_else).unwrap();
+        assert!(activity.check_actor().is_err());
+    }
+}
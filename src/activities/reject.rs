@@ -0,0 +1,82 @@
+use crate::{
+    objects::{
+        actor::EventActor,
+        event::{is_local_event_url, local_event},
+    },
+    state::MyStateHandle,
+    storage_follows::reject_follow,
+    storage_rsvps::RsvpStatus,
+    waitlist::answer_event,
+};
+use activitypub_federation::{core::object_id::ObjectId, data::Data, traits::ActivityHandler};
+use activitystreams_kinds::activity::RejectType;
+use serde::{Deserialize, Serialize};
+use url::Url;
+
+/// Sent by attendees that decline an event, by servers that reject a follow, and by events that
+/// turn down a Join.
+#[derive(Deserialize, Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct Reject {
+    actor: ObjectId<EventActor>,
+    object: Url,
+    #[serde(rename = "type")]
+    kind: RejectType,
+    id: Url,
+}
+
+impl Reject {
+    pub fn new(actor: ObjectId<EventActor>, object: Url, id: Url) -> Reject {
+        Reject {
+            actor,
+            object,
+            kind: Default::default(),
+            id,
+        }
+    }
+}
+
+#[async_trait::async_trait(?Send)]
+impl ActivityHandler for Reject {
+    type DataType = MyStateHandle;
+    type Error = crate::error::ApEventsError;
+
+    fn id(&self) -> &Url {
+        &self.id
+    }
+
+    fn actor(&self) -> &Url {
+        self.actor.inner()
+    }
+
+    async fn verify(
+        &self,
+        _data: &Data<Self::DataType>,
+        _request_counter: &mut i32,
+    ) -> Result<(), Self::Error> {
+        Ok(())
+    }
+
+    async fn receive(
+        self,
+        app_state: &Data<Self::DataType>,
+        _request_counter: &mut i32,
+    ) -> Result<(), Self::Error> {
+        if !is_local_event_url(app_state, &self.object) {
+            return reject_follow(app_state, self.actor.inner().as_str(), self.object.as_str())
+                .await;
+        }
+
+        let found_event = local_event(app_state, &self.object).await?;
+        answer_event(
+            app_state,
+            &found_event,
+            self.actor.inner().as_str(),
+            RsvpStatus::NotGoing,
+            Some(self.id.as_str()),
+            None,
+        )
+        .await?;
+        Ok(())
+    }
+}
@@ -0,0 +1,72 @@
+use crate::{
+    activities::follow::Follow,
+    ap::object_or_id::ObjectOrId,
+    error::ApEventsError,
+    objects::actor::EventActor,
+    state::MyStateHandle,
+    storage_follows::{remove_follow, remove_follow_activity},
+};
+use activitypub_federation::{core::object_id::ObjectId, data::Data, traits::ActivityHandler};
+use activitystreams_kinds::activity::UndoType;
+use serde::{Deserialize, Serialize};
+use url::Url;
+
+#[derive(Deserialize, Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct Undo {
+    actor: ObjectId<EventActor>,
+    object: ObjectOrId<Follow>,
+    #[serde(rename = "type")]
+    kind: UndoType,
+    id: Url,
+}
+
+#[async_trait::async_trait(?Send)]
+impl ActivityHandler for Undo {
+    type DataType = MyStateHandle;
+    type Error = crate::error::ApEventsError;
+
+    fn id(&self) -> &Url {
+        &self.id
+    }
+
+    fn actor(&self) -> &Url {
+        self.actor.inner()
+    }
+
+    async fn verify(
+        &self,
+        _data: &Data<Self::DataType>,
+        _request_counter: &mut i32,
+    ) -> Result<(), Self::Error> {
+        if let ObjectOrId::Object(follow) = &self.object {
+            if follow.actor.inner() != self.actor.inner() {
+                return Err(ApEventsError::new(
+                    "actors may only undo their own follows".to_string(),
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    async fn receive(
+        self,
+        app_state: &Data<Self::DataType>,
+        _request_counter: &mut i32,
+    ) -> Result<(), Self::Error> {
+        match &self.object {
+            ObjectOrId::Object(follow) => {
+                remove_follow(
+                    app_state,
+                    self.actor.inner().as_str(),
+                    follow.object.inner().as_str(),
+                )
+                .await
+            }
+            ObjectOrId::Id(follow_id) => {
+                remove_follow_activity(app_state, self.actor.inner().as_str(), follow_id.as_str())
+                    .await
+            }
+        }
+    }
+}
@@ -0,0 +1,81 @@
+use crate::{
+    ap::{actor::Actor as ApActor, event::Event as ApEvent, normalize::PUBLIC},
+    objects::actor::EventActor,
+    state::MyStateHandle,
+};
+use activitypub_federation::{core::object_id::ObjectId, data::Data, traits::ActivityHandler};
+use activitystreams_kinds::activity::UpdateType;
+use serde::{Deserialize, Serialize};
+use url::Url;
+
+#[derive(Deserialize, Serialize, Debug)]
+#[serde(untagged)]
+pub enum UpdateObject {
+    Event(Box<ApEvent>),
+    Actor(Box<ApActor>),
+}
+
+/// Sent by events when their Event object or the event actor itself changes.
+#[derive(Deserialize, Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct Update {
+    actor: ObjectId<EventActor>,
+    object: UpdateObject,
+    #[serde(rename = "type")]
+    kind: UpdateType,
+    id: Url,
+    to: Vec<String>,
+    cc: Vec<String>,
+}
+
+impl Update {
+    /// Wraps an object with the same addressing as the object. Actors are addressed publicly and
+    /// to their followers.
+    pub fn new(actor: ObjectId<EventActor>, object: UpdateObject, id: Url) -> Update {
+        let (to, cc) = match &object {
+            UpdateObject::Event(event) => (event.to.clone(), event.cc.clone()),
+            UpdateObject::Actor(actor) => (
+                vec![PUBLIC.to_string()],
+                actor.followers.clone().into_iter().collect(),
+            ),
+        };
+        Update {
+            actor,
+            object,
+            kind: Default::default(),
+            id,
+            to,
+            cc,
+        }
+    }
+}
+
+#[async_trait::async_trait(?Send)]
+impl ActivityHandler for Update {
+    type DataType = MyStateHandle;
+    type Error = crate::error::ApEventsError;
+
+    fn id(&self) -> &Url {
+        &self.id
+    }
+
+    fn actor(&self) -> &Url {
+        self.actor.inner()
+    }
+
+    async fn verify(
+        &self,
+        _data: &Data<Self::DataType>,
+        _request_counter: &mut i32,
+    ) -> Result<(), Self::Error> {
+        Ok(())
+    }
+
+    async fn receive(
+        self,
+        _data: &Data<Self::DataType>,
+        _request_counter: &mut i32,
+    ) -> Result<(), Self::Error> {
+        Ok(())
+    }
+}
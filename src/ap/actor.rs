@@ -62,13 +62,23 @@ pub struct Actor {
     #[serde(rename = "publicKey")]
     pub public_key: Option<PublicKey>,
 
-    #[serde(rename = "attachment")]
+    #[serde(rename = "attachment", default)]
     pub attachments: Vec<ActorAttachment>,
 
+    #[serde(default)]
     pub endpoints: HashMap<String, String>,
 
     pub icon: Option<ActorMedia>,
     pub image: Option<ActorMedia>,
+
+    /// Other accounts of the same person. A `Move` is only honored when the new account lists
+    /// the old one here.
+    #[serde(rename = "alsoKnownAs", default, skip_serializing_if = "Vec::is_empty")]
+    pub also_known_as: Vec<String>,
+
+    /// The account this one moved to.
+    #[serde(rename = "movedTo", skip_serializing_if = "Option::is_none")]
+    pub moved_to: Option<String>,
 }
 
 #[cfg(test)]
@@ -110,7 +120,9 @@ mod tests {
             ],
             endpoints: HashMap::from([("sharedInbox".to_string(), "https://thegem.city/inbox".to_string())]),
             icon: Some(ActorMedia { kind: "Image".to_string(), media_type: "image/jpeg".to_string(), url: "https://s3-us-east-2.amazonaws.com/thegem-city-assets/accounts/avatars/109/272/112/841/303/240/original/6ad687938d3a1af9.jpg".to_string() }),
-            image: Some(ActorMedia { kind: "Image".to_string(), media_type: "image/png".to_string(), url: "https://s3-us-east-2.amazonaws.com/thegem-city-assets/accounts/headers/109/272/112/841/303/240/original/0a834dbf6f05f8b8.png".to_string() })
+            image: Some(ActorMedia { kind: "Image".to_string(), media_type: "image/png".to_string(), url: "https://s3-us-east-2.amazonaws.com/thegem-city-assets/accounts/headers/109/272/112/841/303/240/original/0a834dbf6f05f8b8.png".to_string() }),
+            also_known_as: vec!["https://mastodon.social/users/ngerakines".to_string()],
+            moved_to: None,
         };
         assert_eq!(json, cmp_actor);
     }
@@ -0,0 +1,209 @@
+use chrono::{DateTime, FixedOffset};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+
+use crate::ap::note::Tag;
+
+/// The JSON-LD context for event actors and objects. It extends ActivityStreams with the terms
+/// that Mobilizon, Gancio and Friendica use for events, and the Mastodon terms used by actors.
+pub fn event_context() -> Vec<Value> {
+    vec![
+        Value::String("https://www.w3.org/ns/activitystreams".to_string()),
+        Value::String("https://w3id.org/security/v1".to_string()),
+        json!({
+            "mz": "https://joinmobilizon.org/ns#",
+            "rdfs": "http://www.w3.org/2000/01/rdf-schema#",
+            "sc": "http://schema.org#",
+            "toot": "http://joinmastodon.org/ns#",
+            "discoverable": "toot:discoverable",
+            "Hashtag": "as:Hashtag",
+            "PropertyValue": "sc:PropertyValue",
+            "value": "sc:value",
+            "timezone": {
+                "@id": "mz:timezone",
+                "@type": "sc:Text"
+            },
+            "joinMode": {
+                "@id": "mz:joinMode",
+                "@type": "mz:joinModeType"
+            },
+            "joinModeType": {
+                "@id": "mz:joinModeType",
+                "@type": "rdfs:Class"
+            },
+            "participantCount": {
+                "@id": "mz:participantCount",
+                "@type": "sc:Integer"
+            },
+            "maximumAttendeeCapacity": "sc:maximumAttendeeCapacity",
+            "remainingAttendeeCapacity": "sc:remainingAttendeeCapacity",
+            "ical": "http://www.w3.org/2002/12/cal/ical#",
+            "apevents": "https://github.com/ngerakines/apevents/ns#",
+            "participants": {
+                "@id": "apevents:participants",
+                "@type": "@id"
+            },
+            "eventStatus": {
+                "@id": "sc:eventStatus",
+                "@type": "@id"
+            },
+            "address": "sc:address",
+            "accessibilitySummary": "sc:accessibilitySummary",
+            "eventAttendanceMode": {
+                "@id": "sc:eventAttendanceMode",
+                "@type": "@id"
+            }
+        }),
+    ]
+}
+
+/// Where an event is held. Places on this server are also actors, and their Place objects have
+/// an id, an address, coordinates and accessibility notes.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct Place {
+    #[serde(rename = "id", default, skip_serializing_if = "Option::is_none")]
+    pub ap_id: Option<String>,
+
+    #[serde(rename = "type")]
+    pub kind: String,
+
+    pub name: String,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub address: Option<String>,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub latitude: Option<f64>,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub longitude: Option<f64>,
+
+    #[serde(
+        rename = "accessibilitySummary",
+        default,
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub accessibility: Option<String>,
+
+    /// The actor of a place on this server, which can be followed.
+    #[serde(
+        rename = "attributedTo",
+        default,
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub attributed_to: Option<String>,
+}
+
+/// A link attached to an event, such as the link to join it online.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct Link {
+    #[serde(rename = "type")]
+    pub kind: String,
+
+    pub name: String,
+    pub href: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct Event {
+    #[serde(rename = "id")]
+    pub ap_id: String,
+
+    #[serde(rename = "type")]
+    pub kind: String,
+
+    pub name: String,
+    pub content: String,
+    pub url: String,
+
+    #[serde(rename = "attributedTo")]
+    pub attributed_to: String,
+
+    #[serde(rename = "startTime")]
+    pub start_time: DateTime<FixedOffset>,
+
+    #[serde(rename = "endTime")]
+    pub end_time: DateTime<FixedOffset>,
+
+    pub timezone: String,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub location: Option<Place>,
+
+    #[serde(rename = "joinMode")]
+    pub join_mode: String,
+
+    /// The collection of attendees that are going.
+    pub participants: String,
+
+    #[serde(rename = "participantCount")]
+    pub participant_count: i64,
+
+    #[serde(
+        rename = "maximumAttendeeCapacity",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub maximum_attendee_capacity: Option<i32>,
+
+    /// The seats left before attendees are put on the waitlist.
+    #[serde(
+        rename = "remainingAttendeeCapacity",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub remaining_attendee_capacity: Option<i64>,
+
+    /// The recurrence rule of a recurring event. Occurrences are published as their own objects.
+    #[serde(rename = "ical:rrule", skip_serializing_if = "Option::is_none")]
+    pub rrule: Option<String>,
+
+    /// `CONFIRMED`, `TENTATIVE` or `CANCELLED`, as used by Mobilizon.
+    #[serde(rename = "ical:status")]
+    pub status: String,
+
+    /// The schema.org status, such as `https://schema.org/EventPostponed`.
+    #[serde(rename = "eventStatus")]
+    pub event_status: String,
+
+    /// The schema.org attendance mode, such as `https://schema.org/OnlineEventAttendanceMode`.
+    #[serde(rename = "eventAttendanceMode", default)]
+    pub event_attendance_mode: String,
+
+    /// The link to join the event online, unless only attendees may see it.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub attachment: Vec<Link>,
+
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tag: Vec<Tag>,
+
+    pub to: Vec<String>,
+    pub cc: Vec<String>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Compact IRIs in the context, such as `sc:Text`, have to use a prefix it defines.
+    #[test]
+    fn context_prefixes_are_defined() {
+        let context = event_context();
+        let terms = context[2].as_object().unwrap();
+        let iris = terms.values().flat_map(|definition| match definition {
+            Value::String(iri) => vec![iri.as_str()],
+            Value::Object(definition) => definition.values().filter_map(Value::as_str).collect(),
+            _ => vec![],
+        });
+        for iri in iris {
+            let Some((prefix, _)) = iri.split_once(':') else {
+                continue;
+            };
+            if matches!(prefix, "http" | "https") || iri.starts_with('@') {
+                continue;
+            }
+            assert!(
+                prefix == "as" || terms.contains_key(prefix),
+                "{iri} uses an undefined prefix"
+            );
+        }
+    }
+}
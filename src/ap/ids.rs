@@ -1,9 +1,13 @@
+use base64::{alphabet::URL_SAFE, engine::fast_portable};
 use rand::{thread_rng, Rng};
 use thiserror::Error;
 use url::Url;
 
 use crate::error::ApEventsError;
 
+const URL_SAFE_ENGINE: fast_portable::FastPortable =
+    fast_portable::FastPortable::from(&URL_SAFE, fast_portable::NO_PAD);
+
 #[derive(Error, Debug)]
 pub enum ObjectIdError {
     #[error("invalid id: {0}")]
@@ -17,6 +21,13 @@ pub enum ObjectIdError {
 pub enum KindType {
     Follow,
     Accept,
+    Reject,
+    Create,
+    Note,
+    Update,
+    Delete,
+    Announce,
+    Move,
 }
 
 impl TryFrom<u8> for KindType {
@@ -26,6 +37,13 @@ impl TryFrom<u8> for KindType {
         match val {
             1 => Ok(KindType::Follow),
             2 => Ok(KindType::Accept),
+            3 => Ok(KindType::Reject),
+            4 => Ok(KindType::Create),
+            5 => Ok(KindType::Note),
+            6 => Ok(KindType::Update),
+            7 => Ok(KindType::Delete),
+            8 => Ok(KindType::Announce),
+            9 => Ok(KindType::Move),
             _ => Err(ObjectIdError::InvalidObjectID(val)),
         }
     }
@@ -35,10 +53,9 @@ impl TryFrom<Vec<u8>> for KindType {
     type Error = ObjectIdError;
 
     fn try_from(val: Vec<u8>) -> Result<KindType, ObjectIdError> {
-        match val {
-            _ if 1 == val[0] => Ok(KindType::Follow),
-            _ if 2 == val[0] => Ok(KindType::Accept),
-            _ => Err(ObjectIdError::CannotParse),
+        match val.first() {
+            Some(prefix) => KindType::try_from(*prefix),
+            None => Err(ObjectIdError::CannotParse),
         }
     }
 }
@@ -48,10 +65,32 @@ impl KindType {
         "objects".to_string()
     }
 
+    /// The activity or object `type` that objects of this kind are stored and served as.
+    pub fn name(&self) -> &'static str {
+        match self {
+            KindType::Follow => "Follow",
+            KindType::Accept => "Accept",
+            KindType::Reject => "Reject",
+            KindType::Create => "Create",
+            KindType::Note => "Note",
+            KindType::Update => "Update",
+            KindType::Delete => "Delete",
+            KindType::Announce => "Announce",
+            KindType::Move => "Move",
+        }
+    }
+
     pub fn as_bytes(&self) -> Result<[u8; 1], ObjectIdError> {
         match self {
             KindType::Follow => Ok(1u8.to_be_bytes()),
             KindType::Accept => Ok(2u8.to_be_bytes()),
+            KindType::Reject => Ok(3u8.to_be_bytes()),
+            KindType::Create => Ok(4u8.to_be_bytes()),
+            KindType::Note => Ok(5u8.to_be_bytes()),
+            KindType::Update => Ok(6u8.to_be_bytes()),
+            KindType::Delete => Ok(7u8.to_be_bytes()),
+            KindType::Announce => Ok(8u8.to_be_bytes()),
+            KindType::Move => Ok(9u8.to_be_bytes()),
         }
     }
 }
@@ -62,7 +101,7 @@ pub fn generate_object_id(external_base: &str, kind_type: KindType) -> Result<Ur
     let mut arr: [u8; 20] = [0; 20];
     thread_rng().fill(&mut arr[..]);
     arr[0] = bytes[0];
-    let generated_id = base64::encode(arr);
+    let generated_id = base64::encode_engine(arr, &URL_SAFE_ENGINE);
 
     Url::parse(&format!(
         "{}/{}/{}",
@@ -73,6 +112,20 @@ pub fn generate_object_id(external_base: &str, kind_type: KindType) -> Result<Ur
     .map_err(|err| err.into())
 }
 
+/// A random url-safe token for links that grant access on their own, such as private feeds.
+pub fn generate_token() -> String {
+    let mut arr: [u8; 24] = [0; 24];
+    thread_rng().fill(&mut arr[..]);
+    base64::encode_engine(arr, &URL_SAFE_ENGINE)
+}
+
+/// Reads the kind of a generated object from the last path segment of its id.
+pub fn parse_object_id(generated_id: &str) -> Result<KindType, ObjectIdError> {
+    let bytes = base64::decode_engine(generated_id, &URL_SAFE_ENGINE)
+        .map_err(|_| ObjectIdError::CannotParse)?;
+    KindType::try_from(bytes)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -87,24 +140,67 @@ mod tests {
             <u8 as TryInto<KindType>>::try_into(2u8).expect("2 is accept"),
             KindType::Accept
         );
-        assert!(<u8 as TryInto<KindType>>::try_into(3u8).is_err());
+        assert_eq!(
+            <u8 as TryInto<KindType>>::try_into(3u8).expect("3 is reject"),
+            KindType::Reject
+        );
+        assert_eq!(
+            <u8 as TryInto<KindType>>::try_into(6u8).expect("6 is update"),
+            KindType::Update
+        );
+        assert_eq!(
+            <u8 as TryInto<KindType>>::try_into(7u8).expect("7 is delete"),
+            KindType::Delete
+        );
+        assert_eq!(
+            <u8 as TryInto<KindType>>::try_into(8u8).expect("8 is announce"),
+            KindType::Announce
+        );
+        assert_eq!(
+            <u8 as TryInto<KindType>>::try_into(9u8).expect("9 is move"),
+            KindType::Move
+        );
+        assert!(<u8 as TryInto<KindType>>::try_into(10u8).is_err());
     }
 
     #[test]
     fn kind_from_vector() {
         assert_eq!(
             <Vec<u8> as TryInto<KindType>>::try_into(
-                base64::decode("AQGFsrFc/j9nBxsU1S/8XfzVoJQ=").expect("object id must decode")
+                base64::decode_engine("AQGFsrFc_j9nBxsU1S_8XfzVoJQ", &URL_SAFE_ENGINE)
+                    .expect("object id must decode")
             )
             .expect("id is follow"),
             KindType::Follow
         );
         assert_eq!(
             <Vec<u8> as TryInto<KindType>>::try_into(
-                base64::decode("ArDFU9LX4YbQc8FZ+od5OSBvo78=").expect("object id must decode")
+                base64::decode_engine("ArDFU9LX4YbQc8FZ-od5OSBvo78", &URL_SAFE_ENGINE)
+                    .expect("object id must decode")
             )
             .expect("id is accept"),
             KindType::Accept
         );
+        assert!(<Vec<u8> as TryInto<KindType>>::try_into(vec![]).is_err());
+    }
+
+    #[test]
+    fn generated_ids_round_trip() {
+        let id = generate_object_id("https://events.thegem.city", KindType::Accept)
+            .expect("id is generated");
+        let segment = id
+            .path_segments()
+            .and_then(|mut segments| segments.next_back())
+            .expect("id has a path");
+
+        assert!(id
+            .as_str()
+            .starts_with("https://events.thegem.city/objects/"));
+        assert!(!segment.contains(['+', '/', '=']));
+        assert_eq!(
+            parse_object_id(segment).expect("id parses"),
+            KindType::Accept
+        );
+        assert!(parse_object_id("not+base64").is_err());
     }
 }
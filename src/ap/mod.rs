@@ -1,2 +1,6 @@
 pub mod actor;
+pub mod event;
 pub mod ids;
+pub mod normalize;
+pub mod note;
+pub mod object_or_id;
@@ -0,0 +1,374 @@
+use serde_json::{Map, Value};
+use thiserror::Error;
+
+pub const PUBLIC: &str = "https://www.w3.org/ns/activitystreams#Public";
+
+const DEFAULT_CONTEXT: &str = "https://www.w3.org/ns/activitystreams";
+
+const AS_NAMESPACES: [&str; 4] = [
+    "https://www.w3.org/ns/activitystreams#",
+    "http://www.w3.org/ns/activitystreams#",
+    "https://www.w3.org/ns/activitystreams",
+    "http://www.w3.org/ns/activitystreams",
+];
+
+// Fields that only ever reference another object by id.
+const REFERENCE_FIELDS: [&str; 3] = ["actor", "attributedTo", "inReplyTo"];
+
+const ADDRESSING_FIELDS: [&str; 5] = ["to", "cc", "bto", "bcc", "audience"];
+
+// Activity types whose `object` is always handled as a reference, even when the sender embeds it.
+const REFERENCE_OBJECT_KINDS: [&str; 6] = [
+    "Follow",
+    "Join",
+    "Leave",
+    "TentativeAccept",
+    "Reject",
+    "Move",
+];
+
+#[derive(Error, Debug)]
+pub enum NormalizeError {
+    #[error("activity is not a json object")]
+    NotAnObject,
+
+    #[error("embedded {0} has no id")]
+    MissingId(String),
+}
+
+/// Returns true if the value is any of the spellings of the ActivityStreams Public collection.
+pub fn is_public(value: &str) -> bool {
+    matches!(
+        value,
+        PUBLIC | "http://www.w3.org/ns/activitystreams#Public" | "as:Public" | "Public"
+    )
+}
+
+/// Returns true if a normalized activity or object is addressed to the Public collection.
+pub fn is_public_addressed(value: &Value) -> bool {
+    ["to", "cc"].iter().any(|field| match value.get(field) {
+        Some(Value::Array(items)) => items.iter().any(|i| i.as_str() == Some(PUBLIC)),
+        Some(Value::String(item)) => item == PUBLIC,
+        _ => false,
+    })
+}
+
+/// Rewrites an incoming activity into the shape that the activity structs deserialize.
+///
+/// Compact IRIs and full ActivityStreams IRIs are reduced to plain terms, references that were
+/// embedded as objects are reduced to their ids, addressing is always a list with the Public
+/// collection in its canonical form, and a default `@context` is added when one is missing.
+pub fn normalize_activity(value: Value) -> Result<Value, NormalizeError> {
+    let mut map = match value {
+        Value::Object(map) => map,
+        _ => return Err(NormalizeError::NotAnObject),
+    };
+
+    let context = map
+        .remove("@context")
+        .unwrap_or_else(|| Value::String(DEFAULT_CONTEXT.to_string()));
+    let prefixes = context_prefixes(&context);
+
+    let mut normalized = normalize_object(map, &prefixes)?;
+    normalized.insert("@context".to_string(), context);
+    Ok(Value::Object(normalized))
+}
+
+fn context_prefixes(context: &Value) -> Vec<String> {
+    let mut prefixes = vec!["as".to_string()];
+    let entries = match context {
+        Value::Array(entries) => entries.iter().collect(),
+        entry => vec![entry],
+    };
+    for entry in entries {
+        if let Value::Object(terms) = entry {
+            for (term, iri) in terms {
+                if iri.as_str().map(|i| AS_NAMESPACES.contains(&i)) == Some(true) {
+                    prefixes.push(term.clone());
+                }
+            }
+        }
+    }
+    prefixes
+}
+
+fn compact_term(term: &str, prefixes: &[String]) -> String {
+    for namespace in AS_NAMESPACES.iter().filter(|n| n.ends_with('#')) {
+        if let Some(stripped) = term.strip_prefix(namespace) {
+            return stripped.to_string();
+        }
+    }
+    match term.split_once(':') {
+        Some((prefix, rest)) if prefixes.iter().any(|p| p == prefix) => rest.to_string(),
+        _ => match term {
+            "@id" => "id".to_string(),
+            "@type" => "type".to_string(),
+            _ => term.to_string(),
+        },
+    }
+}
+
+fn normalize_object(
+    map: Map<String, Value>,
+    prefixes: &[String],
+) -> Result<Map<String, Value>, NormalizeError> {
+    let mut compacted = Map::new();
+    for (key, value) in map {
+        if key == "@context" {
+            continue;
+        }
+        compacted.insert(compact_term(&key, prefixes), value);
+    }
+
+    if let Some(kind) = compacted.remove("type") {
+        compacted.insert("type".to_string(), normalize_type(kind, prefixes));
+    }
+    if let Some(id) = compacted.remove("id") {
+        compacted.insert("id".to_string(), single(id));
+    }
+
+    for field in REFERENCE_FIELDS {
+        if let Some(value) = compacted.remove(field) {
+            compacted.insert(field.to_string(), reference(single(value), field)?);
+        }
+    }
+
+    for field in ADDRESSING_FIELDS {
+        if let Some(value) = compacted.remove(field) {
+            compacted.insert(field.to_string(), addressing(value, field)?);
+        }
+    }
+
+    if let Some(object) = compacted.remove("object") {
+        let object = match single(object) {
+            Value::Object(inner) => Value::Object(normalize_object(inner, prefixes)?),
+            other => other,
+        };
+        let as_reference = compacted
+            .get("type")
+            .and_then(Value::as_str)
+            .map(|kind| REFERENCE_OBJECT_KINDS.contains(&kind))
+            == Some(true);
+        let object = match as_reference {
+            true => reference(object, "object")?,
+            false => object,
+        };
+        compacted.insert("object".to_string(), object);
+    }
+
+    Ok(compacted)
+}
+
+fn normalize_type(kind: Value, prefixes: &[String]) -> Value {
+    match kind {
+        Value::String(kind) => Value::String(compact_term(&kind, prefixes)),
+        Value::Array(kinds) => {
+            let compacted: Vec<String> = kinds
+                .iter()
+                .filter_map(Value::as_str)
+                .map(|kind| compact_term(kind, prefixes))
+                .collect();
+            // Prefer a plain ActivityStreams term over extension types.
+            compacted
+                .iter()
+                .find(|kind| !kind.contains(':'))
+                .or_else(|| compacted.first())
+                .map(|kind| Value::String(kind.clone()))
+                .unwrap_or(Value::Null)
+        }
+        other => other,
+    }
+}
+
+fn single(value: Value) -> Value {
+    match value {
+        Value::Array(mut items) if items.len() == 1 => items.remove(0),
+        other => other,
+    }
+}
+
+fn reference(value: Value, field: &str) -> Result<Value, NormalizeError> {
+    match value {
+        Value::Object(map) => map
+            .get("id")
+            .or_else(|| map.get("@id"))
+            .or_else(|| map.get("href"))
+            .cloned()
+            .map(single)
+            .ok_or_else(|| NormalizeError::MissingId(field.to_string())),
+        other => Ok(other),
+    }
+}
+
+fn addressing(value: Value, field: &str) -> Result<Value, NormalizeError> {
+    let items = match value {
+        Value::Array(items) => items,
+        Value::Null => vec![],
+        item => vec![item],
+    };
+    let mut addresses = vec![];
+    for item in items {
+        match reference(item, field)? {
+            Value::String(address) if is_public(&address) => {
+                addresses.push(Value::String(PUBLIC.to_string()))
+            }
+            address => addresses.push(address),
+        }
+    }
+    Ok(Value::Array(addresses))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use activitypub_federation::deser::context::WithContext;
+    use std::{fs::File, io::Read, path::PathBuf};
+
+    use crate::objects::actor::PersonAcceptedActivities;
+
+    fn load_fixture(name: &str) -> Value {
+        let mut path = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+        path.push("resources/test/activities");
+        path.push(name);
+
+        let mut file = File::open(path).expect("fixture exists");
+        let mut data = String::new();
+        file.read_to_string(&mut data).expect("fixture is readable");
+        serde_json::from_str(&data).expect("fixture is json")
+    }
+
+    #[test]
+    fn public_spellings() {
+        assert!(is_public("https://www.w3.org/ns/activitystreams#Public"));
+        assert!(is_public("http://www.w3.org/ns/activitystreams#Public"));
+        assert!(is_public("as:Public"));
+        assert!(is_public("Public"));
+        assert!(!is_public("https://thegem.city/users/nick/followers"));
+    }
+
+    #[test]
+    fn normalize_fixtures() {
+        // (fixture, type, actor, object id, addressed to public)
+        let table = [
+            (
+                "mastodon-follow.json",
+                "Follow",
+                "https://thegem.city/users/nick",
+                "https://events.thegem.city/actor/readily-splendid-mule",
+                false,
+            ),
+            (
+                "compacted-follow.json",
+                "Follow",
+                "https://thegem.city/users/nick",
+                "https://events.thegem.city/actor/readily-splendid-mule",
+                false,
+            ),
+            (
+                "expanded-follow.json",
+                "Follow",
+                "https://thegem.city/users/nick",
+                "https://events.thegem.city/actor/readily-splendid-mule",
+                false,
+            ),
+            (
+                "pleroma-follow-inline.json",
+                "Follow",
+                "https://pleroma.example/users/alice",
+                "https://events.thegem.city/actor/readily-splendid-mule",
+                true,
+            ),
+            (
+                "public-spellings-follow.json",
+                "Follow",
+                "https://thegem.city/users/nick",
+                "https://events.thegem.city/actor/readily-splendid-mule",
+                true,
+            ),
+            (
+                "mastodon-accept-inline.json",
+                "Accept",
+                "https://thegem.city/users/nick",
+                "https://events.thegem.city/objects/AXBhdWV3aS0xMjM0NTY3ODkwYWI",
+                false,
+            ),
+            (
+                "mastodon-undo-follow.json",
+                "Undo",
+                "https://thegem.city/users/nick",
+                "https://thegem.city/1f4c8a5e-7d62-4a39-9a3f-3c1e1f6c2b10",
+                false,
+            ),
+            (
+                "accept-string-object.json",
+                "Accept",
+                "https://thegem.city/users/nick",
+                "https://events.thegem.city/objects/AXBhdWV3aS0xMjM0NTY3ODkwYWI",
+                false,
+            ),
+            (
+                "mobilizon-join.json",
+                "Join",
+                "https://mobilizon.example/@alice",
+                "https://events.thegem.city/actor/readily-splendid-mule/event",
+                false,
+            ),
+            (
+                "mobilizon-leave.json",
+                "Leave",
+                "https://mobilizon.example/@alice",
+                "https://events.thegem.city/actor/readily-splendid-mule/event",
+                false,
+            ),
+            (
+                "tentative-accept-event.json",
+                "TentativeAccept",
+                "https://friendica.example/profile/bob",
+                "https://events.thegem.city/actor/readily-splendid-mule/event",
+                false,
+            ),
+            (
+                "reject-follow-inline.json",
+                "Reject",
+                "https://thegem.city/users/nick",
+                "https://events.thegem.city/objects/AXBhdWV3aS0xMjM0NTY3ODkwYWI",
+                false,
+            ),
+            (
+                "mastodon-move.json",
+                "Move",
+                "https://thegem.city/users/nick",
+                "https://thegem.city/users/nick",
+                false,
+            ),
+        ];
+
+        for (fixture, kind, actor, object, public) in table {
+            let normalized = normalize_activity(load_fixture(fixture)).expect(fixture);
+
+            assert_eq!(normalized["type"], kind, "{fixture}");
+            assert_eq!(normalized["actor"], actor, "{fixture}");
+            let object_id = match &normalized["object"] {
+                Value::Object(inner) => inner["id"].clone(),
+                other => other.clone(),
+            };
+            assert_eq!(object_id, object, "{fixture}");
+            assert_eq!(is_public_addressed(&normalized), public, "{fixture}");
+
+            serde_json::from_value::<WithContext<PersonAcceptedActivities>>(normalized)
+                .unwrap_or_else(|err| panic!("{fixture}: {err}"));
+        }
+    }
+
+    #[test]
+    fn rejects_non_objects() {
+        assert!(normalize_activity(Value::String("nope".to_string())).is_err());
+        assert!(normalize_activity(serde_json::json!({
+            "type": "Follow",
+            "id": "https://thegem.city/1",
+            "actor": {"type": "Person"},
+        }))
+        .is_err());
+    }
+}
@@ -0,0 +1,59 @@
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct Tag {
+    #[serde(rename = "type")]
+    pub kind: String,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub href: Option<String>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+}
+
+impl Tag {
+    pub fn mention(href: &str, name: &str) -> Tag {
+        Tag {
+            kind: "Mention".to_string(),
+            href: Some(href.to_string()),
+            name: Some(name.to_string()),
+        }
+    }
+
+    /// A hashtag, linking to where events with the tag are listed.
+    pub fn hashtag(href: &str, tag: &str) -> Tag {
+        Tag {
+            kind: "Hashtag".to_string(),
+            href: Some(href.to_string()),
+            name: Some(format!("#{tag}")),
+        }
+    }
+}
+
+/// A post. Event actors receive commands as direct messages and answer with them.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct Note {
+    #[serde(rename = "id")]
+    pub ap_id: String,
+
+    #[serde(rename = "type")]
+    pub kind: activitystreams_kinds::object::NoteType,
+
+    #[serde(rename = "attributedTo")]
+    pub attributed_to: String,
+
+    pub content: String,
+
+    #[serde(rename = "inReplyTo", skip_serializing_if = "Option::is_none")]
+    pub in_reply_to: Option<String>,
+
+    #[serde(default)]
+    pub to: Vec<String>,
+
+    #[serde(default)]
+    pub cc: Vec<String>,
+
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tag: Vec<Tag>,
+}
@@ -0,0 +1,20 @@
+use activitypub_federation::traits::ActivityHandler;
+use serde::{Deserialize, Serialize};
+use url::Url;
+
+/// An activity field that remote servers send either as an embedded object or as a bare id.
+#[derive(Deserialize, Serialize, Clone, Debug)]
+#[serde(untagged)]
+pub enum ObjectOrId<T> {
+    Object(T),
+    Id(Url),
+}
+
+impl<T: ActivityHandler> ObjectOrId<T> {
+    pub fn id(&self) -> &Url {
+        match self {
+            ObjectOrId::Object(object) => object.id(),
+            ObjectOrId::Id(id) => id,
+        }
+    }
+}
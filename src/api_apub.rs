@@ -1,10 +1,23 @@
 use crate::{
+    ap::{event::event_context, normalize::normalize_activity},
+    collections::{collection_response, CollectionQuery, CollectionSource},
     error::ApEventsError,
-    objects::actor::{EventActor, PersonAcceptedActivities},
+    objects::{
+        actor::{EventActor, PersonAcceptedActivities},
+        event::event_into_apub,
+    },
+    places::place_into_apub,
     state::MyStateHandle,
+    storage_actor::actor_deleted_at,
+    storage_events::get_event,
+    storage_follows::followers_on_origin,
+    storage_objects::get_generated_object,
+    storage_places::get_place,
+    storage_rsvps::{count_rsvps, RsvpStatus, ALL_OCCURRENCES},
+    util::signature_key_id,
 };
 use activitypub_federation::{
-    core::{inbox::receive_activity, object_id::ObjectId},
+    core::{inbox::receive_activity, object_id::ObjectId, signatures::verify_signature},
     data::Data,
     deser::context::WithContext,
     traits::ApubObject,
@@ -12,9 +25,9 @@ use activitypub_federation::{
 };
 
 use actix_web::{web, HttpRequest, HttpResponse};
-use serde::{Deserialize, Serialize};
-use serde_json::Value;
-use std::{ops::Deref, str::FromStr, vec};
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use std::ops::Deref;
 use url::Url;
 
 pub async fn handle_wellknown_host_meta(
@@ -32,6 +45,17 @@ pub async fn handle_instance_get_event_actor(
     // TODO: Validate signatures
 
     let request_url = format!("{}/actor/{}", app_state.external_base, name);
+    if let Some(deleted_at) = actor_deleted_at(&app_state, &request_url).await? {
+        return Ok(HttpResponse::Gone()
+            .content_type(APUB_JSON_CONTENT_TYPE)
+            .json(WithContext::new_default(Tombstone {
+                ap_id: request_url,
+                kind: "Tombstone".to_string(),
+                former_type: app_state.actor_kind.clone(),
+                deleted: deleted_at.and_utc(),
+            })));
+    }
+
     let url = Url::parse(&request_url)?;
     let user = ObjectId::<EventActor>::new(url)
         .dereference_local(&app_state)
@@ -41,223 +65,251 @@ pub async fn handle_instance_get_event_actor(
         .content_type(APUB_JSON_CONTENT_TYPE)
         .json(WithContext::new(
             user.into_apub(&app_state).await?,
-            vec![
-                Value::from_str("\"https://www.w3.org/ns/activitystreams\"")?,
-                Value::from_str("\"https://w3id.org/security/v1\"")?,
-            ],
+            event_context(),
         )))
 }
 
-#[derive(Serialize, Deserialize)]
-#[serde(rename_all = "camelCase")]
-pub struct FollowersCollection {
-    #[serde(rename = "id")]
-    pub ap_id: String,
-    #[serde(rename = "type")]
-    pub kind: String,
-    pub total_items: u32,
-    #[serde(skip_serializing_if = "Option::is_none")]
-    pub first: Option<String>,
+async fn local_actor(app_state: &MyStateHandle, name: &str) -> Result<EventActor, ApEventsError> {
+    let request_url = format!("{}/actor/{}", app_state.external_base, name);
+    let url = Url::parse(&request_url)?;
+    ObjectId::<EventActor>::new(url)
+        .dereference_local(app_state)
+        .await
 }
 
-#[derive(Serialize, Deserialize)]
-#[serde(rename_all = "camelCase")]
-pub struct FollowersCollectionPage {
-    #[serde(rename = "id")]
-    pub ap_id: String,
-    #[serde(rename = "type")]
-    pub kind: String,
-    pub total_items: u32,
-    pub part_of: String,
-    pub first: Option<String>,
-    #[serde(skip_serializing_if = "Option::is_none")]
-    pub prev: Option<String>,
-    #[serde(skip_serializing_if = "Option::is_none")]
-    pub next: Option<String>,
-    pub items: Vec<String>,
-}
+pub async fn handle_instance_get_event_actor_event(
+    name: web::Path<String>,
+    app_state: web::Data<MyStateHandle>,
+) -> Result<HttpResponse, ApEventsError> {
+    let user = local_actor(&app_state, &name).await?;
+    let event = get_event(&app_state, user.ap_id.inner().as_str())
+        .await?
+        .filter(|event| !event.is_draft())
+        .ok_or(ApEventsError::ObjectNotFound(user.event_url()?.to_string()))?;
+    let participant_count = count_rsvps(
+        &app_state,
+        user.ap_id.inner().as_str(),
+        ALL_OCCURRENCES,
+        RsvpStatus::Going,
+    )
+    .await?;
 
-#[derive(Deserialize)]
-pub struct Pagination {
-    pub page: Option<i32>,
+    Ok(HttpResponse::Ok()
+        .content_type(APUB_JSON_CONTENT_TYPE)
+        .json(WithContext::new(
+            event_into_apub(&event, &user, None, participant_count)?,
+            event_context(),
+        )))
 }
 
-pub async fn handle_instance_get_event_actor_followers(
+/// The `Place` object of a place actor.
+pub async fn handle_instance_get_place_actor_place(
     name: web::Path<String>,
     app_state: web::Data<MyStateHandle>,
-    pagination: web::Query<Pagination>,
 ) -> Result<HttpResponse, ApEventsError> {
-    // TODO: Validate signatures
-
-    let request_url = format!("{}/actor/{}", app_state.external_base, name);
-    let url = Url::parse(&request_url)?;
-    let user = ObjectId::<EventActor>::new(url)
-        .dereference_local(&app_state)
-        .await?;
+    let user = local_actor(&app_state, &name).await?;
+    let place = get_place(&app_state, user.ap_id.inner().as_str())
+        .await?
+        .ok_or(ApEventsError::ObjectNotFound(user.place_url()?.to_string()))?;
 
-    let ap_id = user.followers_url()?.to_string();
+    Ok(HttpResponse::Ok()
+        .content_type(APUB_JSON_CONTENT_TYPE)
+        .json(WithContext::new(
+            place_into_apub(&place, &user)?,
+            event_context(),
+        )))
+}
 
-    let total: (i64,) =
-        sqlx::query_as("SELECT COUNT(*) FROM follow_activities WHERE followee_ap_id = $1")
-            .bind(&request_url)
-            .fetch_one(&app_state.pool)
-            .await?;
+/// A single occurrence of a recurring event, with the number of attendees going to it.
+pub async fn handle_instance_get_event_actor_occurrence(
+    path: web::Path<(String, String)>,
+    app_state: web::Data<MyStateHandle>,
+) -> Result<HttpResponse, ApEventsError> {
+    let (name, occurrence_id) = path.into_inner();
+    let user = local_actor(&app_state, &name).await?;
+    let not_found =
+        || ApEventsError::ObjectNotFound(format!("{}/event/{}", user.ap_id, occurrence_id));
+    let event = get_event(&app_state, user.ap_id.inner().as_str())
+        .await?
+        .filter(|event| !event.is_draft())
+        .ok_or_else(not_found)?;
+    let occurrence = event.occurrence(&occurrence_id).ok_or_else(not_found)?;
+    let participant_count = count_rsvps(
+        &app_state,
+        user.ap_id.inner().as_str(),
+        &occurrence_id,
+        RsvpStatus::Going,
+    )
+    .await?;
 
-    let first: Option<String> = match total.0 {
-        0 => None,
-        _ => Some(format!("{}/?page=1", ap_id)),
-    };
+    Ok(HttpResponse::Ok()
+        .content_type(APUB_JSON_CONTENT_TYPE)
+        .json(WithContext::new(
+            event_into_apub(&event, &user, Some(&occurrence), participant_count)?,
+            event_context(),
+        )))
+}
 
-    if pagination.page.is_none() {
-        return Ok(HttpResponse::Ok()
-            .content_type(APUB_JSON_CONTENT_TYPE)
-            .json(WithContext::new(
-                FollowersCollection {
-                    ap_id,
-                    kind: "OrderedCollection".to_string(),
-                    total_items: total.0 as u32,
-                    first,
-                },
-                vec![Value::from_str(
-                    "\"https://www.w3.org/ns/activitystreams\"",
-                )?],
-            )));
-    }
+pub async fn handle_instance_get_event_actor_followers(
+    name: web::Path<String>,
+    app_state: web::Data<MyStateHandle>,
+    query: web::Query<CollectionQuery>,
+) -> Result<HttpResponse, ApEventsError> {
+    // TODO: Validate signatures
 
-    let page = pagination.page.unwrap();
+    let user = local_actor(&app_state, &name).await?;
+    collection_response(
+        &app_state,
+        user.followers_url()?,
+        &CollectionSource::followers(),
+        user.ap_id.inner().as_str(),
+        &query,
+    )
+    .await
+}
 
-    if page < 0 {
-        return Err(ApEventsError::Generic(
-            "invalid query string parameter: page".to_string(),
-        ));
-    }
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PartialFollowersCollection {
+    #[serde(rename = "id")]
+    pub ap_id: String,
+    #[serde(rename = "type")]
+    pub kind: String,
+    pub ordered_items: Vec<String>,
+}
 
-    let offset = (100 * page) - 100;
+/// The actor that signed a request, once the signature is checked. Unsigned requests have none.
+async fn request_signer(
+    request: &HttpRequest,
+    app_state: &MyStateHandle,
+) -> Result<Option<Url>, ApEventsError> {
+    let key_id = match signature_key_id(request) {
+        Some(key_id) => key_id,
+        None => return Ok(None),
+    };
+    let mut signer_id = Url::parse(&key_id)?;
+    signer_id.set_fragment(None);
 
-    let items: Vec<(String,)> = sqlx::query_as(
-        "SELECT followee_ap_id FROM follow_activities WHERE followee_ap_id = $1 ORDER BY created_at ASC LIMIT 100 OFFSET $2",
-    )
-    .bind(&request_url)
-    .bind(offset)
-    .fetch_all(&app_state.pool)
-    .await?;
+    let signer = ObjectId::<EventActor>::new(signer_id.clone())
+        .dereference(app_state, &app_state.local_instance, &mut 0)
+        .await?;
+    verify_signature(request, &signer.public_key)?;
+    Ok(Some(signer_id))
+}
 
-    let prev = match page {
-        1 => None,
-        _ => Some(format!("{}?page={}", ap_id, page - 1)),
-    };
+/// The followers of an event that are on the requesting instance, used by remote servers to
+/// reconcile follows after a `Collection-Synchronization` digest mismatch.
+pub async fn handle_instance_get_event_actor_followers_synchronization(
+    request: HttpRequest,
+    name: web::Path<String>,
+    app_state: web::Data<MyStateHandle>,
+) -> Result<HttpResponse, ApEventsError> {
+    let user = local_actor(&app_state, &name).await?;
 
-    let next = match items.len() {
-        100 => Some(format!("{}?page={}", ap_id, page + 1)),
-        _ => None,
-    };
+    let signer_id = request_signer(&request, &app_state)
+        .await?
+        .ok_or_else(|| ApEventsError::InvalidQuery("request must be signed".to_string()))?;
 
     Ok(HttpResponse::Ok()
         .content_type(APUB_JSON_CONTENT_TYPE)
-        .json(WithContext::new(
-            FollowersCollectionPage {
-                ap_id: format!("{}?page={}", ap_id, page),
-                kind: "OrderedCollection".to_string(),
-                total_items: total.0 as u32,
-                part_of: ap_id,
-                first,
-                next,
-                prev,
-                items: items.iter().map(|i| i.0.clone()).collect(),
-            },
-            vec![Value::from_str(
-                "\"https://www.w3.org/ns/activitystreams\"",
-            )?],
-        )))
+        .json(WithContext::new_default(PartialFollowersCollection {
+            ap_id: user.followers_synchronization_url()?.to_string(),
+            kind: "OrderedCollection".to_string(),
+            ordered_items: followers_on_origin(&app_state, user.ap_id.inner().as_str(), &signer_id)
+                .await?,
+        })))
 }
 
 pub async fn handle_instance_get_event_actor_following(
     name: web::Path<String>,
     app_state: web::Data<MyStateHandle>,
-    pagination: web::Query<Pagination>,
+    query: web::Query<CollectionQuery>,
 ) -> Result<HttpResponse, ApEventsError> {
     // TODO: Validate signatures
 
-    let request_url = format!("{}/actor/{}", app_state.external_base, name);
-    let url = Url::parse(&request_url)?;
-    let user = ObjectId::<EventActor>::new(url)
-        .dereference_local(&app_state)
-        .await?;
+    let user = local_actor(&app_state, &name).await?;
+    collection_response(
+        &app_state,
+        user.following_url()?,
+        &CollectionSource::following(),
+        user.ap_id.inner().as_str(),
+        &query,
+    )
+    .await
+}
 
-    let ap_id = user.following_url()?.to_string();
+pub async fn handle_instance_get_event_actor_participants(
+    name: web::Path<String>,
+    app_state: web::Data<MyStateHandle>,
+    query: web::Query<CollectionQuery>,
+) -> Result<HttpResponse, ApEventsError> {
+    let user = local_actor(&app_state, &name).await?;
+    collection_response(
+        &app_state,
+        user.participants_url()?,
+        &CollectionSource::participants(),
+        user.ap_id.inner().as_str(),
+        &query,
+    )
+    .await
+}
 
-    let total: (i64,) =
-        sqlx::query_as("SELECT COUNT(*) FROM follow_activities WHERE follower_ap_id = $1")
-            .bind(&request_url)
-            .fetch_one(&app_state.pool)
-            .await?;
+pub async fn handle_instance_get_event_actor_outbox(
+    name: web::Path<String>,
+    app_state: web::Data<MyStateHandle>,
+    query: web::Query<CollectionQuery>,
+) -> Result<HttpResponse, ApEventsError> {
+    let user = local_actor(&app_state, &name).await?;
+    collection_response(
+        &app_state,
+        user.outbox_url()?,
+        &CollectionSource::outbox(),
+        user.ap_id.inner().as_str(),
+        &query,
+    )
+    .await
+}
 
-    let first: Option<String> = match total.0 {
-        0 => None,
-        _ => Some(format!("{}/?page=1", ap_id)),
-    };
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Tombstone {
+    #[serde(rename = "id")]
+    pub ap_id: String,
+    #[serde(rename = "type")]
+    pub kind: String,
+    pub former_type: String,
+    pub deleted: DateTime<Utc>,
+}
 
-    if pagination.page.is_none() {
-        return Ok(HttpResponse::Ok()
-            .content_type(APUB_JSON_CONTENT_TYPE)
-            .json(WithContext::new(
-                FollowersCollection {
-                    ap_id,
-                    kind: "OrderedCollection".to_string(),
-                    total_items: total.0 as u32,
-                    first,
-                },
-                vec![Value::from_str(
-                    "\"https://www.w3.org/ns/activitystreams\"",
-                )?],
-            )));
+/// Serves an activity or object sent by a local actor. Objects that are not public, such as
+/// direct messages, are only served to a signed fetch by one of their addressees.
+pub async fn handle_instance_get_object(
+    request: HttpRequest,
+    name: web::Path<String>,
+    app_state: web::Data<MyStateHandle>,
+) -> Result<HttpResponse, ApEventsError> {
+    let found_object = get_generated_object(&app_state, &name).await?;
+    if !found_object.is_public {
+        let signer = request_signer(&request, &app_state).await?;
+        if !found_object.readable_by(signer.as_ref().map(Url::as_str)) {
+            return Err(ApEventsError::ObjectNotFound(found_object.ap_id));
+        }
     }
 
-    let page = pagination.page.unwrap();
-
-    if page < 0 {
-        return Err(ApEventsError::Generic(
-            "invalid query string parameter: page".to_string(),
-        ));
+    if let Some(deleted_at) = found_object.deleted_at {
+        return Ok(HttpResponse::Gone()
+            .content_type(APUB_JSON_CONTENT_TYPE)
+            .json(WithContext::new_default(Tombstone {
+                ap_id: found_object.ap_id,
+                kind: "Tombstone".to_string(),
+                former_type: found_object.kind,
+                deleted: deleted_at.and_utc(),
+            })));
     }
 
-    let offset = (100 * page) - 100;
-
-    let items: Vec<(String,)> = sqlx::query_as(
-        "SELECT followee_ap_id FROM follow_activities WHERE follower_ap_id = $1 ORDER BY created_at ASC LIMIT 100 OFFSET $2",
-    )
-    .bind(&request_url)
-    .bind(offset)
-    .fetch_all(&app_state.pool)
-    .await?;
-
-    let prev = match page {
-        1 => None,
-        _ => Some(format!("{}?page={}", ap_id, page - 1)),
-    };
-
-    let next = match items.len() {
-        100 => Some(format!("{}?page={}", ap_id, page + 1)),
-        _ => None,
-    };
-
     Ok(HttpResponse::Ok()
         .content_type(APUB_JSON_CONTENT_TYPE)
-        .json(WithContext::new(
-            FollowersCollectionPage {
-                ap_id: format!("{}?page={}", ap_id, page),
-                kind: "OrderedCollection".to_string(),
-                total_items: total.0 as u32,
-                part_of: ap_id,
-                first,
-                next,
-                prev,
-                items: items.iter().map(|i| i.0.clone()).collect(),
-            },
-            vec![Value::from_str(
-                "\"https://www.w3.org/ns/activitystreams\"",
-            )?],
-        )))
+        .json(found_object.payload))
 }
 
 pub async fn handle_instance_post_event_actor_inbox(
@@ -266,7 +318,8 @@ pub async fn handle_instance_post_event_actor_inbox(
     app_state: web::Data<MyStateHandle>,
 ) -> Result<HttpResponse, ApEventsError> {
     let data: MyStateHandle = app_state.into_inner().deref().clone();
-    let activity = serde_json::from_str(&payload)?;
+    let activity = normalize_activity(serde_json::from_str(&payload)?)?;
+    let activity = serde_json::from_value(activity)?;
     receive_activity::<WithContext<PersonAcceptedActivities>, EventActor, MyStateHandle>(
         request,
         activity,
@@ -275,3 +328,63 @@ pub async fn handle_instance_post_event_actor_inbox(
     )
     .await
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        ap::ids::{generate_object_id, KindType},
+        storage_objects::store_object,
+    };
+    use actix_web::test::TestRequest;
+    use serde_json::json;
+
+    #[actix_web::test]
+    async fn direct_messages_are_not_served() {
+        let Some(app_state) = crate::state::test_state().await else {
+            return;
+        };
+        let actor = format!("https://events.example/actor/{}", rand::random::<u64>());
+        let store = |to: Vec<&'static str>| {
+            let app_state = app_state.clone();
+            let actor = actor.clone();
+            async move {
+                let id = generate_object_id(&app_state.external_base, KindType::Create).unwrap();
+                store_object(
+                    &app_state,
+                    &actor,
+                    &json!({
+                        "id": id,
+                        "type": "Create",
+                        "actor": actor,
+                        "to": to,
+                        "object": {"type": "Note", "to": to, "content": "<p>#admin</p>"}
+                    }),
+                )
+                .await
+                .unwrap();
+                id.path_segments().unwrap().next_back().unwrap().to_string()
+            }
+        };
+        let get = |name: String| {
+            let app_state = app_state.clone();
+            async move {
+                handle_instance_get_object(
+                    TestRequest::default().to_http_request(),
+                    web::Path::from(name),
+                    web::Data::new(app_state),
+                )
+                .await
+            }
+        };
+
+        let direct = store(vec!["https://thegem.city/users/nick"]).await;
+        assert!(matches!(
+            get(direct).await,
+            Err(ApEventsError::ObjectNotFound(_))
+        ));
+
+        let public = store(vec!["https://www.w3.org/ns/activitystreams#Public"]).await;
+        assert!(get(public).await.is_ok());
+    }
+}
@@ -1,96 +1,616 @@
-use std::collections::HashMap;
-
 use activitypub_federation::{
-    core::signatures::generate_actor_keypair, deser::context::WithContext, traits::ApubObject,
-    APUB_JSON_CONTENT_TYPE,
+    deser::context::WithContext, traits::ApubObject, APUB_JSON_CONTENT_TYPE,
 };
 use actix_web::{http::header, web, HttpResponse};
+use chrono::{DateTime, Utc};
 use serde::Deserialize;
+use url::Url;
 
-use crate::ap::actor::{Actor, PublicKey};
+use crate::ap::event::event_context;
 use crate::error::ApEventsError;
+use crate::import::{fetch_calendar, import_calendar};
+use crate::lifecycle::{admin_link, send_event_object, transition_event};
+use crate::migrate::{export_event, import_event, move_event};
 use crate::objects::actor::EventActor;
+use crate::objects::event::event_into_apub;
+use crate::places::{announce_at_place, find_duplicates, place_into_apub};
 use crate::state::MyStateHandle;
-use crate::storage_actor::create_actor;
+use crate::storage_actor::create_local_actor;
+use crate::storage_calendar_tokens::issue_calendar_token;
+use crate::storage_calendars::{get_calendar, upsert_calendar, Calendar};
+use crate::storage_events::{
+    exclude_occurrence, get_event, normalize_tag, upsert_event, Event, EventStatus,
+};
+use crate::storage_import_feeds::add_import_feed;
+use crate::storage_objects::delete_object;
+use crate::storage_organizers::Role;
+use crate::storage_places::{all_places, get_place, merge_places, upsert_place, Place};
+use crate::storage_rsvps::{count_rsvps, RsvpStatus, ALL_OCCURRENCES};
+use crate::sweeper::sweep_events;
+use crate::updates::event_edited;
+use crate::waitlist::fill_waitlists;
 
 pub async fn handle_internal_create_user(
     app_state: web::Data<MyStateHandle>,
 ) -> Result<HttpResponse, ApEventsError> {
-    let name = petname::Petnames::default().generate_one(3, "-");
-    let object_id = format!("{}/actor/{}", app_state.external_base, name);
+    let (name, _) = create_local_actor(&app_state).await?;
+
+    Ok(HttpResponse::Ok()
+        .append_header(header::ContentType(mime::TEXT_PLAIN))
+        .body(name))
+}
+
+#[derive(Deserialize)]
+pub struct FollowRequest {
+    follower: String,
+    followee: String,
+}
+
+pub async fn handle_internal_follow_remote(
+    app_state: web::Data<MyStateHandle>,
+    follow_request: web::Json<FollowRequest>,
+) -> Result<HttpResponse, ApEventsError> {
+    let found_actor: EventActor = sqlx::query_as("SELECT * FROM actors WHERE ap_id = $1")
+        .bind(follow_request.follower.clone())
+        .fetch_one(&app_state.pool)
+        .await?;
+
+    found_actor
+        .follow(follow_request.followee.clone(), &app_state)
+        .await?;
+
+    Ok(HttpResponse::Ok()
+        .content_type(APUB_JSON_CONTENT_TYPE)
+        .json(WithContext::new_default(
+            found_actor.into_apub(&app_state).await?,
+        )))
+}
+
+#[derive(Deserialize)]
+pub struct DeleteObjectRequest {
+    ap_id: String,
+}
+
+pub async fn handle_internal_delete_object(
+    app_state: web::Data<MyStateHandle>,
+    delete_request: web::Json<DeleteObjectRequest>,
+) -> Result<HttpResponse, ApEventsError> {
+    if !delete_object(&app_state, &delete_request.ap_id).await? {
+        return Err(ApEventsError::ObjectNotFound(delete_request.ap_id.clone()));
+    }
+
+    Ok(HttpResponse::NoContent().finish())
+}
+
+fn default_timezone() -> String {
+    "UTC".to_string()
+}
+
+fn default_join_mode() -> String {
+    "free".to_string()
+}
+
+fn default_status() -> String {
+    "published".to_string()
+}
+
+#[derive(Deserialize)]
+pub struct EventRequest {
+    actor: String,
+    title: String,
+    #[serde(default)]
+    summary: String,
+    starts_at: DateTime<Utc>,
+    ends_at: DateTime<Utc>,
+    #[serde(default = "default_timezone")]
+    timezone: String,
+    location: Option<String>,
+    /// The calendar actor the event belongs to. Events stay in their calendar when it is left
+    /// out.
+    calendar: Option<String>,
+    /// The place actor the event is held at. The place's name and address replace `location`.
+    place: Option<String>,
+    /// A link to join the event online, such as a video call.
+    online_url: Option<String>,
+    /// Whether the online link is only sent to attendees that are going.
+    #[serde(default)]
+    online_url_private: bool,
+    #[serde(default = "default_join_mode")]
+    join_mode: String,
+    capacity: Option<i32>,
+    rrule: Option<String>,
+    #[serde(default)]
+    exdates: Vec<DateTime<Utc>>,
+    /// The organizer, who can manage the event by direct message.
+    owner: Option<String>,
+    /// Either published or draft. Only used when the event is created.
+    #[serde(default = "default_status")]
+    status: String,
+    /// When a draft is published.
+    publish_at: Option<DateTime<Utc>>,
+    /// Hashtags to find the event by, with or without the `#`.
+    #[serde(default)]
+    tags: Vec<String>,
+}
+
+pub async fn handle_internal_set_event(
+    app_state: web::Data<MyStateHandle>,
+    event_request: web::Json<EventRequest>,
+) -> Result<HttpResponse, ApEventsError> {
+    let event_request = event_request.into_inner();
+    let found_actor: EventActor = sqlx::query_as("SELECT * FROM actors WHERE ap_id = $1")
+        .bind(&event_request.actor)
+        .fetch_one(&app_state.pool)
+        .await?;
 
-    let keypair = generate_actor_keypair().map_err(|_| ApEventsError::Unknown)?;
+    let status: EventStatus = event_request.status.parse()?;
+    if !matches!(status, EventStatus::Draft | EventStatus::Published) {
+        return Err(ApEventsError::InvalidEvent(
+            "new events are either draft or published".to_string(),
+        ));
+    }
 
-    create_actor(
+    let location = match &event_request.place {
+        Some(place_ap_id) => Some(
+            get_place(&app_state, place_ap_id)
+                .await?
+                .ok_or_else(|| {
+                    ApEventsError::InvalidEvent(format!("{place_ap_id} is not a place"))
+                })?
+                .location(),
+        ),
+        None => event_request.location,
+    };
+
+    if let Some(calendar_ap_id) = &event_request.calendar {
+        if get_calendar(&app_state, calendar_ap_id).await?.is_none() {
+            return Err(ApEventsError::InvalidEvent(format!(
+                "{calendar_ap_id} is not a calendar"
+            )));
+        }
+    }
+
+    let event = Event {
+        actor_ap_id: event_request.actor,
+        title: event_request.title,
+        summary: event_request.summary,
+        starts_at: event_request.starts_at,
+        ends_at: event_request.ends_at,
+        timezone: event_request.timezone,
+        location,
+        online_url: event_request.online_url,
+        online_url_private: event_request.online_url_private,
+        join_mode: event_request.join_mode,
+        capacity: event_request.capacity,
+        rrule: event_request.rrule,
+        exdates: event_request.exdates,
+        status,
+        publish_at: event_request.publish_at,
+        owner_ap_id: event_request.owner,
+        tags: event_request
+            .tags
+            .iter()
+            .map(|tag| normalize_tag(tag))
+            .collect(),
+        place_ap_id: event_request.place,
+        calendar_ap_id: event_request.calendar,
+        sequence: 0,
+        updated_at: Utc::now().naive_utc(),
+    };
+    let previous = get_event(&app_state, &event.actor_ap_id).await?;
+    let event = upsert_event(&app_state, &event).await?;
+    if let Some(previous) = &previous {
+        event_edited(&app_state, previous, &event).await?;
+        if event.capacity != previous.capacity {
+            fill_waitlists(&app_state, &found_actor, &event).await?;
+        }
+    }
+    // Followers of a place hear about a published event once it is held there.
+    if !event.is_draft()
+        && previous
+            .as_ref()
+            .is_none_or(|previous| previous.is_draft() || previous.place_ap_id != event.place_ap_id)
+    {
+        announce_at_place(&app_state, &event).await?;
+    }
+    // Calendars announce the published events added to them.
+    if !event.is_draft()
+        && event.calendar_ap_id.is_some()
+        && previous
+            .as_ref()
+            .is_none_or(|previous| previous.calendar_ap_id != event.calendar_ap_id)
+    {
+        send_event_object(&app_state, &found_actor, &event, previous.is_none()).await?;
+    }
+    let participant_count = count_rsvps(
         &app_state,
-        Actor {
-            ap_id: object_id.clone(),
-            kind: "Person".to_string(),
+        &event.actor_ap_id,
+        ALL_OCCURRENCES,
+        RsvpStatus::Going,
+    )
+    .await?;
 
-            following: Some(format!("{}/actor/{}/inbox", app_state.external_base, name)),
-            followers: Some(format!("{}/actor/{}/inbox", app_state.external_base, name)),
-            inbox: Some(format!("{}/actor/{}/inbox", app_state.external_base, name)),
-            outbox: None,
-            featured: None,
+    Ok(HttpResponse::Ok()
+        .content_type(APUB_JSON_CONTENT_TYPE)
+        .json(WithContext::new(
+            event_into_apub(&event, &found_actor, None, participant_count)?,
+            event_context(),
+        )))
+}
+
+#[derive(Deserialize)]
+pub struct CalendarRequest {
+    /// The calendar to change. A new actor is created for the calendar when it is left out.
+    actor: Option<String>,
+    name: String,
+    #[serde(default)]
+    summary: String,
+    #[serde(default = "default_timezone")]
+    timezone: String,
+    /// The organizer, who can add events to the calendar by direct message.
+    owner: Option<String>,
+}
+
+/// Creates or updates a calendar that groups the events of an organizer. The calendar's actor
+/// is returned.
+pub async fn handle_internal_set_calendar(
+    app_state: web::Data<MyStateHandle>,
+    calendar_request: web::Json<CalendarRequest>,
+) -> Result<HttpResponse, ApEventsError> {
+    let calendar_request = calendar_request.into_inner();
+    let calendar = Calendar {
+        actor_ap_id: calendar_request.actor.clone().unwrap_or_default(),
+        name: calendar_request.name,
+        summary: calendar_request.summary,
+        timezone: calendar_request.timezone,
+        owner_ap_id: calendar_request.owner,
+    };
+    calendar.validate()?;
 
-            featured_tags: None,
+    let actor_ap_id = match calendar_request.actor {
+        Some(actor_ap_id) => {
+            if get_event(&app_state, &actor_ap_id).await?.is_some()
+                || get_place(&app_state, &actor_ap_id).await?.is_some()
+            {
+                return Err(ApEventsError::InvalidCalendar(format!(
+                    "{actor_ap_id} is an event or a place"
+                )));
+            }
+            actor_ap_id
+        }
+        None => {
+            let (name, _) = create_local_actor(&app_state).await?;
+            format!("{}/actor/{}", app_state.external_base, name)
+        }
+    };
+    let found_actor: EventActor = sqlx::query_as("SELECT * FROM actors WHERE ap_id = $1")
+        .bind(&actor_ap_id)
+        .fetch_one(&app_state.pool)
+        .await?;
+    upsert_calendar(
+        &app_state,
+        &Calendar {
+            actor_ap_id,
+            ..calendar
+        },
+    )
+    .await?;
+
+    Ok(HttpResponse::Ok()
+        .content_type(APUB_JSON_CONTENT_TYPE)
+        .json(WithContext::new_default(
+            found_actor.into_apub(&app_state).await?,
+        )))
+}
+
+#[derive(Deserialize)]
+pub struct PlaceRequest {
+    /// The place to change. A new actor is created for the place when it is left out.
+    actor: Option<String>,
+    name: String,
+    address: Option<String>,
+    latitude: Option<f64>,
+    longitude: Option<f64>,
+    accessibility: Option<String>,
+}
+
+/// Creates or updates a place that events can be held at and people can follow.
+pub async fn handle_internal_set_place(
+    app_state: web::Data<MyStateHandle>,
+    place_request: web::Json<PlaceRequest>,
+) -> Result<HttpResponse, ApEventsError> {
+    let place_request = place_request.into_inner();
+    let actor_ap_id = match place_request.actor {
+        Some(actor_ap_id) => {
+            if get_event(&app_state, &actor_ap_id).await?.is_some() {
+                return Err(ApEventsError::InvalidPlace(format!(
+                    "{actor_ap_id} is an event"
+                )));
+            }
+            actor_ap_id
+        }
+        None => {
+            let (name, _) = create_local_actor(&app_state).await?;
+            format!("{}/actor/{}", app_state.external_base, name)
+        }
+    };
+    let found_actor: EventActor = sqlx::query_as("SELECT * FROM actors WHERE ap_id = $1")
+        .bind(&actor_ap_id)
+        .fetch_one(&app_state.pool)
+        .await?;
+
+    let place = Place {
+        actor_ap_id,
+        name: place_request.name,
+        address: place_request.address,
+        latitude: place_request.latitude,
+        longitude: place_request.longitude,
+        accessibility: place_request.accessibility,
+    };
+    upsert_place(&app_state, &place).await?;
+
+    Ok(HttpResponse::Ok()
+        .content_type(APUB_JSON_CONTENT_TYPE)
+        .json(WithContext::new(
+            place_into_apub(&place, &found_actor)?,
+            event_context(),
+        )))
+}
 
-            name: name.clone(),
-            summary: None,
-            preferred_username: Some(name.clone()),
+/// Lists groups of places that are likely the same venue.
+pub async fn handle_internal_place_duplicates(
+    app_state: web::Data<MyStateHandle>,
+) -> Result<HttpResponse, ApEventsError> {
+    Ok(HttpResponse::Ok().json(find_duplicates(&all_places(&app_state).await?)))
+}
 
-            url: Some(format!("{}/@{}", app_state.external_base, name.clone())),
+#[derive(Deserialize)]
+pub struct MergePlacesRequest {
+    into: String,
+    duplicates: Vec<String>,
+}
 
-            discoverable: None,
-            published: None,
+/// Merges duplicate places into one, moving their events and followers to it.
+pub async fn handle_internal_merge_places(
+    app_state: web::Data<MyStateHandle>,
+    merge_request: web::Json<MergePlacesRequest>,
+) -> Result<HttpResponse, ApEventsError> {
+    let into = get_place(&app_state, &merge_request.into)
+        .await?
+        .ok_or_else(|| ApEventsError::ObjectNotFound(merge_request.into.clone()))?;
+    if merge_request.duplicates.contains(&into.actor_ap_id) {
+        return Err(ApEventsError::InvalidPlace(
+            "a place cannot be merged into itself".to_string(),
+        ));
+    }
+    for duplicate in &merge_request.duplicates {
+        if get_place(&app_state, duplicate).await?.is_none() {
+            return Err(ApEventsError::ObjectNotFound(duplicate.clone()));
+        }
+    }
+    merge_places(&app_state, &into, &merge_request.duplicates).await?;
 
-            public_key: Some(PublicKey {
-                ap_id: format!("{}/actor/{}#main-key", app_state.external_base, name),
-                owner: object_id,
-                public_key_pem: keypair.public_key,
-            }),
+    Ok(HttpResponse::NoContent().finish())
+}
 
-            attachments: vec![],
+#[derive(Deserialize)]
+pub struct CancelEventRequest {
+    actor: String,
+    occurrence: Option<String>,
+}
 
-            endpoints: HashMap::from([(
-                "sharedInbox".to_string(),
-                format!("{}/inbox", app_state.external_base),
-            )]),
+/// Cancels an event, or removes a single occurrence from a recurring event.
+pub async fn handle_internal_cancel_event(
+    app_state: web::Data<MyStateHandle>,
+    cancel_request: web::Json<CancelEventRequest>,
+) -> Result<HttpResponse, ApEventsError> {
+    let found_actor: EventActor = sqlx::query_as("SELECT * FROM actors WHERE ap_id = $1")
+        .bind(&cancel_request.actor)
+        .fetch_one(&app_state.pool)
+        .await?;
+    let event = get_event(&app_state, &cancel_request.actor)
+        .await?
+        .ok_or_else(|| ApEventsError::ObjectNotFound(cancel_request.actor.clone()))?;
 
-            icon: None,
-            image: None,
+    let cancelled = match &cancel_request.occurrence {
+        None => {
+            transition_event(&app_state, &found_actor, &event, EventStatus::Cancelled).await?;
+            true
+        }
+        Some(occurrence_id) => match event.occurrence(occurrence_id) {
+            Some(occurrence) => exclude_occurrence(&app_state, &event, &occurrence).await?,
+            None => false,
         },
-        Some(keypair.private_key),
+    };
+    if !cancelled {
+        return Err(ApEventsError::ObjectNotFound(cancel_request.actor.clone()));
+    }
+
+    Ok(HttpResponse::NoContent().finish())
+}
+
+#[derive(Deserialize)]
+pub struct EventStatusRequest {
+    actor: String,
+    status: String,
+}
+
+/// Moves an event to another state, such as publishing a draft or postponing an event.
+pub async fn handle_internal_event_status(
+    app_state: web::Data<MyStateHandle>,
+    status_request: web::Json<EventStatusRequest>,
+) -> Result<HttpResponse, ApEventsError> {
+    let to: EventStatus = status_request.status.parse()?;
+    let found_actor: EventActor = sqlx::query_as("SELECT * FROM actors WHERE ap_id = $1")
+        .bind(&status_request.actor)
+        .fetch_one(&app_state.pool)
+        .await?;
+    let event = get_event(&app_state, &status_request.actor)
+        .await?
+        .ok_or_else(|| ApEventsError::ObjectNotFound(status_request.actor.clone()))?;
+    let event = transition_event(&app_state, &found_actor, &event, to).await?;
+    let participant_count = count_rsvps(
+        &app_state,
+        &event.actor_ap_id,
+        ALL_OCCURRENCES,
+        RsvpStatus::Going,
     )
     .await?;
 
     Ok(HttpResponse::Ok()
-        .append_header(header::ContentType(mime::TEXT_PLAIN))
-        .body(name))
+        .content_type(APUB_JSON_CONTENT_TYPE)
+        .json(WithContext::new(
+            event_into_apub(&event, &found_actor, None, participant_count)?,
+            event_context(),
+        )))
 }
 
 #[derive(Deserialize)]
-pub struct FollowRequest {
-    follower: String,
-    followee: String,
+pub struct AdminLinkRequest {
+    actor: String,
 }
 
-pub async fn handle_internal_follow_remote(
+/// Issues a link to the admin page of an event that works for 24 hours.
+pub async fn handle_internal_admin_link(
     app_state: web::Data<MyStateHandle>,
-    follow_request: web::Json<FollowRequest>,
+    link_request: web::Json<AdminLinkRequest>,
 ) -> Result<HttpResponse, ApEventsError> {
     let found_actor: EventActor = sqlx::query_as("SELECT * FROM actors WHERE ap_id = $1")
-        .bind(follow_request.follower.clone())
+        .bind(&link_request.actor)
         .fetch_one(&app_state.pool)
         .await?;
 
-    found_actor
-        .follow(follow_request.followee.clone(), &app_state)
+    Ok(HttpResponse::Ok()
+        .append_header(header::ContentType(mime::TEXT_PLAIN))
+        .body(admin_link(&app_state, &found_actor, Role::Owner).await?))
+}
+
+#[derive(Deserialize)]
+pub struct ExportEventRequest {
+    actor: String,
+}
+
+/// Exports an event as an archive signed by the event actor, for another instance to import.
+pub async fn handle_internal_export_event(
+    app_state: web::Data<MyStateHandle>,
+    export_request: web::Json<ExportEventRequest>,
+) -> Result<HttpResponse, ApEventsError> {
+    let found_actor: EventActor = sqlx::query_as("SELECT * FROM actors WHERE ap_id = $1")
+        .bind(&export_request.actor)
+        .fetch_one(&app_state.pool)
         .await?;
 
+    Ok(HttpResponse::Ok().json(export_event(&app_state, &found_actor).await?))
+}
+
+/// Imports an event exported by another instance and responds with the new event actor.
+pub async fn handle_internal_import_event(
+    app_state: web::Data<MyStateHandle>,
+    archive: web::Json<serde_json::Value>,
+) -> Result<HttpResponse, ApEventsError> {
+    let mut request_counter = 0;
+    let (found_actor, _) =
+        import_event(&app_state, archive.into_inner(), &mut request_counter).await?;
+
     Ok(HttpResponse::Ok()
         .content_type(APUB_JSON_CONTENT_TYPE)
         .json(WithContext::new_default(
             found_actor.into_apub(&app_state).await?,
         )))
 }
+
+#[derive(Deserialize)]
+pub struct MoveEventRequest {
+    actor: String,
+    /// The actor the event was imported as on another instance.
+    target: Url,
+}
+
+/// Moves an event to the actor it was imported as, and tells its followers to follow that actor.
+pub async fn handle_internal_move_event(
+    app_state: web::Data<MyStateHandle>,
+    move_request: web::Json<MoveEventRequest>,
+) -> Result<HttpResponse, ApEventsError> {
+    let found_actor: EventActor = sqlx::query_as("SELECT * FROM actors WHERE ap_id = $1")
+        .bind(&move_request.actor)
+        .fetch_one(&app_state.pool)
+        .await?;
+    move_event(&app_state, &found_actor, &move_request.target).await?;
+
+    Ok(HttpResponse::NoContent().finish())
+}
+
+#[derive(Deserialize)]
+pub struct CalendarTokenRequest {
+    attendee: String,
+}
+
+/// Issues a new private calendar feed for an attendee and responds with its url. Any previous
+/// feed url for the attendee stops working.
+pub async fn handle_internal_calendar_token(
+    app_state: web::Data<MyStateHandle>,
+    token_request: web::Json<CalendarTokenRequest>,
+) -> Result<HttpResponse, ApEventsError> {
+    let token = issue_calendar_token(&app_state, &token_request.attendee).await?;
+
+    Ok(HttpResponse::Ok()
+        .append_header(header::ContentType(mime::TEXT_PLAIN))
+        .body(format!(
+            "{}/calendars/{}.ics",
+            app_state.external_base, token
+        )))
+}
+
+/// Imports the events of an iCalendar file sent as the request body.
+pub async fn handle_internal_import(
+    app_state: web::Data<MyStateHandle>,
+    body: String,
+) -> Result<HttpResponse, ApEventsError> {
+    Ok(HttpResponse::Ok().json(import_calendar(&app_state, &body).await))
+}
+
+#[derive(Deserialize)]
+pub struct ImportSourceRequest {
+    path: Option<String>,
+    url: Option<String>,
+    #[serde(default)]
+    sync: bool,
+}
+
+/// Imports the events of an iCalendar file on the server or at a url. Urls can also be registered
+/// to be imported again periodically.
+pub async fn handle_internal_import_source(
+    app_state: web::Data<MyStateHandle>,
+    source: web::Json<ImportSourceRequest>,
+) -> Result<HttpResponse, ApEventsError> {
+    let input = match (&source.path, &source.url) {
+        (Some(path), None) => std::fs::read_to_string(path)
+            .map_err(|err| ApEventsError::InvalidQuery(format!("cannot read {path}: {err}")))?,
+        (None, Some(url)) => fetch_calendar(url).await?,
+        _ => {
+            return Err(ApEventsError::InvalidQuery(
+                "exactly one of path or url is required".to_string(),
+            ))
+        }
+    };
+
+    if let (Some(url), true) = (&source.url, source.sync) {
+        add_import_feed(&app_state, url).await?;
+    }
+
+    Ok(HttpResponse::Ok().json(import_calendar(&app_state, &input).await))
+}
+
+#[derive(Deserialize)]
+pub struct SweepRequest {
+    #[serde(default)]
+    dry_run: bool,
+}
+
+/// Deletes the events that have expired, or only reports them when `dry_run` is set.
+pub async fn handle_internal_sweep(
+    app_state: web::Data<MyStateHandle>,
+    sweep_request: web::Json<SweepRequest>,
+) -> Result<HttpResponse, ApEventsError> {
+    Ok(HttpResponse::Ok().json(sweep_events(&app_state, Utc::now(), sweep_request.dry_run).await?))
+}
@@ -0,0 +1,106 @@
+use activitypub_federation::traits::Actor;
+use chrono::{NaiveDateTime, Utc};
+use url::Url;
+
+use crate::{
+    activities::announce::Announce,
+    ap::{
+        event::event_context,
+        ids::{generate_object_id, KindType},
+    },
+    error::ApEventsError,
+    lifecycle::send_event_object,
+    objects::actor::EventActor,
+    recurrence::resolve_local,
+    state::MyStateHandle,
+    storage_actor::{create_local_actor, get_actors},
+    storage_calendars::{get_calendar, Calendar},
+    storage_events::{upsert_event, Event, EventStatus},
+    storage_follows::followers,
+};
+
+/// The details of an event created inside a calendar. Times are local to the calendar's
+/// timezone.
+#[derive(Debug, PartialEq, Eq)]
+pub struct NewEvent {
+    pub title: String,
+    pub summary: String,
+    pub starts_at: NaiveDateTime,
+    pub ends_at: NaiveDateTime,
+    pub location: Option<String>,
+}
+
+/// Creates and publishes an event in a calendar. The event is organized by the calendar's owner,
+/// and the calendar announces it to its followers.
+pub async fn create_calendar_event(
+    app_state: &MyStateHandle,
+    calendar: &Calendar,
+    new_event: NewEvent,
+) -> Result<(EventActor, Event), ApEventsError> {
+    let tz = calendar.tz();
+    let mut event = Event {
+        actor_ap_id: String::new(),
+        title: new_event.title,
+        summary: new_event.summary,
+        starts_at: resolve_local(&tz, &new_event.starts_at),
+        ends_at: resolve_local(&tz, &new_event.ends_at),
+        timezone: calendar.timezone.clone(),
+        location: new_event.location,
+        online_url: None,
+        online_url_private: false,
+        place_ap_id: None,
+        calendar_ap_id: Some(calendar.actor_ap_id.clone()),
+        join_mode: "free".to_string(),
+        capacity: None,
+        rrule: None,
+        exdates: vec![],
+        status: EventStatus::Published,
+        publish_at: None,
+        owner_ap_id: calendar.owner_ap_id.clone(),
+        tags: vec![],
+        sequence: 0,
+        updated_at: Utc::now().naive_utc(),
+    };
+    // Checked before the actor is created, so that invalid events leave nothing behind.
+    event.validate(Utc::now())?;
+
+    let (_, actor) = create_local_actor(app_state).await?;
+    event.actor_ap_id = actor.ap_id.to_string();
+    let event = upsert_event(app_state, &event).await?;
+    send_event_object(app_state, &actor, &event, true).await?;
+    Ok((actor, event))
+}
+
+/// Shares an activity about an event with the followers of the calendar it belongs to, as an
+/// `Announce` sent by the calendar.
+pub async fn announce_in_calendar(
+    app_state: &MyStateHandle,
+    event: &Event,
+    activity: Url,
+) -> Result<(), ApEventsError> {
+    let calendar_ap_id = match &event.calendar_ap_id {
+        Some(calendar_ap_id) => calendar_ap_id,
+        None => return Ok(()),
+    };
+    if get_calendar(app_state, calendar_ap_id).await?.is_none() {
+        return Ok(());
+    }
+    let calendar_actor: EventActor = sqlx::query_as("SELECT * FROM actors WHERE ap_id = $1")
+        .bind(calendar_ap_id)
+        .fetch_one(&app_state.pool)
+        .await?;
+
+    let inboxes = get_actors(app_state, &followers(app_state, calendar_ap_id).await?)
+        .await?
+        .iter()
+        .map(|follower| follower.shared_inbox_or_inbox())
+        .collect();
+    let announce = Announce::new(
+        &calendar_actor,
+        activity,
+        generate_object_id(&app_state.external_base, KindType::Announce)?,
+    )?;
+    calendar_actor
+        .send_with_context(announce, Some(event_context()), inboxes, app_state)
+        .await
+}
@@ -0,0 +1,483 @@
+use std::str::FromStr;
+
+use activitypub_federation::{deser::context::WithContext, APUB_JSON_CONTENT_TYPE};
+use actix_web::HttpResponse;
+use base64::{alphabet::URL_SAFE, engine::fast_portable};
+use chrono::{DateTime, NaiveDateTime};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use sqlx::{Postgres, QueryBuilder};
+use url::Url;
+
+use crate::{error::ApEventsError, state::MyStateHandle, storage_objects::OUTBOX_KINDS};
+
+const PAGE_SIZE: i64 = 100;
+
+const CURSOR_ENGINE: fast_portable::FastPortable =
+    fast_portable::FastPortable::from(&URL_SAFE, fast_portable::NO_PAD);
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct OrderedCollection {
+    #[serde(rename = "id")]
+    pub ap_id: String,
+    #[serde(rename = "type")]
+    pub kind: String,
+    pub total_items: u32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub first: Option<String>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct OrderedCollectionPage {
+    #[serde(rename = "id")]
+    pub ap_id: String,
+    #[serde(rename = "type")]
+    pub kind: String,
+    pub total_items: u32,
+    pub part_of: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub prev: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub next: Option<String>,
+    pub ordered_items: Vec<Value>,
+}
+
+#[derive(Deserialize)]
+pub struct CollectionQuery {
+    /// Any value asks for a page, since servers send both `page=true` and `page=1`.
+    pub page: Option<String>,
+    pub after: Option<String>,
+    pub before: Option<String>,
+}
+
+impl CollectionQuery {
+    /// Whether a page of the collection is asked for rather than the collection itself.
+    pub fn wants_page(&self) -> bool {
+        self.page
+            .as_deref()
+            .is_some_and(|page| !matches!(page, "false" | "0"))
+    }
+}
+
+/// A position in a collection, encoded into page urls so that clients treat it as opaque.
+#[derive(Debug, PartialEq, Eq)]
+pub struct Cursor {
+    pub created_at: NaiveDateTime,
+    pub key: String,
+}
+
+impl Cursor {
+    pub fn encode(&self) -> String {
+        base64::encode_engine(
+            format!(
+                "{}|{}",
+                self.created_at.and_utc().timestamp_micros(),
+                self.key
+            ),
+            &CURSOR_ENGINE,
+        )
+    }
+
+    pub fn decode(value: &str) -> Result<Cursor, ApEventsError> {
+        let invalid = || ApEventsError::InvalidQuery("invalid cursor".to_string());
+
+        let decoded = base64::decode_engine(value, &CURSOR_ENGINE).map_err(|_| invalid())?;
+        let decoded = String::from_utf8(decoded).map_err(|_| invalid())?;
+        let (micros, key) = decoded.split_once('|').ok_or_else(invalid)?;
+        let micros: i64 = micros.parse().map_err(|_| invalid())?;
+        let created_at = DateTime::from_timestamp_micros(micros)
+            .ok_or_else(invalid)?
+            .naive_utc();
+
+        Ok(Cursor {
+            created_at,
+            key: key.to_string(),
+        })
+    }
+}
+
+/// Describes where the items of an ordered collection come from. Every source is paged over
+/// `(created_at, key)`, and `item` is a SQL expression that produces each item as json.
+pub struct CollectionSource {
+    table: &'static str,
+    owner_column: &'static str,
+    key_column: &'static str,
+    item: &'static str,
+    filter: fn(&mut QueryBuilder<'static, Postgres>),
+    /// Narrows the items that are listed without changing how many are counted.
+    listed: fn(&mut QueryBuilder<'static, Postgres>),
+    newest_first: bool,
+}
+
+impl CollectionSource {
+    pub fn followers() -> CollectionSource {
+        CollectionSource {
+            table: "follow_activities",
+            owner_column: "followee_ap_id",
+            key_column: "follower_ap_id",
+            item: "to_jsonb(follower_ap_id)",
+            filter: |query| {
+                query.push(" AND accepted_at IS NOT NULL");
+            },
+            listed: |_| {},
+            newest_first: false,
+        }
+    }
+
+    pub fn following() -> CollectionSource {
+        CollectionSource {
+            table: "follow_activities",
+            owner_column: "follower_ap_id",
+            key_column: "followee_ap_id",
+            item: "to_jsonb(followee_ap_id)",
+            filter: |query| {
+                query.push(" AND accepted_at IS NOT NULL");
+            },
+            listed: |_| {},
+            newest_first: false,
+        }
+    }
+
+    /// Attendees that are going to an event, as opposed to a single occurrence of it. Hidden
+    /// attendees are left out, and so is everyone when the organizer hid the attendee list.
+    pub fn participants() -> CollectionSource {
+        CollectionSource {
+            table: "rsvps",
+            owner_column: "actor_ap_id",
+            key_column: "attendee_ap_id",
+            item: "to_jsonb(attendee_ap_id)",
+            filter: |query| {
+                query.push(" AND occurrence = '' AND status = 'going'");
+            },
+            // Hidden attendees count toward the total, but are not listed.
+            listed: |query| {
+                query.push(" AND NOT hidden AND NOT EXISTS (SELECT 1 FROM events WHERE events.actor_ap_id = rsvps.actor_ap_id AND events.hide_attendees)");
+            },
+            newest_first: false,
+        }
+    }
+
+    /// Public activities, newest first. Items are embedded, so their context is dropped.
+    pub fn outbox() -> CollectionSource {
+        CollectionSource {
+            table: "objects",
+            owner_column: "actor_ap_id",
+            key_column: "ap_id",
+            item: "payload - '@context'",
+            filter: |query| {
+                query
+                    .push(" AND is_public AND deleted_at IS NULL AND kind = ANY(")
+                    .push_bind(&OUTBOX_KINDS[..])
+                    .push(")");
+            },
+            listed: |_| {},
+            newest_first: true,
+        }
+    }
+
+    fn push_where(&self, query: &mut QueryBuilder<'static, Postgres>, owner: &str) {
+        query
+            .push(" FROM ")
+            .push(self.table)
+            .push(" WHERE ")
+            .push(self.owner_column)
+            .push(" = ")
+            .push_bind(owner.to_string());
+        (self.filter)(query);
+    }
+
+    fn count_query(&self, owner: &str) -> QueryBuilder<'static, Postgres> {
+        let mut query = QueryBuilder::new("SELECT COUNT(*)");
+        self.push_where(&mut query, owner);
+        query
+    }
+
+    /// The `totalItems` of the collection.
+    pub async fn count(
+        &self,
+        app_state: &MyStateHandle,
+        owner: &str,
+    ) -> Result<u32, ApEventsError> {
+        let (total,): (i64,) = self
+            .count_query(owner)
+            .build_query_as()
+            .fetch_one(&app_state.pool)
+            .await?;
+        Ok(total as u32)
+    }
+
+    /// Builds the query for one page. `forward` pages move along the collection order, backward
+    /// pages move against it and have to be reversed by the caller.
+    fn page_query(
+        &self,
+        owner: &str,
+        cursor: Option<&Cursor>,
+        forward: bool,
+    ) -> QueryBuilder<'static, Postgres> {
+        let mut query = QueryBuilder::new("SELECT created_at, ");
+        query
+            .push(self.key_column)
+            .push(" AS collection_key, ")
+            .push(self.item)
+            .push(" AS collection_item");
+        self.push_where(&mut query, owner);
+        (self.listed)(&mut query);
+
+        let descending = self.newest_first == forward;
+        if let Some(cursor) = cursor {
+            query
+                .push(" AND (created_at, ")
+                .push(self.key_column)
+                .push(if descending { ") < (" } else { ") > (" })
+                .push_bind(cursor.created_at)
+                .push(", ")
+                .push_bind(cursor.key.clone())
+                .push(")");
+        }
+
+        let direction = if descending { " DESC" } else { " ASC" };
+        query
+            .push(" ORDER BY created_at")
+            .push(direction)
+            .push(", ")
+            .push(self.key_column)
+            .push(direction)
+            .push(" LIMIT ")
+            .push_bind(PAGE_SIZE + 1);
+        query
+    }
+}
+
+/// Answers a request for a collection, or for one of its pages when `page` is set.
+pub async fn collection_response(
+    app_state: &MyStateHandle,
+    collection_id: Url,
+    source: &CollectionSource,
+    owner: &str,
+    query: &CollectionQuery,
+) -> Result<HttpResponse, ApEventsError> {
+    let ap_id = collection_id.to_string();
+
+    let total_items = source.count(app_state, owner).await?;
+
+    let context = vec![Value::from_str(
+        "\"https://www.w3.org/ns/activitystreams\"",
+    )?];
+
+    if !query.wants_page() {
+        return Ok(HttpResponse::Ok()
+            .content_type(APUB_JSON_CONTENT_TYPE)
+            .json(WithContext::new(
+                OrderedCollection {
+                    first: (total_items > 0).then(|| page_url(&collection_id, None)),
+                    ap_id,
+                    kind: "OrderedCollection".to_string(),
+                    total_items,
+                },
+                context,
+            )));
+    }
+
+    let (cursor, forward) = match (&query.after, &query.before) {
+        (Some(after), None) => (Some(Cursor::decode(after)?), true),
+        (None, Some(before)) => (Some(Cursor::decode(before)?), false),
+        (None, None) => (None, true),
+        _ => {
+            return Err(ApEventsError::InvalidQuery(
+                "only one of after and before may be given".to_string(),
+            ))
+        }
+    };
+
+    let mut rows: Vec<(NaiveDateTime, String, Value)> = source
+        .page_query(owner, cursor.as_ref(), forward)
+        .build_query_as()
+        .fetch_all(&app_state.pool)
+        .await?;
+
+    let has_more = rows.len() as i64 > PAGE_SIZE;
+    rows.truncate(PAGE_SIZE as usize);
+    if !forward {
+        rows.reverse();
+    }
+
+    let first_cursor = rows.first().map(|(created_at, key, _)| Cursor {
+        created_at: *created_at,
+        key: key.clone(),
+    });
+    let last_cursor = rows.last().map(|(created_at, key, _)| Cursor {
+        created_at: *created_at,
+        key: key.clone(),
+    });
+
+    let (has_prev, has_next) = match forward {
+        true => (cursor.is_some(), has_more),
+        false => (has_more, true),
+    };
+
+    Ok(HttpResponse::Ok()
+        .content_type(APUB_JSON_CONTENT_TYPE)
+        .json(WithContext::new(
+            OrderedCollectionPage {
+                ap_id: match (&query.after, &query.before) {
+                    (Some(after), _) => format!("{}?page=true&after={}", ap_id, after),
+                    (_, Some(before)) => format!("{}?page=true&before={}", ap_id, before),
+                    _ => page_url(&collection_id, None),
+                },
+                kind: "OrderedCollectionPage".to_string(),
+                total_items,
+                part_of: ap_id.clone(),
+                prev: first_cursor
+                    .filter(|_| has_prev)
+                    .map(|c| format!("{}?page=true&before={}", ap_id, c.encode())),
+                next: last_cursor
+                    .filter(|_| has_next)
+                    .map(|c| page_url(&collection_id, Some(&c))),
+                ordered_items: rows.into_iter().map(|(_, _, item)| item).collect(),
+            },
+            context,
+        )))
+}
+
+fn page_url(collection_id: &Url, after: Option<&Cursor>) -> String {
+    match after {
+        Some(cursor) => format!("{}?page=true&after={}", collection_id, cursor.encode()),
+        None => format!("{}?page=true", collection_id),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use chrono::NaiveDate;
+
+    #[test]
+    fn page_parameter() {
+        let wants_page = |query: &str| {
+            actix_web::web::Query::<CollectionQuery>::from_query(query)
+                .expect("query parses")
+                .wants_page()
+        };
+        assert!(wants_page("page=true"));
+        assert!(wants_page("page=1"));
+        assert!(wants_page("page=true&after=abc"));
+        assert!(!wants_page(""));
+        assert!(!wants_page("page=false"));
+        assert!(!wants_page("page=0"));
+    }
+
+    #[test]
+    fn cursor_round_trip() {
+        let cursor = Cursor {
+            created_at: NaiveDate::from_ymd_opt(2022, 11, 1)
+                .and_then(|d| d.and_hms_micro_opt(19, 0, 0, 123_456))
+                .expect("valid date"),
+            key: "https://thegem.city/users/nick".to_string(),
+        };
+        let encoded = cursor.encode();
+
+        assert!(!encoded.contains(['+', '/', '=', '|']));
+        assert_eq!(Cursor::decode(&encoded).expect("cursor decodes"), cursor);
+    }
+
+    #[test]
+    fn cursor_rejects_garbage() {
+        assert!(Cursor::decode("").is_err());
+        assert!(Cursor::decode("not a cursor").is_err());
+        assert!(Cursor::decode(&base64::encode_engine("123", &CURSOR_ENGINE)).is_err());
+        assert!(Cursor::decode(&base64::encode_engine("abc|key", &CURSOR_ENGINE)).is_err());
+    }
+
+    #[test]
+    fn page_queries() {
+        let cursor = Cursor {
+            created_at: NaiveDate::from_ymd_opt(2022, 11, 1)
+                .and_then(|d| d.and_hms_opt(19, 0, 0))
+                .expect("valid date"),
+            key: "https://thegem.city/users/nick".to_string(),
+        };
+
+        assert_eq!(
+            CollectionSource::following()
+                .page_query("https://events.thegem.city/actor/a", Some(&cursor), true)
+                .sql(),
+            "SELECT created_at, followee_ap_id AS collection_key, to_jsonb(followee_ap_id) AS collection_item FROM follow_activities WHERE follower_ap_id = $1 AND accepted_at IS NOT NULL AND (created_at, followee_ap_id) > ($2, $3) ORDER BY created_at ASC, followee_ap_id ASC LIMIT $4"
+        );
+        assert_eq!(
+            CollectionSource::followers()
+                .page_query("https://events.thegem.city/actor/a", None, true)
+                .sql(),
+            "SELECT created_at, follower_ap_id AS collection_key, to_jsonb(follower_ap_id) AS collection_item FROM follow_activities WHERE followee_ap_id = $1 AND accepted_at IS NOT NULL ORDER BY created_at ASC, follower_ap_id ASC LIMIT $2"
+        );
+        assert_eq!(
+            CollectionSource::outbox()
+                .page_query("https://events.thegem.city/actor/a", Some(&cursor), true)
+                .sql(),
+            "SELECT created_at, ap_id AS collection_key, payload - '@context' AS collection_item FROM objects WHERE actor_ap_id = $1 AND is_public AND deleted_at IS NULL AND kind = ANY($2) AND (created_at, ap_id) < ($3, $4) ORDER BY created_at DESC, ap_id DESC LIMIT $5"
+        );
+        assert_eq!(
+            CollectionSource::outbox()
+                .page_query("https://events.thegem.city/actor/a", None, false)
+                .sql(),
+            "SELECT created_at, ap_id AS collection_key, payload - '@context' AS collection_item FROM objects WHERE actor_ap_id = $1 AND is_public AND deleted_at IS NULL AND kind = ANY($2) ORDER BY created_at ASC, ap_id ASC LIMIT $3"
+        );
+    }
+
+    #[actix_web::test]
+    async fn participants_leave_out_hidden_answers() {
+        let Some(app_state) = crate::state::test_state().await else {
+            return;
+        };
+        let event = format!("https://events.example/actor/{}", rand::random::<u64>());
+        sqlx::query(
+            "INSERT INTO events (actor_ap_id, title, starts_at, ends_at) VALUES ($1, 'Swing night', now(), now() + interval '3 hours')",
+        )
+        .bind(&event)
+        .execute(&app_state.pool)
+        .await
+        .unwrap();
+        for (occurrence, attendee, status, hidden) in [
+            ("", "alice", "going", false),
+            ("", "bob", "going", true),
+            ("", "carol", "maybe", false),
+            ("20301105T000000Z", "dana", "going", false),
+        ] {
+            sqlx::query(
+                "INSERT INTO rsvps (actor_ap_id, occurrence, attendee_ap_id, status, hidden) VALUES ($1, $2, $3, $4, $5)",
+            )
+            .bind(&event)
+            .bind(occurrence)
+            .bind(attendee)
+            .bind(status)
+            .bind(hidden)
+            .execute(&app_state.pool)
+            .await
+            .unwrap();
+        }
+
+        let participants = || async {
+            let source = CollectionSource::participants();
+            let total = source.count(&app_state, &event).await.unwrap();
+            let rows: Vec<(NaiveDateTime, String, Value)> = source
+                .page_query(&event, None, true)
+                .build_query_as()
+                .fetch_all(&app_state.pool)
+                .await
+                .unwrap();
+            let items: Vec<Value> = rows.into_iter().map(|(_, _, item)| item).collect();
+            (total, items)
+        };
+        // Bob is counted but not listed.
+        assert_eq!(participants().await, (2, vec![Value::from("alice")]));
+
+        sqlx::query("UPDATE events SET hide_attendees = true WHERE actor_ap_id = $1")
+            .bind(&event)
+            .execute(&app_state.pool)
+            .await
+            .unwrap();
+        assert_eq!(participants().await, (2, vec![]));
+    }
+}
@@ -0,0 +1,722 @@
+use activitypub_federation::core::object_id::ObjectId;
+use askama::{Html, MarkupDisplay};
+use chrono::{NaiveDateTime, NaiveTime};
+use url::Url;
+
+use crate::{
+    ap::{normalize::PUBLIC, note::Note},
+    calendars::{create_calendar_event, NewEvent},
+    error::ApEventsError,
+    lifecycle::{admin_link, transition_event},
+    messages::send_note,
+    objects::{
+        actor::EventActor,
+        event::{is_local_event_url, local_event, LocalEvent},
+    },
+    state::MyStateHandle,
+    storage_actor::get_actors,
+    storage_calendars::{get_calendar, Calendar},
+    storage_events::EventStatus,
+    storage_organizers::{organizer_role, remove_organizer, set_organizer, Role},
+    storage_rsvps::RsvpStatus,
+    storage_transfers::{cancel_transfer, complete_transfer, pending_transfer},
+    transfers::offer_event,
+    waitlist::{answer_event, waitlisted_notice},
+};
+
+/// Something that can be asked of an event by direct message.
+#[derive(Debug, PartialEq, Eq)]
+pub enum Command {
+    /// `#admin` replies with a link to the admin page.
+    Admin,
+    /// `#publish`, `#postpone`, `#cancel` and `#complete` move the event to another state.
+    Status(EventStatus),
+    /// `#rsvp going`, `#rsvp maybe` and `#rsvp not going` answer the event. Adding `hidden` or
+    /// `public` chooses whether others can see the answer, otherwise the last choice is kept.
+    Rsvp(RsvpStatus, Option<bool>),
+    /// `#cohost @someone` and `#moderator @someone` add an organizer with that role, and adding
+    /// `remove` removes the mentioned organizer instead.
+    Organizer(Role, bool),
+    /// `#transfer @someone` asks someone to take over the event from its owner.
+    Transfer,
+    /// `#transfer accept` takes over an event after being asked to.
+    AcceptTransfer,
+    /// `#transfer cancel` withdraws the request to take over the event.
+    CancelTransfer,
+    /// `#transfer decline` turns down a request to take over the event.
+    DeclineTransfer,
+}
+
+/// Something that the owner of a calendar can ask of it by direct message.
+#[derive(Debug, PartialEq, Eq)]
+pub enum CalendarCommand {
+    /// `#admin` replies with a link to the calendar's admin page.
+    Admin,
+    /// `#event Title; 2022-11-01 20:00; 23:00; Location` creates an event in the calendar. The
+    /// end is a time on the same day or a full date and time, and the location can be left out.
+    Event(NewEvent),
+}
+
+const HELP: &str = "<p>Send #rsvp going, #rsvp maybe or #rsvp not going to answer this event, and add hidden to keep your answer between you and the organizer. Organizers can also send #admin, #publish, #postpone, #cancel or #complete, and owners can send #cohost or #moderator mentioning someone to make them an organizer, or add remove right after the command to remove them. Owners can hand the event over with #transfer mentioning the new owner, who accepts with #transfer accept or declines with #transfer decline.</p>";
+
+/// Tags that break the text, so that the words on either side of them stay apart.
+const BREAKING_TAGS: &[&str] = &[
+    "br",
+    "p",
+    "div",
+    "li",
+    "ul",
+    "ol",
+    "blockquote",
+    "pre",
+    "h1",
+    "h2",
+    "h3",
+    "h4",
+    "h5",
+    "h6",
+];
+
+/// The plain text of a message, without markup. Inline tags such as links and spans are dropped
+/// without a trace, because Mastodon wraps the words of hashtags and mentions in them.
+pub fn note_text(content: &str) -> String {
+    let mut text = String::with_capacity(content.len());
+    let mut tag: Option<String> = None;
+    for c in content.chars() {
+        match (&mut tag, c) {
+            (None, '<') => tag = Some(String::new()),
+            (Some(inside), '>') => {
+                let name = inside
+                    .trim_start_matches('/')
+                    .split(|c: char| c.is_whitespace() || c == '/')
+                    .next()
+                    .unwrap_or_default()
+                    .to_lowercase();
+                if BREAKING_TAGS.contains(&name.as_str()) {
+                    text.push(' ');
+                }
+                tag = None;
+            }
+            (Some(inside), _) => inside.push(c),
+            (None, _) => text.push(c),
+        }
+    }
+    text.replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&#39;", "'")
+        .replace("&nbsp;", " ")
+        .replace("&amp;", "&")
+}
+
+const CALENDAR_HELP: &str = "<p>Organizers can send #event followed by a title, start, end and location separated by semicolons (i.e. #event Swing night; 2022-11-01 20:00; 23:00; The bar) to add an event to this calendar, or #admin to manage it.</p>";
+
+/// Finds the first command in a message. Mentions and other text around it are ignored.
+pub fn parse_command(text: &str) -> Option<Command> {
+    let words: Vec<String> = text.split_whitespace().map(str::to_lowercase).collect();
+    let position = words.iter().position(|word| word.starts_with('#'))?;
+    let argument = words[position + 1..].join(" ");
+    // Only the word right after an answer asks for it to be hidden or public, so that the rest
+    // of the message can say anything.
+    let answer_length = if argument.starts_with("not going") {
+        2
+    } else {
+        1
+    };
+    let hidden = words.get(position + 1 + answer_length).and_then(|word| {
+        match word.trim_matches(|c: char| !c.is_alphanumeric()) {
+            "hidden" | "private" => Some(true),
+            "public" => Some(false),
+            _ => None,
+        }
+    });
+    // Only `remove` right after the command removes, so that the rest of the message can
+    // mention removing someone else.
+    let removes = words
+        .get(position + 1)
+        .is_some_and(|word| word.trim_matches(|c: char| !c.is_alphanumeric()) == "remove");
+
+    match words[position].as_str() {
+        "#admin" => Some(Command::Admin),
+        "#publish" => Some(Command::Status(EventStatus::Published)),
+        "#postpone" => Some(Command::Status(EventStatus::Postponed)),
+        "#cancel" => Some(Command::Status(EventStatus::Cancelled)),
+        "#complete" => Some(Command::Status(EventStatus::Completed)),
+        "#rsvp" if argument.starts_with("not going") => {
+            Some(Command::Rsvp(RsvpStatus::NotGoing, hidden))
+        }
+        "#rsvp" if argument.starts_with("going") => Some(Command::Rsvp(RsvpStatus::Going, hidden)),
+        "#rsvp" if argument.starts_with("maybe") => Some(Command::Rsvp(RsvpStatus::Maybe, hidden)),
+        "#cohost" => Some(Command::Organizer(Role::CoHost, !removes)),
+        "#moderator" => Some(Command::Organizer(Role::Moderator, !removes)),
+        "#transfer" if argument.starts_with("accept") => Some(Command::AcceptTransfer),
+        "#transfer" if argument.starts_with("cancel") => Some(Command::CancelTransfer),
+        "#transfer" if argument.starts_with("decline") => Some(Command::DeclineTransfer),
+        "#transfer" => Some(Command::Transfer),
+        _ => None,
+    }
+}
+
+/// Finds the first calendar command in a message. Event details keep the case they were written
+/// in.
+pub fn parse_calendar_command(text: &str) -> Option<CalendarCommand> {
+    let mut words = text.split_whitespace();
+    let command = words.find(|word| word.starts_with('#'))?.to_lowercase();
+    match command.as_str() {
+        "#admin" => Some(CalendarCommand::Admin),
+        "#event" => {
+            let details = words.collect::<Vec<_>>().join(" ");
+            let parts: Vec<&str> = details.split(';').map(str::trim).collect();
+            let (title, starts_at, ends_at, location) = match parts.as_slice() {
+                [title, starts_at, ends_at] => (title, starts_at, ends_at, None),
+                [title, starts_at, ends_at, location] => {
+                    (title, starts_at, ends_at, Some(location.to_string()))
+                }
+                _ => return None,
+            };
+            let starts_at = NaiveDateTime::parse_from_str(starts_at, "%Y-%m-%d %H:%M").ok()?;
+            let ends_at = match NaiveTime::parse_from_str(ends_at, "%H:%M") {
+                Ok(time) => starts_at.date().and_time(time),
+                Err(_) => NaiveDateTime::parse_from_str(ends_at, "%Y-%m-%d %H:%M").ok()?,
+            };
+            Some(CalendarCommand::Event(NewEvent {
+                title: title.to_string(),
+                summary: String::new(),
+                starts_at,
+                ends_at,
+                location: location.filter(|location| !location.is_empty()),
+            }))
+        }
+        _ => None,
+    }
+}
+
+/// Who a direct message was sent to.
+enum Recipient {
+    Event(Box<LocalEvent>),
+    Calendar(Calendar),
+}
+
+/// Answers a message sent to an event. Only direct messages are read, and the event replies
+/// to the sender by direct message.
+pub async fn handle_note(
+    app_state: &MyStateHandle,
+    sender: ObjectId<EventActor>,
+    note: Note,
+    request_counter: &mut i32,
+) -> Result<(), ApEventsError> {
+    if note.to.iter().chain(note.cc.iter()).any(|to| to == PUBLIC) {
+        return Ok(());
+    }
+    let mut found = None;
+    for recipient in note.to.iter().chain(note.cc.iter()) {
+        let recipient = match Url::parse(recipient) {
+            Ok(recipient) if is_local_event_url(app_state, &recipient) => recipient,
+            _ => continue,
+        };
+        if let Ok(event) = local_event(app_state, &recipient).await {
+            found = Some(Recipient::Event(Box::new(event)));
+            break;
+        }
+        if let Some(calendar) = get_calendar(app_state, recipient.as_str()).await? {
+            found = Some(Recipient::Calendar(calendar));
+            break;
+        }
+    }
+    let found = match found {
+        Some(found) => found,
+        None => return Ok(()),
+    };
+
+    let sender = sender
+        .dereference(app_state, &app_state.local_instance, request_counter)
+        .await?;
+    let text = note_text(&note.content);
+    let (actor, reply) = match found {
+        Recipient::Event(found_event) => {
+            let reply = match parse_command(&text) {
+                Some(command) => {
+                    let mentioned = match command {
+                        Command::Organizer(..) | Command::Transfer => {
+                            mentioned_actor(app_state, &note, &found_event.actor, request_counter)
+                                .await?
+                        }
+                        _ => None,
+                    };
+                    run_command(app_state, &found_event, &sender, command, mentioned).await?
+                }
+                None => HELP.to_string(),
+            };
+            (found_event.actor, reply)
+        }
+        Recipient::Calendar(calendar) => {
+            let actor = ObjectId::<EventActor>::new(Url::parse(&calendar.actor_ap_id)?)
+                .dereference_local(app_state)
+                .await?;
+            let reply = match parse_calendar_command(&text) {
+                Some(command) => {
+                    run_calendar_command(app_state, &calendar, &actor, &sender, command).await?
+                }
+                None => CALENDAR_HELP.to_string(),
+            };
+            (actor, reply)
+        }
+    };
+    send_note(app_state, &actor, &[sender], false, reply, Some(note.ap_id)).await
+}
+
+async fn run_calendar_command(
+    app_state: &MyStateHandle,
+    calendar: &Calendar,
+    actor: &EventActor,
+    sender: &EventActor,
+    command: CalendarCommand,
+) -> Result<String, ApEventsError> {
+    let name = MarkupDisplay::new_unsafe(&calendar.name, Html);
+    if calendar.owner_ap_id.as_deref() != Some(sender.ap_id.inner().as_str()) {
+        return Ok(format!("<p>Only the organizer of {name} can do that.</p>"));
+    }
+
+    match command {
+        CalendarCommand::Admin => {
+            let link = admin_link(app_state, actor, Role::Owner).await?;
+            Ok(format!(
+                "<p>Manage {name} at <a href=\"{link}\">{link}</a>. The link works for 24 hours.</p>"
+            ))
+        }
+        CalendarCommand::Event(new_event) => {
+            match create_calendar_event(app_state, calendar, new_event).await {
+                Ok((event_actor, event)) => {
+                    let title = MarkupDisplay::new_unsafe(&event.title, Html);
+                    let url = event_actor.ap_id.inner();
+                    Ok(format!(
+                        "<p>{title} is now on {name} at <a href=\"{url}\">{url}</a>. Send it #admin to manage it.</p>"
+                    ))
+                }
+                Err(ApEventsError::InvalidEvent(message)) => {
+                    Ok(format!("<p>The event was not created: {message}.</p>"))
+                }
+                Err(err) => Err(err),
+            }
+        }
+    }
+}
+
+/// The first actor mentioned in a message other than the event it was sent to.
+async fn mentioned_actor(
+    app_state: &MyStateHandle,
+    note: &Note,
+    event_actor: &EventActor,
+    request_counter: &mut i32,
+) -> Result<Option<EventActor>, ApEventsError> {
+    let event_ap_id = event_actor.ap_id.inner().as_str();
+    let href = note.tag.iter().find_map(|tag| {
+        tag.href
+            .as_deref()
+            .filter(|href| tag.kind == "Mention" && *href != event_ap_id)
+    });
+    match href {
+        Some(href) => Ok(Some(
+            ObjectId::<EventActor>::new(Url::parse(href)?)
+                .dereference(app_state, &app_state.local_instance, request_counter)
+                .await?,
+        )),
+        None => Ok(None),
+    }
+}
+
+/// Runs a command sent to an event. Organizer commands are checked against the sender's role,
+/// and `mentioned` is who organizer changes apply to.
+async fn run_command(
+    app_state: &MyStateHandle,
+    found_event: &LocalEvent,
+    sender: &EventActor,
+    command: Command,
+    mentioned: Option<EventActor>,
+) -> Result<String, ApEventsError> {
+    let event = &found_event.event;
+    let title = MarkupDisplay::new_unsafe(&event.title, Html);
+    let sender_ap_id = sender.ap_id.inner().as_str();
+    let role = organizer_role(app_state, event, sender_ap_id).await?;
+
+    match (command, role) {
+        (
+            Command::Admin
+            | Command::Status(_)
+            | Command::Organizer(..)
+            | Command::Transfer
+            | Command::CancelTransfer,
+            None,
+        ) => Ok(format!(
+            "<p>Only the organizers of {title} can do that.</p>"
+        )),
+        (Command::Admin, Some(role)) => {
+            let link = admin_link(app_state, &found_event.actor, role).await?;
+            Ok(format!(
+                "<p>Manage {title} at <a href=\"{link}\">{link}</a>. The link works for 24 hours.</p>"
+            ))
+        }
+        (Command::Status(_), Some(role)) if !role.can_manage_event() => Ok(format!(
+            "<p>A {role} of {title} cannot change its status.</p>"
+        )),
+        (Command::Status(to), Some(_)) => {
+            match transition_event(app_state, &found_event.actor, event, to).await {
+                Ok(_) => Ok(format!("<p>{title} is now {to}.</p>")),
+                Err(ApEventsError::InvalidEvent(message)) => {
+                    Ok(format!("<p>{title} was not changed: {message}.</p>"))
+                }
+                Err(err) => Err(err),
+            }
+        }
+        (Command::Organizer(..), Some(role)) if !role.can_manage_organizers() => Ok(format!(
+            "<p>Only the owner of {title} can change its organizers.</p>"
+        )),
+        (Command::Organizer(to, add), Some(_)) => {
+            let mentioned = match mentioned {
+                Some(mentioned) => mentioned,
+                None => {
+                    return Ok(format!(
+                        "<p>Mention who should become a {to} of {title}.</p>"
+                    ))
+                }
+            };
+            let mentioned_ap_id = mentioned.ap_id.inner().as_str();
+            let mentioned_ref = MarkupDisplay::new_unsafe(&mentioned.actor_ref, Html);
+            if !add {
+                return match remove_organizer(app_state, &event.actor_ap_id, mentioned_ap_id)
+                    .await?
+                {
+                    true => Ok(format!(
+                        "<p>{mentioned_ref} no longer organizes {title}.</p>"
+                    )),
+                    false => Ok(format!(
+                        "<p>{mentioned_ref} is not a co-host or moderator of {title}.</p>"
+                    )),
+                };
+            }
+            match set_organizer(app_state, event, mentioned_ap_id, to).await {
+                Ok(()) => {}
+                Err(ApEventsError::InvalidEvent(message)) => {
+                    return Ok(format!("<p>{title} was not changed: {message}.</p>"))
+                }
+                Err(err) => return Err(err),
+            }
+            send_note(
+                app_state,
+                &found_event.actor,
+                std::slice::from_ref(&mentioned),
+                false,
+                format!("<p>You are now a {to} of {title}. Send #admin to manage it.</p>"),
+                None,
+            )
+            .await?;
+            Ok(format!("<p>{mentioned_ref} is now a {to} of {title}.</p>"))
+        }
+        (Command::AcceptTransfer, _) => {
+            if !complete_transfer(app_state, &event.actor_ap_id, sender_ap_id).await? {
+                return Ok(format!("<p>Nobody has asked you to take over {title}.</p>"));
+            }
+            // The event was loaded before the transfer, so it still names the previous owner.
+            let previous_ap_ids: Vec<String> = event.owner_ap_id.iter().cloned().collect();
+            let previous = get_actors(app_state, &previous_ap_ids).await?;
+            if !previous.is_empty() {
+                let sender_ref = MarkupDisplay::new_unsafe(&sender.actor_ref, Html);
+                send_note(
+                    app_state,
+                    &found_event.actor,
+                    &previous,
+                    false,
+                    format!("<p>{sender_ref} now owns {title}.</p>"),
+                    None,
+                )
+                .await?;
+            }
+            Ok(format!(
+                "<p>You now own {title}. Send #admin to manage it.</p>"
+            ))
+        }
+        (Command::DeclineTransfer, _) => {
+            if pending_transfer(app_state, &event.actor_ap_id)
+                .await?
+                .as_deref()
+                != Some(sender_ap_id)
+                || !cancel_transfer(app_state, &event.actor_ap_id).await?
+            {
+                return Ok(format!("<p>Nobody has asked you to take over {title}.</p>"));
+            }
+            let owner_ap_ids: Vec<String> = event.owner_ap_id.iter().cloned().collect();
+            let owner = get_actors(app_state, &owner_ap_ids).await?;
+            if !owner.is_empty() {
+                let sender_ref = MarkupDisplay::new_unsafe(&sender.actor_ref, Html);
+                send_note(
+                    app_state,
+                    &found_event.actor,
+                    &owner,
+                    false,
+                    format!("<p>{sender_ref} declined to take over {title}.</p>"),
+                    None,
+                )
+                .await?;
+            }
+            Ok(format!("<p>You will not take over {title}.</p>"))
+        }
+        (Command::Transfer | Command::CancelTransfer, Some(role))
+            if !role.can_manage_organizers() =>
+        {
+            Ok(format!(
+                "<p>Only the owner of {title} can hand it over.</p>"
+            ))
+        }
+        (Command::Transfer, Some(_)) => {
+            let mentioned = match mentioned {
+                Some(mentioned) => mentioned,
+                None => return Ok(format!("<p>Mention who should take over {title}.</p>")),
+            };
+            match offer_event(app_state, &found_event.actor, event, &mentioned).await {
+                Ok(()) => {}
+                Err(ApEventsError::InvalidEvent(message)) => {
+                    return Ok(format!("<p>{title} was not handed over: {message}.</p>"))
+                }
+                Err(err) => return Err(err),
+            }
+            let mentioned_ref = MarkupDisplay::new_unsafe(&mentioned.actor_ref, Html);
+            Ok(format!(
+                "<p>{mentioned_ref} has been asked to take over {title}, and becomes its owner once they reply #transfer accept.</p>"
+            ))
+        }
+        (Command::CancelTransfer, Some(_)) => {
+            match cancel_transfer(app_state, &event.actor_ap_id).await? {
+                true => Ok(format!(
+                    "<p>Nobody is being asked to take over {title} anymore.</p>"
+                )),
+                false => Ok(format!(
+                    "<p>Nobody has been asked to take over {title}.</p>"
+                )),
+            }
+        }
+        // Answers by message have no activity to accept, so they are stored without one.
+        (Command::Rsvp(status, hidden), _) => {
+            let privacy = match hidden {
+                Some(true) => " Only the organizer can see your answer.",
+                Some(false) => " Your answer is public.",
+                None => "",
+            };
+            match answer_event(app_state, found_event, sender_ap_id, status, None, hidden).await? {
+                None => Ok(format!("<p>{title} is not taking more attendees.</p>")),
+                Some(RsvpStatus::Waitlisted) => {
+                    waitlisted_notice(app_state, found_event, sender_ap_id).await
+                }
+                Some(RsvpStatus::Going) => Ok(format!("<p>You are going to {title}.{privacy}</p>")),
+                Some(RsvpStatus::Maybe) => Ok(format!("<p>You might go to {title}.{privacy}</p>")),
+                Some(RsvpStatus::NotGoing) => {
+                    Ok(format!("<p>You are not going to {title}.{privacy}</p>"))
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use chrono::NaiveDate;
+
+    #[test]
+    fn text_of_notes() {
+        assert_eq!(
+            note_text("<p><span class=\"h-card\"><a href=\"https://events.thegem.city/@readily-splendid-mule\" class=\"u-url mention\">@<span>readily-splendid-mule</span></a></span> #rsvp going</p>").split_whitespace().collect::<Vec<_>>(),
+            vec!["@readily-splendid-mule", "#rsvp", "going"]
+        );
+        assert_eq!(
+            note_text("<p>one<br>two<br />three</p><p>four</p>")
+                .split_whitespace()
+                .collect::<Vec<_>>(),
+            vec!["one", "two", "three", "four"]
+        );
+        assert_eq!(note_text("Tom &amp; Jerry&#39;s"), "Tom & Jerry's");
+        assert_eq!(note_text("&amp;lt;"), "&lt;");
+    }
+
+    #[test]
+    fn commands() {
+        assert_eq!(parse_command("@event #admin"), Some(Command::Admin));
+        assert_eq!(
+            parse_command("#Publish please"),
+            Some(Command::Status(EventStatus::Published))
+        );
+        assert_eq!(
+            parse_command("#postpone"),
+            Some(Command::Status(EventStatus::Postponed))
+        );
+        assert_eq!(
+            parse_command("#cancel"),
+            Some(Command::Status(EventStatus::Cancelled))
+        );
+        assert_eq!(
+            parse_command("#complete"),
+            Some(Command::Status(EventStatus::Completed))
+        );
+        assert_eq!(
+            parse_command("@event #rsvp going"),
+            Some(Command::Rsvp(RsvpStatus::Going, None))
+        );
+        assert_eq!(
+            parse_command("#rsvp maybe"),
+            Some(Command::Rsvp(RsvpStatus::Maybe, None))
+        );
+        assert_eq!(
+            parse_command("#rsvp  Not   going, sorry"),
+            Some(Command::Rsvp(RsvpStatus::NotGoing, None))
+        );
+        assert_eq!(
+            parse_command("#rsvp going hidden"),
+            Some(Command::Rsvp(RsvpStatus::Going, Some(true)))
+        );
+        assert_eq!(
+            parse_command("#rsvp maybe (Public)"),
+            Some(Command::Rsvp(RsvpStatus::Maybe, Some(false)))
+        );
+        assert_eq!(
+            parse_command("#rsvp not going private"),
+            Some(Command::Rsvp(RsvpStatus::NotGoing, Some(true)))
+        );
+        assert_eq!(
+            parse_command("@event #rsvp going, hidden please"),
+            Some(Command::Rsvp(RsvpStatus::Going, Some(true)))
+        );
+        assert_eq!(
+            parse_command("#rsvp going to the hidden bar with the public"),
+            Some(Command::Rsvp(RsvpStatus::Going, None))
+        );
+        assert_eq!(
+            parse_command("hidden message: #rsvp maybe"),
+            Some(Command::Rsvp(RsvpStatus::Maybe, None))
+        );
+        assert_eq!(
+            parse_command("#rsvp not going, public"),
+            Some(Command::Rsvp(RsvpStatus::NotGoing, Some(false)))
+        );
+        assert_eq!(
+            parse_command("#cohost @bob"),
+            Some(Command::Organizer(Role::CoHost, true))
+        );
+        assert_eq!(
+            parse_command("#CoHost remove @bob"),
+            Some(Command::Organizer(Role::CoHost, false))
+        );
+        assert_eq!(
+            parse_command("#cohost @alice please don't remove bob"),
+            Some(Command::Organizer(Role::CoHost, true))
+        );
+        assert_eq!(
+            parse_command("#moderator remove: @carol"),
+            Some(Command::Organizer(Role::Moderator, false))
+        );
+        assert_eq!(
+            parse_command("@event #moderator @carol please"),
+            Some(Command::Organizer(Role::Moderator, true))
+        );
+        assert_eq!(parse_command("#transfer @dana"), Some(Command::Transfer));
+        assert_eq!(
+            parse_command("@event #Transfer Accept"),
+            Some(Command::AcceptTransfer)
+        );
+        assert_eq!(
+            parse_command("#transfer accept"),
+            Some(Command::AcceptTransfer)
+        );
+        assert_eq!(
+            parse_command("#transfer cancel"),
+            Some(Command::CancelTransfer)
+        );
+        assert_eq!(
+            parse_command("#transfer decline"),
+            Some(Command::DeclineTransfer)
+        );
+        assert_eq!(
+            parse_command("@event #Transfer Decline, sorry"),
+            Some(Command::DeclineTransfer)
+        );
+        assert_eq!(parse_command("#rsvp"), None);
+        assert_eq!(parse_command("#party"), None);
+        assert_eq!(parse_command("hello there"), None);
+    }
+
+    #[test]
+    fn mastodon_commands() {
+        let mention = "<span class=\"h-card\" translate=\"no\"><a href=\"https://events.thegem.city/@readily-splendid-mule\" class=\"u-url mention\">@<span>readily-splendid-mule</span></a></span>";
+        let hashtag = |tag: &str| {
+            format!("<a href=\"https://mastodon.social/tags/{tag}\" class=\"mention hashtag\" rel=\"tag\">#<span>{tag}</span></a>")
+        };
+
+        assert_eq!(
+            parse_command(&note_text(&format!(
+                "<p>{mention} {} going</p>",
+                hashtag("rsvp")
+            ))),
+            Some(Command::Rsvp(RsvpStatus::Going, None))
+        );
+        assert_eq!(
+            parse_command(&note_text(&format!(
+                "<p>{mention} {}</p>",
+                hashtag("admin")
+            ))),
+            Some(Command::Admin)
+        );
+        assert_eq!(
+            parse_command(&note_text(&format!(
+                "<p>{mention} {}</p>",
+                hashtag("cancel")
+            ))),
+            Some(Command::Status(EventStatus::Cancelled))
+        );
+        assert_eq!(
+            parse_command(&note_text(&format!(
+                "<p>{mention}</p><p>{} accept</p>",
+                hashtag("transfer")
+            ))),
+            Some(Command::AcceptTransfer)
+        );
+    }
+
+    #[test]
+    fn calendar_commands() {
+        let at = |day: u32, hour: u32, minute: u32| {
+            NaiveDate::from_ymd_opt(2022, 11, day)
+                .unwrap()
+                .and_hms_opt(hour, minute, 0)
+                .unwrap()
+        };
+        assert_eq!(
+            parse_calendar_command("@swing #Admin"),
+            Some(CalendarCommand::Admin)
+        );
+        assert_eq!(
+            parse_calendar_command("@swing #event Swing Night; 2022-11-01 20:00; 23:30; The Bar"),
+            Some(CalendarCommand::Event(NewEvent {
+                title: "Swing Night".to_string(),
+                summary: String::new(),
+                starts_at: at(1, 20, 0),
+                ends_at: at(1, 23, 30),
+                location: Some("The Bar".to_string()),
+            }))
+        );
+        assert_eq!(
+            parse_calendar_command("#event Late night ;2022-11-01 22:00; 2022-11-02 02:00;"),
+            Some(CalendarCommand::Event(NewEvent {
+                title: "Late night".to_string(),
+                summary: String::new(),
+                starts_at: at(1, 22, 0),
+                ends_at: at(2, 2, 0),
+                location: None,
+            }))
+        );
+        assert_eq!(
+            parse_calendar_command("#event Swing night; tomorrow; 23:00"),
+            None
+        );
+        assert_eq!(parse_calendar_command("#event Swing night"), None);
+        assert_eq!(parse_calendar_command("#rsvp going"), None);
+    }
+}
@@ -0,0 +1,152 @@
+use chrono::{DateTime, Duration, Utc};
+use log::{info, warn};
+use reqwest::StatusCode;
+use serde_json::Value;
+use url::Url;
+
+use crate::{
+    error::ApEventsError,
+    state::MyStateHandle,
+    storage_deliveries::{claim_deliveries, finish_delivery, retry_delivery, Delivery},
+    util::deliver_activity,
+};
+
+/// How many deliveries are attempted at once.
+const DELIVERY_BATCH: i64 = 50;
+
+/// How long to wait before each retry of a failed delivery: a restart, maintenance, and a major
+/// outage. The delivery is dropped after the last one.
+const RETRY_DELAYS_MINUTES: [i64; 3] = [1, 60, 60 * 60];
+
+/// When to try a delivery again after it failed `attempts` times, if at all.
+pub fn retry_at(attempts: i32, now: DateTime<Utc>) -> Option<DateTime<Utc>> {
+    let delay = RETRY_DELAYS_MINUTES.get(usize::try_from(attempts).ok()?.checked_sub(1)?)?;
+    Some(now + Duration::minutes(*delay))
+}
+
+/// Whether an activity is addressed to a collection through `to` or `cc`.
+pub fn addressed_to(activity: &Value, collection: &Url) -> bool {
+    ["to", "cc"].iter().any(|field| match activity.get(field) {
+        Some(Value::String(value)) => value == collection.as_str(),
+        Some(Value::Array(values)) => values
+            .iter()
+            .any(|value| value.as_str() == Some(collection.as_str())),
+        _ => false,
+    })
+}
+
+/// Whether a response means the inbox will never take the activity, so it is not retried.
+fn is_rejection(status: StatusCode) -> bool {
+    status.is_client_error()
+        && status != StatusCode::REQUEST_TIMEOUT
+        && status != StatusCode::TOO_MANY_REQUESTS
+}
+
+/// Sends the queued deliveries that are due, in batches, until none are left. Failed deliveries
+/// are retried later with backoff.
+pub async fn run_deliveries(app_state: MyStateHandle) -> Result<(), ApEventsError> {
+    loop {
+        let deliveries = claim_deliveries(&app_state, Utc::now(), DELIVERY_BATCH).await?;
+        if deliveries.is_empty() {
+            return Ok(());
+        }
+
+        let attempts: Vec<_> = deliveries
+            .into_iter()
+            .map(|delivery| actix_web::rt::spawn(attempt_delivery(app_state.clone(), delivery)))
+            .collect();
+        for attempt in attempts {
+            if let Ok(Err(err)) = attempt.await {
+                warn!("delivery failed: {}", err);
+            }
+        }
+    }
+}
+
+async fn attempt_delivery(
+    app_state: MyStateHandle,
+    delivery: Delivery,
+) -> Result<(), ApEventsError> {
+    let private_key = match delivery.private_key {
+        Some(private_key) => private_key,
+        None => {
+            warn!("dropping delivery {}, its actor cannot sign", delivery.id);
+            return finish_delivery(&app_state, delivery.id).await;
+        }
+    };
+
+    let failure = match deliver_activity(
+        &Url::parse(&delivery.inbox)?,
+        delivery.activity,
+        delivery.public_key_id,
+        private_key,
+        delivery.collection_synchronization,
+    )
+    .await
+    {
+        Ok(status) if status.is_success() => None,
+        Ok(status) if is_rejection(status) => {
+            info!("{} rejected delivery {}", delivery.inbox, delivery.id);
+            None
+        }
+        Ok(status) => Some(format!("status {status}")),
+        Err(err) => Some(err.to_string()),
+    };
+
+    match failure {
+        None => finish_delivery(&app_state, delivery.id).await,
+        Some(failure) => match retry_at(delivery.attempts + 1, Utc::now()) {
+            Some(next_attempt_at) => {
+                info!(
+                    "delivery {} to {} failed with {}, retrying at {}",
+                    delivery.id, delivery.inbox, failure, next_attempt_at
+                );
+                retry_delivery(&app_state, delivery.id, next_attempt_at).await
+            }
+            None => {
+                warn!(
+                    "giving up on delivery {} to {} after {} attempts: {}",
+                    delivery.id,
+                    delivery.inbox,
+                    delivery.attempts + 1,
+                    failure
+                );
+                finish_delivery(&app_state, delivery.id).await
+            }
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+    use serde_json::json;
+
+    #[test]
+    fn retries() {
+        let now = Utc.with_ymd_and_hms(2024, 3, 1, 12, 0, 0).unwrap();
+        assert_eq!(retry_at(1, now), Some(now + Duration::minutes(1)));
+        assert_eq!(retry_at(2, now), Some(now + Duration::hours(1)));
+        assert_eq!(retry_at(3, now), Some(now + Duration::hours(60)));
+        assert_eq!(retry_at(4, now), None);
+
+        assert!(is_rejection(StatusCode::GONE));
+        assert!(!is_rejection(StatusCode::TOO_MANY_REQUESTS));
+        assert!(!is_rejection(StatusCode::BAD_GATEWAY));
+    }
+
+    #[test]
+    fn addressing() {
+        let followers = Url::parse("https://events.thegem.city/actor/a/followers").unwrap();
+        assert!(addressed_to(
+            &json!({"to": ["https://www.w3.org/ns/activitystreams#Public"], "cc": [followers.as_str()]}),
+            &followers
+        ));
+        assert!(addressed_to(&json!({"to": followers.as_str()}), &followers));
+        assert!(!addressed_to(
+            &json!({"to": ["https://thegem.city/users/nick"]}),
+            &followers
+        ));
+    }
+}
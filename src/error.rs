@@ -2,7 +2,7 @@ use actix_web::{http::StatusCode, HttpResponse, ResponseError};
 use serde::Serialize;
 use thiserror::Error;
 
-use crate::ap::ids::ObjectIdError;
+use crate::ap::{ids::ObjectIdError, normalize::NormalizeError};
 
 #[derive(Debug, Error)]
 pub enum ApEventsError {
@@ -24,6 +24,9 @@ pub enum ApEventsError {
     #[error("an unexpected error has occured")]
     ObjectIdError(#[from] ObjectIdError),
 
+    #[error("invalid activity: {0}")]
+    NormalizeError(#[from] NormalizeError),
+
     #[error("an unexpected error has occured")]
     JsonError(#[from] serde_json::Error),
 
@@ -44,6 +47,30 @@ pub enum ApEventsError {
     #[error("an unexpected error has occured")]
     ClientRequestError(#[from] reqwest::Error),
 
+    #[error("an unexpected error has occured")]
+    OpenSsl(#[from] openssl::error::ErrorStack),
+
+    #[error("{0}")]
+    InvalidQuery(String),
+
+    #[error("invalid event: {0}")]
+    InvalidEvent(String),
+
+    #[error("invalid place: {0}")]
+    InvalidPlace(String),
+
+    #[error("invalid calendar: {0}")]
+    InvalidCalendar(String),
+
+    #[error("invalid archive: {0}")]
+    InvalidArchive(String),
+
+    #[error("forbidden: {0}")]
+    Forbidden(String),
+
+    #[error("object not found: {0}")]
+    ObjectNotFound(String),
+
     #[error("actor not found: {0}")]
     ActorNotFound(String, #[source] anyhow::Error),
 
@@ -66,6 +93,14 @@ impl ApEventsError {
     pub fn name(&self) -> String {
         match self {
             Self::ActorNotFound(_, _) => "Actor Not Found".to_string(),
+            Self::NormalizeError(_) => "Invalid Activity".to_string(),
+            Self::InvalidQuery(_) => "Invalid Query".to_string(),
+            Self::InvalidEvent(_) => "Invalid Event".to_string(),
+            Self::InvalidPlace(_) => "Invalid Place".to_string(),
+            Self::InvalidCalendar(_) => "Invalid Calendar".to_string(),
+            Self::InvalidArchive(_) => "Invalid Archive".to_string(),
+            Self::Forbidden(_) => "Forbidden".to_string(),
+            Self::ObjectNotFound(_) | Self::ObjectIdError(_) => "Object Not Found".to_string(),
             Self::Generic(_) => "Generic".to_string(),
             Self::Unknown => "Unknown".to_string(),
             _ => "Unknown".to_string(),
@@ -77,6 +112,14 @@ impl ResponseError for ApEventsError {
     fn status_code(&self) -> StatusCode {
         match *self {
             Self::ActorNotFound(_, _) => StatusCode::NOT_FOUND,
+            Self::NormalizeError(_)
+            | Self::InvalidQuery(_)
+            | Self::InvalidEvent(_)
+            | Self::InvalidPlace(_)
+            | Self::InvalidCalendar(_)
+            | Self::InvalidArchive(_) => StatusCode::BAD_REQUEST,
+            Self::Forbidden(_) => StatusCode::FORBIDDEN,
+            Self::ObjectNotFound(_) | Self::ObjectIdError(_) => StatusCode::NOT_FOUND,
             Self::Generic(_) => StatusCode::INTERNAL_SERVER_ERROR,
             Self::Unknown => StatusCode::INTERNAL_SERVER_ERROR,
             _ => StatusCode::INTERNAL_SERVER_ERROR,
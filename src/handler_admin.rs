@@ -0,0 +1,415 @@
+use actix_web::{
+    http::header,
+    web::{Data, Form, Path, Query},
+    HttpResponse, Result,
+};
+use askama_actix::{Template, TemplateToResponse};
+use chrono::{NaiveDateTime, Utc};
+use serde::Deserialize;
+
+use crate::{
+    calendars::{create_calendar_event, NewEvent},
+    error::ApEventsError,
+    fed::actor_maybe,
+    handler_events::{format_time, format_when},
+    lifecycle::transition_event,
+    objects::actor::EventActor,
+    recurrence::{resolve_local, Occurrence},
+    reminders::{format_offsets, parse_offsets},
+    state::MyStateHandle,
+    storage_actor::get_actors,
+    storage_admin_tokens::admin_token_role,
+    storage_calendars::{calendar_events, get_calendar, Calendar},
+    storage_events::{get_event, set_publish_at, Event, EventStatus},
+    storage_organizers::{organizers, remove_organizer, set_organizer, Role},
+    storage_reminders::{event_reminders, set_event_reminders},
+    storage_rsvps::{attendees_hidden, going_with_privacy, set_attendees_hidden, ALL_OCCURRENCES},
+    storage_transfers::{cancel_transfer, pending_transfer},
+    transfers::offer_event,
+};
+
+#[derive(Template)]
+#[template(path = "admin.html")]
+struct AdminTemplate<'a> {
+    display_name: &'a str,
+    ap_id: &'a str,
+    token: &'a str,
+    status: &'a str,
+    when: &'a str,
+    timezone: &'a str,
+    publish_at: Option<String>,
+    is_draft: bool,
+    transitions: Vec<&'static str>,
+    reminders: &'a str,
+    hide_attendees: bool,
+    attendees: Vec<AdminAttendeeTemplate<bool>>,
+    /// The role of the organizer the link was issued to, which limits what the page offers.
+    role: &'a str,
+    can_manage_event: bool,
+    can_manage_organizers: bool,
+    organizers: Vec<AdminAttendeeTemplate<&'static str>>,
+    /// Who has been asked to take over the event, if anyone.
+    transfer_to: Option<String>,
+}
+
+#[derive(Template)]
+#[template(path = "calendar_admin.html")]
+struct CalendarAdminTemplate<'a> {
+    display_name: &'a str,
+    ap_id: &'a str,
+    token: &'a str,
+    timezone: &'a str,
+    /// The calendar's upcoming events: their url and title.
+    events: Vec<(String, String)>,
+}
+
+/// Someone going to the event: their url, actor reference and whether they asked to be hidden.
+/// Organizers are listed the same way, with their role.
+struct AdminAttendeeTemplate<T>(String, String, T);
+
+#[derive(Deserialize)]
+pub struct AdminQuery {
+    token: String,
+}
+
+#[derive(Deserialize)]
+pub struct AdminForm {
+    token: String,
+    /// The state to move the event to, `schedule` to set the publish time of a draft,
+    /// `reminders` to change when attendees are reminded, `attendees` to show or hide the
+    /// attendee list, `organizer` and `remove_organizer` to change the organizers, or `transfer`
+    /// and `cancel_transfer` to hand the event over.
+    action: String,
+    /// A local time in the event's timezone, such as `2022-11-01T20:00`. Empty unschedules.
+    #[serde(default)]
+    publish_at: String,
+    /// Reminder offsets such as `24h, 1h`, for the `reminders` action. Empty turns reminders off.
+    #[serde(default)]
+    reminders: String,
+    /// Set when the `attendees` action should hide the attendee list.
+    #[serde(default)]
+    hide_attendees: Option<String>,
+    /// The details of the event that the `event` action adds to a calendar. Times are local to
+    /// the calendar's timezone.
+    #[serde(default)]
+    title: String,
+    #[serde(default)]
+    summary: String,
+    #[serde(default)]
+    starts_at: String,
+    #[serde(default)]
+    ends_at: String,
+    #[serde(default)]
+    location: String,
+    /// The actor reference or url of the organizer to add or remove, or of the new owner.
+    #[serde(default)]
+    organizer: String,
+    /// The role to give the organizer, either `co-host` or `moderator`.
+    #[serde(default)]
+    role: String,
+}
+
+/// The actor that an admin token grants access to, and the role of the organizer it was issued
+/// to.
+async fn authorized_actor(
+    app_state: &MyStateHandle,
+    name: &str,
+    token: &str,
+) -> Result<(EventActor, Role), ApEventsError> {
+    let actor_ap_id = format!("{}/actor/{}", app_state.external_base, name);
+    let role = admin_token_role(app_state, &actor_ap_id, token, Utc::now())
+        .await?
+        .ok_or_else(|| {
+            ApEventsError::Forbidden("the admin link is invalid or has expired".to_string())
+        })?;
+
+    let found_actor = sqlx::query_as("SELECT * FROM actors WHERE ap_id = $1")
+        .bind(&actor_ap_id)
+        .fetch_one(&app_state.pool)
+        .await?;
+    Ok((found_actor, role))
+}
+
+fn forbidden(role: Role, action: &str) -> ApEventsError {
+    ApEventsError::Forbidden(format!("a {role} cannot {action}"))
+}
+
+/// Checks that a role may take an action of the event admin page. Only the owner changes who
+/// organizes the event, and moderators cannot change the event at all.
+fn check_action(role: Role, action: &str) -> Result<(), ApEventsError> {
+    match action {
+        "organizer" | "remove_organizer" if !role.can_manage_organizers() => {
+            Err(forbidden(role, "change the organizers"))
+        }
+        "transfer" | "cancel_transfer" if !role.can_manage_organizers() => {
+            Err(forbidden(role, "hand the event over"))
+        }
+        "organizer" | "remove_organizer" | "transfer" | "cancel_transfer" => Ok(()),
+        _ if !role.can_manage_event() => Err(forbidden(role, "change the event")),
+        _ => Ok(()),
+    }
+}
+
+/// The event of an actor that an admin token grants access to.
+async fn actor_event(
+    app_state: &MyStateHandle,
+    found_actor: EventActor,
+) -> Result<(EventActor, Event), ApEventsError> {
+    let actor_ap_id = found_actor.ap_id.to_string();
+    let event = get_event(app_state, &actor_ap_id)
+        .await?
+        .ok_or(ApEventsError::ObjectNotFound(actor_ap_id))?;
+    Ok((found_actor, event))
+}
+
+fn parse_local_time(value: &str) -> Result<NaiveDateTime, ApEventsError> {
+    NaiveDateTime::parse_from_str(value.trim(), "%Y-%m-%dT%H:%M")
+        .map_err(|_| ApEventsError::InvalidQuery(format!("invalid time {value}")))
+}
+
+/// The admin page of a calendar, where events are added to it.
+async fn calendar_admin(
+    app_state: &MyStateHandle,
+    found_actor: &EventActor,
+    calendar: &Calendar,
+    token: &str,
+) -> Result<HttpResponse, ApEventsError> {
+    let events = calendar_events(app_state, &calendar.actor_ap_id, Utc::now())
+        .await?
+        .into_iter()
+        .map(|event| (event.actor_ap_id, event.title))
+        .collect();
+
+    Ok(CalendarAdminTemplate {
+        display_name: &calendar.name,
+        ap_id: &found_actor.ap_id.to_string(),
+        token,
+        timezone: &calendar.timezone,
+        events,
+    }
+    .to_response())
+}
+
+/// The admin page of an event, reached through the link that `#admin` replies with.
+pub async fn handle_admin(
+    info: Path<String>,
+    query: Query<AdminQuery>,
+    app_state: Data<MyStateHandle>,
+) -> Result<HttpResponse, ApEventsError> {
+    let (found_actor, role) = authorized_actor(&app_state, &info, &query.token).await?;
+    if let Some(calendar) = get_calendar(&app_state, found_actor.ap_id.inner().as_str()).await? {
+        return calendar_admin(&app_state, &found_actor, &calendar, &query.token).await;
+    }
+    let (found_actor, event) = actor_event(&app_state, found_actor).await?;
+    let tz = event.tz();
+    let reminders = event_reminders(&app_state, &event.actor_ap_id)
+        .await?
+        .unwrap_or_else(|| app_state.default_reminders.clone());
+
+    // The organizer sees everyone going, including hidden attendees.
+    let going = going_with_privacy(&app_state, &event.actor_ap_id, ALL_OCCURRENCES).await?;
+    let going_ap_ids: Vec<String> = going
+        .iter()
+        .map(|(attendee_ap_id, _)| attendee_ap_id.clone())
+        .collect();
+    let found_attendees = get_actors(&app_state, &going_ap_ids).await?;
+    let attendees = going
+        .into_iter()
+        .map(|(attendee_ap_id, hidden)| {
+            let actor_ref = found_attendees
+                .iter()
+                .find(|attendee| attendee.ap_id.inner().as_str() == attendee_ap_id)
+                .map_or_else(
+                    || attendee_ap_id.clone(),
+                    |attendee| attendee.actor_ref.clone(),
+                );
+            AdminAttendeeTemplate(attendee_ap_id, actor_ref, hidden)
+        })
+        .collect();
+
+    let organizers = organizers(&app_state, &event).await?;
+    let organizer_ap_ids: Vec<String> = organizers
+        .iter()
+        .map(|(organizer_ap_id, _)| organizer_ap_id.clone())
+        .collect();
+    let found_organizers = get_actors(&app_state, &organizer_ap_ids).await?;
+    let organizers = organizers
+        .into_iter()
+        .map(|(organizer_ap_id, role)| {
+            let actor_ref = found_organizers
+                .iter()
+                .find(|organizer| organizer.ap_id.inner().as_str() == organizer_ap_id)
+                .map_or_else(
+                    || organizer_ap_id.clone(),
+                    |organizer| organizer.actor_ref.clone(),
+                );
+            AdminAttendeeTemplate(organizer_ap_id, actor_ref, role.as_str())
+        })
+        .collect();
+
+    let transfer_to = match pending_transfer(&app_state, &event.actor_ap_id).await? {
+        Some(nominee_ap_id) => Some(
+            get_actors(&app_state, std::slice::from_ref(&nominee_ap_id))
+                .await?
+                .into_iter()
+                .next()
+                .map_or(nominee_ap_id, |nominee| nominee.actor_ref),
+        ),
+        None => None,
+    };
+
+    Ok(AdminTemplate {
+        display_name: &event.title,
+        ap_id: &found_actor.ap_id.to_string(),
+        token: &query.token,
+        status: event.status.as_str(),
+        when: &format_when(
+            &tz,
+            &Occurrence {
+                starts_at: event.starts_at,
+                ends_at: event.ends_at,
+            },
+        ),
+        timezone: &event.timezone,
+        publish_at: event
+            .publish_at
+            .map(|publish_at| format_time(&tz, &publish_at)),
+        is_draft: event.is_draft(),
+        transitions: event
+            .status
+            .transitions()
+            .iter()
+            .map(|status| status.as_str())
+            .collect(),
+        reminders: &format_offsets(&reminders),
+        hide_attendees: attendees_hidden(&app_state, &event.actor_ap_id).await?,
+        attendees,
+        role: role.as_str(),
+        can_manage_event: role.can_manage_event(),
+        can_manage_organizers: role.can_manage_organizers(),
+        organizers,
+        transfer_to,
+    }
+    .to_response())
+}
+
+pub async fn handle_admin_post(
+    info: Path<String>,
+    form: Form<AdminForm>,
+    app_state: Data<MyStateHandle>,
+) -> Result<HttpResponse, ApEventsError> {
+    let (found_actor, role) = authorized_actor(&app_state, &info, &form.token).await?;
+    if let Some(calendar) = get_calendar(&app_state, found_actor.ap_id.inner().as_str()).await? {
+        if !role.can_manage_event() {
+            return Err(forbidden(role, "add events"));
+        }
+        if form.action != "event" {
+            return Err(ApEventsError::InvalidQuery(format!(
+                "calendars cannot {}",
+                form.action
+            )));
+        }
+        let new_event = NewEvent {
+            title: form.title.trim().to_string(),
+            summary: form.summary.trim().to_string(),
+            starts_at: parse_local_time(&form.starts_at)?,
+            ends_at: parse_local_time(&form.ends_at)?,
+            location: Some(form.location.trim().to_string())
+                .filter(|location| !location.is_empty()),
+        };
+        let (event_actor, _) = create_calendar_event(&app_state, &calendar, new_event).await?;
+        return Ok(HttpResponse::SeeOther()
+            .append_header((header::LOCATION, event_actor.ap_id.inner().as_str()))
+            .finish());
+    }
+    let (found_actor, event) = actor_event(&app_state, found_actor).await?;
+    check_action(role, &form.action)?;
+
+    if form.action == "attendees" {
+        set_attendees_hidden(
+            &app_state,
+            &event.actor_ap_id,
+            form.hide_attendees.is_some(),
+        )
+        .await?;
+    } else if form.action == "organizer" || form.action == "remove_organizer" {
+        let organizer = form.organizer.trim().to_string();
+        if form.action == "organizer" {
+            let organizer = actor_maybe(&app_state, event.actor_ap_id.clone(), organizer).await?;
+            set_organizer(
+                &app_state,
+                &event,
+                organizer.ap_id.inner().as_str(),
+                form.role.parse()?,
+            )
+            .await?;
+        } else if !remove_organizer(&app_state, &event.actor_ap_id, &organizer).await? {
+            return Err(ApEventsError::ObjectNotFound(organizer));
+        }
+    } else if form.action == "transfer" || form.action == "cancel_transfer" {
+        if form.action == "transfer" {
+            let nominee = actor_maybe(
+                &app_state,
+                event.actor_ap_id.clone(),
+                form.organizer.trim().to_string(),
+            )
+            .await?;
+            offer_event(&app_state, &found_actor, &event, &nominee).await?;
+        } else {
+            cancel_transfer(&app_state, &event.actor_ap_id).await?;
+        }
+    } else if form.action == "reminders" {
+        let reminders = parse_offsets(&form.reminders)?;
+        set_event_reminders(&app_state, &event.actor_ap_id, &reminders).await?;
+    } else if form.action == "schedule" {
+        let publish_at = match form.publish_at.trim() {
+            "" => None,
+            publish_at => Some(resolve_local(&event.tz(), &parse_local_time(publish_at)?)),
+        };
+        if !set_publish_at(&app_state, &event.actor_ap_id, publish_at).await? {
+            return Err(ApEventsError::InvalidEvent(
+                "only drafts can be scheduled".to_string(),
+            ));
+        }
+    } else {
+        let to: EventStatus = form.action.parse()?;
+        transition_event(&app_state, &found_actor, &event, to).await?;
+    }
+
+    Ok(HttpResponse::SeeOther()
+        .append_header((
+            header::LOCATION,
+            format!("{}/admin?token={}", found_actor.ap_id.inner(), form.token),
+        ))
+        .finish())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn actions_by_role() {
+        let allowed = |role: Role, action: &str| check_action(role, action).is_ok();
+
+        for action in ["attendees", "reminders", "schedule", "cancelled"] {
+            assert!(allowed(Role::Owner, action), "{action}");
+            assert!(allowed(Role::CoHost, action), "{action}");
+            assert!(!allowed(Role::Moderator, action), "{action}");
+        }
+        for action in [
+            "organizer",
+            "remove_organizer",
+            "transfer",
+            "cancel_transfer",
+        ] {
+            assert!(allowed(Role::Owner, action), "{action}");
+            assert!(!allowed(Role::CoHost, action), "{action}");
+            assert!(!allowed(Role::Moderator, action), "{action}");
+        }
+        assert!(matches!(
+            check_action(Role::Moderator, "attendees"),
+            Err(ApEventsError::Forbidden(_))
+        ));
+    }
+}
@@ -0,0 +1,229 @@
+use std::time::SystemTime;
+
+use activitypub_federation::core::object_id::ObjectId;
+use actix_web::{
+    http::header::{self, EntityTag, Header, HeaderValue, HttpDate, IfModifiedSince, IfNoneMatch},
+    web::{Data, Path},
+    HttpRequest, HttpResponse,
+};
+use chrono::{NaiveDateTime, Timelike, Utc};
+use sha2::{Digest, Sha256};
+use url::Url;
+
+use crate::{
+    error::ApEventsError,
+    ical::{write_calendar, CalendarEvent},
+    objects::actor::EventActor,
+    state::MyStateHandle,
+    storage_calendar_tokens::calendar_token_attendee,
+    storage_calendars::{calendar_events, get_calendar},
+    storage_events::{attending_events, get_event, upcoming_events, Event},
+    storage_rsvps::latest_rsvp_update,
+};
+
+const CALENDAR_CONTENT_TYPE: &str = "text/calendar; charset=utf-8";
+
+/// The `webcal://` form of a feed url, which calendar apps open as a subscription.
+pub fn webcal_url(url: &str) -> String {
+    match url.split_once("://") {
+        Some((_, rest)) => format!("webcal://{rest}"),
+        None => url.to_string(),
+    }
+}
+
+fn last_modified(events: &[Event]) -> Option<NaiveDateTime> {
+    events.iter().map(|event| event.updated_at).max()
+}
+
+/// Returns true if the client's cached copy, described by its conditional request headers, is
+/// still current. `If-None-Match` takes precedence over `If-Modified-Since`.
+fn is_not_modified(
+    request: &HttpRequest,
+    etag: &EntityTag,
+    last_modified: Option<SystemTime>,
+) -> bool {
+    if request.headers().contains_key(header::IF_NONE_MATCH) {
+        return match IfNoneMatch::parse(request) {
+            Ok(IfNoneMatch::Any) => true,
+            Ok(IfNoneMatch::Items(tags)) => tags.iter().any(|tag| tag.weak_eq(etag)),
+            Err(_) => false,
+        };
+    }
+    match (IfModifiedSince::parse(request), last_modified) {
+        (Ok(IfModifiedSince(since)), Some(last_modified)) => {
+            last_modified <= SystemTime::from(since)
+        }
+        _ => false,
+    }
+}
+
+/// Attendee feeds include online links that only attendees may see.
+fn calendar_response(
+    request: &HttpRequest,
+    events: &[Event],
+    last_modified: Option<NaiveDateTime>,
+    for_attendee: bool,
+) -> HttpResponse {
+    let entries: Vec<CalendarEvent> = match for_attendee {
+        true => events.iter().map(CalendarEvent::for_attendee).collect(),
+        false => events.iter().map(CalendarEvent::new).collect(),
+    };
+    let calendar = write_calendar(&entries);
+
+    let digest = Sha256::digest(calendar.as_bytes());
+    let etag = EntityTag::new_strong(
+        digest[..16]
+            .iter()
+            .map(|byte| format!("{:02x}", byte))
+            .collect(),
+    );
+    // HTTP dates only have second precision.
+    let last_modified = last_modified
+        .and_then(|last_modified| last_modified.with_nanosecond(0))
+        .map(|last_modified| SystemTime::from(last_modified.and_utc()));
+
+    let not_modified = is_not_modified(request, &etag, last_modified);
+    let mut response = match not_modified {
+        true => HttpResponse::NotModified(),
+        false => HttpResponse::Ok(),
+    };
+    response.insert_header(header::ETag(etag));
+    if let Some(last_modified) = last_modified {
+        response.insert_header(header::LastModified(HttpDate::from(last_modified)));
+    }
+
+    match not_modified {
+        true => response.finish(),
+        false => response.content_type(CALENDAR_CONTENT_TYPE).body(calendar),
+    }
+}
+
+pub async fn handle_event_ics(
+    request: HttpRequest,
+    name: Path<String>,
+    app_state: Data<MyStateHandle>,
+) -> Result<HttpResponse, ApEventsError> {
+    let actor_ap_id = format!("{}/actor/{}", app_state.external_base, name);
+    ObjectId::<EventActor>::new(Url::parse(&actor_ap_id)?)
+        .dereference_local(&app_state)
+        .await?;
+    let event = get_event(&app_state, &actor_ap_id)
+        .await?
+        .filter(|event| !event.is_draft())
+        .ok_or(ApEventsError::ObjectNotFound(actor_ap_id))?;
+
+    let updated_at = event.updated_at;
+    Ok(calendar_response(
+        &request,
+        &[event],
+        Some(updated_at),
+        false,
+    ))
+}
+
+/// The upcoming events of a calendar.
+pub async fn handle_calendar_ics(
+    request: HttpRequest,
+    name: Path<String>,
+    app_state: Data<MyStateHandle>,
+) -> Result<HttpResponse, ApEventsError> {
+    let actor_ap_id = format!("{}/actor/{}", app_state.external_base, name);
+    let calendar = get_calendar(&app_state, &actor_ap_id)
+        .await?
+        .ok_or(ApEventsError::ObjectNotFound(actor_ap_id))?;
+
+    let events = calendar_events(&app_state, &calendar.actor_ap_id, Utc::now()).await?;
+    Ok(calendar_response(
+        &request,
+        &events,
+        last_modified(&events),
+        false,
+    ))
+}
+
+/// All upcoming events on the instance.
+pub async fn handle_instance_ics(
+    request: HttpRequest,
+    app_state: Data<MyStateHandle>,
+) -> Result<HttpResponse, ApEventsError> {
+    let events = upcoming_events(&app_state, Utc::now()).await?;
+    Ok(calendar_response(
+        &request,
+        &events,
+        last_modified(&events),
+        false,
+    ))
+}
+
+/// The events that the owner of a calendar token is going to.
+pub async fn handle_attendee_ics(
+    request: HttpRequest,
+    token: Path<String>,
+    app_state: Data<MyStateHandle>,
+) -> Result<HttpResponse, ApEventsError> {
+    let attendee_ap_id = calendar_token_attendee(&app_state, &token)
+        .await?
+        .ok_or_else(|| ApEventsError::ObjectNotFound("calendar".to_string()))?;
+
+    let events = attending_events(&app_state, &attendee_ap_id).await?;
+    // Leaving an event removes it from the feed without touching the event itself.
+    let updated_at =
+        last_modified(&events).max(latest_rsvp_update(&app_state, &attendee_ap_id).await?);
+
+    let mut response = calendar_response(&request, &events, updated_at, true);
+    response
+        .headers_mut()
+        .insert(header::CACHE_CONTROL, HeaderValue::from_static("private"));
+    Ok(response)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use actix_web::test::TestRequest;
+    use std::time::Duration;
+
+    #[test]
+    fn webcal_urls() {
+        assert_eq!(
+            webcal_url("https://events.thegem.city/events.ics"),
+            "webcal://events.thegem.city/events.ics"
+        );
+        assert_eq!(
+            webcal_url("http://localhost:8080/events.ics"),
+            "webcal://localhost:8080/events.ics"
+        );
+    }
+
+    #[test]
+    fn conditional_requests() {
+        let etag = EntityTag::new_strong("abcd".to_string());
+        let modified = SystemTime::UNIX_EPOCH + Duration::from_secs(1_666_276_200);
+
+        let request = TestRequest::default().to_http_request();
+        assert!(!is_not_modified(&request, &etag, Some(modified)));
+
+        let request = TestRequest::default()
+            .insert_header((header::IF_NONE_MATCH, "\"zzzz\", W/\"abcd\""))
+            .to_http_request();
+        assert!(is_not_modified(&request, &etag, Some(modified)));
+
+        let request = TestRequest::default()
+            .insert_header((header::IF_NONE_MATCH, "\"zzzz\""))
+            .insert_header((header::IF_MODIFIED_SINCE, HttpDate::from(modified)))
+            .to_http_request();
+        assert!(!is_not_modified(&request, &etag, Some(modified)));
+
+        let request = TestRequest::default()
+            .insert_header((header::IF_MODIFIED_SINCE, HttpDate::from(modified)))
+            .to_http_request();
+        assert!(is_not_modified(&request, &etag, Some(modified)));
+        assert!(!is_not_modified(
+            &request,
+            &etag,
+            Some(modified + Duration::from_secs(1))
+        ));
+        assert!(!is_not_modified(&request, &etag, None));
+    }
+}
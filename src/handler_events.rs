@@ -1,10 +1,29 @@
 use actix_web::{
-    web::{Data, Path},
+    http::header,
+    web::{Data, Path, Query},
     HttpResponse, Result,
 };
 use askama_actix::{Template, TemplateToResponse};
+use chrono::{DateTime, Utc};
+use chrono_tz::{OffsetName, Tz};
+use serde::{Deserialize, Serialize};
 
-use crate::{objects::actor::EventActor, state::MyStateHandle};
+use crate::{
+    collections::CollectionSource,
+    handler_calendar::webcal_url,
+    objects::actor::EventActor,
+    recurrence::Occurrence,
+    search::{search_events, EventSearch, SearchMode},
+    state::MyStateHandle,
+    storage_actor::get_actors,
+    storage_calendars::{all_calendars, calendar_events, get_calendar, Calendar},
+    storage_events::{get_event, Event},
+    storage_objects::get_generated_object,
+    storage_places::{all_places, get_place, place_events, Place},
+    storage_rsvps::{
+        attendees_hidden, count_rsvps, count_seats, going_with_privacy, RsvpStatus, ALL_OCCURRENCES,
+    },
+};
 
 use crate::error::ApEventsError;
 
@@ -15,58 +34,551 @@ struct EventTemplate<'a> {
     ap_id: &'a str,
     actor_ref: &'a str,
     summary: &'a str,
+    status: &'a str,
     when: &'a str,
+    viewer_when: Option<String>,
     location: &'a str,
+    place_url: Option<String>,
+    /// The calendar the event belongs to: its url and name.
+    calendar: Option<EventElementTemplate>,
+    attendance: &'a str,
+    online_url: Option<&'a str>,
+    online_url_private: bool,
     follower_count: u32,
     attendee_count: u32,
+    attendees: Vec<AttendeeElementTemplate>,
     hidden_attendee_count: u32,
+    capacity: Option<i32>,
+    waitlist_count: u32,
+    occurrences: Vec<OccurrenceElementTemplate>,
 }
 
+/// An upcoming date of a recurring event: its page url and when it is.
+struct OccurrenceElementTemplate(String, String);
+
 struct EventElementTemplate(String, String);
 
+/// An attendee that is shown on an event page: their url and actor reference.
+struct AttendeeElementTemplate(String, String);
+
 #[derive(Template)]
 #[template(path = "index.html")]
 struct HomeTemplate<'a> {
     display_name: &'a str,
+    calendar_url: &'a str,
     events: Vec<EventElementTemplate>,
+    places: Vec<EventElementTemplate>,
+    calendars: Vec<EventElementTemplate>,
+    q: String,
+    from: String,
+    to: String,
+    place: String,
+    tag: String,
+    mode: &'a str,
+    /// The query strings of the pages around this one.
+    previous_page: Option<String>,
+    next_page: Option<String>,
 }
 
-pub async fn handle_home(app_state: Data<MyStateHandle>) -> Result<HttpResponse, ApEventsError> {
-    let found_actors: Vec<EventActor> = sqlx::query_as("SELECT * FROM actors")
-        .fetch_all(&app_state.pool)
-        .await?;
+#[derive(Template)]
+#[template(path = "calendar.html")]
+struct CalendarTemplate<'a> {
+    display_name: &'a str,
+    ap_id: &'a str,
+    actor_ref: &'a str,
+    summary: &'a str,
+    calendar_url: &'a str,
+    events: Vec<OccurrenceElementTemplate>,
+}
+
+#[derive(Template)]
+#[template(path = "place.html")]
+struct PlaceTemplate<'a> {
+    display_name: &'a str,
+    ap_id: &'a str,
+    actor_ref: &'a str,
+    address: Option<&'a str>,
+    map_url: Option<String>,
+    accessibility: Option<&'a str>,
+    events: Vec<OccurrenceElementTemplate>,
+}
+
+/// Lists upcoming local events that match the search in the query string, along with the
+/// instance's calendars and places.
+pub async fn handle_home(
+    query: Query<EventSearch>,
+    app_state: Data<MyStateHandle>,
+) -> Result<HttpResponse, ApEventsError> {
+    let filters = query.filters(Utc::now())?;
+    let page = search_events(&app_state, &filters).await?;
+    let events = page
+        .results
+        .iter()
+        .map(|result| {
+            EventElementTemplate(
+                result.event.actor_ap_id.clone(),
+                format!(
+                    "{}, {}",
+                    result.event.title,
+                    format_when(&result.event.tz(), &result.occurrence)
+                ),
+            )
+        })
+        .collect();
+
+    let places = all_places(&app_state).await?;
+    let calendars = all_calendars(&app_state).await?;
 
     Ok(HomeTemplate {
         display_name: "A cool event",
-        events: found_actors
-            .iter()
-            .map(|x| EventElementTemplate(x.ap_id.to_string(), x.actor_ref.clone()))
+        calendar_url: &webcal_url(&format!("{}/events.ics", app_state.external_base)),
+        events,
+        places: places
+            .into_iter()
+            .map(|place| EventElementTemplate(place.actor_ap_id, place.name))
             .collect(),
+        calendars: calendars
+            .into_iter()
+            .map(|calendar| EventElementTemplate(calendar.actor_ap_id, calendar.name))
+            .collect(),
+        q: filters.q.clone().unwrap_or_default(),
+        from: filters
+            .from_day
+            .map(|day| day.to_string())
+            .unwrap_or_default(),
+        to: filters
+            .to_day
+            .map(|day| day.to_string())
+            .unwrap_or_default(),
+        place: filters.place.clone().unwrap_or_default(),
+        tag: filters.tag.clone().unwrap_or_default(),
+        mode: match filters.mode {
+            Some(SearchMode::Online) => "online",
+            Some(SearchMode::InPerson) => "in-person",
+            None => "",
+        },
+        previous_page: (filters.page > 1).then(|| filters.page_query(filters.page - 1)),
+        next_page: page.has_more.then(|| filters.page_query(filters.page + 1)),
     }
     .to_response())
 }
 
+/// An event in the public event listing.
+#[derive(Serialize)]
+struct EventListing<'a> {
+    id: &'a str,
+    title: &'a str,
+    summary: &'a str,
+    starts_at: DateTime<Utc>,
+    ends_at: DateTime<Utc>,
+    timezone: &'a str,
+    status: &'a str,
+    attendance_mode: &'a str,
+    location: Option<&'a str>,
+    place: Option<&'a str>,
+    online_url: Option<&'a str>,
+    tags: &'a [String],
+}
+
+#[derive(Serialize)]
+struct EventListingPage<'a> {
+    events: Vec<EventListing<'a>>,
+    next: Option<String>,
+}
+
+/// The same listing as the home page, as json. Recurring events are listed with their first
+/// occurrence in the searched range.
+pub async fn handle_events_api(
+    query: Query<EventSearch>,
+    app_state: Data<MyStateHandle>,
+) -> Result<HttpResponse, ApEventsError> {
+    let filters = query.filters(Utc::now())?;
+    let page = search_events(&app_state, &filters).await?;
+    let events = page
+        .results
+        .iter()
+        .map(|result| EventListing {
+            id: &result.event.actor_ap_id,
+            title: &result.event.title,
+            summary: &result.event.summary,
+            starts_at: result.occurrence.starts_at,
+            ends_at: result.occurrence.ends_at,
+            timezone: &result.event.timezone,
+            status: result.event.status.as_str(),
+            attendance_mode: result.event.attendance_mode().as_str(),
+            location: result.event.location.as_deref(),
+            place: result.event.place_ap_id.as_deref(),
+            online_url: result.event.public_online_url(),
+            tags: &result.event.tags,
+        })
+        .collect();
+
+    Ok(HttpResponse::Ok().json(EventListingPage {
+        events,
+        next: page.has_more.then(|| {
+            format!(
+                "{}/api/v1/events?{}",
+                app_state.external_base,
+                filters.page_query(filters.page + 1)
+            )
+        }),
+    }))
+}
+
+const UPCOMING_OCCURRENCES: usize = 10;
+
+/// A time in the given timezone, with its UTC offset unless the timezone is UTC itself. Zones
+/// without an abbreviation only show the offset.
+pub fn format_time(tz: &Tz, value: &DateTime<Utc>) -> String {
+    let local = value.with_timezone(tz);
+    let numeric = local.offset().abbreviation().starts_with(['+', '-']);
+    match tz {
+        Tz::UTC => local.format("%-m/%-d/%Y at %-I:%M %p %Z").to_string(),
+        _ if numeric => local.format("%-m/%-d/%Y at %-I:%M %p (UTC%:z)").to_string(),
+        _ => local
+            .format("%-m/%-d/%Y at %-I:%M %p %Z (UTC%:z)")
+            .to_string(),
+    }
+}
+
+pub fn format_when(tz: &Tz, occurrence: &Occurrence) -> String {
+    format!(
+        "{} until {}",
+        format_time(tz, &occurrence.starts_at),
+        format_time(tz, &occurrence.ends_at)
+    )
+}
+
+#[derive(Deserialize)]
+pub struct EventPageQuery {
+    /// An IANA timezone to also show the event's times in, usually the viewer's own.
+    tz: Option<String>,
+}
+
+impl EventPageQuery {
+    fn viewer_tz(&self) -> Result<Option<Tz>, ApEventsError> {
+        self.tz
+            .as_deref()
+            .filter(|tz| !tz.is_empty())
+            .map(|tz| {
+                tz.parse::<Tz>()
+                    .map_err(|_| ApEventsError::InvalidQuery(format!("unknown timezone {tz}")))
+            })
+            .transpose()
+    }
+}
+
 pub async fn handle_event(
+    info: Path<String>,
+    query: Query<EventPageQuery>,
+    app_state: Data<MyStateHandle>,
+) -> Result<HttpResponse, ApEventsError> {
+    let actor_ap_id = format!("{}/actor/{}", app_state.external_base, info);
+    if let Some(place) = get_place(&app_state, &actor_ap_id).await? {
+        return place_page(&app_state, &place).await;
+    }
+    if let Some(calendar) = get_calendar(&app_state, &actor_ap_id).await? {
+        return calendar_page(&app_state, &calendar).await;
+    }
+    event_page(&app_state, &info, None, query.viewer_tz()?).await
+}
+
+pub async fn handle_place(
     info: Path<String>,
     app_state: Data<MyStateHandle>,
 ) -> Result<HttpResponse, ApEventsError> {
     let actor_ap_id = format!("{}/actor/{}", app_state.external_base, info);
+    let place = get_place(&app_state, &actor_ap_id)
+        .await?
+        .ok_or(ApEventsError::ObjectNotFound(actor_ap_id))?;
+    place_page(&app_state, &place).await
+}
 
+/// The page of a place, listing its upcoming events by their next date.
+async fn place_page(
+    app_state: &MyStateHandle,
+    place: &Place,
+) -> Result<HttpResponse, ApEventsError> {
     let found_actor: EventActor = sqlx::query_as("SELECT * FROM actors WHERE ap_id = $1")
-        .bind(actor_ap_id)
+        .bind(&place.actor_ap_id)
         .fetch_one(&app_state.pool)
         .await?;
+    let now = Utc::now();
+    let events = place_events(app_state, &place.actor_ap_id, now).await?;
+    let events = upcoming_elements(&events, now);
+
+    Ok(PlaceTemplate {
+        display_name: &place.name,
+        ap_id: &place.actor_ap_id,
+        actor_ref: &found_actor.actor_ref,
+        address: place.address.as_deref(),
+        map_url: match (place.latitude, place.longitude) {
+            (Some(latitude), Some(longitude)) => Some(format!(
+                "https://www.openstreetmap.org/?mlat={latitude}&mlon={longitude}#map=18/{latitude}/{longitude}"
+            )),
+            _ => None,
+        },
+        accessibility: place.accessibility.as_deref(),
+        events,
+    }
+    .to_response())
+}
+
+/// The page of a calendar, listing its upcoming events by their next date.
+async fn calendar_page(
+    app_state: &MyStateHandle,
+    calendar: &Calendar,
+) -> Result<HttpResponse, ApEventsError> {
+    let found_actor: EventActor = sqlx::query_as("SELECT * FROM actors WHERE ap_id = $1")
+        .bind(&calendar.actor_ap_id)
+        .fetch_one(&app_state.pool)
+        .await?;
+    let now = Utc::now();
+    let events = calendar_events(app_state, &calendar.actor_ap_id, now).await?;
+
+    Ok(CalendarTemplate {
+        display_name: &calendar.name,
+        ap_id: &calendar.actor_ap_id,
+        actor_ref: &found_actor.actor_ref,
+        summary: &calendar.summary,
+        calendar_url: &webcal_url(found_actor.calendar_feed_url()?.as_str()),
+        events: upcoming_elements(&events, now),
+    }
+    .to_response())
+}
+
+/// Links to events with their title and next date.
+fn upcoming_elements(events: &[Event], now: DateTime<Utc>) -> Vec<OccurrenceElementTemplate> {
+    events
+        .iter()
+        .filter_map(|event| {
+            let next = event.next_occurrences(now, 1).into_iter().next()?;
+            Some(OccurrenceElementTemplate(
+                event.actor_ap_id.clone(),
+                format!("{}, {}", event.title, format_when(&event.tz(), &next)),
+            ))
+        })
+        .collect()
+}
+
+pub async fn handle_event_occurrence(
+    info: Path<(String, String)>,
+    query: Query<EventPageQuery>,
+    app_state: Data<MyStateHandle>,
+) -> Result<HttpResponse, ApEventsError> {
+    let (name, occurrence_id) = info.into_inner();
+    event_page(&app_state, &name, Some(&occurrence_id), query.viewer_tz()?).await
+}
+
+/// The page of an event. Recurring events show their next occurrence, or the given one, and list
+/// the upcoming ones. Times are shown in the event's timezone, and also in the viewer's when it
+/// is given and differs.
+async fn event_page(
+    app_state: &MyStateHandle,
+    name: &str,
+    occurrence_id: Option<&str>,
+    viewer_tz: Option<Tz>,
+) -> Result<HttpResponse, ApEventsError> {
+    let actor_ap_id = format!("{}/actor/{}", app_state.external_base, name);
+
+    let found_actor: EventActor = sqlx::query_as("SELECT * FROM actors WHERE ap_id = $1")
+        .bind(&actor_ap_id)
+        .fetch_one(&app_state.pool)
+        .await?;
+    let event = get_event(app_state, &actor_ap_id)
+        .await?
+        .filter(|event| !event.is_draft())
+        .ok_or_else(|| ApEventsError::ObjectNotFound(actor_ap_id.clone()))?;
+
+    let upcoming = match event.rrule {
+        Some(_) => event.next_occurrences(Utc::now(), UPCOMING_OCCURRENCES),
+        None => vec![],
+    };
+    let occurrence = match occurrence_id {
+        Some(occurrence_id) => Some(
+            event
+                .occurrence(occurrence_id)
+                .ok_or_else(|| ApEventsError::ObjectNotFound(occurrence_id.to_string()))?,
+        ),
+        None => upcoming.first().copied(),
+    };
+    let shown = occurrence.unwrap_or(Occurrence {
+        starts_at: event.starts_at,
+        ends_at: event.ends_at,
+    });
+    let tz = event.tz();
+    let when = format_when(&tz, &shown);
+    let viewer_when = viewer_tz
+        .filter(|viewer_tz| *viewer_tz != tz)
+        .map(|viewer_tz| format_when(&viewer_tz, &shown));
+    let rsvp_occurrence = match occurrence_id {
+        Some(occurrence_id) => occurrence_id,
+        None => ALL_OCCURRENCES,
+    };
+
+    // Hidden attendees are counted but not listed, and nobody is listed when the organizer hid
+    // the attendee list. Attendees are counted the same way as seats on the waitlist.
+    let attendee_count = count_seats(
+        app_state,
+        &actor_ap_id,
+        rsvp_occurrence,
+        &event.seats_since(Utc::now()),
+    )
+    .await?;
+    let going = going_with_privacy(app_state, &actor_ap_id, rsvp_occurrence).await?;
+    let listed: Vec<String> = match attendees_hidden(app_state, &actor_ap_id).await? {
+        true => vec![],
+        false => going
+            .iter()
+            .filter(|(_, hidden)| !hidden)
+            .map(|(attendee_ap_id, _)| attendee_ap_id.clone())
+            .collect(),
+    };
+    let hidden_attendee_count = going.len() - listed.len();
+    let mut shown = get_actors(app_state, &listed).await?;
+    shown.sort_by(|a, b| a.actor_ref.cmp(&b.actor_ref));
+
+    // Counted the same way as the followers collection.
+    let follower_count = CollectionSource::followers()
+        .count(app_state, &actor_ap_id)
+        .await?;
+
+    let calendar = match &event.calendar_ap_id {
+        Some(calendar_ap_id) => get_calendar(app_state, calendar_ap_id)
+            .await?
+            .map(|calendar| EventElementTemplate(calendar.actor_ap_id, calendar.name)),
+        None => None,
+    };
 
     Ok(EventTemplate {
-        display_name: "A cool event",
+        display_name: &event.title,
         ap_id: &found_actor.ap_id.to_string(),
         actor_ref: &found_actor.actor_ref,
-        summary: "Welcome!",
-        when: "11/1/2022 at 7:00 PM eastern",
-        location: "The bar, 555 nowhere, dayton, oh 45419",
-        follower_count: 25,
-        attendee_count: 3,
-        hidden_attendee_count: 0,
+        summary: &event.summary,
+        status: event.status.as_str(),
+        when: &when,
+        viewer_when,
+        location: event.location.as_deref().unwrap_or(""),
+        place_url: event.place_ap_id.clone(),
+        calendar,
+        attendance: event.attendance_mode().as_str(),
+        online_url: event.public_online_url(),
+        online_url_private: event.online_url.is_some() && event.online_url_private,
+        follower_count,
+        attendee_count: attendee_count as u32,
+        attendees: shown
+            .into_iter()
+            .map(|attendee| AttendeeElementTemplate(attendee.ap_id.to_string(), attendee.actor_ref))
+            .collect(),
+        hidden_attendee_count: hidden_attendee_count as u32,
+        capacity: event.capacity,
+        waitlist_count: count_rsvps(
+            app_state,
+            &found_actor.ap_id.to_string(),
+            rsvp_occurrence,
+            RsvpStatus::Waitlisted,
+        )
+        .await? as u32,
+        occurrences: upcoming
+            .iter()
+            .map(|occurrence| {
+                Ok(OccurrenceElementTemplate(
+                    found_actor.occurrence_url(occurrence)?.to_string(),
+                    format_when(&tz, occurrence),
+                ))
+            })
+            .collect::<Result<_, ApEventsError>>()?,
     }
     .to_response())
 }
+
+pub async fn handle_object(
+    info: Path<String>,
+    app_state: Data<MyStateHandle>,
+) -> Result<HttpResponse, ApEventsError> {
+    let found_object = get_generated_object(&app_state, &info).await?;
+
+    Ok(HttpResponse::SeeOther()
+        .append_header((header::LOCATION, found_object.actor_ap_id))
+        .finish())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use chrono::TimeZone;
+
+    fn occurrence(starts_at: DateTime<Utc>, hours: i64) -> Occurrence {
+        Occurrence {
+            starts_at,
+            ends_at: starts_at + chrono::Duration::hours(hours),
+        }
+    }
+
+    #[test]
+    fn when_with_offsets() {
+        let new_york: Tz = "America/New_York".parse().unwrap();
+        let swing_night = occurrence(Utc.with_ymd_and_hms(2022, 11, 2, 0, 0, 0).unwrap(), 3);
+        assert_eq!(
+            format_when(&new_york, &swing_night),
+            "11/1/2022 at 8:00 PM EDT (UTC-04:00) until 11/1/2022 at 11:00 PM EDT (UTC-04:00)"
+        );
+        assert_eq!(
+            format_when(&Tz::UTC, &swing_night),
+            "11/2/2022 at 12:00 AM UTC until 11/2/2022 at 3:00 AM UTC"
+        );
+
+        let kathmandu: Tz = "Asia/Kathmandu".parse().unwrap();
+        assert_eq!(
+            format_when(&kathmandu, &swing_night),
+            "11/2/2022 at 5:45 AM (UTC+05:45) until 11/2/2022 at 8:45 AM (UTC+05:45)"
+        );
+    }
+
+    #[test]
+    fn when_across_dst() {
+        let new_york: Tz = "America/New_York".parse().unwrap();
+
+        // Clocks fall back at 2am, so 1:30am happens twice and the event is three hours long.
+        let fall_back = occurrence(Utc.with_ymd_and_hms(2022, 11, 6, 4, 30, 0).unwrap(), 3);
+        assert_eq!(
+            format_when(&new_york, &fall_back),
+            "11/6/2022 at 12:30 AM EDT (UTC-04:00) until 11/6/2022 at 2:30 AM EST (UTC-05:00)"
+        );
+        let repeated = occurrence(Utc.with_ymd_and_hms(2022, 11, 6, 6, 30, 0).unwrap(), 1);
+        assert_eq!(
+            format_when(&new_york, &repeated),
+            "11/6/2022 at 1:30 AM EST (UTC-05:00) until 11/6/2022 at 2:30 AM EST (UTC-05:00)"
+        );
+
+        // Clocks spring forward at 2am, so there is no 2:30am.
+        let spring_forward = occurrence(Utc.with_ymd_and_hms(2023, 3, 12, 6, 30, 0).unwrap(), 1);
+        assert_eq!(
+            format_when(&new_york, &spring_forward),
+            "3/12/2023 at 1:30 AM EST (UTC-05:00) until 3/12/2023 at 3:30 AM EDT (UTC-04:00)"
+        );
+
+        // Lord Howe Island moves its clocks by half an hour.
+        let lord_howe: Tz = "Australia/Lord_Howe".parse().unwrap();
+        let half_hour = occurrence(Utc.with_ymd_and_hms(2022, 10, 1, 15, 0, 0).unwrap(), 1);
+        assert_eq!(
+            format_when(&lord_howe, &half_hour),
+            "10/2/2022 at 1:30 AM (UTC+10:30) until 10/2/2022 at 3:00 AM (UTC+11:00)"
+        );
+    }
+
+    #[test]
+    fn viewer_timezones() {
+        let query = |tz: Option<&str>| EventPageQuery {
+            tz: tz.map(|tz| tz.to_string()),
+        };
+        assert_eq!(query(None).viewer_tz().unwrap(), None);
+        assert_eq!(query(Some("")).viewer_tz().unwrap(), None);
+        assert_eq!(
+            query(Some("Europe/Berlin")).viewer_tz().unwrap(),
+            Some(Tz::Europe__Berlin)
+        );
+        assert!(query(Some("Eastern")).viewer_tz().is_err());
+    }
+}
@@ -0,0 +1,839 @@
+use std::collections::BTreeMap;
+
+use chrono::{DateTime, Duration, NaiveDateTime, Offset, TimeZone, Utc};
+use chrono_tz::{OffsetComponents, OffsetName, Tz};
+
+use crate::storage_events::Event;
+
+/// Content lines longer than this many octets are folded, as required by RFC 5545 section 3.1.
+const MAX_LINE_OCTETS: usize = 75;
+
+/// An event along with the ids and links that the calendar entry is published with.
+pub struct CalendarEvent<'a> {
+    pub event: &'a Event,
+    pub uid: String,
+    pub url: String,
+    pub organizer: String,
+    pub organizer_name: String,
+    /// The link to join the event online, if whoever gets the calendar may see it.
+    pub online_url: Option<&'a str>,
+}
+
+impl<'a> CalendarEvent<'a> {
+    /// The calendar entry for a local event, identified by its Event object and organized by its
+    /// actor.
+    pub fn new(event: &'a Event) -> CalendarEvent<'a> {
+        CalendarEvent {
+            event,
+            uid: format!("{}/event", event.actor_ap_id),
+            url: event.actor_ap_id.clone(),
+            organizer: event.actor_ap_id.clone(),
+            organizer_name: event.title.clone(),
+            online_url: event.public_online_url(),
+        }
+    }
+
+    /// The calendar entry for someone going to the event, who may see its private online link.
+    pub fn for_attendee(event: &'a Event) -> CalendarEvent<'a> {
+        CalendarEvent {
+            online_url: event.online_url.as_deref(),
+            ..CalendarEvent::new(event)
+        }
+    }
+}
+
+/// Escapes a TEXT value (RFC 5545 section 3.3.11).
+pub fn escape_text(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for c in value.replace("\r\n", "\n").chars() {
+        match c {
+            '\\' => escaped.push_str("\\\\"),
+            ';' => escaped.push_str("\\;"),
+            ',' => escaped.push_str("\\,"),
+            '\n' | '\r' => escaped.push_str("\\n"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// Quotes a parameter value. Parameter values cannot contain double quotes at all, so they are
+/// dropped along with control characters.
+fn quote_param(value: &str) -> String {
+    let value: String = value
+        .chars()
+        .filter(|c| *c != '"' && !c.is_control())
+        .collect();
+    format!("\"{value}\"")
+}
+
+/// Folds a content line into lines of at most 75 octets, each ending with CRLF. Continuation
+/// lines start with a space and multi-byte characters are never split.
+pub fn fold_line(line: &str) -> String {
+    let mut folded = String::with_capacity(line.len() + line.len() / MAX_LINE_OCTETS * 3 + 2);
+    let mut octets = 0;
+    for c in line.chars() {
+        if octets + c.len_utf8() > MAX_LINE_OCTETS {
+            folded.push_str("\r\n ");
+            octets = 1;
+        }
+        folded.push(c);
+        octets += c.len_utf8();
+    }
+    folded.push_str("\r\n");
+    folded
+}
+
+fn format_utc(value: &DateTime<Utc>) -> String {
+    value.format("%Y%m%dT%H%M%SZ").to_string()
+}
+
+fn format_local(value: &NaiveDateTime) -> String {
+    value.format("%Y%m%dT%H%M%S").to_string()
+}
+
+fn format_offset(seconds: i32) -> String {
+    let sign = if seconds < 0 { '-' } else { '+' };
+    let seconds = seconds.abs();
+    let (hours, minutes, seconds) = (seconds / 3600, seconds / 60 % 60, seconds % 60);
+    match seconds {
+        0 => format!("{sign}{hours:02}{minutes:02}"),
+        _ => format!("{sign}{hours:02}{minutes:02}{seconds:02}"),
+    }
+}
+
+fn utc_offset(tz: &Tz, at: &DateTime<Utc>) -> i32 {
+    tz.offset_from_utc_datetime(&at.naive_utc())
+        .fix()
+        .local_minus_utc()
+}
+
+/// The instants between `from` and `to` at which the timezone's offset changes.
+fn transitions(tz: &Tz, from: DateTime<Utc>, to: DateTime<Utc>) -> Vec<DateTime<Utc>> {
+    let mut found = vec![];
+    let mut at = from;
+    while at < to {
+        let next = at + Duration::days(1);
+        if utc_offset(tz, &at) != utc_offset(tz, &next) {
+            // The offset changes somewhere in this day, so find the first second with the new
+            // offset.
+            let (mut low, mut high) = (at, next);
+            while high - low > Duration::seconds(1) {
+                let middle = low + (high - low) / 2;
+                if utc_offset(tz, &middle) == utc_offset(tz, &low) {
+                    low = middle;
+                } else {
+                    high = middle;
+                }
+            }
+            found.push(high);
+        }
+        at = next;
+    }
+    found
+}
+
+#[derive(Default)]
+struct Writer {
+    out: String,
+}
+
+impl Writer {
+    fn property(&mut self, name: &str, value: &str) {
+        self.out.push_str(&fold_line(&format!("{name}:{value}")));
+    }
+
+    fn text(&mut self, name: &str, value: &str) {
+        self.property(name, &escape_text(value));
+    }
+
+    /// Writes a VTIMEZONE with an observance for every offset change between the two instants,
+    /// starting a year early so that the observance in effect at `from` is included.
+    fn timezone(&mut self, tz: &Tz, from: DateTime<Utc>, to: DateTime<Utc>) {
+        self.property("BEGIN", "VTIMEZONE");
+        self.property("TZID", tz.name());
+
+        let changes = transitions(tz, from - Duration::days(366), to);
+        if changes.is_empty() {
+            let offset = tz.offset_from_utc_datetime(&from.naive_utc());
+            let offset_seconds = offset.fix().local_minus_utc();
+            self.property("BEGIN", "STANDARD");
+            self.property("DTSTART", "19700101T000000");
+            self.property("TZOFFSETFROM", &format_offset(offset_seconds));
+            self.property("TZOFFSETTO", &format_offset(offset_seconds));
+            self.text("TZNAME", offset.abbreviation());
+            self.property("END", "STANDARD");
+        }
+
+        for change in changes {
+            let before = utc_offset(tz, &(change - Duration::seconds(1)));
+            let offset = tz.offset_from_utc_datetime(&change.naive_utc());
+            let kind = match offset.dst_offset().is_zero() {
+                true => "STANDARD",
+                false => "DAYLIGHT",
+            };
+            // DTSTART is the local time in the observance that is ending.
+            let starts = change.naive_utc() + Duration::seconds(before.into());
+
+            self.property("BEGIN", kind);
+            self.property("DTSTART", &format_local(&starts));
+            self.property("TZOFFSETFROM", &format_offset(before));
+            self.property("TZOFFSETTO", &format_offset(offset.fix().local_minus_utc()));
+            self.text("TZNAME", offset.abbreviation());
+            self.property("END", kind);
+        }
+
+        self.property("END", "VTIMEZONE");
+    }
+
+    fn date_time(&mut self, name: &str, tz: &Tz, value: &DateTime<Utc>) {
+        if *tz == Tz::UTC {
+            self.property(name, &format_utc(value));
+        } else {
+            let local = value.with_timezone(tz).naive_local();
+            self.property(&format!("{name};TZID={}", tz.name()), &format_local(&local));
+        }
+    }
+
+    fn event(&mut self, entry: &CalendarEvent) {
+        let event = entry.event;
+        let tz = event.tz();
+
+        self.property("BEGIN", "VEVENT");
+        self.text("UID", &entry.uid);
+        self.property("DTSTAMP", &format_utc(&event.updated_at.and_utc()));
+        self.date_time("DTSTART", &tz, &event.starts_at);
+        self.date_time("DTEND", &tz, &event.ends_at);
+        if let Some(rrule) = &event.rrule {
+            self.property("RRULE", rrule);
+            for exdate in &event.exdates {
+                self.date_time("EXDATE", &tz, exdate);
+            }
+        }
+        self.property("SEQUENCE", &event.sequence.to_string());
+        self.property("STATUS", event.status.ical_status());
+        self.text("SUMMARY", &event.title);
+        if !event.summary.is_empty() {
+            self.text("DESCRIPTION", &event.summary);
+        }
+        if let Some(location) = &event.location {
+            self.text("LOCATION", location);
+        }
+        if let Some(online_url) = entry.online_url {
+            // RFC 7986 section 5.11.
+            self.property("CONFERENCE;VALUE=URI", online_url);
+        }
+        self.property("URL", &entry.url);
+        self.property(
+            &format!("ORGANIZER;CN={}", quote_param(&entry.organizer_name)),
+            &entry.organizer,
+        );
+        self.property("END", "VEVENT");
+    }
+}
+
+/// Writes an iCalendar object (RFC 5545) containing the given events and the timezones they use.
+pub fn write_calendar(events: &[CalendarEvent]) -> String {
+    let mut writer = Writer::default();
+    writer.property("BEGIN", "VCALENDAR");
+    writer.property("VERSION", "2.0");
+    writer.property("PRODID", "-//apevents//apevents//EN");
+    writer.property("CALSCALE", "GREGORIAN");
+    writer.property("METHOD", "PUBLISH");
+
+    let mut timezones: BTreeMap<&str, (Tz, DateTime<Utc>, DateTime<Utc>)> = BTreeMap::new();
+    for entry in events {
+        let tz = entry.event.tz();
+        if tz == Tz::UTC {
+            continue;
+        }
+        // Observances of recurring events are written for up to a year of occurrences.
+        let horizon = entry.event.starts_at + Duration::days(366);
+        let ends_at = entry
+            .event
+            .occurrences()
+            .take_while(|occurrence| occurrence.starts_at <= horizon)
+            .map(|occurrence| occurrence.ends_at)
+            .last()
+            .unwrap_or(entry.event.ends_at);
+        let range = timezones
+            .entry(tz.name())
+            .or_insert((tz, entry.event.starts_at, ends_at));
+        range.1 = range.1.min(entry.event.starts_at);
+        range.2 = range.2.max(ends_at);
+    }
+    for (tz, from, to) in timezones.values() {
+        writer.timezone(tz, *from, *to);
+    }
+
+    for entry in events {
+        writer.event(entry);
+    }
+
+    writer.property("END", "VCALENDAR");
+    writer.out
+}
+
+/// A property of a parsed component, with its parameters and raw value.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ContentLine {
+    pub name: String,
+    pub params: Vec<(String, String)>,
+    pub value: String,
+}
+
+impl ContentLine {
+    pub fn param(&self, name: &str) -> Option<&str> {
+        self.params
+            .iter()
+            .find(|(param, _)| param == name)
+            .map(|(_, value)| value.as_str())
+    }
+}
+
+/// An event read from an iCalendar file, before it is checked against the event rules.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ImportedEvent {
+    pub uid: String,
+    pub title: String,
+    pub summary: String,
+    pub location: Option<String>,
+    pub starts_at: DateTime<Utc>,
+    pub ends_at: DateTime<Utc>,
+    pub timezone: String,
+    pub rrule: Option<String>,
+    pub exdates: Vec<DateTime<Utc>>,
+    pub cancelled: bool,
+}
+
+/// Why a single VEVENT could not be read. The uid is missing when the event has none or the file
+/// could not be read at all.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ImportError {
+    pub uid: Option<String>,
+    pub message: String,
+}
+
+/// Reverses `escape_text`.
+pub fn unescape_text(value: &str) -> String {
+    let mut unescaped = String::with_capacity(value.len());
+    let mut chars = value.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            unescaped.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('n') | Some('N') => unescaped.push('\n'),
+            Some(escaped) => unescaped.push(escaped),
+            None => unescaped.push('\\'),
+        }
+    }
+    unescaped
+}
+
+/// Joins folded lines back together. Both CRLF and bare LF line endings are accepted, since
+/// exports are often re-saved by text editors.
+fn unfold(input: &str) -> Vec<String> {
+    let mut lines: Vec<String> = vec![];
+    for raw in input.split('\n') {
+        let raw = raw.strip_suffix('\r').unwrap_or(raw);
+        match (raw.strip_prefix([' ', '\t']), lines.last_mut()) {
+            (Some(continued), Some(line)) => line.push_str(continued),
+            _ if raw.is_empty() => {}
+            _ => lines.push(raw.to_string()),
+        }
+    }
+    lines
+}
+
+/// Splits on a separator that is not inside a quoted parameter value.
+fn split_unquoted(value: &str, separator: char) -> Vec<&str> {
+    let mut parts = vec![];
+    let (mut start, mut quoted) = (0, false);
+    for (index, c) in value.char_indices() {
+        if c == '"' {
+            quoted = !quoted;
+        } else if c == separator && !quoted {
+            parts.push(&value[start..index]);
+            start = index + c.len_utf8();
+        }
+    }
+    parts.push(&value[start..]);
+    parts
+}
+
+fn parse_content_line(line: &str) -> Option<ContentLine> {
+    let mut quoted = false;
+    let split = line.char_indices().find(|(_, c)| {
+        if *c == '"' {
+            quoted = !quoted;
+        }
+        *c == ':' && !quoted
+    })?;
+    let (head, value) = (&line[..split.0], &line[split.0 + 1..]);
+
+    let mut parts = split_unquoted(head, ';').into_iter();
+    let name = parts.next()?.trim().to_ascii_uppercase();
+    let params = parts
+        .filter_map(|param| param.split_once('='))
+        .map(|(key, value)| {
+            (
+                key.trim().to_ascii_uppercase(),
+                value.trim_matches('"').to_string(),
+            )
+        })
+        .collect();
+
+    Some(ContentLine {
+        name,
+        params,
+        value: value.to_string(),
+    })
+}
+
+/// Parses a DURATION value such as `PT1H30M` or `P1W`.
+fn parse_duration(value: &str) -> Option<Duration> {
+    let (negative, value) = match value.strip_prefix('-') {
+        Some(value) => (true, value),
+        None => (false, value.strip_prefix('+').unwrap_or(value)),
+    };
+    let value = value.strip_prefix('P')?;
+
+    let mut total = Duration::zero();
+    let mut number = String::new();
+    let mut in_time = false;
+    for c in value.chars() {
+        match c {
+            'T' => in_time = true,
+            '0'..='9' => number.push(c),
+            unit => {
+                let amount: i64 = number.parse().ok()?;
+                number.clear();
+                total += match (unit, in_time) {
+                    ('W', false) => Duration::weeks(amount),
+                    ('D', false) => Duration::days(amount),
+                    ('H', true) => Duration::hours(amount),
+                    ('M', true) => Duration::minutes(amount),
+                    ('S', true) => Duration::seconds(amount),
+                    _ => return None,
+                };
+            }
+        }
+    }
+    match number.is_empty() {
+        true => Some(if negative { -total } else { total }),
+        false => None,
+    }
+}
+
+/// A DATE or DATE-TIME value in UTC, the timezone it was given in, and whether it was a DATE.
+/// Floating times are read in the calendar's default timezone.
+fn parse_date_time(
+    line: &ContentLine,
+    default_tz: Tz,
+) -> Result<(DateTime<Utc>, Tz, bool), String> {
+    let tz = match line.param("TZID") {
+        Some(tzid) => tzid
+            .parse::<Tz>()
+            .map_err(|_| format!("{} has an unknown timezone {tzid}", line.name))?,
+        None => default_tz,
+    };
+    let invalid = || format!("{} has an invalid value {}", line.name, line.value);
+
+    if line.param("VALUE") == Some("DATE") || line.value.len() == 8 {
+        let date =
+            chrono::NaiveDate::parse_from_str(&line.value, "%Y%m%d").map_err(|_| invalid())?;
+        let midnight = date.and_hms_opt(0, 0, 0).ok_or_else(invalid)?;
+        let starts = tz
+            .from_local_datetime(&midnight)
+            .earliest()
+            .ok_or_else(invalid)?;
+        return Ok((starts.with_timezone(&Utc), tz, true));
+    }
+
+    if let Some(utc) = line.value.strip_suffix('Z') {
+        let value = NaiveDateTime::parse_from_str(utc, "%Y%m%dT%H%M%S").map_err(|_| invalid())?;
+        return Ok((value.and_utc(), Tz::UTC, false));
+    }
+
+    let value =
+        NaiveDateTime::parse_from_str(&line.value, "%Y%m%dT%H%M%S").map_err(|_| invalid())?;
+    let local = tz.from_local_datetime(&value).earliest().ok_or_else(|| {
+        format!(
+            "{} {} does not exist in {}",
+            line.name,
+            line.value,
+            tz.name()
+        )
+    })?;
+    Ok((local.with_timezone(&Utc), tz, false))
+}
+
+fn read_event(properties: &[ContentLine], default_tz: Tz) -> Result<ImportedEvent, ImportError> {
+    let find = |name: &str| properties.iter().find(|line| line.name == name);
+    let uid = find("UID").map(|line| unescape_text(&line.value));
+    let error = |message: String| ImportError {
+        uid: uid.clone(),
+        message,
+    };
+
+    let uid = uid
+        .clone()
+        .ok_or_else(|| error("UID is missing".to_string()))?;
+    if find("RECURRENCE-ID").is_some() {
+        return Err(error(
+            "changes to single occurrences are not supported".to_string(),
+        ));
+    }
+
+    let start = find("DTSTART").ok_or_else(|| error("DTSTART is missing".to_string()))?;
+    let (starts_at, tz, all_day) = parse_date_time(start, default_tz).map_err(error)?;
+    let ends_at = match (find("DTEND"), find("DURATION")) {
+        (Some(end), _) => parse_date_time(end, tz).map_err(error)?.0,
+        (None, Some(duration)) => {
+            starts_at
+                + parse_duration(&duration.value).ok_or_else(|| {
+                    error(format!("DURATION has an invalid value {}", duration.value))
+                })?
+        }
+        (None, None) if all_day => starts_at + Duration::days(1),
+        (None, None) => starts_at,
+    };
+
+    let mut exdates = vec![];
+    for line in properties.iter().filter(|line| line.name == "EXDATE") {
+        for value in split_unquoted(&line.value, ',') {
+            let exdate = ContentLine {
+                value: value.to_string(),
+                ..line.clone()
+            };
+            exdates.push(parse_date_time(&exdate, tz).map_err(error)?.0);
+        }
+    }
+
+    Ok(ImportedEvent {
+        uid,
+        title: find("SUMMARY")
+            .map(|line| unescape_text(&line.value))
+            .unwrap_or_default(),
+        summary: find("DESCRIPTION")
+            .map(|line| unescape_text(&line.value))
+            .unwrap_or_default(),
+        location: find("LOCATION")
+            .map(|line| unescape_text(&line.value))
+            .filter(|location| !location.is_empty()),
+        starts_at,
+        ends_at,
+        timezone: tz.name().to_string(),
+        rrule: find("RRULE").map(|line| line.value.clone()),
+        exdates,
+        cancelled: find("STATUS").map(|line| line.value.eq_ignore_ascii_case("CANCELLED"))
+            == Some(true),
+    })
+}
+
+/// Reads the events of an iCalendar object. Each VEVENT is read on its own so that one bad
+/// event does not prevent the others from being imported.
+pub fn parse_calendar(input: &str) -> Vec<Result<ImportedEvent, ImportError>> {
+    let lines: Vec<ContentLine> = unfold(input)
+        .iter()
+        .filter_map(|line| parse_content_line(line))
+        .collect();
+
+    if lines
+        .first()
+        .map(|line| (line.name.as_str(), line.value.as_str()))
+        != Some(("BEGIN", "VCALENDAR"))
+    {
+        return vec![Err(ImportError {
+            uid: None,
+            message: "not an iCalendar file".to_string(),
+        })];
+    }
+
+    let default_tz = lines
+        .iter()
+        .find(|line| line.name == "X-WR-TIMEZONE")
+        .and_then(|line| line.value.parse::<Tz>().ok())
+        .unwrap_or(Tz::UTC);
+
+    let mut events = vec![];
+    let mut components: Vec<&str> = vec![];
+    let mut properties: Vec<ContentLine> = vec![];
+    for line in &lines {
+        match line.name.as_str() {
+            "BEGIN" => components.push(&line.value),
+            "END" if components.pop() == Some("VEVENT") => {
+                events.push(read_event(&properties, default_tz));
+                properties.clear();
+            }
+            "END" => {}
+            // Only the event's own properties, not those of alarms inside it.
+            _ if components.last() == Some(&"VEVENT") => properties.push(line.clone()),
+            _ => {}
+        }
+    }
+    events
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::storage_events::EventStatus;
+    use std::{fs::File, io::Read, path::PathBuf};
+
+    fn load_golden(name: &str) -> String {
+        let mut path = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+        path.push("resources/test/ical");
+        path.push(name);
+
+        let mut file = File::open(path).expect("golden file exists");
+        let mut data = String::new();
+        file.read_to_string(&mut data)
+            .expect("golden file is readable");
+        data
+    }
+
+    fn calendar_event(event: &Event) -> CalendarEvent<'_> {
+        CalendarEvent {
+            event,
+            uid: "https://events.thegem.city/actor/readily-splendid-mule/event".to_string(),
+            url: "https://events.thegem.city/actor/readily-splendid-mule".to_string(),
+            organizer: "https://events.thegem.city/actor/readily-splendid-mule".to_string(),
+            organizer_name: "readily-splendid-mule".to_string(),
+            online_url: event.public_online_url(),
+        }
+    }
+
+    #[test]
+    fn escaping() {
+        assert_eq!(
+            escape_text("a, b; c\\d\r\ne\nf"),
+            "a\\, b\\; c\\\\d\\ne\\nf"
+        );
+    }
+
+    #[test]
+    fn folding() {
+        assert_eq!(fold_line("SUMMARY:short"), "SUMMARY:short\r\n");
+
+        let folded = fold_line(&format!("DESCRIPTION:{}", "é".repeat(80)));
+        for line in folded.split_terminator("\r\n") {
+            assert!(line.len() <= MAX_LINE_OCTETS, "{line}");
+        }
+        assert_eq!(
+            folded.replace("\r\n ", ""),
+            format!("DESCRIPTION:{}\r\n", "é".repeat(80))
+        );
+    }
+
+    #[test]
+    fn offsets() {
+        assert_eq!(format_offset(-5 * 3600), "-0500");
+        assert_eq!(format_offset(5 * 3600 + 45 * 60), "+0545");
+        assert_eq!(format_offset(0), "+0000");
+    }
+
+    #[test]
+    fn golden_calendars() {
+        let new_york = Event::example();
+        assert_eq!(
+            write_calendar(&[calendar_event(&new_york)]),
+            load_golden("new-york.ics")
+        );
+
+        let utc = Event {
+            timezone: "UTC".to_string(),
+            location: None,
+            ..Event::example()
+        };
+        assert_eq!(
+            write_calendar(&[calendar_event(&utc)]),
+            load_golden("utc.ics")
+        );
+
+        let escaped = Event {
+            title: "Potluck; bring a dish, or two".to_string(),
+            summary: "Ünïcödé descriptions wrap at 75 octets without splitting characters: ☕☕☕☕☕☕☕☕☕☕☕☕☕☕☕☕☕☕☕☕.\nSecond line with a backslash \\ too.".to_string(),
+            timezone: "Asia/Kathmandu".to_string(),
+            ..Event::example()
+        };
+        assert_eq!(
+            write_calendar(&[calendar_event(&escaped)]),
+            load_golden("escaped.ics")
+        );
+    }
+
+    #[test]
+    fn online_links() {
+        let online = Event {
+            online_url: Some("https://meet.example.com/swing-night".to_string()),
+            ..Event::example()
+        };
+        let conference = "CONFERENCE;VALUE=URI:https://meet.example.com/swing-night\r\n";
+        assert!(write_calendar(&[calendar_event(&online)]).contains(conference));
+
+        let private = Event {
+            online_url_private: true,
+            ..online
+        };
+        assert!(!write_calendar(&[calendar_event(&private)]).contains("CONFERENCE"));
+        assert!(write_calendar(&[CalendarEvent::for_attendee(&private)]).contains(conference));
+    }
+
+    #[test]
+    fn golden_recurring() {
+        let weekly = Event {
+            rrule: Some("FREQ=WEEKLY;BYDAY=TU;COUNT=10".to_string()),
+            exdates: vec![Utc.with_ymd_and_hms(2022, 11, 16, 1, 0, 0).unwrap()],
+            ..Event::example()
+        };
+        assert_eq!(
+            write_calendar(&[calendar_event(&weekly)]),
+            load_golden("recurring.ics")
+        );
+    }
+
+    #[test]
+    fn golden_feed() {
+        let new_york = Event::example();
+        let cancelled = Event {
+            actor_ap_id: "https://events.thegem.city/actor/quietly-patient-heron".to_string(),
+            title: "Board games".to_string(),
+            summary: "".to_string(),
+            starts_at: Utc.with_ymd_and_hms(2023, 1, 14, 18, 0, 0).unwrap(),
+            ends_at: Utc.with_ymd_and_hms(2023, 1, 14, 22, 0, 0).unwrap(),
+            timezone: "Europe/Berlin".to_string(),
+            location: None,
+            status: EventStatus::Cancelled,
+            sequence: 5,
+            ..Event::example()
+        };
+        assert_eq!(
+            write_calendar(&[
+                CalendarEvent::new(&new_york),
+                CalendarEvent::new(&cancelled)
+            ]),
+            load_golden("feed.ics")
+        );
+    }
+
+    #[test]
+    fn unescaping() {
+        assert_eq!(
+            unescape_text("a\\, b\\; c\\\\d\\ne\\Nf"),
+            "a, b; c\\d\ne\nf"
+        );
+        for value in ["Potluck; bring a dish, or two", "back\\slash\nnewline"] {
+            assert_eq!(unescape_text(&escape_text(value)), value);
+        }
+    }
+
+    #[test]
+    fn durations() {
+        assert_eq!(parse_duration("PT2H30M"), Some(Duration::minutes(150)));
+        assert_eq!(parse_duration("P1W"), Some(Duration::weeks(1)));
+        assert_eq!(parse_duration("P1DT12H"), Some(Duration::hours(36)));
+        assert_eq!(parse_duration("-PT15M"), Some(Duration::minutes(-15)));
+        assert_eq!(parse_duration("PT"), Some(Duration::zero()));
+        assert_eq!(parse_duration("P1H"), None);
+        assert_eq!(parse_duration("PT5"), None);
+        assert_eq!(parse_duration("1H"), None);
+    }
+
+    #[test]
+    fn import_google() {
+        let events = parse_calendar(&load_golden("import-google.ics"));
+        assert_eq!(events.len(), 4);
+
+        assert_eq!(
+            events[0],
+            Ok(ImportedEvent {
+                uid: "7kukuqrfedlm2f9t0vq4fbmbbq@google.com".to_string(),
+                title: "Swing night with a title long enough that Google folds the line so it continues here".to_string(),
+                summary: "Dancing at the bar.\nBeginners welcome, no partner needed.".to_string(),
+                location: Some("The bar, 555 nowhere, dayton, oh 45419".to_string()),
+                starts_at: Utc.with_ymd_and_hms(2022, 11, 2, 0, 0, 0).unwrap(),
+                ends_at: Utc.with_ymd_and_hms(2022, 11, 2, 3, 0, 0).unwrap(),
+                timezone: "America/New_York".to_string(),
+                rrule: None,
+                exdates: vec![],
+                cancelled: false,
+            })
+        );
+
+        // All-day events are read in the calendar's timezone and last one day.
+        let all_day = events[1].as_ref().unwrap();
+        assert_eq!(
+            all_day.starts_at,
+            Utc.with_ymd_and_hms(2022, 11, 5, 4, 0, 0).unwrap()
+        );
+        assert_eq!(
+            all_day.ends_at,
+            Utc.with_ymd_and_hms(2022, 11, 6, 4, 0, 0).unwrap()
+        );
+        assert_eq!(all_day.timezone, "America/New_York");
+        assert_eq!(all_day.summary, "");
+        assert_eq!(all_day.location, None);
+
+        let cancelled = events[2].as_ref().unwrap();
+        assert!(cancelled.cancelled);
+        assert_eq!(cancelled.timezone, "UTC");
+        assert_eq!(
+            cancelled.ends_at,
+            Utc.with_ymd_and_hms(2022, 11, 13, 1, 30, 0).unwrap()
+        );
+
+        assert_eq!(
+            events[3],
+            Err(ImportError {
+                uid: Some("no-start@google.com".to_string()),
+                message: "DTSTART is missing".to_string(),
+            })
+        );
+    }
+
+    #[test]
+    fn import_nextcloud() {
+        let events = parse_calendar(&load_golden("import-nextcloud.ics"));
+        assert_eq!(events.len(), 3);
+
+        let board_games = events[0].as_ref().unwrap();
+        assert_eq!(board_games.uid, "board-games-2023");
+        assert_eq!(board_games.timezone, "Europe/Berlin");
+        assert_eq!(
+            board_games.starts_at,
+            Utc.with_ymd_and_hms(2023, 1, 14, 18, 0, 0).unwrap()
+        );
+        assert_eq!(
+            board_games.ends_at,
+            Utc.with_ymd_and_hms(2023, 1, 14, 22, 0, 0).unwrap()
+        );
+        assert_eq!(board_games.rrule.as_deref(), Some("FREQ=WEEKLY;BYDAY=SA"));
+        assert_eq!(
+            board_games.exdates,
+            vec![
+                Utc.with_ymd_and_hms(2023, 1, 21, 18, 0, 0).unwrap(),
+                Utc.with_ymd_and_hms(2023, 1, 28, 18, 0, 0).unwrap()
+            ]
+        );
+
+        assert_eq!(
+            events[1].as_ref().unwrap_err().uid.as_deref(),
+            Some("moved-occurrence")
+        );
+        assert_eq!(
+            events[2].as_ref().unwrap_err().message,
+            "DTSTART has an unknown timezone Mars/Olympus_Mons"
+        );
+    }
+
+    #[test]
+    fn import_without_calendar() {
+        let events = parse_calendar("not a calendar");
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0].as_ref().unwrap_err().uid, None);
+    }
+}
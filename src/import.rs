@@ -0,0 +1,170 @@
+use chrono::{DateTime, Utc};
+use log::{info, warn};
+use serde::Serialize;
+
+use crate::{
+    error::ApEventsError,
+    ical::{parse_calendar, ImportedEvent},
+    lifecycle::transition_event,
+    objects::actor::EventActor,
+    state::MyStateHandle,
+    storage_actor::create_local_actor,
+    storage_events::{
+        get_event, imported_event_actor, set_import_uid, upsert_event, Event, EventStatus,
+    },
+    storage_import_feeds::{import_feeds, mark_import_feed_synced},
+    updates::event_edited,
+};
+
+#[derive(Serialize, Debug)]
+pub struct ImportResult {
+    pub uid: Option<String>,
+    pub actor: Option<String>,
+    pub status: &'static str,
+    pub error: Option<String>,
+}
+
+impl ImportResult {
+    fn failed(uid: Option<String>, error: String) -> ImportResult {
+        ImportResult {
+            uid,
+            actor: None,
+            status: "failed",
+            error: Some(error),
+        }
+    }
+}
+
+#[derive(Serialize, Debug, Default)]
+pub struct ImportReport {
+    pub created: usize,
+    pub updated: usize,
+    pub failed: usize,
+    pub events: Vec<ImportResult>,
+}
+
+/// Creates or updates one event actor per VEVENT in the calendar. Events are matched to their
+/// actors by UID, so importing the same calendar again updates the same events.
+pub async fn import_calendar(app_state: &MyStateHandle, input: &str) -> ImportReport {
+    let now = Utc::now();
+    let mut report = ImportReport::default();
+
+    for parsed in parse_calendar(input) {
+        let result = match parsed {
+            Err(err) => ImportResult::failed(err.uid, err.message),
+            Ok(imported) => import_event(app_state, &imported, now)
+                .await
+                .unwrap_or_else(|err| ImportResult::failed(Some(imported.uid), err.to_string())),
+        };
+        match result.status {
+            "created" => report.created += 1,
+            "updated" => report.updated += 1,
+            _ => report.failed += 1,
+        }
+        report.events.push(result);
+    }
+    report
+}
+
+async fn import_event(
+    app_state: &MyStateHandle,
+    imported: &ImportedEvent,
+    now: DateTime<Utc>,
+) -> Result<ImportResult, ApEventsError> {
+    let existing_actor = imported_event_actor(app_state, &imported.uid).await?;
+    let previous = match &existing_actor {
+        Some(actor_ap_id) => get_event(app_state, actor_ap_id).await?,
+        None => None,
+    };
+
+    // Settings that calendars have no notion of are kept from the previous import.
+    let mut event = Event {
+        actor_ap_id: existing_actor.clone().unwrap_or_default(),
+        title: imported.title.clone(),
+        summary: imported.summary.clone(),
+        starts_at: imported.starts_at,
+        ends_at: imported.ends_at,
+        timezone: imported.timezone.clone(),
+        location: imported.location.clone(),
+        join_mode: previous
+            .as_ref()
+            .map(|previous| previous.join_mode.clone())
+            .unwrap_or_else(|| "free".to_string()),
+        capacity: previous.as_ref().and_then(|previous| previous.capacity),
+        rrule: imported.rrule.clone(),
+        exdates: imported.exdates.clone(),
+        status: EventStatus::Published,
+        publish_at: None,
+        owner_ap_id: None,
+        tags: vec![],
+        online_url: None,
+        online_url_private: false,
+        place_ap_id: None,
+        calendar_ap_id: None,
+        sequence: 0,
+        updated_at: now.naive_utc(),
+    };
+
+    // Checked before an actor is created so that invalid events do not leave actors behind.
+    event.validate(now)?;
+
+    let found_actor: EventActor = match &existing_actor {
+        Some(actor_ap_id) => {
+            sqlx::query_as("SELECT * FROM actors WHERE ap_id = $1")
+                .bind(actor_ap_id)
+                .fetch_one(&app_state.pool)
+                .await?
+        }
+        None => create_local_actor(app_state).await?.1,
+    };
+    event.actor_ap_id = found_actor.ap_id.to_string();
+    let stored = upsert_event(app_state, &event).await?;
+    if let Some(previous) = &previous {
+        event_edited(app_state, previous, &stored).await?;
+    }
+    if existing_actor.is_none() {
+        set_import_uid(app_state, &event.actor_ap_id, &imported.uid).await?;
+    }
+    if imported.cancelled && stored.status.can_become(EventStatus::Cancelled) {
+        transition_event(app_state, &found_actor, &stored, EventStatus::Cancelled).await?;
+    }
+
+    Ok(ImportResult {
+        uid: Some(imported.uid.clone()),
+        actor: Some(event.actor_ap_id),
+        status: match existing_actor {
+            Some(_) => "updated",
+            None => "created",
+        },
+        error: None,
+    })
+}
+
+/// Downloads a calendar feed. `webcal://` urls are fetched over https.
+pub async fn fetch_calendar(url: &str) -> Result<String, ApEventsError> {
+    let url = match url.strip_prefix("webcal://") {
+        Some(rest) => format!("https://{rest}"),
+        None => url.to_string(),
+    };
+    Ok(reqwest::get(url).await?.error_for_status()?.text().await?)
+}
+
+/// Imports every registered feed again.
+pub async fn sync_import_feeds(app_state: MyStateHandle) -> Result<(), ApEventsError> {
+    for url in import_feeds(&app_state).await? {
+        let input = match fetch_calendar(&url).await {
+            Ok(input) => input,
+            Err(err) => {
+                warn!("cannot fetch import feed {}: {}", url, err);
+                continue;
+            }
+        };
+        let report = import_calendar(&app_state, &input).await;
+        info!(
+            "synced import feed {}: {} created, {} updated, {} failed",
+            url, report.created, report.updated, report.failed
+        );
+        mark_import_feed_synced(&app_state, &url).await?;
+    }
+    Ok(())
+}
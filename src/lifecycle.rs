@@ -0,0 +1,192 @@
+use activitypub_federation::{core::object_id::ObjectId, traits::Actor};
+use askama::{Html, MarkupDisplay};
+use chrono::{Duration, Utc};
+use log::{info, warn};
+use url::Url;
+
+use crate::{
+    activities::{
+        create::{Create, CreateObject},
+        update::{Update, UpdateObject},
+    },
+    ap::{
+        event::event_context,
+        ids::{generate_object_id, KindType},
+    },
+    calendars::announce_in_calendar,
+    error::ApEventsError,
+    messages::{send_note, send_note_to_each},
+    objects::{actor::EventActor, event::event_into_apub},
+    places::announce_at_place,
+    state::MyStateHandle,
+    storage_actor::get_actors,
+    storage_admin_tokens::issue_admin_token,
+    storage_events::{due_drafts, ended_events, get_event, set_event_status, Event, EventStatus},
+    storage_follows::followers,
+    storage_organizers::Role,
+    storage_rsvps::{attendees, count_rsvps, listed_attendees, RsvpStatus, ALL_OCCURRENCES},
+};
+
+/// A link to the admin page of an event for an organizer that works for the next 24 hours.
+pub async fn admin_link(
+    app_state: &MyStateHandle,
+    actor: &EventActor,
+    role: Role,
+) -> Result<String, ApEventsError> {
+    let token = issue_admin_token(
+        app_state,
+        actor.ap_id.inner().as_str(),
+        role,
+        Utc::now() + Duration::hours(24),
+    )
+    .await?;
+    Ok(format!("{}/admin?token={}", actor.ap_id.inner(), token))
+}
+
+/// Moves an event to another state and tells its followers and attendees. Published drafts are
+/// sent as a `Create` of the Event object and every other change as an `Update`, and attendees are
+/// sent a notice when the event is postponed or cancelled. The changed event is returned.
+pub async fn transition_event(
+    app_state: &MyStateHandle,
+    actor: &EventActor,
+    event: &Event,
+    to: EventStatus,
+) -> Result<Event, ApEventsError> {
+    if !event.status.can_become(to) {
+        return Err(ApEventsError::InvalidEvent(format!(
+            "a {} event cannot become {}",
+            event.status, to
+        )));
+    }
+    if !set_event_status(app_state, &event.actor_ap_id, event.status, to).await? {
+        return Err(ApEventsError::InvalidEvent(format!(
+            "the event is no longer {}",
+            event.status
+        )));
+    }
+    let changed = get_event(app_state, &event.actor_ap_id)
+        .await?
+        .ok_or_else(|| ApEventsError::ObjectNotFound(event.actor_ap_id.clone()))?;
+
+    announce_event(app_state, actor, &changed, event.is_draft()).await?;
+    Ok(changed)
+}
+
+async fn announce_event(
+    app_state: &MyStateHandle,
+    actor: &EventActor,
+    event: &Event,
+    created: bool,
+) -> Result<(), ApEventsError> {
+    let attendees = send_event_object(app_state, actor, event, created).await?;
+    if created {
+        announce_at_place(app_state, event).await?;
+    }
+
+    let title = MarkupDisplay::new_unsafe(&event.title, Html);
+    let notice = match event.status {
+        EventStatus::Postponed => format!(
+            "<p>{title} has been postponed. You will hear from us again when it has a new date.</p>"
+        ),
+        EventStatus::Cancelled => format!("<p>{title} has been cancelled.</p>"),
+        _ => return Ok(()),
+    };
+    send_note(app_state, actor, &[], true, notice.clone(), None).await?;
+    let followers = followers(app_state, actor.ap_id.inner().as_str()).await?;
+    let attendees = attendees
+        .into_iter()
+        .filter(|attendee| !followers.contains(&attendee.ap_id.to_string()))
+        .collect();
+    send_note_to_each(app_state, actor, attendees, &notice).await
+}
+
+/// The followers and the attendees of an event, who hear about changes to it.
+pub async fn event_audience(
+    app_state: &MyStateHandle,
+    actor: &EventActor,
+) -> Result<(Vec<EventActor>, Vec<EventActor>), ApEventsError> {
+    let actor_ap_id = actor.ap_id.inner().as_str();
+    let followers = get_actors(app_state, &followers(app_state, actor_ap_id).await?).await?;
+    let attendees = get_actors(app_state, &attendees(app_state, actor_ap_id).await?).await?;
+    Ok((followers, attendees))
+}
+
+/// Sends the Event object to the event's followers and attendees, as a `Create` when it is first
+/// published and as an `Update` after that. The event's calendar announces the activity to its
+/// own followers. The attendees are returned.
+pub async fn send_event_object(
+    app_state: &MyStateHandle,
+    actor: &EventActor,
+    event: &Event,
+    created: bool,
+) -> Result<Vec<EventActor>, ApEventsError> {
+    let (followers, attendees) = event_audience(app_state, actor).await?;
+    let inboxes = followers
+        .iter()
+        .chain(attendees.iter())
+        .map(|recipient| recipient.shared_inbox_or_inbox())
+        .collect();
+
+    let participant_count = count_rsvps(
+        app_state,
+        actor.ap_id.inner().as_str(),
+        ALL_OCCURRENCES,
+        RsvpStatus::Going,
+    )
+    .await?;
+    // Every attendee is sent the object, but only those who may be listed are addressed in it.
+    let mut object = event_into_apub(event, actor, None, participant_count)?;
+    object
+        .cc
+        .extend(listed_attendees(app_state, actor.ap_id.inner().as_str()).await?);
+
+    let activity_id = if created {
+        let id = generate_object_id(&app_state.external_base, KindType::Create)?;
+        let create = Create::new(
+            actor.ap_id.clone(),
+            CreateObject::Event(Box::new(object)),
+            id.clone(),
+        );
+        actor
+            .send_with_context(create, Some(event_context()), inboxes, app_state)
+            .await?;
+        id
+    } else {
+        let id = generate_object_id(&app_state.external_base, KindType::Update)?;
+        let update = Update::new(
+            actor.ap_id.clone(),
+            UpdateObject::Event(Box::new(object)),
+            id.clone(),
+        );
+        actor
+            .send_with_context(update, Some(event_context()), inboxes, app_state)
+            .await?;
+        id
+    };
+    announce_in_calendar(app_state, event, activity_id).await?;
+    Ok(attendees)
+}
+
+/// Publishes drafts whose publish time has come and completes events that have ended.
+pub async fn run_lifecycle(app_state: MyStateHandle) -> Result<(), ApEventsError> {
+    let now = Utc::now();
+    let due = due_drafts(&app_state, now)
+        .await?
+        .into_iter()
+        .map(|event| (event, EventStatus::Published));
+    let ended = ended_events(&app_state, now)
+        .await?
+        .into_iter()
+        .map(|event| (event, EventStatus::Completed));
+
+    for (event, to) in due.chain(ended) {
+        let actor = ObjectId::<EventActor>::new(Url::parse(&event.actor_ap_id)?)
+            .dereference_local(&app_state)
+            .await?;
+        match transition_event(&app_state, &actor, &event, to).await {
+            Ok(_) => info!("event {} is now {}", event.actor_ap_id, to),
+            Err(err) => warn!("cannot move event {} to {}: {}", event.actor_ap_id, to, err),
+        }
+    }
+    Ok(())
+}
@@ -1,10 +1,17 @@
 extern crate env_logger;
 
-use std::env;
+use std::{env, io, time::Duration};
 
 use actix_web::{http::header, middleware::Logger, web, App, HttpResponse, HttpServer, Responder};
 use api_apub::{handle_instance_post_event_actor_inbox, handle_wellknown_host_meta};
-use api_internal::handle_internal_follow_remote;
+use api_internal::{
+    handle_internal_admin_link, handle_internal_calendar_token, handle_internal_cancel_event,
+    handle_internal_delete_object, handle_internal_event_status, handle_internal_export_event,
+    handle_internal_follow_remote, handle_internal_import, handle_internal_import_event,
+    handle_internal_import_source, handle_internal_merge_places, handle_internal_move_event,
+    handle_internal_place_duplicates, handle_internal_set_calendar, handle_internal_set_event,
+    handle_internal_set_place, handle_internal_sweep,
+};
 use http_signature_normalization_actix::prelude::VerifyDigest;
 use sha2::{Digest, Sha256};
 
@@ -13,15 +20,50 @@ mod ap;
 mod api_apub;
 mod api_internal;
 mod api_nodeinfo;
+mod calendars;
+mod collections;
+mod commands;
+mod deliveries;
 mod error;
 mod fed;
+mod handler_admin;
+mod handler_calendar;
 mod handler_events;
+mod ical;
+mod import;
 mod instance;
+mod lifecycle;
+mod messages;
+mod migrate;
 mod objects;
+mod places;
+mod recurrence;
+mod reminders;
+mod search;
 mod state;
 mod storage_actor;
+mod storage_admin_tokens;
+mod storage_archive;
+mod storage_calendar_tokens;
+mod storage_calendars;
+mod storage_deliveries;
 mod storage_domains;
+mod storage_events;
+mod storage_follows;
+mod storage_import_feeds;
+mod storage_objects;
+mod storage_organizers;
+mod storage_places;
+mod storage_reminders;
+mod storage_rsvps;
+mod storage_transfers;
+mod storage_updates;
+mod sweeper;
+mod tasks;
+mod transfers;
+mod updates;
 mod util;
+mod waitlist;
 mod webfinger;
 
 use actix_files as fs;
@@ -29,15 +71,33 @@ use actix_webfinger::WebfingerGuard;
 use util::HeaderStart;
 
 use crate::api_apub::{
-    handle_instance_get_event_actor, handle_instance_get_event_actor_followers,
-    handle_instance_get_event_actor_following,
+    handle_instance_get_event_actor, handle_instance_get_event_actor_event,
+    handle_instance_get_event_actor_followers,
+    handle_instance_get_event_actor_followers_synchronization,
+    handle_instance_get_event_actor_following, handle_instance_get_event_actor_occurrence,
+    handle_instance_get_event_actor_outbox, handle_instance_get_event_actor_participants,
+    handle_instance_get_object, handle_instance_get_place_actor_place,
 };
 use crate::api_internal::handle_internal_create_user;
 use crate::api_nodeinfo::{
     handle_instance_info_v1, handle_instance_peers, handle_nodeinfo_20, handle_wellknown_nodeinfo,
 };
-use crate::handler_events::{handle_event, handle_home};
-use crate::state::state_factory;
+use crate::deliveries::run_deliveries;
+use crate::handler_admin::{handle_admin, handle_admin_post};
+use crate::handler_calendar::{
+    handle_attendee_ics, handle_calendar_ics, handle_event_ics, handle_instance_ics,
+};
+use crate::handler_events::{
+    handle_event, handle_event_occurrence, handle_events_api, handle_home, handle_object,
+    handle_place,
+};
+use crate::import::{import_calendar, sync_import_feeds};
+use crate::lifecycle::run_lifecycle;
+use crate::reminders::run_reminders;
+use crate::state::{state_factory, MyStateHandle};
+use crate::sweeper::{run_sweeper, sweep_events};
+use crate::tasks::spawn_periodic;
+use crate::updates::run_update_notifications;
 use crate::webfinger::handle_webfinger;
 
 async fn handle_index() -> impl Responder {
@@ -46,10 +106,128 @@ async fn handle_index() -> impl Responder {
         .body("Hello World!")
 }
 
+/// `apevents import <path>` imports an iCalendar file and prints the report.
+async fn run_import(path: &str) -> io::Result<()> {
+    let input = std::fs::read_to_string(path)?;
+    let app_state = state_factory()
+        .await
+        .map_err(|err| io::Error::other(err.to_string()))?;
+
+    let report = import_calendar(&app_state, &input).await;
+    println!("{}", serde_json::to_string_pretty(&report)?);
+    Ok(())
+}
+
+/// `apevents sweep [--dry-run]` deletes expired events, or only lists them, and prints the
+/// report.
+async fn run_sweep(dry_run: bool) -> io::Result<()> {
+    let app_state = state_factory()
+        .await
+        .map_err(|err| io::Error::other(err.to_string()))?;
+
+    let report = sweep_events(&app_state, chrono::Utc::now(), dry_run)
+        .await
+        .map_err(|err| io::Error::other(err.to_string()))?;
+    println!("{}", serde_json::to_string_pretty(&report)?);
+    Ok(())
+}
+
+fn spawn_tasks(app_state: MyStateHandle) {
+    let delivery_interval: u64 = env::var("DELIVERY_INTERVAL")
+        .ok()
+        .and_then(|value| value.parse().ok())
+        .unwrap_or(30);
+    if delivery_interval > 0 {
+        spawn_periodic(
+            "activity deliveries",
+            app_state.clone(),
+            Duration::from_secs(delivery_interval),
+            run_deliveries,
+        );
+    }
+    let lifecycle_interval: u64 = env::var("LIFECYCLE_INTERVAL")
+        .ok()
+        .and_then(|value| value.parse().ok())
+        .unwrap_or(60);
+    if lifecycle_interval > 0 {
+        spawn_periodic(
+            "event lifecycle",
+            app_state.clone(),
+            Duration::from_secs(lifecycle_interval),
+            run_lifecycle,
+        );
+    }
+
+    let update_interval: u64 = env::var("UPDATE_INTERVAL")
+        .ok()
+        .and_then(|value| value.parse().ok())
+        .unwrap_or(30);
+    if update_interval > 0 {
+        spawn_periodic(
+            "event update notifications",
+            app_state.clone(),
+            Duration::from_secs(update_interval),
+            run_update_notifications,
+        );
+    }
+    let reminder_interval: u64 = env::var("REMINDER_INTERVAL")
+        .ok()
+        .and_then(|value| value.parse().ok())
+        .unwrap_or(60);
+    if reminder_interval > 0 {
+        spawn_periodic(
+            "event reminders",
+            app_state.clone(),
+            Duration::from_secs(reminder_interval),
+            run_reminders,
+        );
+    }
+    let sweep_interval: u64 = env::var("SWEEP_INTERVAL")
+        .ok()
+        .and_then(|value| value.parse().ok())
+        .unwrap_or(3600);
+    if sweep_interval > 0 {
+        spawn_periodic(
+            "event sweeper",
+            app_state.clone(),
+            Duration::from_secs(sweep_interval),
+            run_sweeper,
+        );
+    }
+    let import_sync_interval: u64 = env::var("IMPORT_SYNC_INTERVAL")
+        .ok()
+        .and_then(|value| value.parse().ok())
+        .unwrap_or(3600);
+    if import_sync_interval > 0 {
+        spawn_periodic(
+            "import feed sync",
+            app_state,
+            Duration::from_secs(import_sync_interval),
+            sync_import_feeds,
+        );
+    }
+}
+
 #[actix_web::main]
 async fn main() -> std::io::Result<()> {
     env_logger::init_from_env(env_logger::Env::new().default_filter_or("info"));
 
+    let args: Vec<String> = env::args().collect();
+    match args.as_slice() {
+        [_, command, path] if command == "import" => return run_import(path).await,
+        [_, command, options @ ..] if command == "sweep" => {
+            return run_sweep(options.iter().any(|option| option == "--dry-run")).await
+        }
+        _ => {}
+    }
+
+    // Background tasks share one state, separate from the per-worker states of the server.
+    spawn_tasks(
+        state_factory()
+            .await
+            .map_err(|err| io::Error::other(err.to_string()))?,
+    );
+
     let listen_address: String =
         env::var("LISTEN_ADDRESS").unwrap_or_else(|_| "0.0.0.0".to_string());
     let listen_port: String = env::var("LISTEN_PORT")
@@ -67,7 +245,20 @@ async fn main() -> std::io::Result<()> {
                     .guard(HeaderStart("accept", "text/html"))
                     .route("/", web::get().to(handle_home))
                     .route("/actor/{name}", web::get().to(handle_event))
-                    .route("/@{name}", web::get().to(handle_event)),
+                    .route("/actor/{name}/event", web::get().to(handle_event))
+                    .route(
+                        "/actor/{name}/event/{occurrence}",
+                        web::get().to(handle_event_occurrence),
+                    )
+                    .route("/actor/{name}/admin", web::get().to(handle_admin))
+                    .route("/actor/{name}/place", web::get().to(handle_place))
+                    .route("/@{name}", web::get().to(handle_event))
+                    .route("/objects/{id}", web::get().to(handle_object)),
+            )
+            .service(
+                web::scope("")
+                    .guard(HeaderStart("accept", "text/calendar"))
+                    .route("/actor/{name}/event", web::get().to(handle_event_ics)),
             )
             .service(
                 actix_web::web::resource("/.well-known/webfinger")
@@ -84,6 +275,7 @@ async fn main() -> std::io::Result<()> {
             )
             .route("/nodeinfo/2.0", web::get().to(handle_nodeinfo_20))
             .route("/api/v1/instance", web::get().to(handle_instance_info_v1))
+            .route("/api/v1/events", web::get().to(handle_events_api))
             .route(
                 "/api/v1/instance/peers",
                 web::get().to(handle_instance_peers),
@@ -93,14 +285,47 @@ async fn main() -> std::io::Result<()> {
                 "/actor/{name}",
                 web::get().to(handle_instance_get_event_actor),
             )
+            .route(
+                "/actor/{name}/event",
+                web::get().to(handle_instance_get_event_actor_event),
+            )
+            .route(
+                "/actor/{name}/event/{occurrence}",
+                web::get().to(handle_instance_get_event_actor_occurrence),
+            )
+            .route(
+                "/actor/{name}/place",
+                web::get().to(handle_instance_get_place_actor_place),
+            )
+            .route("/actor/{name}/admin", web::post().to(handle_admin_post))
+            .route("/events.ics", web::get().to(handle_instance_ics))
+            .route("/calendars/{token}.ics", web::get().to(handle_attendee_ics))
+            .route("/actor/{name}/event.ics", web::get().to(handle_event_ics))
+            .route(
+                "/actor/{name}/calendar.ics",
+                web::get().to(handle_calendar_ics),
+            )
             .route(
                 "/actor/{name}/followers",
                 web::get().to(handle_instance_get_event_actor_followers),
             )
+            .route(
+                "/actor/{name}/followers_synchronization",
+                web::get().to(handle_instance_get_event_actor_followers_synchronization),
+            )
             .route(
                 "/actor/{name}/following",
                 web::get().to(handle_instance_get_event_actor_following),
             )
+            .route(
+                "/actor/{name}/participants",
+                web::get().to(handle_instance_get_event_actor_participants),
+            )
+            .route(
+                "/actor/{name}/outbox",
+                web::get().to(handle_instance_get_event_actor_outbox),
+            )
+            .route("/objects/{id}", web::get().to(handle_instance_get_object))
             .route(
                 "/internal/api/user",
                 web::post().to(handle_internal_create_user),
@@ -109,6 +334,67 @@ async fn main() -> std::io::Result<()> {
                 "/internal/api/follow",
                 web::post().to(handle_internal_follow_remote),
             )
+            .route(
+                "/internal/api/event",
+                web::post().to(handle_internal_set_event),
+            )
+            .route(
+                "/internal/api/event/cancel",
+                web::post().to(handle_internal_cancel_event),
+            )
+            .route(
+                "/internal/api/event/status",
+                web::post().to(handle_internal_event_status),
+            )
+            .route(
+                "/internal/api/event/export",
+                web::post().to(handle_internal_export_event),
+            )
+            .route(
+                "/internal/api/event/import",
+                web::post().to(handle_internal_import_event),
+            )
+            .route(
+                "/internal/api/event/move",
+                web::post().to(handle_internal_move_event),
+            )
+            .route(
+                "/internal/api/admin_link",
+                web::post().to(handle_internal_admin_link),
+            )
+            .route(
+                "/internal/api/calendar_token",
+                web::post().to(handle_internal_calendar_token),
+            )
+            .route(
+                "/internal/api/import",
+                web::post().to(handle_internal_import),
+            )
+            .route(
+                "/internal/api/import/source",
+                web::post().to(handle_internal_import_source),
+            )
+            .route(
+                "/internal/api/calendar",
+                web::post().to(handle_internal_set_calendar),
+            )
+            .route(
+                "/internal/api/place",
+                web::post().to(handle_internal_set_place),
+            )
+            .route(
+                "/internal/api/place/duplicates",
+                web::get().to(handle_internal_place_duplicates),
+            )
+            .route(
+                "/internal/api/place/merge",
+                web::post().to(handle_internal_merge_places),
+            )
+            .route("/internal/api/sweep", web::post().to(handle_internal_sweep))
+            .route(
+                "/internal/api/object",
+                web::delete().to(handle_internal_delete_object),
+            )
             .service(
                 web::scope("")
                     .wrap(VerifyDigest::new(Sha256::new()))
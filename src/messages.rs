@@ -0,0 +1,99 @@
+use activitypub_federation::{deser::context::WithContext, traits::Actor};
+
+use crate::{
+    activities::create::{Create, CreateObject},
+    ap::{
+        ids::{generate_object_id, KindType},
+        note::{Note, Tag},
+    },
+    error::ApEventsError,
+    objects::actor::EventActor,
+    state::MyStateHandle,
+    storage_actor::get_actors,
+    storage_follows::followers,
+    storage_objects::store_object,
+};
+
+/// Sends a message from an event. Mentioned actors are addressed directly, and the event's
+/// followers too when `to_followers` is set. Messages are never public.
+pub async fn send_note(
+    app_state: &MyStateHandle,
+    actor: &EventActor,
+    mentions: &[EventActor],
+    to_followers: bool,
+    content: String,
+    in_reply_to: Option<String>,
+) -> Result<(), ApEventsError> {
+    let mut to: Vec<String> = mentions
+        .iter()
+        .map(|mention| mention.ap_id.to_string())
+        .collect();
+    let mut inboxes: Vec<_> = mentions
+        .iter()
+        .map(|mention| mention.shared_inbox_or_inbox())
+        .collect();
+    if to_followers {
+        to.push(actor.followers_url()?.to_string());
+        let followers = followers(app_state, actor.ap_id.inner().as_str()).await?;
+        inboxes.extend(
+            get_actors(app_state, &followers)
+                .await?
+                .iter()
+                .map(|follower| follower.shared_inbox_or_inbox()),
+        );
+    }
+
+    let note = Note {
+        ap_id: generate_object_id(&app_state.external_base, KindType::Note)?.to_string(),
+        kind: Default::default(),
+        attributed_to: actor.ap_id.to_string(),
+        content,
+        in_reply_to,
+        to,
+        cc: vec![],
+        tag: mentions
+            .iter()
+            .map(|mention| {
+                Tag::mention(
+                    mention.ap_id.inner().as_str(),
+                    &format!("@{}", mention.actor_ref),
+                )
+            })
+            .collect(),
+    };
+    store_object(
+        app_state,
+        actor.ap_id.inner().as_str(),
+        &serde_json::to_value(WithContext::new_default(note.clone()))?,
+    )
+    .await?;
+
+    let create = Create::new(
+        actor.ap_id.clone(),
+        CreateObject::Note(note),
+        generate_object_id(&app_state.external_base, KindType::Create)?,
+    );
+    actor.send(create, inboxes, app_state).await
+}
+
+/// Sends a message to each recipient on their own, so that recipients such as attendees do not
+/// learn who else was sent it.
+pub async fn send_note_to_each(
+    app_state: &MyStateHandle,
+    actor: &EventActor,
+    recipients: Vec<EventActor>,
+    content: &str,
+) -> Result<(), ApEventsError> {
+    for recipient in recipients {
+        send_note(
+            app_state,
+            actor,
+            &[recipient],
+            false,
+            content.to_string(),
+            None,
+        )
+        .await?;
+    }
+    Ok(())
+}
@@ -0,0 +1,489 @@
+use activitypub_federation::{core::object_id::ObjectId, traits::Actor};
+use chrono::{DateTime, Utc};
+use openssl::{
+    hash::MessageDigest,
+    pkey::PKey,
+    sign::{Signer, Verifier},
+};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use url::Url;
+
+use crate::{
+    activities::move_actor::Move,
+    ap::{
+        actor::Actor as ActPubActor,
+        ids::{generate_object_id, KindType},
+    },
+    error::ApEventsError,
+    lifecycle::send_event_object,
+    objects::{actor::EventActor, event::is_local_event_url},
+    state::MyStateHandle,
+    storage_actor::{
+        actor_aliases, add_actor_alias, create_local_actor, get_actors, set_moved_to, signing_actor,
+    },
+    storage_events::{get_event, upsert_event, Event},
+    storage_follows::{followers, local_followers, remove_follow},
+    storage_organizers::{organizers, set_organizer, Role},
+    storage_rsvps::{all_rsvps, attendees_hidden, set_attendees_hidden, set_rsvp},
+    util::fetch_object_http,
+};
+
+/// The details of an exported event. Places and calendars belong to the instance the event is
+/// exported from, so only the location is kept.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ArchivedEvent {
+    pub title: String,
+    pub summary: String,
+    pub starts_at: DateTime<Utc>,
+    pub ends_at: DateTime<Utc>,
+    pub timezone: String,
+    pub location: Option<String>,
+    pub online_url: Option<String>,
+    pub online_url_private: bool,
+    pub join_mode: String,
+    pub capacity: Option<i32>,
+    pub rrule: Option<String>,
+    pub exdates: Vec<DateTime<Utc>>,
+    pub status: String,
+    pub publish_at: Option<DateTime<Utc>>,
+    pub owner: Option<String>,
+    pub hide_attendees: bool,
+    #[serde(default)]
+    pub tags: Vec<String>,
+}
+
+/// An answer to an exported event.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ArchivedRsvp {
+    pub attendee: String,
+    pub occurrence: String,
+    pub status: String,
+    pub hidden: bool,
+}
+
+/// A co-host or moderator of an exported event.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ArchivedOrganizer {
+    pub organizer: String,
+    pub role: String,
+}
+
+/// An event exported for another instance to import. The archive is signed with the key of the
+/// event actor, so the importing instance can check that it came from that actor.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct EventArchive {
+    pub actor: String,
+    pub exported_at: DateTime<Utc>,
+    pub event: ArchivedEvent,
+    pub rsvps: Vec<ArchivedRsvp>,
+    pub organizers: Vec<ArchivedOrganizer>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub signature: Option<String>,
+}
+
+/// A JSON value with the keys of every object in sorted order, so that it is signed the same way
+/// whatever order the keys arrive in.
+fn canonical(value: &Value) -> Value {
+    match value {
+        Value::Object(object) => {
+            let mut keys: Vec<&String> = object.keys().collect();
+            keys.sort();
+            Value::Object(
+                keys.into_iter()
+                    .map(|key| (key.clone(), canonical(&object[key])))
+                    .collect(),
+            )
+        }
+        Value::Array(items) => Value::Array(items.iter().map(canonical).collect()),
+        other => other.clone(),
+    }
+}
+
+/// An archive as it is signed: its canonical JSON without the signature.
+fn signed_bytes(archive: &Value) -> Result<Vec<u8>, ApEventsError> {
+    let mut archive = archive.clone();
+    if let Some(object) = archive.as_object_mut() {
+        object.remove("signature");
+    }
+    Ok(serde_json::to_vec(&canonical(&archive))?)
+}
+
+/// Signs an archive with an RSA private key in PEM format.
+fn sign_value(archive: &Value, private_key: &str) -> Result<String, ApEventsError> {
+    let private_key = PKey::private_key_from_pem(private_key.as_bytes())?;
+    let mut signer = Signer::new(MessageDigest::sha256(), &private_key)?;
+    signer.update(&signed_bytes(archive)?)?;
+    Ok(base64::encode(signer.sign_to_vec()?))
+}
+
+impl EventArchive {
+    /// Signs the archive with an RSA private key in PEM format.
+    pub fn sign(mut self, private_key: &str) -> Result<EventArchive, ApEventsError> {
+        self.signature = Some(sign_value(&serde_json::to_value(&self)?, private_key)?);
+        Ok(self)
+    }
+
+    /// Checks the signature of an archive as it was received against an RSA public key in PEM
+    /// format. The received JSON is checked rather than the parsed archive, so that archives
+    /// written by other versions verify even when they are missing fields or have extra ones.
+    pub fn verify(received: &Value, public_key: &str) -> Result<(), ApEventsError> {
+        let invalid = || ApEventsError::InvalidArchive("the signature does not match".to_string());
+        let signature = received
+            .get("signature")
+            .and_then(Value::as_str)
+            .ok_or_else(|| {
+                ApEventsError::InvalidArchive("the archive is not signed".to_string())
+            })?;
+        let signature = base64::decode(signature).map_err(|_| invalid())?;
+
+        let public_key = PKey::public_key_from_pem(public_key.as_bytes())?;
+        let mut verifier = Verifier::new(MessageDigest::sha256(), &public_key)?;
+        verifier.update(&signed_bytes(received)?)?;
+        match verifier.verify(&signature) {
+            Ok(true) => Ok(()),
+            _ => Err(invalid()),
+        }
+    }
+}
+
+/// Exports a local event with its answers and organizers, signed by the event actor.
+pub async fn export_event(
+    app_state: &MyStateHandle,
+    actor: &EventActor,
+) -> Result<EventArchive, ApEventsError> {
+    let actor_ap_id = actor.ap_id.inner().as_str();
+    let event = get_event(app_state, actor_ap_id)
+        .await?
+        .ok_or_else(|| ApEventsError::ObjectNotFound(actor_ap_id.to_string()))?;
+    let private_key = actor
+        .private_key
+        .as_deref()
+        .ok_or_else(|| ApEventsError::new("actor cannot sign archives".to_string()))?;
+
+    let archive = EventArchive {
+        actor: actor_ap_id.to_string(),
+        exported_at: Utc::now(),
+        event: ArchivedEvent {
+            title: event.title.clone(),
+            summary: event.summary.clone(),
+            starts_at: event.starts_at,
+            ends_at: event.ends_at,
+            timezone: event.timezone.clone(),
+            location: event.location.clone(),
+            online_url: event.online_url.clone(),
+            online_url_private: event.online_url_private,
+            join_mode: event.join_mode.clone(),
+            capacity: event.capacity,
+            rrule: event.rrule.clone(),
+            exdates: event.exdates.clone(),
+            status: event.status.as_str().to_string(),
+            publish_at: event.publish_at,
+            owner: event.owner_ap_id.clone(),
+            hide_attendees: attendees_hidden(app_state, actor_ap_id).await?,
+            tags: event.tags.clone(),
+        },
+        rsvps: all_rsvps(app_state, actor_ap_id)
+            .await?
+            .into_iter()
+            .map(|(occurrence, attendee, status, hidden)| ArchivedRsvp {
+                attendee,
+                occurrence,
+                status: status.as_str().to_string(),
+                hidden,
+            })
+            .collect(),
+        organizers: organizers(app_state, &event)
+            .await?
+            .into_iter()
+            .filter(|(_, role)| *role != Role::Owner)
+            .map(|(organizer, role)| ArchivedOrganizer {
+                organizer,
+                role: role.as_str().to_string(),
+            })
+            .collect(),
+        signature: None,
+    };
+    archive.sign(private_key)
+}
+
+/// Imports an event exported by another instance as a new local event that is also known as the
+/// exported actor. The archive must be signed by the exported actor.
+pub async fn import_event(
+    app_state: &MyStateHandle,
+    received: Value,
+    request_counter: &mut i32,
+) -> Result<(EventActor, Event), ApEventsError> {
+    let archive: EventArchive = serde_json::from_value(received.clone())
+        .map_err(|err| ApEventsError::InvalidArchive(err.to_string()))?;
+    let source_url = Url::parse(&archive.actor)?;
+    if is_local_event_url(app_state, &source_url) {
+        return Err(ApEventsError::InvalidArchive(
+            "the event is already on this instance".to_string(),
+        ));
+    }
+    let source = ObjectId::<EventActor>::new(source_url)
+        .dereference(app_state, &app_state.local_instance, request_counter)
+        .await?;
+    EventArchive::verify(&received, &source.public_key)?;
+
+    let archived = archive.event;
+    let mut event = Event {
+        actor_ap_id: String::new(),
+        title: archived.title,
+        summary: archived.summary,
+        starts_at: archived.starts_at,
+        ends_at: archived.ends_at,
+        timezone: archived.timezone,
+        location: archived.location,
+        online_url: archived.online_url,
+        online_url_private: archived.online_url_private,
+        place_ap_id: None,
+        calendar_ap_id: None,
+        join_mode: archived.join_mode,
+        capacity: archived.capacity,
+        rrule: archived.rrule,
+        exdates: archived.exdates,
+        status: archived.status.parse()?,
+        publish_at: archived.publish_at,
+        owner_ap_id: archived.owner,
+        tags: archived.tags,
+        sequence: 0,
+        updated_at: Utc::now().naive_utc(),
+    };
+    // Checked before the actor is created, so that invalid archives leave nothing behind.
+    event.validate(Utc::now())?;
+    let organizer_roles = archive
+        .organizers
+        .iter()
+        .map(|organizer| organizer.role.parse::<Role>())
+        .collect::<Result<Vec<_>, _>>()?;
+    let rsvp_statuses = archive
+        .rsvps
+        .iter()
+        .map(|rsvp| rsvp.status.parse())
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let (_, actor) = create_local_actor(app_state).await?;
+    let actor_ap_id = actor.ap_id.inner().as_str();
+    add_actor_alias(app_state, actor_ap_id, &archive.actor).await?;
+    event.actor_ap_id = actor_ap_id.to_string();
+    let event = upsert_event(app_state, &event).await?;
+    set_attendees_hidden(app_state, actor_ap_id, archived.hide_attendees).await?;
+    for (organizer, role) in archive.organizers.iter().zip(organizer_roles) {
+        set_organizer(app_state, &event, &organizer.organizer, role).await?;
+    }
+    for (rsvp, status) in archive.rsvps.iter().zip(rsvp_statuses) {
+        set_rsvp(
+            app_state,
+            actor_ap_id,
+            &rsvp.occurrence,
+            &rsvp.attendee,
+            status,
+            None,
+            Some(rsvp.hidden),
+        )
+        .await?;
+    }
+    if !event.is_draft() {
+        send_event_object(app_state, &actor, &event, true).await?;
+    }
+    Ok((actor, event))
+}
+
+/// The accounts that an actor says it is also known as. Remote actors are fetched fresh, so
+/// that an old copy cannot vouch for a move.
+pub async fn target_aliases(
+    app_state: &MyStateHandle,
+    target: &Url,
+) -> Result<Vec<String>, ApEventsError> {
+    let known = get_actors(app_state, &[target.to_string()]).await?;
+    if known.first().is_some_and(|known| known.local) {
+        return Ok(actor_aliases(app_state, target.as_str()).await?.0);
+    }
+
+    let signer = signing_actor(app_state)
+        .await?
+        .ok_or_else(|| ApEventsError::new("no actor can sign requests".to_string()))?;
+    let private_key = signer
+        .private_key
+        .ok_or_else(|| ApEventsError::new("actor cannot sign requests".to_string()))?;
+    let target: ActPubActor = fetch_object_http(target, signer.public_key_id, private_key).await?;
+    Ok(target.also_known_as)
+}
+
+/// Moves a local event to the actor it was imported as on another instance. The event publishes
+/// `movedTo` and sends `Move` to its followers, whose servers follow the new actor instead.
+pub async fn move_event(
+    app_state: &MyStateHandle,
+    actor: &EventActor,
+    target: &Url,
+) -> Result<(), ApEventsError> {
+    let actor_ap_id = actor.ap_id.inner().as_str();
+    if !target_aliases(app_state, target)
+        .await?
+        .iter()
+        .any(|alias| alias == actor_ap_id)
+    {
+        return Err(ApEventsError::InvalidQuery(format!(
+            "{target} does not list {actor_ap_id} in alsoKnownAs"
+        )));
+    }
+
+    add_actor_alias(app_state, actor_ap_id, target.as_str()).await?;
+    set_moved_to(app_state, actor_ap_id, target.as_str()).await?;
+
+    let inboxes = get_actors(app_state, &followers(app_state, actor_ap_id).await?)
+        .await?
+        .iter()
+        .map(|follower| follower.shared_inbox_or_inbox())
+        .collect();
+    let move_activity = Move::new(
+        actor,
+        target.clone(),
+        generate_object_id(&app_state.external_base, KindType::Move)?,
+    )?;
+    actor.send(move_activity, inboxes, app_state).await
+}
+
+/// Moves the follows of local actors from an account that moved to its new account.
+pub async fn follow_moved(
+    app_state: &MyStateHandle,
+    moved_from: &Url,
+    moved_to: &Url,
+) -> Result<(), ApEventsError> {
+    let local = local_followers(app_state, moved_from.as_str()).await?;
+    for follower in get_actors(app_state, &local).await? {
+        follower.follow(moved_to.to_string(), app_state).await?;
+        remove_follow(
+            app_state,
+            follower.ap_id.inner().as_str(),
+            moved_from.as_str(),
+        )
+        .await?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use activitypub_federation::core::signatures::generate_actor_keypair;
+    use chrono::TimeZone;
+
+    fn archive() -> EventArchive {
+        EventArchive {
+            actor: "https://events.thegem.city/actor/readily-splendid-mule".to_string(),
+            exported_at: Utc.with_ymd_and_hms(2022, 10, 1, 12, 0, 0).unwrap(),
+            event: ArchivedEvent {
+                title: "Swing night".to_string(),
+                summary: String::new(),
+                starts_at: Utc.with_ymd_and_hms(2022, 11, 1, 20, 0, 0).unwrap(),
+                ends_at: Utc.with_ymd_and_hms(2022, 11, 1, 23, 0, 0).unwrap(),
+                timezone: "America/New_York".to_string(),
+                location: Some("The bar".to_string()),
+                online_url: None,
+                online_url_private: false,
+                join_mode: "free".to_string(),
+                capacity: Some(40),
+                rrule: None,
+                exdates: vec![],
+                status: "published".to_string(),
+                publish_at: None,
+                owner: Some("https://thegem.city/users/nick".to_string()),
+                hide_attendees: false,
+                tags: vec!["swing".to_string()],
+            },
+            rsvps: vec![ArchivedRsvp {
+                attendee: "https://thegem.city/users/mattie".to_string(),
+                occurrence: String::new(),
+                status: "going".to_string(),
+                hidden: true,
+            }],
+            organizers: vec![ArchivedOrganizer {
+                organizer: "https://thegem.city/users/dana".to_string(),
+                role: "co-host".to_string(),
+            }],
+            signature: None,
+        }
+    }
+
+    #[test]
+    fn signed_archives() {
+        let keypair = generate_actor_keypair().expect("keypair");
+        let other = generate_actor_keypair().expect("keypair");
+
+        let unsigned = serde_json::to_value(archive()).expect("serialized");
+        assert!(EventArchive::verify(&unsigned, &keypair.public_key).is_err());
+
+        let signed = archive().sign(&keypair.private_key).expect("signed");
+        let json = serde_json::to_string(&signed).expect("serialized");
+        let received: Value = serde_json::from_str(&json).expect("parsed");
+        assert_eq!(
+            serde_json::from_value::<EventArchive>(received.clone()).expect("archive"),
+            signed
+        );
+        assert!(EventArchive::verify(&received, &keypair.public_key).is_ok());
+        assert!(EventArchive::verify(&received, &other.public_key).is_err());
+
+        // Keys can arrive in any order.
+        let reordered: Value = serde_json::from_str(&format!(
+            "{{\"signature\": {}, \"organizers\": {}, \"rsvps\": {}, \"event\": {}, \"exportedAt\": {}, \"actor\": {}}}",
+            received["signature"],
+            received["organizers"],
+            received["rsvps"],
+            received["event"],
+            received["exportedAt"],
+            received["actor"],
+        ))
+        .expect("reordered");
+        assert!(EventArchive::verify(&reordered, &keypair.public_key).is_ok());
+
+        let mut tampered = received;
+        tampered["event"]["owner"] = "https://example.com/users/mallory".into();
+        assert!(EventArchive::verify(&tampered, &keypair.public_key).is_err());
+    }
+
+    #[test]
+    fn archives_without_tags() {
+        let keypair = generate_actor_keypair().expect("keypair");
+
+        // Written by an instance from before events had tags.
+        let mut received = serde_json::json!({
+            "actor": "https://events.thegem.city/actor/readily-splendid-mule",
+            "exportedAt": "2022-10-01T12:00:00Z",
+            "event": {
+                "title": "Swing night",
+                "summary": "",
+                "startsAt": "2022-11-01T20:00:00Z",
+                "endsAt": "2022-11-01T23:00:00Z",
+                "timezone": "America/New_York",
+                "location": "The bar",
+                "onlineUrl": null,
+                "onlineUrlPrivate": false,
+                "joinMode": "free",
+                "capacity": 40,
+                "rrule": null,
+                "exdates": [],
+                "status": "published",
+                "publishAt": null,
+                "owner": "https://thegem.city/users/nick",
+                "hideAttendees": false
+            },
+            "rsvps": [],
+            "organizers": []
+        });
+        received["signature"] = sign_value(&received, &keypair.private_key)
+            .expect("signed")
+            .into();
+
+        assert!(EventArchive::verify(&received, &keypair.public_key).is_ok());
+        let parsed: EventArchive = serde_json::from_value(received).expect("archive");
+        assert!(parsed.event.tags.is_empty());
+    }
+}
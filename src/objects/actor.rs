@@ -1,23 +1,35 @@
 use std::collections::HashMap;
 
 use crate::{
-    activities::{accept::Accept, follow::Follow},
+    activities::{
+        accept::Accept, create::Create, follow::Follow, join::Join, leave::Leave, move_actor::Move,
+        reject::Reject, tentative_accept::TentativeAccept, undo::Undo,
+    },
     ap::{
         self,
-        actor::{Actor as ActPubActor, PublicKey as ActorPublicKey},
+        actor::{Actor as ActPubActor, ActorAttachment, PublicKey as ActorPublicKey},
         ids::{generate_object_id, KindType},
     },
+    deliveries::{addressed_to, run_deliveries},
     error::ApEventsError,
     fed::actor_maybe,
+    recurrence::Occurrence,
     state::MyStateHandle,
+    storage_actor::{actor_aliases, create_actor},
+    storage_calendars::get_calendar,
+    storage_deliveries::queue_deliveries,
+    storage_events::get_event,
+    storage_follows::{create_follow, followers_digest, followers_on_origin},
+    storage_objects::store_object,
+    storage_places::get_place,
 };
 use activitypub_federation::{
-    core::{activity_queue::send_activity, object_id::ObjectId, signatures::PublicKey},
+    core::object_id::ObjectId,
     data::Data,
     deser::context::WithContext,
     traits::{ActivityHandler, Actor, ApubObject},
-    LocalInstance,
 };
+use log::warn;
 use serde::{Deserialize, Serialize};
 use sqlx::{postgres::PgRow, FromRow, Row};
 use url::Url;
@@ -31,9 +43,6 @@ pub struct EventActor {
     pub public_key: String,
     pub private_key: Option<String>,
 
-    #[serde(skip_deserializing)]
-    pub followers: Vec<Url>,
-
     #[serde(skip_deserializing)]
     pub local: bool,
 }
@@ -44,13 +53,16 @@ pub struct EventActor {
 pub enum PersonAcceptedActivities {
     Follow(Follow),
     Accept(Accept),
+    Undo(Undo),
+    Join(Join),
+    Leave(Leave),
+    TentativeAccept(TentativeAccept),
+    Reject(Reject),
+    Create(Create),
+    Move(Move),
 }
 
 impl EventActor {
-    pub fn followers(&self) -> &Vec<Url> {
-        &self.followers
-    }
-
     pub fn followers_url(&self) -> Result<Url, ApEventsError> {
         Ok(Url::parse(&format!("{}/followers", self.ap_id.inner()))?)
     }
@@ -59,8 +71,41 @@ impl EventActor {
         Ok(Url::parse(&format!("{}/following", self.ap_id.inner()))?)
     }
 
-    fn public_key(&self) -> PublicKey {
-        PublicKey::new_main_key(self.ap_id.clone().into_inner(), self.public_key.clone())
+    pub fn outbox_url(&self) -> Result<Url, ApEventsError> {
+        Ok(Url::parse(&format!("{}/outbox", self.ap_id.inner()))?)
+    }
+
+    pub fn event_url(&self) -> Result<Url, ApEventsError> {
+        Ok(Url::parse(&format!("{}/event", self.ap_id.inner()))?)
+    }
+
+    /// The `Place` object of an actor that is a place.
+    pub fn place_url(&self) -> Result<Url, ApEventsError> {
+        Ok(Url::parse(&format!("{}/place", self.ap_id.inner()))?)
+    }
+
+    /// The iCalendar feed of an actor that is a calendar.
+    pub fn calendar_feed_url(&self) -> Result<Url, ApEventsError> {
+        Ok(Url::parse(&format!("{}/calendar.ics", self.ap_id.inner()))?)
+    }
+
+    pub fn occurrence_url(&self, occurrence: &Occurrence) -> Result<Url, ApEventsError> {
+        Ok(Url::parse(&format!(
+            "{}/event/{}",
+            self.ap_id.inner(),
+            occurrence.id()
+        ))?)
+    }
+
+    pub fn participants_url(&self) -> Result<Url, ApEventsError> {
+        Ok(Url::parse(&format!("{}/participants", self.ap_id.inner()))?)
+    }
+
+    pub fn followers_synchronization_url(&self) -> Result<Url, ApEventsError> {
+        Ok(Url::parse(&format!(
+            "{}/followers_synchronization",
+            self.ap_id.inner()
+        ))?)
     }
 
     pub async fn follow(
@@ -78,43 +123,114 @@ impl EventActor {
             id.clone(),
         );
 
-        sqlx::query(
-            "INSERT INTO follow_activities (follower_ap_id, followee_ap_id, activity_ap_id) VALUES ($1, $2, $3)",
+        create_follow(
+            app_state,
+            self.ap_id.inner().as_str(),
+            found_remote_actor.ap_id.inner().as_str(),
+            id.as_str(),
         )
-            .bind(self.ap_id.to_string())
-            .bind(&other)
-            .bind(id.to_string())
-            .execute(&app_state.pool)
-            .await?;
+        .await?;
 
         self.send(
             follow,
             vec![found_remote_actor.shared_inbox_or_inbox()],
-            &app_state.local_instance,
+            app_state,
         )
         .await?;
         Ok(())
     }
 
+    /// The `Collection-Synchronization` header for deliveries to an inbox, describing the
+    /// followers this actor has on the inbox's instance.
+    async fn collection_synchronization(
+        &self,
+        app_state: &MyStateHandle,
+        inbox: &Url,
+    ) -> Result<String, ApEventsError> {
+        let followers = followers_on_origin(app_state, self.ap_id.inner().as_str(), inbox).await?;
+        Ok(format!(
+            "collectionId=\"{}\", url=\"{}\", digest=\"{}\"",
+            self.followers_url()?,
+            self.followers_synchronization_url()?,
+            followers_digest(&followers)
+        ))
+    }
+
     pub(crate) async fn send<Activity>(
         &self,
         activity: Activity,
         recipients: Vec<Url>,
-        local_instance: &LocalInstance,
+        app_state: &MyStateHandle,
     ) -> Result<(), <Activity as ActivityHandler>::Error>
     where
         Activity: ActivityHandler + Serialize,
-        <Activity as ActivityHandler>::Error: From<anyhow::Error> + From<serde_json::Error>,
+        <Activity as ActivityHandler>::Error:
+            From<anyhow::Error> + From<serde_json::Error> + From<ApEventsError>,
     {
-        let activity = WithContext::new_default(activity);
-        send_activity(
-            activity,
-            self.public_key(),
-            self.private_key.clone().expect("has private key"),
-            recipients,
-            local_instance,
+        self.send_with_context(activity, None, recipients, app_state)
+            .await
+    }
+
+    /// Sends an activity with a JSON-LD context other than the ActivityStreams default, for
+    /// activities that embed objects with extension terms.
+    pub(crate) async fn send_with_context<Activity>(
+        &self,
+        activity: Activity,
+        context: Option<Vec<serde_json::Value>>,
+        mut recipients: Vec<Url>,
+        app_state: &MyStateHandle,
+    ) -> Result<(), <Activity as ActivityHandler>::Error>
+    where
+        Activity: ActivityHandler + Serialize,
+        <Activity as ActivityHandler>::Error:
+            From<anyhow::Error> + From<serde_json::Error> + From<ApEventsError>,
+    {
+        let activity = match context {
+            Some(context) => serde_json::to_value(WithContext::new(activity, context))?,
+            None => serde_json::to_value(WithContext::new_default(activity))?,
+        };
+        store_object(app_state, self.ap_id.inner().as_str(), &activity).await?;
+        if self.private_key.is_none() {
+            return Err(ApEventsError::new("actor cannot sign activities".to_string()).into());
+        }
+
+        // Only activities for followers describe the followers collection.
+        let for_followers = addressed_to(&activity, &self.followers_url()?);
+        let activity = serde_json::to_string(&activity)?;
+
+        recipients.sort();
+        recipients.dedup();
+        let local_prefix = format!("{}/", app_state.external_base);
+
+        let mut deliveries = vec![];
+        for inbox in recipients
+            .into_iter()
+            .filter(|inbox| !inbox.as_str().starts_with(&local_prefix))
+        {
+            let collection_synchronization = if for_followers {
+                Some(self.collection_synchronization(app_state, &inbox).await?)
+            } else {
+                None
+            };
+            deliveries.push((inbox, collection_synchronization));
+        }
+        if deliveries.is_empty() {
+            return Ok(());
+        }
+
+        queue_deliveries(
+            app_state,
+            self.ap_id.inner().as_str(),
+            &activity,
+            &deliveries,
         )
         .await?;
+        let app_state = app_state.clone();
+        actix_web::rt::spawn(async move {
+            if let Err(err) = run_deliveries(app_state).await {
+                warn!("{}", err);
+            }
+        });
         Ok(())
     }
 }
@@ -131,7 +247,6 @@ impl TryFrom<ap::actor::Actor> for EventActor {
             public_key: public_key.public_key_pem,
             private_key: None,
             inbox: Url::parse(&actor.inbox.unwrap())?,
-            followers: vec![],
             local: true,
         })
     }
@@ -148,7 +263,6 @@ impl FromRow<'_, PgRow> for EventActor {
             public_key: row.try_get("public_key")?,
             private_key: row.try_get("private_key")?,
             inbox: Url::parse(row.try_get("inbox_id")?).expect("msg"),
-            followers: vec![],
             local: row.try_get("is_local")?,
         })
     }
@@ -165,45 +279,106 @@ impl ApubObject for EventActor {
         object_id: Url,
         data: &Self::DataType,
     ) -> Result<Option<Self>, Self::Error> {
-        let found_actor: EventActor = sqlx::query_as("SELECT * FROM actors WHERE ap_id = $1")
-            .bind(&object_id.to_string())
-            .fetch_one(&data.pool)
+        sqlx::query_as("SELECT * FROM actors WHERE ap_id = $1")
+            .bind(object_id.to_string())
+            .fetch_optional(&data.pool)
             .await
-            .map_err(|err| ApEventsError::ActorNotFound(object_id.to_string(), err.into()))?;
-        Ok(Some(found_actor))
+            .map_err(|err| ApEventsError::ActorNotFound(object_id.to_string(), err.into()))
     }
 
     async fn into_apub(self, data: &Self::DataType) -> Result<ActPubActor, Self::Error> {
         let ap_id = self.ap_id.to_string();
         let actor_ref_parts: Vec<&str> = self.actor_ref.split('@').collect();
+        // Drafts are described once they are published.
+        let event = get_event(data, &ap_id)
+            .await?
+            .filter(|event| !event.is_draft());
+        let calendar = get_calendar(data, &ap_id).await?;
+
+        let (name, summary, attachments) = match &event {
+            Some(event) => {
+                let tz = event.tz();
+                let mut attachments = vec![
+                    property_value("starts_at", event.starts_at.with_timezone(&tz).to_rfc3339()),
+                    property_value("ends_at", event.ends_at.with_timezone(&tz).to_rfc3339()),
+                    property_value("event", self.event_url()?.to_string()),
+                ];
+                if let Some(location) = &event.location {
+                    attachments.push(property_value("location", location.clone()));
+                }
+                (
+                    event.title.clone(),
+                    Some(event.summary.clone()),
+                    attachments,
+                )
+            }
+            None => match get_place(data, &ap_id).await? {
+                Some(place) => {
+                    let mut attachments = vec![];
+                    if let Some(address) = &place.address {
+                        attachments.push(property_value("address", address.clone()));
+                    }
+                    if let (Some(latitude), Some(longitude)) = (place.latitude, place.longitude) {
+                        attachments.push(property_value(
+                            "coordinates",
+                            format!("{latitude}, {longitude}"),
+                        ));
+                    }
+                    if let Some(accessibility) = &place.accessibility {
+                        attachments.push(property_value("accessibility", accessibility.clone()));
+                    }
+                    attachments.push(property_value("place", self.place_url()?.to_string()));
+                    (place.name, None, attachments)
+                }
+                None => match &calendar {
+                    Some(calendar) => (
+                        calendar.name.clone(),
+                        Some(calendar.summary.clone()),
+                        vec![property_value(
+                            "calendar",
+                            self.calendar_feed_url()?.to_string(),
+                        )],
+                    ),
+                    None => (actor_ref_parts[0].to_string(), None, vec![]),
+                },
+            },
+        };
+        let discoverable = !attachments.is_empty();
+        let (also_known_as, moved_to) = actor_aliases(data, &ap_id).await?;
 
         Ok(ActPubActor {
             ap_id: ap_id.clone(),
-            kind: "Person".to_string(),
+            // Calendars are groups of events, whatever kind events are.
+            kind: match calendar {
+                Some(_) => "Group".to_string(),
+                None => data.actor_kind.clone(),
+            },
             following: Some(self.following_url()?.to_string()),
             followers: Some(self.followers_url()?.to_string()),
             inbox: Some(self.inbox().to_string()),
-            outbox: None,
+            outbox: Some(self.outbox_url()?.to_string()),
             featured: None,
             featured_tags: None,
-            name: actor_ref_parts[0].to_string(),
+            name,
             preferred_username: Some(actor_ref_parts[0].to_string()),
-            summary: None,
+            summary,
             url: Some(format!("{}@{}", data.external_base, actor_ref_parts[0])),
-            discoverable: Some(false),
+            discoverable: Some(discoverable),
             published: None,
             public_key: Some(ActorPublicKey {
                 ap_id: self.public_key_id,
                 owner: ap_id,
                 public_key_pem: self.public_key,
             }),
-            attachments: vec![],
+            attachments,
             endpoints: HashMap::from([(
                 "sharedInbox".to_string(),
                 format!("{}/inbox", data.external_base),
             )]),
             icon: None,
             image: None,
+            also_known_as,
+            moved_to,
         })
     }
 
@@ -221,10 +396,24 @@ impl ApubObject for EventActor {
         data: &Self::DataType,
         _request_counter: &mut i32,
     ) -> Result<Self, Self::Error> {
-        Ok(sqlx::query_as("SELECT * FROM actors WHERE ap_id = $1")
-            .bind(apub.ap_id)
-            .fetch_one(&data.pool)
-            .await?)
+        let found_actor: Option<EventActor> =
+            sqlx::query_as("SELECT * FROM actors WHERE ap_id = $1")
+                .bind(&apub.ap_id)
+                .fetch_optional(&data.pool)
+                .await?;
+
+        match found_actor {
+            Some(found_actor) => Ok(found_actor),
+            None => create_actor(data, apub, None).await,
+        }
+    }
+}
+
+fn property_value(name: &str, value: String) -> ActorAttachment {
+    ActorAttachment {
+        kind: "PropertyValue".to_string(),
+        name: name.to_string(),
+        value,
     }
 }
 
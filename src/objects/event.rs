@@ -0,0 +1,266 @@
+use activitypub_federation::core::object_id::ObjectId;
+use askama::{Html, MarkupDisplay};
+use chrono::{DateTime, Utc};
+use url::Url;
+
+use crate::{
+    ap::{
+        event::{Event as ApEvent, Link, Place},
+        normalize::PUBLIC,
+        note::Tag,
+    },
+    error::ApEventsError,
+    objects::actor::EventActor,
+    recurrence::Occurrence,
+    state::MyStateHandle,
+    storage_events::{get_event, Event},
+    storage_rsvps::ALL_OCCURRENCES,
+};
+
+/// Returns true if the url is an event actor or Event object on this server.
+pub fn is_local_event_url(app_state: &MyStateHandle, object: &Url) -> bool {
+    object
+        .as_str()
+        .starts_with(&format!("{}/actor/", app_state.external_base))
+}
+
+/// An event that an activity refers to, and the occurrence if it refers to a single one.
+pub struct LocalEvent {
+    pub actor: EventActor,
+    pub event: Event,
+    pub occurrence: Option<Occurrence>,
+}
+
+impl LocalEvent {
+    /// The occurrence id that RSVPs to this event are stored under.
+    pub fn occurrence_id(&self) -> String {
+        self.occurrence
+            .map(|occurrence| occurrence.id())
+            .unwrap_or_else(|| ALL_OCCURRENCES.to_string())
+    }
+
+    /// The first occurrence whose seats count against answers to the whole event.
+    pub fn seats_since(&self, now: DateTime<Utc>) -> String {
+        self.event.seats_since(now)
+    }
+}
+
+/// Finds the event that an activity refers to. Attendees address either the Event object of an
+/// occurrence, the Event object or the event actor itself.
+pub async fn local_event(
+    app_state: &MyStateHandle,
+    object: &Url,
+) -> Result<LocalEvent, ApEventsError> {
+    if !is_local_event_url(app_state, object) {
+        return Err(ApEventsError::ObjectNotFound(object.to_string()));
+    }
+    let (actor_ap_id, occurrence_id) = match object.as_str().split_once("/event/") {
+        Some((actor_ap_id, occurrence_id)) => (actor_ap_id, Some(occurrence_id)),
+        None => (
+            object
+                .as_str()
+                .strip_suffix("/event")
+                .unwrap_or(object.as_str()),
+            None,
+        ),
+    };
+
+    let found_actor = ObjectId::<EventActor>::new(Url::parse(actor_ap_id)?)
+        .dereference_local(app_state)
+        .await?;
+    let event = get_event(app_state, actor_ap_id)
+        .await?
+        .ok_or_else(|| ApEventsError::ObjectNotFound(object.to_string()))?;
+    let occurrence = match occurrence_id {
+        Some(occurrence_id) => Some(
+            event
+                .occurrence(occurrence_id)
+                .ok_or_else(|| ApEventsError::ObjectNotFound(object.to_string()))?,
+        ),
+        None => None,
+    };
+    Ok(LocalEvent {
+        actor: found_actor,
+        event,
+        occurrence,
+    })
+}
+
+/// The `Event` object that accompanies an event actor, or one of its occurrences. Times are given
+/// in the event's own timezone so that remote calendars show the local time the organizer
+/// entered.
+pub fn event_into_apub(
+    event: &Event,
+    actor: &EventActor,
+    occurrence: Option<&Occurrence>,
+    participant_count: i64,
+) -> Result<ApEvent, ApEventsError> {
+    let tz = event.tz();
+    let (ap_id, starts_at, ends_at) = match occurrence {
+        Some(occurrence) => (
+            actor.occurrence_url(occurrence)?,
+            occurrence.starts_at,
+            occurrence.ends_at,
+        ),
+        None => (actor.event_url()?, event.starts_at, event.ends_at),
+    };
+
+    Ok(ApEvent {
+        ap_id: ap_id.to_string(),
+        kind: "Event".to_string(),
+        name: event.title.clone(),
+        content: format!("<p>{}</p>", MarkupDisplay::new_unsafe(&event.summary, Html)),
+        url: actor.ap_id.to_string(),
+        attributed_to: actor.ap_id.to_string(),
+        start_time: starts_at.with_timezone(&tz).fixed_offset(),
+        end_time: ends_at.with_timezone(&tz).fixed_offset(),
+        timezone: event.timezone.clone(),
+        location: event.location.as_ref().map(|location| Place {
+            ap_id: event
+                .place_ap_id
+                .as_ref()
+                .map(|place_ap_id| format!("{place_ap_id}/place")),
+            kind: "Place".to_string(),
+            name: location.clone(),
+            address: None,
+            latitude: None,
+            longitude: None,
+            accessibility: None,
+            attributed_to: event.place_ap_id.clone(),
+        }),
+        join_mode: event.join_mode.clone(),
+        participants: actor.participants_url()?.to_string(),
+        participant_count,
+        maximum_attendee_capacity: event.capacity,
+        remaining_attendee_capacity: event
+            .capacity
+            .map(|capacity| (i64::from(capacity) - participant_count).max(0)),
+        rrule: match occurrence {
+            Some(_) => None,
+            None => event.rrule.clone(),
+        },
+        status: event.status.ical_status().to_string(),
+        event_status: event.status.schema_status().to_string(),
+        event_attendance_mode: event.attendance_mode().schema_mode().to_string(),
+        attachment: event
+            .public_online_url()
+            .map(|online_url| Link {
+                kind: "Link".to_string(),
+                name: "Join online".to_string(),
+                href: online_url.to_string(),
+            })
+            .into_iter()
+            .collect(),
+        tag: event
+            .tags
+            .iter()
+            .map(|tag| {
+                Ok(Tag::hashtag(
+                    actor.ap_id.inner().join(&format!("/?tag={tag}"))?.as_str(),
+                    tag,
+                ))
+            })
+            .collect::<Result<_, ApEventsError>>()?,
+        to: vec![PUBLIC.to_string()],
+        cc: vec![actor.followers_url()?.to_string()],
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::ap::{event::event_context, normalize::normalize_activity};
+
+    #[test]
+    fn event_object() {
+        let actor = EventActor {
+            ap_id: ObjectId::new(
+                Url::parse("https://events.thegem.city/actor/readily-splendid-mule").unwrap(),
+            ),
+            actor_ref: "readily-splendid-mule@events.thegem.city".to_string(),
+            inbox: Url::parse("https://events.thegem.city/actor/readily-splendid-mule/inbox")
+                .unwrap(),
+            public_key_id: "https://events.thegem.city/actor/readily-splendid-mule#main-key"
+                .to_string(),
+            public_key: "".to_string(),
+            private_key: None,
+            local: true,
+        };
+        let event = Event {
+            summary: "Dancing & drinks".to_string(),
+            capacity: Some(40),
+            tags: vec!["swing".to_string()],
+            online_url: Some("https://meet.example.com/swing-night".to_string()),
+            ..Event::example()
+        };
+
+        let json = serde_json::to_value(event_into_apub(&event, &actor, None, 3).unwrap()).unwrap();
+        assert_eq!(
+            json,
+            serde_json::json!({
+                "id": "https://events.thegem.city/actor/readily-splendid-mule/event",
+                "type": "Event",
+                "name": "Swing night",
+                "content": "<p>Dancing &amp; drinks</p>",
+                "url": "https://events.thegem.city/actor/readily-splendid-mule",
+                "attributedTo": "https://events.thegem.city/actor/readily-splendid-mule",
+                "startTime": "2022-11-01T20:00:00-04:00",
+                "endTime": "2022-11-01T23:00:00-04:00",
+                "timezone": "America/New_York",
+                "location": {
+                    "type": "Place",
+                    "name": "The bar, 555 nowhere, dayton, oh 45419"
+                },
+                "joinMode": "free",
+                "participants": "https://events.thegem.city/actor/readily-splendid-mule/participants",
+                "participantCount": 3,
+                "maximumAttendeeCapacity": 40,
+                "remainingAttendeeCapacity": 37,
+                "ical:status": "CONFIRMED",
+                "eventStatus": "https://schema.org/EventScheduled",
+                "eventAttendanceMode": "https://schema.org/MixedEventAttendanceMode",
+                "attachment": [{
+                    "type": "Link",
+                    "name": "Join online",
+                    "href": "https://meet.example.com/swing-night"
+                }],
+                "tag": [{
+                    "type": "Hashtag",
+                    "href": "https://events.thegem.city/?tag=swing",
+                    "name": "#swing"
+                }],
+                "to": ["https://www.w3.org/ns/activitystreams#Public"],
+                "cc": ["https://events.thegem.city/actor/readily-splendid-mule/followers"]
+            })
+        );
+
+        // The object reads back the same once it is normalized as an incoming activity.
+        let normalized = normalize_activity(serde_json::json!({
+            "@context": event_context(),
+            "id": "https://events.thegem.city/activity/AXBhdWV3aS0xMjM0NTY3ODkwYWI",
+            "type": "Create",
+            "actor": "https://events.thegem.city/actor/readily-splendid-mule",
+            "to": ["https://www.w3.org/ns/activitystreams#Public"],
+            "object": json,
+        }))
+        .unwrap();
+        assert_eq!(normalized["@context"], serde_json::json!(event_context()));
+        assert_eq!(normalized["object"], json);
+        let object: ApEvent = serde_json::from_value(normalized["object"].clone()).unwrap();
+        assert_eq!(object, event_into_apub(&event, &actor, None, 3).unwrap());
+
+        // Links that only attendees may see are left out.
+        let private = Event {
+            online_url_private: true,
+            location: None,
+            ..event
+        };
+        let object = event_into_apub(&private, &actor, None, 3).unwrap();
+        assert!(object.attachment.is_empty());
+        assert_eq!(
+            object.event_attendance_mode,
+            "https://schema.org/OnlineEventAttendanceMode"
+        );
+    }
+}
@@ -0,0 +1,202 @@
+use activitypub_federation::traits::Actor;
+use url::Url;
+
+use crate::{
+    activities::announce::Announce,
+    ap::{
+        event::{event_context, Place as ApPlace},
+        ids::{generate_object_id, KindType},
+    },
+    error::ApEventsError,
+    objects::actor::EventActor,
+    state::MyStateHandle,
+    storage_actor::get_actors,
+    storage_events::Event,
+    storage_follows::followers,
+    storage_places::{get_place, Place},
+};
+
+/// Places closer together than this are taken to be the same venue.
+const SAME_PLACE_METERS: f64 = 50.0;
+
+/// The `Place` object of a place on this server.
+pub fn place_into_apub(place: &Place, actor: &EventActor) -> Result<ApPlace, ApEventsError> {
+    Ok(ApPlace {
+        ap_id: Some(actor.place_url()?.to_string()),
+        kind: "Place".to_string(),
+        name: place.name.clone(),
+        address: place.address.clone(),
+        latitude: place.latitude,
+        longitude: place.longitude,
+        accessibility: place.accessibility.clone(),
+        attributed_to: Some(actor.ap_id.to_string()),
+    })
+}
+
+/// Shares an event with the followers of the place it is held at, as an `Announce` of the Event
+/// object sent by the place.
+pub async fn announce_at_place(
+    app_state: &MyStateHandle,
+    event: &Event,
+) -> Result<(), ApEventsError> {
+    let place_ap_id = match &event.place_ap_id {
+        Some(place_ap_id) => place_ap_id,
+        None => return Ok(()),
+    };
+    if get_place(app_state, place_ap_id).await?.is_none() {
+        return Ok(());
+    }
+    let place_actor: EventActor = sqlx::query_as("SELECT * FROM actors WHERE ap_id = $1")
+        .bind(place_ap_id)
+        .fetch_one(&app_state.pool)
+        .await?;
+
+    let inboxes = get_actors(app_state, &followers(app_state, place_ap_id).await?)
+        .await?
+        .iter()
+        .map(|follower| follower.shared_inbox_or_inbox())
+        .collect();
+    let announce = Announce::new(
+        &place_actor,
+        Url::parse(&format!("{}/event", event.actor_ap_id))?,
+        generate_object_id(&app_state.external_base, KindType::Announce)?,
+    )?;
+    place_actor
+        .send_with_context(announce, Some(event_context()), inboxes, app_state)
+        .await
+}
+
+/// Groups places that are likely the same venue: their names or addresses match once case,
+/// punctuation and a leading "the" are ignored, or they are within 50 meters of each other. Each
+/// group lists the actors of two or more places.
+pub fn find_duplicates(places: &[Place]) -> Vec<Vec<String>> {
+    let mut parents: Vec<usize> = (0..places.len()).collect();
+
+    fn root(parents: &mut [usize], mut index: usize) -> usize {
+        while parents[index] != index {
+            parents[index] = parents[parents[index]];
+            index = parents[index];
+        }
+        index
+    }
+
+    for (i, place) in places.iter().enumerate() {
+        for (j, other) in places.iter().enumerate().skip(i + 1) {
+            if same_place(place, other) {
+                let (i, j) = (root(&mut parents, i), root(&mut parents, j));
+                parents[j] = i;
+            }
+        }
+    }
+
+    let mut groups: Vec<(usize, Vec<String>)> = vec![];
+    for (index, place) in places.iter().enumerate() {
+        let group = root(&mut parents, index);
+        match groups.iter_mut().find(|(root, _)| *root == group) {
+            Some((_, members)) => members.push(place.actor_ap_id.clone()),
+            None => groups.push((group, vec![place.actor_ap_id.clone()])),
+        }
+    }
+    groups
+        .into_iter()
+        .map(|(_, members)| members)
+        .filter(|members| members.len() > 1)
+        .collect()
+}
+
+fn same_place(place: &Place, other: &Place) -> bool {
+    if normalize(&place.name) == normalize(&other.name) {
+        return true;
+    }
+    if let (Some(address), Some(other_address)) = (&place.address, &other.address) {
+        let address = normalize(address);
+        if !address.is_empty() && address == normalize(other_address) {
+            return true;
+        }
+    }
+    match (
+        place.latitude,
+        place.longitude,
+        other.latitude,
+        other.longitude,
+    ) {
+        (Some(lat1), Some(lon1), Some(lat2), Some(lon2)) => {
+            distance_meters(lat1, lon1, lat2, lon2) < SAME_PLACE_METERS
+        }
+        _ => false,
+    }
+}
+
+fn normalize(text: &str) -> String {
+    let text = text.trim().to_lowercase();
+    let text = text.strip_prefix("the ").unwrap_or(&text);
+    text.chars().filter(|c| c.is_alphanumeric()).collect()
+}
+
+/// The great-circle distance between two coordinates.
+fn distance_meters(lat1: f64, lon1: f64, lat2: f64, lon2: f64) -> f64 {
+    const EARTH_RADIUS_METERS: f64 = 6_371_000.0;
+    let (lat1, lat2) = (lat1.to_radians(), lat2.to_radians());
+    let half_lat = (lat2 - lat1) / 2.0;
+    let half_lon = (lon2 - lon1).to_radians() / 2.0;
+    let a = half_lat.sin().powi(2) + lat1.cos() * lat2.cos() * half_lon.sin().powi(2);
+    2.0 * EARTH_RADIUS_METERS * a.sqrt().asin()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn place(
+        actor_ap_id: &str,
+        name: &str,
+        address: Option<&str>,
+        at: Option<(f64, f64)>,
+    ) -> Place {
+        Place {
+            actor_ap_id: actor_ap_id.to_string(),
+            name: name.to_string(),
+            address: address.map(str::to_string),
+            latitude: at.map(|at| at.0),
+            longitude: at.map(|at| at.1),
+            accessibility: None,
+        }
+    }
+
+    #[test]
+    fn duplicates() {
+        let places = vec![
+            place(
+                "a",
+                "The Brightside",
+                Some("905 E 3rd St, Dayton, OH"),
+                None,
+            ),
+            place("b", "Brightside", None, Some((39.7582, -84.1813))),
+            place("c", "Yellow Cab Tavern", Some("700 E 4th St"), None),
+            place(
+                "d",
+                "Brightside Music Venue",
+                None,
+                Some((39.7584, -84.1815)),
+            ),
+            place("e", "Yellow Cab", Some("700 e. 4th st."), None),
+            place("f", "Levitt Pavilion", None, Some((39.7614, -84.1916))),
+        ];
+        assert_eq!(
+            find_duplicates(&places),
+            vec![
+                vec!["a".to_string(), "b".to_string(), "d".to_string()],
+                vec!["c".to_string(), "e".to_string()],
+            ]
+        );
+        assert!(find_duplicates(&places[..1]).is_empty());
+    }
+
+    #[test]
+    fn distance() {
+        let meters = distance_meters(39.7582, -84.1813, 39.7614, -84.1916);
+        assert!((900.0..1000.0).contains(&meters), "{meters}");
+        assert_eq!(distance_meters(39.7582, -84.1813, 39.7582, -84.1813), 0.0);
+    }
+}
@@ -0,0 +1,736 @@
+use std::{collections::VecDeque, str::FromStr};
+
+use chrono::{
+    DateTime, Datelike, Duration, LocalResult, Months, NaiveDate, NaiveDateTime, Offset, TimeZone,
+    Utc, Weekday,
+};
+use chrono_tz::Tz;
+
+use crate::error::ApEventsError;
+
+/// Occurrence ids are the UTC start of the occurrence in the iCalendar RECURRENCE-ID form.
+const OCCURRENCE_ID_FORMAT: &str = "%Y%m%dT%H%M%SZ";
+
+/// Periods in a row without a single occurrence before a rule is considered exhausted, so that
+/// rules like `FREQ=YEARLY;BYMONTH=2;BYMONTHDAY=30` end instead of looping forever.
+const MAX_EMPTY_PERIODS: u32 = 1000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Frequency {
+    Daily,
+    Weekly,
+    Monthly,
+    Yearly,
+}
+
+/// A BYDAY entry, such as `TU`, `2TU` (the second Tuesday) or `-1FR` (the last Friday).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ByDay {
+    pub ordinal: Option<i32>,
+    pub weekday: Weekday,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Until {
+    Date(NaiveDate),
+    Local(NaiveDateTime),
+    Utc(DateTime<Utc>),
+}
+
+/// The supported subset of an RFC 5545 RRULE.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Recurrence {
+    pub frequency: Frequency,
+    pub interval: u32,
+    pub count: Option<u32>,
+    pub until: Option<Until>,
+    pub by_day: Vec<ByDay>,
+    pub by_month_day: Vec<i32>,
+    pub by_month: Vec<u32>,
+    pub week_start: Weekday,
+}
+
+fn parse_weekday(value: &str) -> Option<Weekday> {
+    match value {
+        "MO" => Some(Weekday::Mon),
+        "TU" => Some(Weekday::Tue),
+        "WE" => Some(Weekday::Wed),
+        "TH" => Some(Weekday::Thu),
+        "FR" => Some(Weekday::Fri),
+        "SA" => Some(Weekday::Sat),
+        "SU" => Some(Weekday::Sun),
+        _ => None,
+    }
+}
+
+fn parse_until(value: &str) -> Option<Until> {
+    if let Some(utc) = value.strip_suffix('Z') {
+        return NaiveDateTime::parse_from_str(utc, "%Y%m%dT%H%M%S")
+            .ok()
+            .map(|value| Until::Utc(value.and_utc()));
+    }
+    if value.len() == 8 {
+        return NaiveDate::parse_from_str(value, "%Y%m%d")
+            .ok()
+            .map(Until::Date);
+    }
+    NaiveDateTime::parse_from_str(value, "%Y%m%dT%H%M%S")
+        .ok()
+        .map(Until::Local)
+}
+
+impl FromStr for Recurrence {
+    type Err = ApEventsError;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        let invalid = |message: String| ApEventsError::InvalidEvent(format!("rrule {message}"));
+
+        let mut frequency = None;
+        let mut recurrence = Recurrence {
+            frequency: Frequency::Daily,
+            interval: 1,
+            count: None,
+            until: None,
+            by_day: vec![],
+            by_month_day: vec![],
+            by_month: vec![],
+            week_start: Weekday::Mon,
+        };
+
+        let value = value.trim();
+        let value = value.strip_prefix("RRULE:").unwrap_or(value);
+        for part in value.split(';').filter(|part| !part.is_empty()) {
+            let (name, value) = part
+                .split_once('=')
+                .ok_or_else(|| invalid(format!("part {part} has no value")))?;
+            let bad_value = || invalid(format!("{name} has an invalid value {value}"));
+            match name {
+                "FREQ" => {
+                    frequency = Some(match value {
+                        "DAILY" => Frequency::Daily,
+                        "WEEKLY" => Frequency::Weekly,
+                        "MONTHLY" => Frequency::Monthly,
+                        "YEARLY" => Frequency::Yearly,
+                        _ => return Err(bad_value()),
+                    })
+                }
+                "INTERVAL" => {
+                    recurrence.interval = value
+                        .parse()
+                        .ok()
+                        .filter(|interval| *interval >= 1)
+                        .ok_or_else(bad_value)?
+                }
+                "COUNT" => {
+                    recurrence.count = Some(
+                        value
+                            .parse()
+                            .ok()
+                            .filter(|count| *count >= 1)
+                            .ok_or_else(bad_value)?,
+                    )
+                }
+                "UNTIL" => recurrence.until = Some(parse_until(value).ok_or_else(bad_value)?),
+                "BYDAY" => {
+                    for day in value.split(',') {
+                        let split = day.len().checked_sub(2).ok_or_else(bad_value)?;
+                        let (ordinal, weekday) = day.split_at(split);
+                        recurrence.by_day.push(ByDay {
+                            ordinal: match ordinal {
+                                "" => None,
+                                ordinal => Some(
+                                    ordinal
+                                        .parse::<i32>()
+                                        .ok()
+                                        .filter(|ordinal| *ordinal != 0 && ordinal.abs() <= 53)
+                                        .ok_or_else(bad_value)?,
+                                ),
+                            },
+                            weekday: parse_weekday(weekday).ok_or_else(bad_value)?,
+                        });
+                    }
+                }
+                "BYMONTHDAY" => {
+                    for day in value.split(',') {
+                        recurrence.by_month_day.push(
+                            day.parse::<i32>()
+                                .ok()
+                                .filter(|day| *day != 0 && day.abs() <= 31)
+                                .ok_or_else(bad_value)?,
+                        );
+                    }
+                }
+                "BYMONTH" => {
+                    for month in value.split(',') {
+                        recurrence.by_month.push(
+                            month
+                                .parse::<u32>()
+                                .ok()
+                                .filter(|month| (1..=12).contains(month))
+                                .ok_or_else(bad_value)?,
+                        );
+                    }
+                }
+                "WKST" => recurrence.week_start = parse_weekday(value).ok_or_else(bad_value)?,
+                _ => return Err(invalid(format!("part {name} is not supported"))),
+            }
+        }
+
+        recurrence.frequency = frequency.ok_or_else(|| invalid("FREQ is required".to_string()))?;
+        if recurrence.count.is_some() && recurrence.until.is_some() {
+            return Err(invalid("may not have both COUNT and UNTIL".to_string()));
+        }
+        let has_ordinals = recurrence.by_day.iter().any(|day| day.ordinal.is_some());
+        if has_ordinals && !matches!(recurrence.frequency, Frequency::Monthly | Frequency::Yearly) {
+            return Err(invalid(
+                "BYDAY ordinals are only allowed for MONTHLY and YEARLY rules".to_string(),
+            ));
+        }
+        Ok(recurrence)
+    }
+}
+
+/// Every date in the range with the given weekday, narrowed to the nth one (counting from the end
+/// when negative) if there is an ordinal.
+fn weekdays_between(first: NaiveDate, last: NaiveDate, day: &ByDay) -> Vec<NaiveDate> {
+    let offset =
+        (7 + day.weekday.num_days_from_monday() - first.weekday().num_days_from_monday()) % 7;
+    let all: Vec<NaiveDate> = first
+        .checked_add_signed(Duration::days(offset.into()))
+        .into_iter()
+        .flat_map(|start| start.iter_weeks())
+        .take_while(|date| *date <= last)
+        .collect();
+    match day.ordinal {
+        None => all,
+        Some(ordinal) if ordinal > 0 => {
+            all.get(ordinal as usize - 1).copied().into_iter().collect()
+        }
+        Some(ordinal) => all
+            .len()
+            .checked_sub(ordinal.unsigned_abs() as usize)
+            .and_then(|index| all.get(index).copied())
+            .into_iter()
+            .collect(),
+    }
+}
+
+fn last_of_month(first: NaiveDate) -> NaiveDate {
+    first
+        .checked_add_months(Months::new(1))
+        .and_then(|next| next.pred_opt())
+        .unwrap_or(first)
+}
+
+impl Recurrence {
+    /// The dates in the month starting at `first` that the rule selects, given the date of the
+    /// first occurrence.
+    fn month_dates(&self, first: NaiveDate, start: NaiveDate) -> Vec<NaiveDate> {
+        let last = last_of_month(first);
+        if !self.by_month_day.is_empty() {
+            return self
+                .by_month_day
+                .iter()
+                .filter_map(|day| match *day > 0 {
+                    true => first.with_day(*day as u32),
+                    false => last
+                        .checked_sub_signed(Duration::days((-day - 1).into()))
+                        .filter(|date| date.month() == first.month()),
+                })
+                .filter(|date| {
+                    self.by_day.is_empty()
+                        || self.by_day.iter().any(|day| day.weekday == date.weekday())
+                })
+                .collect();
+        }
+        if !self.by_day.is_empty() {
+            return self
+                .by_day
+                .iter()
+                .flat_map(|day| weekdays_between(first, last, day))
+                .collect();
+        }
+        first.with_day(start.day()).into_iter().collect()
+    }
+
+    /// The dates in the period starting at `first` that the rule selects, unsorted.
+    fn period_dates(&self, first: NaiveDate, start: NaiveDate) -> Vec<NaiveDate> {
+        let dates = match self.frequency {
+            Frequency::Daily => vec![first],
+            Frequency::Weekly => {
+                let weekdays: Vec<Weekday> = match self.by_day.is_empty() {
+                    true => vec![start.weekday()],
+                    false => self.by_day.iter().map(|day| day.weekday).collect(),
+                };
+                first
+                    .iter_days()
+                    .take(7)
+                    .filter(|date| weekdays.contains(&date.weekday()))
+                    .collect()
+            }
+            Frequency::Monthly => self.month_dates(first, start),
+            Frequency::Yearly
+                if self.by_month.is_empty()
+                    && self.by_month_day.is_empty()
+                    && !self.by_day.is_empty() =>
+            {
+                let last = NaiveDate::from_ymd_opt(first.year(), 12, 31).unwrap_or(first);
+                self.by_day
+                    .iter()
+                    .flat_map(|day| weekdays_between(first, last, day))
+                    .collect()
+            }
+            Frequency::Yearly => {
+                let months: Vec<u32> =
+                    match (self.by_month.is_empty(), self.by_month_day.is_empty()) {
+                        (false, _) => self.by_month.clone(),
+                        (true, false) => (1..=12).collect(),
+                        (true, true) => vec![start.month()],
+                    };
+                months
+                    .into_iter()
+                    .filter_map(|month| first.with_month(month))
+                    .flat_map(|month| self.month_dates(month, start))
+                    .collect()
+            }
+        };
+
+        dates
+            .into_iter()
+            .filter(|date| self.by_month.is_empty() || self.by_month.contains(&date.month()))
+            .filter(|date| {
+                self.frequency != Frequency::Daily
+                    || ((self.by_day.is_empty()
+                        || self.by_day.iter().any(|day| day.weekday == date.weekday()))
+                        && (self.by_month_day.is_empty()
+                            || self
+                                .month_dates(date.with_day(1).unwrap_or(*date), start)
+                                .contains(date)))
+            })
+            .collect()
+    }
+
+    /// The first day of the period that contains the first occurrence.
+    fn first_period(&self, start: NaiveDate) -> NaiveDate {
+        match self.frequency {
+            Frequency::Daily => start,
+            Frequency::Weekly => {
+                let back = (7 + start.weekday().num_days_from_monday()
+                    - self.week_start.num_days_from_monday())
+                    % 7;
+                start - Duration::days(back.into())
+            }
+            Frequency::Monthly => start.with_day(1).unwrap_or(start),
+            Frequency::Yearly => NaiveDate::from_ymd_opt(start.year(), 1, 1).unwrap_or(start),
+        }
+    }
+
+    fn next_period(&self, period: NaiveDate) -> Option<NaiveDate> {
+        match self.frequency {
+            Frequency::Daily => period.checked_add_signed(Duration::days(self.interval.into())),
+            Frequency::Weekly => period.checked_add_signed(Duration::weeks(self.interval.into())),
+            Frequency::Monthly => period.checked_add_months(Months::new(self.interval)),
+            Frequency::Yearly => period.checked_add_months(Months::new(self.interval * 12)),
+        }
+    }
+}
+
+/// Converts a local time to UTC. Ambiguous times take the first of the two instants, and times
+/// skipped by a DST change are read with the offset from before the change, which moves them
+/// forward by the length of the gap, as RFC 5545 requires.
+pub fn resolve_local(tz: &Tz, local: &NaiveDateTime) -> DateTime<Utc> {
+    match tz.from_local_datetime(local) {
+        LocalResult::Single(value) => value.with_timezone(&Utc),
+        LocalResult::Ambiguous(earliest, _) => earliest.with_timezone(&Utc),
+        LocalResult::None => {
+            let before = tz
+                .offset_from_utc_datetime(&(*local - Duration::days(1)))
+                .fix()
+                .local_minus_utc();
+            (*local - Duration::seconds(before.into())).and_utc()
+        }
+    }
+}
+
+/// A single instance of an event.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Occurrence {
+    pub starts_at: DateTime<Utc>,
+    pub ends_at: DateTime<Utc>,
+}
+
+impl Occurrence {
+    pub fn id(&self) -> String {
+        self.starts_at.format(OCCURRENCE_ID_FORMAT).to_string()
+    }
+
+    pub fn parse_id(id: &str) -> Option<DateTime<Utc>> {
+        NaiveDateTime::parse_from_str(id, OCCURRENCE_ID_FORMAT)
+            .ok()
+            .map(|value| value.and_utc())
+    }
+}
+
+/// The occurrences of an event in order. Occurrences keep the local start time of the first one,
+/// so a weekly 7pm event stays at 7pm across DST changes, and keep its length.
+pub struct Occurrences {
+    recurrence: Option<Recurrence>,
+    tz: Tz,
+    start: NaiveDateTime,
+    length: Duration,
+    exdates: Vec<DateTime<Utc>>,
+    period: Option<NaiveDate>,
+    pending: VecDeque<NaiveDateTime>,
+    generated: u32,
+    empty_periods: u32,
+}
+
+impl Occurrences {
+    pub fn new(
+        recurrence: Option<Recurrence>,
+        tz: Tz,
+        starts_at: DateTime<Utc>,
+        ends_at: DateTime<Utc>,
+        exdates: Vec<DateTime<Utc>>,
+    ) -> Occurrences {
+        let start = starts_at.with_timezone(&tz).naive_local();
+        Occurrences {
+            period: recurrence
+                .as_ref()
+                .map(|recurrence| recurrence.first_period(start.date())),
+            recurrence,
+            tz,
+            start,
+            length: ends_at - starts_at,
+            exdates,
+            pending: VecDeque::from([start]),
+            generated: 0,
+            empty_periods: 0,
+        }
+    }
+
+    fn is_past_until(&self, local: &NaiveDateTime, utc: &DateTime<Utc>) -> bool {
+        match self
+            .recurrence
+            .as_ref()
+            .and_then(|recurrence| recurrence.until)
+        {
+            Some(Until::Date(date)) => local.date() > date,
+            Some(Until::Local(until)) => *local > until,
+            Some(Until::Utc(until)) => *utc > until,
+            None => false,
+        }
+    }
+
+    /// Queues the occurrences of the next period that has any.
+    fn fill(&mut self) {
+        let recurrence = match &self.recurrence {
+            Some(recurrence) => recurrence,
+            None => return,
+        };
+        while self.pending.is_empty() && self.empty_periods < MAX_EMPTY_PERIODS {
+            let period = match self.period {
+                Some(period) => period,
+                None => return,
+            };
+            let mut dates = recurrence.period_dates(period, self.start.date());
+            dates.sort();
+            dates.dedup();
+            self.pending.extend(
+                dates
+                    .into_iter()
+                    .map(|date| date.and_time(self.start.time()))
+                    .filter(|local| *local > self.start),
+            );
+            self.period = recurrence.next_period(period);
+            match self.pending.is_empty() {
+                true => self.empty_periods += 1,
+                false => self.empty_periods = 0,
+            }
+        }
+    }
+}
+
+impl Iterator for Occurrences {
+    type Item = Occurrence;
+
+    fn next(&mut self) -> Option<Occurrence> {
+        loop {
+            self.fill();
+            let local = self.pending.pop_front()?;
+            let count = self
+                .recurrence
+                .as_ref()
+                .and_then(|recurrence| recurrence.count);
+            if count.map(|count| self.generated >= count) == Some(true) {
+                return None;
+            }
+            let starts_at = resolve_local(&self.tz, &local);
+            if self.is_past_until(&local, &starts_at) {
+                self.pending.clear();
+                self.period = None;
+                return None;
+            }
+            // Excluded dates still count towards COUNT.
+            self.generated += 1;
+            if self.recurrence.is_some() && self.exdates.contains(&starts_at) {
+                continue;
+            }
+            return Some(Occurrence {
+                starts_at,
+                ends_at: starts_at + self.length,
+            });
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn local(tz: &Tz, year: i32, month: u32, day: u32, hour: u32, min: u32) -> DateTime<Utc> {
+        resolve_local(
+            tz,
+            &NaiveDate::from_ymd_opt(year, month, day)
+                .unwrap()
+                .and_hms_opt(hour, min, 0)
+                .unwrap(),
+        )
+    }
+
+    fn expand(rule: &str, tz: Tz, starts_at: DateTime<Utc>, take: usize) -> Vec<String> {
+        Occurrences::new(
+            Some(rule.parse().unwrap()),
+            tz,
+            starts_at,
+            starts_at + Duration::hours(2),
+            vec![],
+        )
+        .take(take)
+        .map(|occurrence| {
+            occurrence
+                .starts_at
+                .with_timezone(&tz)
+                .format("%Y-%m-%d %H:%M %Z")
+                .to_string()
+        })
+        .collect()
+    }
+
+    #[test]
+    fn parse_rules() {
+        let recurrence: Recurrence = "FREQ=MONTHLY;INTERVAL=2;BYDAY=2TU,-1FR;COUNT=6"
+            .parse()
+            .unwrap();
+        assert_eq!(recurrence.frequency, Frequency::Monthly);
+        assert_eq!(recurrence.interval, 2);
+        assert_eq!(recurrence.count, Some(6));
+        assert_eq!(
+            recurrence.by_day,
+            vec![
+                ByDay {
+                    ordinal: Some(2),
+                    weekday: Weekday::Tue
+                },
+                ByDay {
+                    ordinal: Some(-1),
+                    weekday: Weekday::Fri
+                }
+            ]
+        );
+        assert_eq!(
+            "RRULE:FREQ=WEEKLY;UNTIL=20230101"
+                .parse::<Recurrence>()
+                .unwrap(),
+            Recurrence {
+                frequency: Frequency::Weekly,
+                interval: 1,
+                count: None,
+                until: Some(Until::Date(NaiveDate::from_ymd_opt(2023, 1, 1).unwrap())),
+                by_day: vec![],
+                by_month_day: vec![],
+                by_month: vec![],
+                week_start: Weekday::Mon,
+            }
+        );
+
+        for invalid in [
+            "",
+            "INTERVAL=2",
+            "FREQ=HOURLY",
+            "FREQ=WEEKLY;INTERVAL=0",
+            "FREQ=WEEKLY;BYDAY=2TU",
+            "FREQ=WEEKLY;BYDAY=XX",
+            "FREQ=MONTHLY;BYMONTHDAY=32",
+            "FREQ=YEARLY;BYMONTH=13",
+            "FREQ=DAILY;COUNT=3;UNTIL=20230101",
+            "FREQ=MONTHLY;BYSETPOS=-1",
+        ] {
+            assert!(invalid.parse::<Recurrence>().is_err(), "{invalid}");
+        }
+    }
+
+    #[test]
+    fn weekly_across_dst() {
+        let tz: Tz = "America/New_York".parse().unwrap();
+        assert_eq!(
+            expand("FREQ=WEEKLY", tz, local(&tz, 2022, 10, 25, 19, 0), 3),
+            vec![
+                "2022-10-25 19:00 EDT",
+                "2022-11-01 19:00 EDT",
+                "2022-11-08 19:00 EST"
+            ]
+        );
+        assert_eq!(
+            expand(
+                "FREQ=WEEKLY;BYDAY=TU,TH;COUNT=5",
+                tz,
+                local(&tz, 2023, 3, 7, 19, 0),
+                10
+            ),
+            vec![
+                "2023-03-07 19:00 EST",
+                "2023-03-09 19:00 EST",
+                "2023-03-14 19:00 EDT",
+                "2023-03-16 19:00 EDT",
+                "2023-03-21 19:00 EDT"
+            ]
+        );
+    }
+
+    #[test]
+    fn skipped_and_repeated_times() {
+        let tz: Tz = "America/New_York".parse().unwrap();
+        // 2:30am does not exist on 2023-03-12 and happens twice on 2023-11-05.
+        assert_eq!(
+            expand("FREQ=DAILY", tz, local(&tz, 2023, 3, 11, 2, 30), 3),
+            vec![
+                "2023-03-11 02:30 EST",
+                "2023-03-12 03:30 EDT",
+                "2023-03-13 02:30 EDT"
+            ]
+        );
+        assert_eq!(
+            expand("FREQ=DAILY", tz, local(&tz, 2023, 11, 4, 1, 30), 2),
+            vec!["2023-11-04 01:30 EDT", "2023-11-05 01:30 EDT"]
+        );
+    }
+
+    #[test]
+    fn monthly_and_yearly() {
+        let tz = Tz::UTC;
+        assert_eq!(
+            expand(
+                "FREQ=MONTHLY;BYDAY=-1FR",
+                tz,
+                local(&tz, 2022, 10, 28, 18, 0),
+                3
+            ),
+            vec![
+                "2022-10-28 18:00 UTC",
+                "2022-11-25 18:00 UTC",
+                "2022-12-30 18:00 UTC"
+            ]
+        );
+        // Months without a 31st are skipped.
+        assert_eq!(
+            expand("FREQ=MONTHLY", tz, local(&tz, 2023, 1, 31, 18, 0), 3),
+            vec![
+                "2023-01-31 18:00 UTC",
+                "2023-03-31 18:00 UTC",
+                "2023-05-31 18:00 UTC"
+            ]
+        );
+        assert_eq!(
+            expand(
+                "FREQ=MONTHLY;BYMONTHDAY=1,-1",
+                tz,
+                local(&tz, 2023, 2, 1, 9, 0),
+                3
+            ),
+            vec![
+                "2023-02-01 09:00 UTC",
+                "2023-02-28 09:00 UTC",
+                "2023-03-01 09:00 UTC"
+            ]
+        );
+        assert_eq!(
+            expand(
+                "FREQ=YEARLY;BYMONTH=11;BYDAY=4TH",
+                tz,
+                local(&tz, 2022, 11, 24, 17, 0),
+                2
+            ),
+            vec!["2022-11-24 17:00 UTC", "2023-11-23 17:00 UTC"]
+        );
+        assert_eq!(
+            expand(
+                "FREQ=YEARLY;BYMONTH=2;BYMONTHDAY=30",
+                tz,
+                local(&tz, 2023, 1, 1, 0, 0),
+                3
+            ),
+            vec!["2023-01-01 00:00 UTC"]
+        );
+    }
+
+    #[test]
+    fn until_count_and_exdates() {
+        let tz: Tz = "Europe/Berlin".parse().unwrap();
+        let starts_at = local(&tz, 2023, 1, 2, 19, 0);
+        assert_eq!(
+            expand(
+                "FREQ=WEEKLY;INTERVAL=2;UNTIL=20230130T180000Z",
+                tz,
+                starts_at,
+                10
+            ),
+            vec![
+                "2023-01-02 19:00 CET",
+                "2023-01-16 19:00 CET",
+                "2023-01-30 19:00 CET"
+            ]
+        );
+        assert_eq!(
+            expand("FREQ=WEEKLY;UNTIL=20230116", tz, starts_at, 10).len(),
+            3
+        );
+
+        let occurrences: Vec<Occurrence> = Occurrences::new(
+            Some("FREQ=WEEKLY;COUNT=3".parse().unwrap()),
+            tz,
+            starts_at,
+            starts_at + Duration::hours(2),
+            vec![local(&tz, 2023, 1, 9, 19, 0)],
+        )
+        .collect();
+        assert_eq!(occurrences.len(), 2);
+        assert_eq!(occurrences[1].starts_at, local(&tz, 2023, 1, 16, 19, 0));
+        assert_eq!(occurrences[1].ends_at, local(&tz, 2023, 1, 16, 21, 0));
+        assert_eq!(occurrences[1].id(), "20230116T180000Z");
+        assert_eq!(
+            Occurrence::parse_id("20230116T180000Z"),
+            Some(occurrences[1].starts_at)
+        );
+    }
+
+    #[test]
+    fn single_event() {
+        let starts_at = local(&Tz::UTC, 2023, 1, 2, 19, 0);
+        let occurrences: Vec<Occurrence> = Occurrences::new(
+            None,
+            Tz::UTC,
+            starts_at,
+            starts_at + Duration::hours(1),
+            vec![starts_at],
+        )
+        .collect();
+        assert_eq!(
+            occurrences,
+            vec![Occurrence {
+                starts_at,
+                ends_at: starts_at + Duration::hours(1)
+            }]
+        );
+    }
+}
@@ -0,0 +1,196 @@
+use activitypub_federation::core::object_id::ObjectId;
+use askama::{Html, MarkupDisplay};
+use chrono::{DateTime, Duration, Utc};
+use log::warn;
+use url::Url;
+
+use crate::{
+    error::ApEventsError,
+    handler_events::format_time,
+    messages::send_note,
+    objects::actor::EventActor,
+    recurrence::Occurrence,
+    state::MyStateHandle,
+    storage_actor::get_actors,
+    storage_events::{upcoming_events, Event, EventStatus},
+    storage_reminders::{claim_reminder, event_reminders},
+    storage_rsvps::going_attendees,
+};
+
+/// Reminders may be sent up to a week before an event.
+const MAX_REMINDER_MINUTES: i32 = 7 * 24 * 60;
+
+/// Reads reminder offsets such as `24h, 1h` or `2d 30m` as minutes before an event starts,
+/// longest first. An empty list turns reminders off.
+pub fn parse_offsets(value: &str) -> Result<Vec<i32>, ApEventsError> {
+    let invalid = |offset: &str| {
+        ApEventsError::InvalidQuery(format!(
+            "invalid reminder {offset}, reminders look like 24h, 1h or 30m"
+        ))
+    };
+
+    let mut offsets = value
+        .split(|c: char| c == ',' || c.is_whitespace())
+        .filter(|offset| !offset.is_empty())
+        .map(|offset| {
+            let unit = offset.chars().next_back().map_or(0, char::len_utf8);
+            let (number, unit) = offset.split_at(offset.len() - unit);
+            let number: i32 = number.parse().map_err(|_| invalid(offset))?;
+            let minutes = match unit {
+                "d" => number.checked_mul(24 * 60),
+                "h" => number.checked_mul(60),
+                "m" => Some(number),
+                _ => None,
+            };
+            match minutes {
+                Some(minutes @ 1..=MAX_REMINDER_MINUTES) => Ok(minutes),
+                _ => Err(invalid(offset)),
+            }
+        })
+        .collect::<Result<Vec<i32>, ApEventsError>>()?;
+    offsets.sort_unstable_by(|a, b| b.cmp(a));
+    offsets.dedup();
+    Ok(offsets)
+}
+
+/// Writes reminder offsets the way `parse_offsets` reads them.
+pub fn format_offsets(offsets: &[i32]) -> String {
+    offsets
+        .iter()
+        .map(|minutes| match minutes {
+            minutes if minutes % (24 * 60) == 0 && *minutes > 24 * 60 => {
+                format!("{}d", minutes / (24 * 60))
+            }
+            minutes if minutes % 60 == 0 => format!("{}h", minutes / 60),
+            minutes => format!("{minutes}m"),
+        })
+        .collect::<Vec<String>>()
+        .join(", ")
+}
+
+/// The reminder that is due for an occurrence, if any. When several are due, such as after the
+/// server was down, only the one closest to the start is sent.
+pub fn due_reminder(offsets: &[i32], starts_at: DateTime<Utc>, now: DateTime<Utc>) -> Option<i32> {
+    if now >= starts_at {
+        return None;
+    }
+    offsets
+        .iter()
+        .copied()
+        .filter(|minutes| starts_at - Duration::minutes((*minutes).into()) <= now)
+        .min()
+}
+
+/// Sends the reminders that are due to everyone going to upcoming events.
+pub async fn run_reminders(app_state: MyStateHandle) -> Result<(), ApEventsError> {
+    let now = Utc::now();
+    for event in upcoming_events(&app_state, now).await? {
+        if event.status != EventStatus::Published {
+            continue;
+        }
+        if let Err(err) = remind_attendees(&app_state, &event, now).await {
+            warn!("cannot send reminders for {}: {}", event.actor_ap_id, err);
+        }
+    }
+    Ok(())
+}
+
+async fn remind_attendees(
+    app_state: &MyStateHandle,
+    event: &Event,
+    now: DateTime<Utc>,
+) -> Result<(), ApEventsError> {
+    let offsets = event_reminders(app_state, &event.actor_ap_id)
+        .await?
+        .unwrap_or_else(|| app_state.default_reminders.clone());
+    let latest = match offsets.iter().max() {
+        Some(minutes) => now + Duration::minutes((*minutes).into()),
+        None => return Ok(()),
+    };
+    let due: Vec<(Occurrence, i32)> = event
+        .occurrences()
+        .filter(|occurrence| occurrence.ends_at > now)
+        .take_while(|occurrence| occurrence.starts_at <= latest)
+        .filter_map(|occurrence| {
+            due_reminder(&offsets, occurrence.starts_at, now).map(|minutes| (occurrence, minutes))
+        })
+        .collect();
+    if due.is_empty() {
+        return Ok(());
+    }
+
+    let actor = ObjectId::<EventActor>::new(Url::parse(&event.actor_ap_id)?)
+        .dereference_local(app_state)
+        .await?;
+    let title = MarkupDisplay::new_unsafe(&event.title, Html);
+    for (occurrence, minutes) in due {
+        let occurrence_id = occurrence.id();
+        // Attendees who answer late get the latest reminder that is due, so the message names
+        // the start time rather than how far away it is.
+        let link = match event.online_link_html() {
+            Some(link) => format!(" {link}"),
+            None => String::new(),
+        };
+        let content = format!(
+            "<p>Reminder: {title} starts on {}.{link}</p>",
+            format_time(&event.tz(), &occurrence.starts_at)
+        );
+        let attendees = going_attendees(app_state, &event.actor_ap_id, &occurrence_id).await?;
+        for attendee in get_actors(app_state, &attendees).await? {
+            let attendee_ap_id = attendee.ap_id.inner().as_str();
+            if !claim_reminder(
+                app_state,
+                &event.actor_ap_id,
+                &occurrence_id,
+                attendee_ap_id,
+                minutes,
+            )
+            .await?
+            {
+                continue;
+            }
+            send_note(app_state, &actor, &[attendee], false, content.clone(), None).await?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use chrono::TimeZone;
+
+    #[test]
+    fn offsets() {
+        assert_eq!(parse_offsets("24h, 1h").unwrap(), vec![1440, 60]);
+        assert_eq!(parse_offsets("30m 2d,1h 1h").unwrap(), vec![2880, 60, 30]);
+        assert_eq!(parse_offsets("  ").unwrap(), Vec::<i32>::new());
+        assert!(parse_offsets("1w").is_err());
+        assert!(parse_offsets("h").is_err());
+        assert!(parse_offsets("0h").is_err());
+        assert!(parse_offsets("8d").is_err());
+        assert!(parse_offsets("1é").is_err());
+        assert!(parse_offsets("99999999d").is_err());
+
+        assert_eq!(format_offsets(&[2880, 1440, 60, 30]), "2d, 24h, 1h, 30m");
+        assert_eq!(format_offsets(&[]), "");
+    }
+
+    #[test]
+    fn due_reminders() {
+        let starts_at = Utc.with_ymd_and_hms(2022, 11, 2, 0, 0, 0).unwrap();
+        let offsets = [1440, 60];
+
+        let before = |hours: i64, minutes: i64| {
+            starts_at - Duration::hours(hours) - Duration::minutes(minutes)
+        };
+        assert_eq!(due_reminder(&offsets, starts_at, before(25, 0)), None);
+        assert_eq!(due_reminder(&offsets, starts_at, before(24, 0)), Some(1440));
+        assert_eq!(due_reminder(&offsets, starts_at, before(2, 0)), Some(1440));
+        assert_eq!(due_reminder(&offsets, starts_at, before(1, 0)), Some(60));
+        assert_eq!(due_reminder(&offsets, starts_at, before(0, 1)), Some(60));
+        assert_eq!(due_reminder(&offsets, starts_at, starts_at), None);
+        assert_eq!(due_reminder(&[], starts_at, before(0, 1)), None);
+    }
+}
@@ -0,0 +1,400 @@
+use chrono::{DateTime, Duration, NaiveDate, Utc};
+use serde::Deserialize;
+use sqlx::{Postgres, QueryBuilder};
+use url::form_urlencoded;
+
+use crate::{
+    error::ApEventsError,
+    recurrence::Occurrence,
+    state::MyStateHandle,
+    storage_events::{normalize_tag, Event},
+};
+
+pub const SEARCH_PAGE_SIZE: usize = 20;
+
+/// The text that events are searched by. It has to stay the same as the `events_search` index.
+const SEARCH_DOCUMENT: &str =
+    "to_tsvector('simple', title || ' ' || summary || ' ' || coalesce(location, ''))";
+
+/// An event search as it is given in the query string of the home page and of `/api/v1/events`.
+/// Empty values are ignored, so that submitting the search form with blank fields works.
+#[derive(Debug, Default, Deserialize)]
+pub struct EventSearch {
+    /// Words to look for in the title, description and location.
+    pub q: Option<String>,
+    /// The first day to list events on, as `YYYY-MM-DD` in UTC.
+    pub from: Option<String>,
+    /// The last day to list events on, as `YYYY-MM-DD` in UTC.
+    pub to: Option<String>,
+    /// The place actor events are held at.
+    pub place: Option<String>,
+    pub tag: Option<String>,
+    /// `online` or `in-person`. Hybrid events match both.
+    pub mode: Option<String>,
+    pub page: Option<u32>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SearchMode {
+    Online,
+    InPerson,
+}
+
+/// A validated event search.
+#[derive(Debug, PartialEq, Eq)]
+pub struct SearchFilters {
+    pub q: Option<String>,
+    pub from_day: Option<NaiveDate>,
+    pub to_day: Option<NaiveDate>,
+    /// Where the searched range starts and ends.
+    pub from: DateTime<Utc>,
+    pub to: Option<DateTime<Utc>>,
+    pub place: Option<String>,
+    pub tag: Option<String>,
+    pub mode: Option<SearchMode>,
+    pub page: u32,
+}
+
+/// An event that matched a search, with its first occurrence in the searched range.
+pub struct SearchResult {
+    pub event: Event,
+    pub occurrence: Occurrence,
+}
+
+pub struct SearchPage {
+    pub results: Vec<SearchResult>,
+    pub has_more: bool,
+}
+
+fn non_empty(value: &Option<String>) -> Option<&str> {
+    value
+        .as_deref()
+        .map(str::trim)
+        .filter(|value| !value.is_empty())
+}
+
+fn parse_day(name: &str, value: &str) -> Result<NaiveDate, ApEventsError> {
+    NaiveDate::parse_from_str(value, "%Y-%m-%d")
+        .map_err(|_| ApEventsError::InvalidQuery(format!("{name} must be a YYYY-MM-DD date")))
+}
+
+impl EventSearch {
+    /// Checks the search. Only upcoming events are listed, so the range never starts before
+    /// `now`, and the last day is included in it.
+    pub fn filters(&self, now: DateTime<Utc>) -> Result<SearchFilters, ApEventsError> {
+        let q = non_empty(&self.q).map(str::to_string);
+        if q.as_ref().is_some_and(|q| q.len() > 256) {
+            return Err(ApEventsError::InvalidQuery(
+                "q must be at most 256 characters".to_string(),
+            ));
+        }
+
+        let from_day = non_empty(&self.from)
+            .map(|from| parse_day("from", from))
+            .transpose()?;
+        let to_day = non_empty(&self.to)
+            .map(|to| parse_day("to", to))
+            .transpose()?;
+        let from = from_day.map_or(now, |from| from.and_hms_opt(0, 0, 0).unwrap().and_utc());
+        let to = to_day.map(|to| to.and_hms_opt(0, 0, 0).unwrap().and_utc() + Duration::days(1));
+        if to.is_some_and(|to| to <= from) {
+            return Err(ApEventsError::InvalidQuery(
+                "to must not be before from".to_string(),
+            ));
+        }
+
+        let mode = match non_empty(&self.mode) {
+            None => None,
+            Some("online") => Some(SearchMode::Online),
+            Some("in-person") => Some(SearchMode::InPerson),
+            Some(mode) => {
+                return Err(ApEventsError::InvalidQuery(format!(
+                    "unknown mode {mode}, expected online or in-person"
+                )))
+            }
+        };
+
+        Ok(SearchFilters {
+            q,
+            from_day,
+            to_day,
+            from: from.max(now),
+            to,
+            place: non_empty(&self.place).map(str::to_string),
+            tag: non_empty(&self.tag).map(normalize_tag),
+            mode,
+            page: self.page.unwrap_or(1).max(1),
+        })
+    }
+}
+
+impl SearchFilters {
+    /// The query string of another page of this search.
+    pub fn page_query(&self, page: u32) -> String {
+        let mut query = form_urlencoded::Serializer::new(String::new());
+        if let Some(q) = &self.q {
+            query.append_pair("q", q);
+        }
+        if let Some(from) = &self.from_day {
+            query.append_pair("from", &from.to_string());
+        }
+        if let Some(to) = &self.to_day {
+            query.append_pair("to", &to.to_string());
+        }
+        if let Some(place) = &self.place {
+            query.append_pair("place", place);
+        }
+        if let Some(tag) = &self.tag {
+            query.append_pair("tag", tag);
+        }
+        match self.mode {
+            Some(SearchMode::Online) => {
+                query.append_pair("mode", "online");
+            }
+            Some(SearchMode::InPerson) => {
+                query.append_pair("mode", "in-person");
+            }
+            None => {}
+        }
+        query.append_pair("page", &page.to_string());
+        query.finish()
+    }
+
+    /// Events that repeat, or the first `limit` of those that don't. Events that don't repeat
+    /// have one occurrence, so they can be ordered and cut in the query. Events that repeat are
+    /// ordered by their first occurrence in the range, which is worked out after they are loaded.
+    fn query(&self, limit: Option<usize>) -> QueryBuilder<'static, Postgres> {
+        let mut query = QueryBuilder::new(
+            "SELECT events.* FROM events JOIN actors ON actors.ap_id = events.actor_ap_id WHERE actors.deleted_at IS NULL AND actors.moved_to IS NULL AND events.status != 'draft'",
+        );
+        match limit {
+            Some(_) => {
+                query
+                    .push(" AND events.rrule IS NULL AND events.ends_at > ")
+                    .push_bind(self.from);
+            }
+            None => {
+                query.push(" AND events.rrule IS NOT NULL");
+            }
+        }
+        if let Some(to) = self.to {
+            query.push(" AND events.starts_at < ").push_bind(to);
+        }
+        if let Some(q) = &self.q {
+            query
+                .push(" AND ")
+                .push(SEARCH_DOCUMENT)
+                .push(" @@ websearch_to_tsquery('simple', ")
+                .push_bind(q.clone())
+                .push(")");
+        }
+        if let Some(place) = &self.place {
+            query
+                .push(" AND events.place_ap_id = ")
+                .push_bind(place.clone());
+        }
+        if let Some(tag) = &self.tag {
+            query
+                .push(" AND events.tags @> ARRAY[")
+                .push_bind(tag.clone())
+                .push("]::varchar[]");
+        }
+        match self.mode {
+            Some(SearchMode::Online) => {
+                query.push(" AND events.online_url IS NOT NULL");
+            }
+            Some(SearchMode::InPerson) => {
+                query.push(" AND (events.location IS NOT NULL OR events.online_url IS NULL)");
+            }
+            None => {}
+        }
+        // Ties are ordered bytewise, the same as the results are sorted in.
+        query.push(" ORDER BY events.starts_at, events.actor_ap_id COLLATE \"C\"");
+        if let Some(limit) = limit {
+            query.push(" LIMIT ").push_bind(limit as i64);
+        }
+        query
+    }
+}
+
+/// Finds local, published events that have an occurrence in the searched range, soonest first.
+/// Only as many events that don't repeat as the page needs are loaded, and they are merged with
+/// the events that repeat.
+pub async fn search_events(
+    app_state: &MyStateHandle,
+    filters: &SearchFilters,
+) -> Result<SearchPage, ApEventsError> {
+    let skip = (filters.page as usize - 1) * SEARCH_PAGE_SIZE;
+    // One more than the page needs, to tell whether there is another page.
+    let needed = skip + SEARCH_PAGE_SIZE + 1;
+    let mut events: Vec<Event> = filters
+        .query(Some(needed))
+        .build_query_as()
+        .fetch_all(&app_state.pool)
+        .await?;
+    let recurring: Vec<Event> = filters
+        .query(None)
+        .build_query_as()
+        .fetch_all(&app_state.pool)
+        .await?;
+    events.extend(recurring);
+
+    let mut results: Vec<SearchResult> = events
+        .into_iter()
+        .filter_map(|event| {
+            let occurrence = event
+                .occurrences()
+                .find(|occurrence| occurrence.ends_at > filters.from)?;
+            if filters.to.is_some_and(|to| occurrence.starts_at >= to) {
+                return None;
+            }
+            Some(SearchResult { event, occurrence })
+        })
+        .collect();
+    results.sort_by(|a, b| {
+        (a.occurrence.starts_at, &a.event.actor_ap_id)
+            .cmp(&(b.occurrence.starts_at, &b.event.actor_ap_id))
+    });
+
+    let has_more = results.len() > skip + SEARCH_PAGE_SIZE;
+    let results = results
+        .into_iter()
+        .skip(skip)
+        .take(SEARCH_PAGE_SIZE)
+        .collect();
+    Ok(SearchPage { results, has_more })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    #[test]
+    fn filters() {
+        let now = Utc.with_ymd_and_hms(2024, 3, 1, 12, 0, 0).unwrap();
+
+        let blank = EventSearch {
+            q: Some(" ".to_string()),
+            from: Some("".to_string()),
+            mode: Some("".to_string()),
+            ..Default::default()
+        };
+        let filters = blank.filters(now).unwrap();
+        assert_eq!(filters.q, None);
+        assert_eq!(filters.from, now);
+        assert_eq!(filters.to, None);
+        assert_eq!(filters.page, 1);
+
+        let search = EventSearch {
+            q: Some("swing night".to_string()),
+            from: Some("2024-02-01".to_string()),
+            to: Some("2024-03-31".to_string()),
+            tag: Some("#Swing".to_string()),
+            mode: Some("in-person".to_string()),
+            page: Some(2),
+            ..Default::default()
+        };
+        let filters = search.filters(now).unwrap();
+        assert_eq!(filters.from, now);
+        assert_eq!(
+            filters.to,
+            Some(Utc.with_ymd_and_hms(2024, 4, 1, 0, 0, 0).unwrap())
+        );
+        assert_eq!(filters.tag.as_deref(), Some("swing"));
+        assert_eq!(filters.mode, Some(SearchMode::InPerson));
+        assert_eq!(
+            filters.page_query(3),
+            "q=swing+night&from=2024-02-01&to=2024-03-31&tag=swing&mode=in-person&page=3"
+        );
+
+        for (from, to, mode) in [
+            (Some("03/01/2024"), None, None),
+            (Some("2024-03-10"), Some("2024-03-09"), None),
+            (None, None, Some("hybrid")),
+        ] {
+            let search = EventSearch {
+                from: from.map(str::to_string),
+                to: to.map(str::to_string),
+                mode: mode.map(str::to_string),
+                ..Default::default()
+            };
+            assert!(matches!(
+                search.filters(now),
+                Err(ApEventsError::InvalidQuery(_))
+            ));
+        }
+    }
+
+    #[actix_web::test]
+    async fn pages_mix_repeating_events() {
+        let Some(app_state) = crate::state::test_state().await else {
+            return;
+        };
+        let tag = format!("search{}", rand::random::<u64>());
+        let now = Utc::now();
+        let day = |days: i64| now + Duration::days(days);
+
+        let insert = |name: String, starts_at: DateTime<Utc>, rrule: Option<&'static str>| {
+            let app_state = app_state.clone();
+            let tag = tag.clone();
+            async move {
+                let actor = format!("https://events.example/actor/{name}");
+                sqlx::query(
+                    "INSERT INTO actors (ap_id, actor_ref, is_local, public_key_id, public_key) VALUES ($1, $1, true, $1, '')",
+                )
+                .bind(&actor)
+                .execute(&app_state.pool)
+                .await
+                .unwrap();
+                sqlx::query(
+                    "INSERT INTO events (actor_ap_id, title, starts_at, ends_at, rrule, tags) VALUES ($1, 'Swing night', $2, $3, $4, ARRAY[$5]::varchar[])",
+                )
+                .bind(&actor)
+                .bind(starts_at)
+                .bind(starts_at + Duration::hours(3))
+                .bind(rrule)
+                .bind(&tag)
+                .execute(&app_state.pool)
+                .await
+                .unwrap();
+                actor
+            }
+        };
+
+        // Events on the days 2 to 26 from now, one that has ended, and one that started long
+        // ago and repeats every day, so it comes first.
+        let mut once = vec![];
+        for days in 2..27 {
+            once.push(insert(format!("{tag}-{days:02}"), day(days), None).await);
+        }
+        insert(format!("{tag}-ended"), day(-2), None).await;
+        let daily = insert(format!("{tag}-daily"), day(-60), Some("FREQ=DAILY")).await;
+
+        let search = |page: u32| EventSearch {
+            tag: Some(tag.clone()),
+            page: Some(page),
+            ..Default::default()
+        };
+        let found = |page: &SearchPage| -> Vec<String> {
+            page.results
+                .iter()
+                .map(|result| result.event.actor_ap_id.clone())
+                .collect()
+        };
+
+        let first = search_events(&app_state, &search(1).filters(now).unwrap())
+            .await
+            .unwrap();
+        assert!(first.has_more);
+        let mut expected = vec![daily.clone()];
+        expected.extend(once[..19].iter().cloned());
+        assert_eq!(found(&first), expected);
+
+        let second = search_events(&app_state, &search(2).filters(now).unwrap())
+            .await
+            .unwrap();
+        assert!(!second.has_more);
+        assert_eq!(found(&second), once[19..].to_vec());
+    }
+}
@@ -1,8 +1,15 @@
+use std::collections::HashMap;
+
+use activitypub_federation::core::signatures::generate_actor_keypair;
+use chrono::NaiveDateTime;
 use reqwest::Url;
 use sqlx::{Postgres, QueryBuilder};
 
 use crate::{
-    ap::actor::Actor, error::ApEventsError, objects::actor::EventActor, state::MyStateHandle,
+    ap::actor::{Actor, PublicKey},
+    error::ApEventsError,
+    objects::actor::EventActor,
+    state::MyStateHandle,
 };
 
 pub async fn create_actor(
@@ -34,10 +41,10 @@ pub async fn create_actor(
         .await?;
     }
 
-    if private_key.is_some() {
+    if let Some(private_key) = private_key {
         sqlx::query("UPDATE actors SET private_key = $2 WHERE ap_id = $1")
             .bind(ap_id)
-            .bind(private_key.unwrap())
+            .bind(private_key)
             .execute(&mut tx)
             .await?;
     }
@@ -93,6 +100,146 @@ pub fn insert_actor_query<'a>(
     Ok(query_builder)
 }
 
+/// The known actors among the given ids. Unknown ids are skipped.
+pub async fn get_actors(
+    app_state: &MyStateHandle,
+    ap_ids: &[String],
+) -> Result<Vec<EventActor>, ApEventsError> {
+    sqlx::query_as("SELECT * FROM actors WHERE ap_id = ANY($1)")
+        .bind(ap_ids)
+        .fetch_all(&app_state.pool)
+        .await
+        .map_err(|err| err.into())
+}
+
+/// When a local actor was deleted, if it was.
+pub async fn actor_deleted_at(
+    app_state: &MyStateHandle,
+    ap_id: &str,
+) -> Result<Option<NaiveDateTime>, ApEventsError> {
+    let found: Option<(Option<NaiveDateTime>,)> =
+        sqlx::query_as("SELECT deleted_at FROM actors WHERE ap_id = $1")
+            .bind(ap_id)
+            .fetch_optional(&app_state.pool)
+            .await?;
+    Ok(found.and_then(|(deleted_at,)| deleted_at))
+}
+
+/// The accounts an actor is also known as, and the account it moved to, if it moved.
+pub async fn actor_aliases(
+    app_state: &MyStateHandle,
+    ap_id: &str,
+) -> Result<(Vec<String>, Option<String>), ApEventsError> {
+    let found: Option<(Vec<String>, Option<String>)> =
+        sqlx::query_as("SELECT also_known_as, moved_to FROM actors WHERE ap_id = $1")
+            .bind(ap_id)
+            .fetch_optional(&app_state.pool)
+            .await?;
+    Ok(found.unwrap_or_default())
+}
+
+/// A local actor whose key can sign requests that are not made on behalf of a particular event.
+pub async fn signing_actor(app_state: &MyStateHandle) -> Result<Option<EventActor>, ApEventsError> {
+    Ok(sqlx::query_as(
+        "SELECT * FROM actors WHERE is_local AND private_key IS NOT NULL AND deleted_at IS NULL ORDER BY created_at LIMIT 1",
+    )
+    .fetch_optional(&app_state.pool)
+    .await?)
+}
+
+/// Records another account of an actor, such as the one it was imported from.
+pub async fn add_actor_alias(
+    app_state: &MyStateHandle,
+    ap_id: &str,
+    alias: &str,
+) -> Result<(), ApEventsError> {
+    sqlx::query(
+        "UPDATE actors SET also_known_as = array_append(also_known_as, $2::varchar), updated_at = now() WHERE ap_id = $1 AND NOT ($2 = ANY (also_known_as))",
+    )
+    .bind(ap_id)
+    .bind(alias)
+    .execute(&app_state.pool)
+    .await?;
+    Ok(())
+}
+
+/// Records that an actor moved to another account.
+pub async fn set_moved_to(
+    app_state: &MyStateHandle,
+    ap_id: &str,
+    moved_to: &str,
+) -> Result<(), ApEventsError> {
+    sqlx::query("UPDATE actors SET moved_to = $2, updated_at = now() WHERE ap_id = $1")
+        .bind(ap_id)
+        .bind(moved_to)
+        .execute(&app_state.pool)
+        .await?;
+    Ok(())
+}
+
+/// Creates a new local event actor with a generated name, returning the name and the actor.
+pub async fn create_local_actor(
+    app_state: &MyStateHandle,
+) -> Result<(String, EventActor), ApEventsError> {
+    let name = petname::Petnames::default().generate_one(3, "-");
+    let object_id = format!("{}/actor/{}", app_state.external_base, name);
+
+    let keypair = generate_actor_keypair().map_err(|_| ApEventsError::Unknown)?;
+
+    let found_actor = create_actor(
+        app_state,
+        Actor {
+            ap_id: object_id.clone(),
+            kind: app_state.actor_kind.clone(),
+
+            following: Some(format!(
+                "{}/actor/{}/following",
+                app_state.external_base, name
+            )),
+            followers: Some(format!(
+                "{}/actor/{}/followers",
+                app_state.external_base, name
+            )),
+            inbox: Some(format!("{}/actor/{}/inbox", app_state.external_base, name)),
+            outbox: Some(format!("{}/actor/{}/outbox", app_state.external_base, name)),
+            featured: None,
+
+            featured_tags: None,
+
+            name: name.clone(),
+            summary: None,
+            preferred_username: Some(name.clone()),
+
+            url: Some(format!("{}/@{}", app_state.external_base, name.clone())),
+
+            discoverable: None,
+            published: None,
+
+            public_key: Some(PublicKey {
+                ap_id: format!("{}/actor/{}#main-key", app_state.external_base, name),
+                owner: object_id,
+                public_key_pem: keypair.public_key,
+            }),
+
+            attachments: vec![],
+
+            endpoints: HashMap::from([(
+                "sharedInbox".to_string(),
+                format!("{}/inbox", app_state.external_base),
+            )]),
+
+            icon: None,
+            image: None,
+            also_known_as: vec![],
+            moved_to: None,
+        },
+        Some(keypair.private_key),
+    )
+    .await?;
+
+    Ok((name, found_actor))
+}
+
 #[cfg(test)]
 mod tests {
     use std::collections::HashMap;
@@ -126,7 +273,9 @@ mod tests {
             ],
             endpoints: HashMap::from([("sharedInbox".to_string(), "https://thegem.city/inbox".to_string())]),
             icon: None,
-            image: None
+            image: None,
+            also_known_as: vec![],
+            moved_to: None,
         };
 
         assert_eq!(insert_actor_query(&cmp_actor, "thegem.city").expect("query built").sql(), "INSERT INTO actors (ap_id, actor_ref, is_local, inbox_id, public_key_id, public_key, resources) VALUES ($1, $2, $3, $4, $5, $6, ARRAY[$1, $2])");
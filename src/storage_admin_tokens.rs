@@ -0,0 +1,44 @@
+use chrono::{DateTime, Utc};
+
+use crate::{
+    ap::ids::generate_token, error::ApEventsError, state::MyStateHandle, storage_organizers::Role,
+};
+
+/// Creates a token for the admin page of an event that works until it expires. The page only
+/// offers what the organizer's role allows. Earlier tokens keep working until they expire too.
+pub async fn issue_admin_token(
+    app_state: &MyStateHandle,
+    actor_ap_id: &str,
+    role: Role,
+    expires_at: DateTime<Utc>,
+) -> Result<String, ApEventsError> {
+    let token = generate_token();
+    sqlx::query(
+        "INSERT INTO admin_tokens (token, actor_ap_id, role, expires_at) VALUES ($1, $2, $3, $4)",
+    )
+    .bind(&token)
+    .bind(actor_ap_id)
+    .bind(role.as_str())
+    .bind(expires_at)
+    .execute(&app_state.pool)
+    .await?;
+    Ok(token)
+}
+
+/// The role that a token grants on the event's admin page, if it grants access at all.
+pub async fn admin_token_role(
+    app_state: &MyStateHandle,
+    actor_ap_id: &str,
+    token: &str,
+    now: DateTime<Utc>,
+) -> Result<Option<Role>, ApEventsError> {
+    let found: Option<(String,)> = sqlx::query_as(
+        "SELECT role FROM admin_tokens WHERE token = $1 AND actor_ap_id = $2 AND expires_at > $3",
+    )
+    .bind(token)
+    .bind(actor_ap_id)
+    .bind(now)
+    .fetch_optional(&app_state.pool)
+    .await?;
+    found.map(|(role,)| role.parse()).transpose()
+}
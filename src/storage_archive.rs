@@ -0,0 +1,88 @@
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+
+use crate::{error::ApEventsError, state::MyStateHandle};
+
+/// What is kept of an event after it expires.
+#[derive(Serialize, Debug, Clone)]
+pub struct ArchivedEvent {
+    pub actor_ap_id: String,
+    pub title: String,
+    pub starts_at: DateTime<Utc>,
+    pub ends_at: DateTime<Utc>,
+    pub timezone: String,
+    pub status: String,
+    pub follower_count: i64,
+    pub attendee_count: i64,
+}
+
+/// Archives the summary of an expired event, then removes the event with its follows, RSVPs,
+/// reminders and admin tokens and marks its actor and the objects it sent deleted. The rows are
+/// kept so that their ids answer with a Tombstone, except for the `Delete` that announced it.
+pub async fn archive_event(
+    app_state: &MyStateHandle,
+    archived: &ArchivedEvent,
+) -> Result<(), ApEventsError> {
+    let actor_ap_id = &archived.actor_ap_id;
+    let mut tx = app_state.pool.begin().await?;
+
+    sqlx::query(
+        "INSERT INTO event_archive (actor_ap_id, title, starts_at, ends_at, timezone, status, follower_count, attendee_count) VALUES ($1, $2, $3, $4, $5, $6, $7, $8) ON CONFLICT ON CONSTRAINT event_archive_pkey DO NOTHING",
+    )
+    .bind(actor_ap_id)
+    .bind(&archived.title)
+    .bind(archived.starts_at)
+    .bind(archived.ends_at)
+    .bind(&archived.timezone)
+    .bind(&archived.status)
+    .bind(archived.follower_count)
+    .bind(archived.attendee_count)
+    .execute(&mut tx)
+    .await?;
+
+    sqlx::query("DELETE FROM rsvps WHERE actor_ap_id = $1")
+        .bind(actor_ap_id)
+        .execute(&mut tx)
+        .await?;
+    sqlx::query("DELETE FROM follow_activities WHERE followee_ap_id = $1 OR follower_ap_id = $1")
+        .bind(actor_ap_id)
+        .execute(&mut tx)
+        .await?;
+    sqlx::query("DELETE FROM pending_updates WHERE actor_ap_id = $1")
+        .bind(actor_ap_id)
+        .execute(&mut tx)
+        .await?;
+    sqlx::query("DELETE FROM sent_reminders WHERE actor_ap_id = $1")
+        .bind(actor_ap_id)
+        .execute(&mut tx)
+        .await?;
+    sqlx::query("DELETE FROM organizers WHERE event_ap_id = $1")
+        .bind(actor_ap_id)
+        .execute(&mut tx)
+        .await?;
+    sqlx::query("DELETE FROM ownership_transfers WHERE event_ap_id = $1")
+        .bind(actor_ap_id)
+        .execute(&mut tx)
+        .await?;
+    sqlx::query("DELETE FROM admin_tokens WHERE actor_ap_id = $1")
+        .bind(actor_ap_id)
+        .execute(&mut tx)
+        .await?;
+    sqlx::query("DELETE FROM events WHERE actor_ap_id = $1")
+        .bind(actor_ap_id)
+        .execute(&mut tx)
+        .await?;
+    sqlx::query(
+        "UPDATE objects SET deleted_at = now(), updated_at = now() WHERE actor_ap_id = $1 AND deleted_at IS NULL AND kind != 'Delete'",
+    )
+    .bind(actor_ap_id)
+    .execute(&mut tx)
+    .await?;
+    sqlx::query("UPDATE actors SET deleted_at = now(), updated_at = now() WHERE ap_id = $1")
+        .bind(actor_ap_id)
+        .execute(&mut tx)
+        .await?;
+
+    tx.commit().await?;
+    Ok(())
+}
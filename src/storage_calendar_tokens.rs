@@ -0,0 +1,31 @@
+use crate::{ap::ids::generate_token, error::ApEventsError, state::MyStateHandle};
+
+/// Creates the private calendar feed token for an attendee, replacing any previous token so that
+/// old feed links stop working.
+pub async fn issue_calendar_token(
+    app_state: &MyStateHandle,
+    attendee_ap_id: &str,
+) -> Result<String, ApEventsError> {
+    let token = generate_token();
+    sqlx::query(
+        "INSERT INTO calendar_tokens (token, attendee_ap_id) VALUES ($1, $2) ON CONFLICT (attendee_ap_id) DO UPDATE SET token = $1, created_at = now()",
+    )
+    .bind(&token)
+    .bind(attendee_ap_id)
+    .execute(&app_state.pool)
+    .await?;
+    Ok(token)
+}
+
+/// The attendee that a calendar feed token belongs to.
+pub async fn calendar_token_attendee(
+    app_state: &MyStateHandle,
+    token: &str,
+) -> Result<Option<String>, ApEventsError> {
+    let found: Option<(String,)> =
+        sqlx::query_as("SELECT attendee_ap_id FROM calendar_tokens WHERE token = $1")
+            .bind(token)
+            .fetch_optional(&app_state.pool)
+            .await?;
+    Ok(found.map(|(attendee_ap_id,)| attendee_ap_id))
+}
@@ -0,0 +1,110 @@
+use chrono::{DateTime, Utc};
+use chrono_tz::Tz;
+use sqlx::{postgres::PgRow, FromRow, Row};
+
+use crate::{error::ApEventsError, state::MyStateHandle, storage_events::Event};
+
+/// A calendar of events run by an organizer, such as a club or a venue's program. Calendars are
+/// `Group` actors, so that people can follow all of its events at once.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Calendar {
+    pub actor_ap_id: String,
+    pub name: String,
+    pub summary: String,
+
+    /// The timezone that times are given in when events are created by direct message.
+    pub timezone: String,
+
+    /// The organizer, who can manage the calendar and create events in it by direct message.
+    pub owner_ap_id: Option<String>,
+}
+
+impl FromRow<'_, PgRow> for Calendar {
+    fn from_row(row: &PgRow) -> sqlx::Result<Self> {
+        Ok(Self {
+            actor_ap_id: row.try_get("actor_ap_id")?,
+            name: row.try_get("name")?,
+            summary: row.try_get("summary")?,
+            timezone: row.try_get("timezone")?,
+            owner_ap_id: row.try_get("owner_ap_id")?,
+        })
+    }
+}
+
+impl Calendar {
+    pub fn tz(&self) -> Tz {
+        self.timezone.parse().unwrap_or(Tz::UTC)
+    }
+
+    pub fn validate(&self) -> Result<(), ApEventsError> {
+        let invalid = |message: &str| Err(ApEventsError::InvalidCalendar(message.to_string()));
+
+        if self.name.trim().is_empty() {
+            return invalid("name is required");
+        }
+        if self.name.chars().count() >= 100 {
+            return invalid("name must be under 100 characters");
+        }
+        if self.summary.chars().count() >= 1000 {
+            return invalid("summary must be under 1000 characters");
+        }
+        if self.timezone.parse::<Tz>().is_err() {
+            return invalid("timezone is unknown");
+        }
+        Ok(())
+    }
+}
+
+pub async fn get_calendar(
+    app_state: &MyStateHandle,
+    actor_ap_id: &str,
+) -> Result<Option<Calendar>, ApEventsError> {
+    sqlx::query_as("SELECT * FROM calendars WHERE actor_ap_id = $1")
+        .bind(actor_ap_id)
+        .fetch_optional(&app_state.pool)
+        .await
+        .map_err(|err| err.into())
+}
+
+pub async fn all_calendars(app_state: &MyStateHandle) -> Result<Vec<Calendar>, ApEventsError> {
+    sqlx::query_as("SELECT * FROM calendars ORDER BY name, actor_ap_id")
+        .fetch_all(&app_state.pool)
+        .await
+        .map_err(|err| err.into())
+}
+
+pub async fn upsert_calendar(
+    app_state: &MyStateHandle,
+    calendar: &Calendar,
+) -> Result<(), ApEventsError> {
+    calendar.validate()?;
+
+    sqlx::query(
+        "INSERT INTO calendars (actor_ap_id, name, summary, timezone, owner_ap_id) VALUES ($1, $2, $3, $4, $5) ON CONFLICT ON CONSTRAINT calendars_pkey DO UPDATE SET name = $2, summary = $3, timezone = $4, owner_ap_id = COALESCE($5, calendars.owner_ap_id), updated_at = now()",
+    )
+    .bind(&calendar.actor_ap_id)
+    .bind(&calendar.name)
+    .bind(&calendar.summary)
+    .bind(&calendar.timezone)
+    .bind(&calendar.owner_ap_id)
+    .execute(&app_state.pool)
+    .await?;
+    Ok(())
+}
+
+/// The published events of a calendar that have not ended yet.
+pub async fn calendar_events(
+    app_state: &MyStateHandle,
+    calendar_ap_id: &str,
+    now: DateTime<Utc>,
+) -> Result<Vec<Event>, ApEventsError> {
+    let mut events: Vec<Event> = sqlx::query_as(
+        "SELECT * FROM events WHERE calendar_ap_id = $1 AND status != 'draft' AND (ends_at > $2 OR rrule IS NOT NULL) ORDER BY starts_at, actor_ap_id",
+    )
+    .bind(calendar_ap_id)
+    .bind(now)
+    .fetch_all(&app_state.pool)
+    .await?;
+    events.retain(|event| !event.next_occurrences(now, 1).is_empty());
+    Ok(events)
+}
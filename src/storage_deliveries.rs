@@ -0,0 +1,101 @@
+use chrono::{DateTime, Duration, Utc};
+use sqlx::{postgres::PgRow, FromRow, Row};
+use url::Url;
+
+use crate::{error::ApEventsError, state::MyStateHandle};
+
+/// How long a claimed delivery is left alone before it is tried again, in case the worker that
+/// claimed it stopped.
+const CLAIM_LEASE_MINUTES: i64 = 5;
+
+/// A queued delivery of an activity to an inbox, with the key of the actor that signs it.
+pub struct Delivery {
+    pub id: i64,
+    pub inbox: String,
+    pub activity: String,
+    pub collection_synchronization: Option<String>,
+    pub attempts: i32,
+    pub public_key_id: String,
+    pub private_key: Option<String>,
+}
+
+impl FromRow<'_, PgRow> for Delivery {
+    fn from_row(row: &PgRow) -> sqlx::Result<Self> {
+        Ok(Self {
+            id: row.try_get("id")?,
+            inbox: row.try_get("inbox")?,
+            activity: row.try_get("activity")?,
+            collection_synchronization: row.try_get("collection_synchronization")?,
+            attempts: row.try_get("attempts")?,
+            public_key_id: row.try_get("public_key_id")?,
+            private_key: row.try_get("private_key")?,
+        })
+    }
+}
+
+/// Queues an activity for each inbox, with the `Collection-Synchronization` header to send it
+/// with, if any.
+pub async fn queue_deliveries(
+    app_state: &MyStateHandle,
+    actor_ap_id: &str,
+    activity: &str,
+    inboxes: &[(Url, Option<String>)],
+) -> Result<(), ApEventsError> {
+    let mut tx = app_state.pool.begin().await?;
+    for (inbox, collection_synchronization) in inboxes {
+        sqlx::query(
+            "INSERT INTO deliveries (actor_ap_id, inbox, activity, collection_synchronization) VALUES ($1, $2, $3, $4)",
+        )
+        .bind(actor_ap_id)
+        .bind(inbox.as_str())
+        .bind(activity)
+        .bind(collection_synchronization)
+        .execute(&mut tx)
+        .await?;
+    }
+    tx.commit().await?;
+    Ok(())
+}
+
+/// Claims deliveries that are due. Claimed deliveries are pushed back by a lease, so that other
+/// workers skip them and they are tried again if this one never finishes them.
+pub async fn claim_deliveries(
+    app_state: &MyStateHandle,
+    now: DateTime<Utc>,
+    limit: i64,
+) -> Result<Vec<Delivery>, ApEventsError> {
+    sqlx::query_as(
+        "WITH claimed AS (UPDATE deliveries SET next_attempt_at = $2 WHERE id IN (SELECT id FROM deliveries WHERE next_attempt_at <= $1 ORDER BY next_attempt_at, id LIMIT $3 FOR UPDATE SKIP LOCKED) RETURNING *) SELECT claimed.*, actors.public_key_id, actors.private_key FROM claimed JOIN actors ON actors.ap_id = claimed.actor_ap_id",
+    )
+    .bind(now)
+    .bind(now + Duration::minutes(CLAIM_LEASE_MINUTES))
+    .bind(limit)
+    .fetch_all(&app_state.pool)
+    .await
+    .map_err(|err| err.into())
+}
+
+/// Removes a delivery once it is delivered, rejected or given up on.
+pub async fn finish_delivery(app_state: &MyStateHandle, id: i64) -> Result<(), ApEventsError> {
+    sqlx::query("DELETE FROM deliveries WHERE id = $1")
+        .bind(id)
+        .execute(&app_state.pool)
+        .await?;
+    Ok(())
+}
+
+/// Counts a failed attempt and schedules the next one.
+pub async fn retry_delivery(
+    app_state: &MyStateHandle,
+    id: i64,
+    next_attempt_at: DateTime<Utc>,
+) -> Result<(), ApEventsError> {
+    sqlx::query(
+        "UPDATE deliveries SET attempts = attempts + 1, next_attempt_at = $2 WHERE id = $1",
+    )
+    .bind(id)
+    .bind(next_attempt_at)
+    .execute(&app_state.pool)
+    .await?;
+    Ok(())
+}
@@ -0,0 +1,839 @@
+use askama::{Html, MarkupDisplay};
+use chrono::{DateTime, Duration, NaiveDateTime, Utc};
+use chrono_tz::Tz;
+use sqlx::{postgres::PgRow, FromRow, Row};
+use std::{fmt, str::FromStr};
+use url::Url;
+
+use crate::{
+    error::ApEventsError,
+    recurrence::{Occurrence, Occurrences, Recurrence},
+    state::MyStateHandle,
+};
+
+/// The Mobilizon join modes that an event can be published with.
+pub const JOIN_MODES: [&str; 4] = ["free", "restricted", "invite", "external"];
+
+/// Where an event is in its lifecycle. Drafts are only visible to their organizer, and the other
+/// states are published with the event.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EventStatus {
+    Draft,
+    Published,
+    Postponed,
+    Cancelled,
+    Completed,
+}
+
+impl EventStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            EventStatus::Draft => "draft",
+            EventStatus::Published => "published",
+            EventStatus::Postponed => "postponed",
+            EventStatus::Cancelled => "cancelled",
+            EventStatus::Completed => "completed",
+        }
+    }
+
+    /// The states that an event in this state can move to. Cancelled and completed events are
+    /// final.
+    pub fn transitions(&self) -> &'static [EventStatus] {
+        match self {
+            EventStatus::Draft => &[EventStatus::Published, EventStatus::Cancelled],
+            EventStatus::Published => &[
+                EventStatus::Postponed,
+                EventStatus::Cancelled,
+                EventStatus::Completed,
+            ],
+            EventStatus::Postponed => &[EventStatus::Published, EventStatus::Cancelled],
+            EventStatus::Cancelled | EventStatus::Completed => &[],
+        }
+    }
+
+    pub fn can_become(&self, status: EventStatus) -> bool {
+        self.transitions().contains(&status)
+    }
+
+    /// The iCalendar STATUS, which Mobilizon also uses as `ical:status`.
+    pub fn ical_status(&self) -> &'static str {
+        match self {
+            EventStatus::Draft | EventStatus::Postponed => "TENTATIVE",
+            EventStatus::Published | EventStatus::Completed => "CONFIRMED",
+            EventStatus::Cancelled => "CANCELLED",
+        }
+    }
+
+    /// The schema.org `eventStatus`.
+    pub fn schema_status(&self) -> &'static str {
+        match self {
+            EventStatus::Postponed => "https://schema.org/EventPostponed",
+            EventStatus::Cancelled => "https://schema.org/EventCancelled",
+            _ => "https://schema.org/EventScheduled",
+        }
+    }
+}
+
+/// How people attend an event, from whether it has a location, an online link or both.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AttendanceMode {
+    InPerson,
+    Online,
+    Hybrid,
+}
+
+impl AttendanceMode {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            AttendanceMode::InPerson => "in person",
+            AttendanceMode::Online => "online",
+            AttendanceMode::Hybrid => "hybrid",
+        }
+    }
+
+    /// The schema.org `eventAttendanceMode`.
+    pub fn schema_mode(&self) -> &'static str {
+        match self {
+            AttendanceMode::InPerson => "https://schema.org/OfflineEventAttendanceMode",
+            AttendanceMode::Online => "https://schema.org/OnlineEventAttendanceMode",
+            AttendanceMode::Hybrid => "https://schema.org/MixedEventAttendanceMode",
+        }
+    }
+}
+
+impl fmt::Display for EventStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for EventStatus {
+    type Err = ApEventsError;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "draft" => Ok(EventStatus::Draft),
+            "published" => Ok(EventStatus::Published),
+            "postponed" => Ok(EventStatus::Postponed),
+            "cancelled" => Ok(EventStatus::Cancelled),
+            "completed" => Ok(EventStatus::Completed),
+            _ => Err(ApEventsError::InvalidEvent(format!(
+                "unknown event status {value}"
+            ))),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Event {
+    pub actor_ap_id: String,
+    pub title: String,
+    pub summary: String,
+    pub starts_at: DateTime<Utc>,
+    pub ends_at: DateTime<Utc>,
+    pub timezone: String,
+    pub location: Option<String>,
+
+    /// The place actor the event is held at. Its name and address are kept in `location`.
+    pub place_ap_id: Option<String>,
+
+    /// The calendar actor that the event belongs to, which announces it to its followers.
+    pub calendar_ap_id: Option<String>,
+
+    /// A link to join the event online, such as a video call.
+    pub online_url: Option<String>,
+
+    /// Private online links are left out of everything public and are only sent to attendees
+    /// that are going.
+    pub online_url_private: bool,
+
+    pub join_mode: String,
+    pub capacity: Option<i32>,
+
+    /// An RFC 5545 RRULE. The start and end of a recurring event are those of its first
+    /// occurrence.
+    pub rrule: Option<String>,
+
+    /// The starts of occurrences that were removed from the recurrence.
+    pub exdates: Vec<DateTime<Utc>>,
+
+    /// Cancelled events stay listed so that calendars can show the cancellation.
+    pub status: EventStatus,
+
+    /// When a draft is published on its own.
+    pub publish_at: Option<DateTime<Utc>>,
+
+    /// The organizer who created the event and may administer it.
+    pub owner_ap_id: Option<String>,
+
+    /// Lowercase hashtags without the `#`, used to find the event.
+    pub tags: Vec<String>,
+
+    /// Bumped every time the event is updated so that calendar clients refresh it.
+    pub sequence: i32,
+    pub updated_at: NaiveDateTime,
+}
+
+impl FromRow<'_, PgRow> for Event {
+    fn from_row(row: &PgRow) -> sqlx::Result<Self> {
+        Ok(Self {
+            actor_ap_id: row.try_get("actor_ap_id")?,
+            title: row.try_get("title")?,
+            summary: row.try_get("summary")?,
+            starts_at: row.try_get("starts_at")?,
+            ends_at: row.try_get("ends_at")?,
+            timezone: row.try_get("timezone")?,
+            location: row.try_get("location")?,
+            place_ap_id: row.try_get("place_ap_id")?,
+            calendar_ap_id: row.try_get("calendar_ap_id")?,
+            online_url: row.try_get("online_url")?,
+            online_url_private: row.try_get("online_url_private")?,
+            join_mode: row.try_get("join_mode")?,
+            capacity: row.try_get("capacity")?,
+            rrule: row.try_get("rrule")?,
+            exdates: row.try_get("exdates")?,
+            status: row
+                .try_get::<String, _>("status")?
+                .parse()
+                .map_err(|err: ApEventsError| sqlx::Error::Decode(err.to_string().into()))?,
+            publish_at: row.try_get("publish_at")?,
+            owner_ap_id: row.try_get("owner_ap_id")?,
+            tags: row.try_get("tags")?,
+            sequence: row.try_get("sequence")?,
+            updated_at: row.try_get("updated_at")?,
+        })
+    }
+}
+
+impl Event {
+    /// The event's timezone. Stored timezones are validated, so this only falls back to UTC for
+    /// rows written outside of `upsert_event`.
+    pub fn tz(&self) -> Tz {
+        self.timezone.parse().unwrap_or(Tz::UTC)
+    }
+
+    /// The online link, when anyone may see it.
+    pub fn public_online_url(&self) -> Option<&str> {
+        match self.online_url_private {
+            true => None,
+            false => self.online_url.as_deref(),
+        }
+    }
+
+    /// The online link, when only attendees that are going may see it.
+    pub fn private_online_url(&self) -> Option<&str> {
+        match self.online_url_private {
+            true => self.online_url.as_deref(),
+            false => None,
+        }
+    }
+
+    /// A sentence of HTML with the online link, for messages to attendees.
+    pub fn online_link_html(&self) -> Option<String> {
+        self.online_url.as_ref().map(|online_url| {
+            let online_url = MarkupDisplay::new_unsafe(online_url, Html);
+            format!("Join online at <a href=\"{online_url}\">{online_url}</a>.")
+        })
+    }
+
+    pub fn attendance_mode(&self) -> AttendanceMode {
+        match (&self.location, &self.online_url) {
+            (Some(_), Some(_)) => AttendanceMode::Hybrid,
+            (None, Some(_)) => AttendanceMode::Online,
+            _ => AttendanceMode::InPerson,
+        }
+    }
+
+    /// Whether the event is over for good and nobody touched it since the cutoff, so that it can
+    /// be deleted.
+    pub fn is_expired(&self, cutoff: DateTime<Utc>) -> bool {
+        matches!(self.status, EventStatus::Completed | EventStatus::Cancelled)
+            && self.ends_at < cutoff
+            && self.updated_at < cutoff.naive_utc()
+    }
+
+    pub fn is_draft(&self) -> bool {
+        self.status == EventStatus::Draft
+    }
+
+    pub fn recurrence(&self) -> Option<Recurrence> {
+        self.rrule.as_ref().and_then(|rrule| rrule.parse().ok())
+    }
+
+    /// Every occurrence of the event, in order. Events without a recurrence have one.
+    pub fn occurrences(&self) -> Occurrences {
+        Occurrences::new(
+            self.recurrence(),
+            self.tz(),
+            self.starts_at,
+            self.ends_at,
+            self.exdates.clone(),
+        )
+    }
+
+    /// The first occurrence whose seats count against answers to the whole event. Seats at
+    /// occurrences that are over are free again.
+    pub fn seats_since(&self, now: DateTime<Utc>) -> String {
+        self.next_occurrences(now, 1)
+            .first()
+            .map(|occurrence| occurrence.id())
+            .unwrap_or_default()
+    }
+
+    /// Occurrences that have not ended yet.
+    pub fn next_occurrences(&self, now: DateTime<Utc>, limit: usize) -> Vec<Occurrence> {
+        self.occurrences()
+            .filter(|occurrence| occurrence.ends_at > now)
+            .take(limit)
+            .collect()
+    }
+
+    pub fn occurrence(&self, id: &str) -> Option<Occurrence> {
+        let starts_at = Occurrence::parse_id(id)?;
+        self.occurrences()
+            .take_while(|occurrence| occurrence.starts_at <= starts_at)
+            .find(|occurrence| occurrence.starts_at == starts_at)
+    }
+
+    /// Whether attendees can join an occurrence, or the next occurrence when none is given,
+    /// through ActivityPub. Other join modes are handled outside of this server, and ended
+    /// occurrences are closed.
+    pub fn accepts_joins(&self, occurrence: Option<&Occurrence>, now: DateTime<Utc>) -> bool {
+        let ends_at = match occurrence {
+            Some(occurrence) => Some(occurrence.ends_at),
+            None => self
+                .next_occurrences(now, 1)
+                .first()
+                .map(|occurrence| occurrence.ends_at),
+        };
+        self.join_mode == "free"
+            && matches!(self.status, EventStatus::Published | EventStatus::Postponed)
+            && ends_at.map(|ends_at| now < ends_at) == Some(true)
+    }
+
+    /// Checks the event against the constraints listed in the README.
+    pub fn validate(&self, now: DateTime<Utc>) -> Result<(), ApEventsError> {
+        let invalid = |message: &str| Err(ApEventsError::InvalidEvent(message.to_string()));
+
+        if self.title.trim().is_empty() {
+            return invalid("title is required");
+        }
+        if self.title.chars().count() >= 64 {
+            return invalid("title must be under 64 characters");
+        }
+        if self.summary.chars().count() >= 1000 {
+            return invalid("description must be under 1000 characters");
+        }
+        // Recurring events keep the start of their first occurrence for as long as they run.
+        if self.rrule.is_none() && self.starts_at < now - Duration::days(30) {
+            return invalid("starts_at may not be more than 30 days in the past");
+        }
+        if self.ends_at < self.starts_at {
+            return invalid("ends_at may not be before starts_at");
+        }
+        if self.ends_at > self.starts_at + Duration::days(7) {
+            return invalid("ends_at may not be more than 7 days after starts_at");
+        }
+        if let Some(location) = &self.location {
+            if location.chars().count() >= 300 {
+                return invalid("location must be under 300 characters");
+            }
+        }
+        if let Some(online_url) = &self.online_url {
+            if online_url.len() >= 300 {
+                return invalid("online_url must be under 300 characters");
+            }
+            match Url::parse(online_url) {
+                Ok(url) if url.scheme() == "https" || url.scheme() == "http" => {}
+                _ => return invalid("online_url must be an http or https url"),
+            }
+        }
+        if self.timezone.parse::<Tz>().is_err() {
+            return invalid("timezone must be an IANA timezone name");
+        }
+        if !JOIN_MODES.contains(&self.join_mode.as_str()) {
+            return invalid("join_mode must be one of free, restricted, invite or external");
+        }
+        if self.capacity.map(|capacity| capacity < 1) == Some(true) {
+            return invalid("capacity must be at least 1");
+        }
+        if let Some(rrule) = &self.rrule {
+            rrule.parse::<Recurrence>()?;
+        }
+        if self.exdates.len() > 100 {
+            return invalid("an event may not exclude more than 100 occurrences");
+        }
+        if self.tags.len() > 10 {
+            return invalid("an event may not have more than 10 tags");
+        }
+        if self.tags.iter().any(|tag| {
+            tag.is_empty()
+                || tag.chars().count() >= 64
+                || *tag != normalize_tag(tag)
+                || !tag.chars().all(|c| c.is_alphanumeric() || c == '_')
+        }) {
+            return invalid("tags must be letters, numbers and underscores");
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+impl Event {
+    /// A published event for tests, which override the fields they are about.
+    pub fn example() -> Event {
+        use chrono::TimeZone;
+
+        Event {
+            actor_ap_id: "https://events.thegem.city/actor/readily-splendid-mule".to_string(),
+            title: "Swing night".to_string(),
+            summary: "Dancing at the bar.".to_string(),
+            starts_at: Utc.with_ymd_and_hms(2022, 11, 2, 0, 0, 0).unwrap(),
+            ends_at: Utc.with_ymd_and_hms(2022, 11, 2, 3, 0, 0).unwrap(),
+            timezone: "America/New_York".to_string(),
+            location: Some("The bar, 555 nowhere, dayton, oh 45419".to_string()),
+            place_ap_id: None,
+            calendar_ap_id: None,
+            online_url: None,
+            online_url_private: false,
+            join_mode: "free".to_string(),
+            capacity: None,
+            rrule: None,
+            exdates: vec![],
+            status: EventStatus::Published,
+            publish_at: None,
+            owner_ap_id: None,
+            tags: vec![],
+            sequence: 2,
+            updated_at: Utc
+                .with_ymd_and_hms(2022, 10, 20, 14, 30, 0)
+                .unwrap()
+                .naive_utc(),
+        }
+    }
+}
+
+/// A tag as it is stored: lowercase, without a leading `#`.
+pub fn normalize_tag(tag: &str) -> String {
+    tag.trim().trim_start_matches('#').to_lowercase()
+}
+
+pub async fn get_event(
+    app_state: &MyStateHandle,
+    actor_ap_id: &str,
+) -> Result<Option<Event>, ApEventsError> {
+    sqlx::query_as("SELECT * FROM events WHERE actor_ap_id = $1")
+        .bind(actor_ap_id)
+        .fetch_optional(&app_state.pool)
+        .await
+        .map_err(|err| err.into())
+}
+
+/// Validates and stores the details of an event, replacing any previous details. The status is
+/// only used for new events, an owner only replaces a previous owner, and the sequence and update
+/// time are ignored. The stored event is returned.
+pub async fn upsert_event(
+    app_state: &MyStateHandle,
+    event: &Event,
+) -> Result<Event, ApEventsError> {
+    event.validate(Utc::now())?;
+
+    // Unchanged details do not bump the sequence, so re-imports do not look like updates.
+    let stored: Option<Event> = sqlx::query_as(
+        "INSERT INTO events (actor_ap_id, title, summary, starts_at, ends_at, timezone, location, join_mode, capacity, rrule, exdates, publish_at, status, owner_ap_id, online_url, online_url_private, place_ap_id, calendar_ap_id, tags) VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13, $14, $15, $16, $17, $18, $19) ON CONFLICT ON CONSTRAINT events_pkey DO UPDATE SET title = $2, summary = $3, starts_at = $4, ends_at = $5, timezone = $6, location = $7, join_mode = $8, capacity = $9, rrule = $10, exdates = $11, publish_at = $12, owner_ap_id = COALESCE($14, events.owner_ap_id), online_url = $15, online_url_private = $16, place_ap_id = $17, calendar_ap_id = COALESCE($18, events.calendar_ap_id), tags = $19, sequence = events.sequence + 1, updated_at = now() WHERE (events.title, events.summary, events.starts_at, events.ends_at, events.timezone, events.location, events.join_mode, events.capacity, events.rrule, events.exdates, events.publish_at, events.owner_ap_id, events.online_url, events.online_url_private, events.place_ap_id, events.calendar_ap_id, events.tags) IS DISTINCT FROM ($2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, COALESCE($14, events.owner_ap_id), $15, $16, $17, COALESCE($18, events.calendar_ap_id), $19) RETURNING *",
+    )
+    .bind(&event.actor_ap_id)
+    .bind(&event.title)
+    .bind(&event.summary)
+    .bind(event.starts_at)
+    .bind(event.ends_at)
+    .bind(&event.timezone)
+    .bind(&event.location)
+    .bind(&event.join_mode)
+    .bind(event.capacity)
+    .bind(&event.rrule)
+    .bind(&event.exdates)
+    .bind(event.publish_at)
+    .bind(event.status.as_str())
+    .bind(&event.owner_ap_id)
+    .bind(&event.online_url)
+    .bind(event.online_url_private)
+    .bind(&event.place_ap_id)
+    .bind(&event.calendar_ap_id)
+    .bind(&event.tags)
+    .fetch_optional(&app_state.pool)
+    .await?;
+
+    match stored {
+        Some(stored) => Ok(stored),
+        None => get_event(app_state, &event.actor_ap_id)
+            .await?
+            .ok_or_else(|| ApEventsError::ObjectNotFound(event.actor_ap_id.clone())),
+    }
+}
+
+/// The event actor that was created for an imported UID.
+pub async fn imported_event_actor(
+    app_state: &MyStateHandle,
+    import_uid: &str,
+) -> Result<Option<String>, ApEventsError> {
+    let found: Option<(String,)> =
+        sqlx::query_as("SELECT actor_ap_id FROM events WHERE import_uid = $1")
+            .bind(import_uid)
+            .fetch_optional(&app_state.pool)
+            .await?;
+    Ok(found.map(|(actor_ap_id,)| actor_ap_id))
+}
+
+pub async fn set_import_uid(
+    app_state: &MyStateHandle,
+    actor_ap_id: &str,
+    import_uid: &str,
+) -> Result<(), ApEventsError> {
+    sqlx::query("UPDATE events SET import_uid = $2 WHERE actor_ap_id = $1")
+        .bind(actor_ap_id)
+        .bind(import_uid)
+        .execute(&app_state.pool)
+        .await?;
+    Ok(())
+}
+
+/// Moves an event from one state to another. Returns false if the event is no longer in the
+/// state it was read in. Callers check that the transition is allowed.
+pub async fn set_event_status(
+    app_state: &MyStateHandle,
+    actor_ap_id: &str,
+    from: EventStatus,
+    to: EventStatus,
+) -> Result<bool, ApEventsError> {
+    let result = sqlx::query(
+        "UPDATE events SET status = $3, sequence = sequence + 1, updated_at = now() WHERE actor_ap_id = $1 AND status = $2",
+    )
+    .bind(actor_ap_id)
+    .bind(from.as_str())
+    .bind(to.as_str())
+    .execute(&app_state.pool)
+    .await?;
+    Ok(result.rows_affected() > 0)
+}
+
+/// Schedules a draft to be published, or unschedules it. Returns false if the event is not a
+/// draft.
+pub async fn set_publish_at(
+    app_state: &MyStateHandle,
+    actor_ap_id: &str,
+    publish_at: Option<DateTime<Utc>>,
+) -> Result<bool, ApEventsError> {
+    let result = sqlx::query(
+        "UPDATE events SET publish_at = $2, updated_at = now() WHERE actor_ap_id = $1 AND status = 'draft'",
+    )
+    .bind(actor_ap_id)
+    .bind(publish_at)
+    .execute(&app_state.pool)
+    .await?;
+    Ok(result.rows_affected() > 0)
+}
+
+/// Drafts whose scheduled publish time has come.
+pub async fn due_drafts(
+    app_state: &MyStateHandle,
+    now: DateTime<Utc>,
+) -> Result<Vec<Event>, ApEventsError> {
+    sqlx::query_as("SELECT * FROM events WHERE status = 'draft' AND publish_at <= $1")
+        .bind(now)
+        .fetch_all(&app_state.pool)
+        .await
+        .map_err(|err| err.into())
+}
+
+/// Published events that have no occurrences left.
+pub async fn ended_events(
+    app_state: &MyStateHandle,
+    now: DateTime<Utc>,
+) -> Result<Vec<Event>, ApEventsError> {
+    let mut events: Vec<Event> =
+        sqlx::query_as("SELECT * FROM events WHERE status = 'published' AND ends_at <= $1")
+            .bind(now)
+            .fetch_all(&app_state.pool)
+            .await?;
+    events.retain(|event| event.next_occurrences(now, 1).is_empty());
+    Ok(events)
+}
+
+/// Completed and cancelled events that ended, and last changed, before the cutoff.
+pub async fn expired_events(
+    app_state: &MyStateHandle,
+    cutoff: DateTime<Utc>,
+) -> Result<Vec<Event>, ApEventsError> {
+    let mut events: Vec<Event> = sqlx::query_as(
+        "SELECT * FROM events WHERE status IN ('completed', 'cancelled') AND ends_at < $1 ORDER BY ends_at, actor_ap_id",
+    )
+    .bind(cutoff)
+    .fetch_all(&app_state.pool)
+    .await?;
+    events.retain(|event| event.is_expired(cutoff));
+    Ok(events)
+}
+
+/// Published events that have not ended yet, including cancelled ones and recurring events with
+/// occurrences left, soonest first.
+pub async fn upcoming_events(
+    app_state: &MyStateHandle,
+    now: DateTime<Utc>,
+) -> Result<Vec<Event>, ApEventsError> {
+    let mut events: Vec<Event> = sqlx::query_as(
+        "SELECT * FROM events WHERE status != 'draft' AND (ends_at > $1 OR rrule IS NOT NULL) ORDER BY starts_at, actor_ap_id",
+    )
+    .bind(now)
+    .fetch_all(&app_state.pool)
+    .await?;
+    events.retain(|event| !event.next_occurrences(now, 1).is_empty());
+    Ok(events)
+}
+
+/// Removes an occurrence from a recurring event. Returns false if the event does not have the
+/// occurrence.
+pub async fn exclude_occurrence(
+    app_state: &MyStateHandle,
+    event: &Event,
+    occurrence: &Occurrence,
+) -> Result<bool, ApEventsError> {
+    if event.rrule.is_none() || event.occurrence(&occurrence.id()).is_none() {
+        return Ok(false);
+    }
+    sqlx::query(
+        "UPDATE events SET exdates = array_append(exdates, $2), sequence = sequence + 1, updated_at = now() WHERE actor_ap_id = $1",
+    )
+    .bind(&event.actor_ap_id)
+    .bind(occurrence.starts_at)
+    .execute(&app_state.pool)
+    .await?;
+    Ok(true)
+}
+
+/// Every event that an attendee is going to, or to an occurrence of, soonest first.
+pub async fn attending_events(
+    app_state: &MyStateHandle,
+    attendee_ap_id: &str,
+) -> Result<Vec<Event>, ApEventsError> {
+    sqlx::query_as(
+        "SELECT * FROM events WHERE status != 'draft' AND EXISTS (SELECT 1 FROM rsvps WHERE rsvps.actor_ap_id = events.actor_ap_id AND rsvps.attendee_ap_id = $1 AND rsvps.status = 'going') ORDER BY starts_at, actor_ap_id",
+    )
+    .bind(attendee_ap_id)
+    .fetch_all(&app_state.pool)
+    .await
+    .map_err(|err| err.into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use chrono::TimeZone;
+
+    #[test]
+    fn validate_event() {
+        let now = Utc.with_ymd_and_hms(2022, 11, 1, 0, 0, 0).unwrap();
+        assert!(Event::example().validate(now).is_ok());
+
+        let invalid = [
+            Event {
+                title: "a".repeat(64),
+                ..Event::example()
+            },
+            Event {
+                summary: "a".repeat(1000),
+                ..Event::example()
+            },
+            Event {
+                location: Some("a".repeat(300)),
+                ..Event::example()
+            },
+            Event {
+                online_url: Some("javascript:alert(1)".to_string()),
+                ..Event::example()
+            },
+            Event {
+                online_url: Some("meet.example.com/swing".to_string()),
+                ..Event::example()
+            },
+            Event {
+                ends_at: Utc.with_ymd_and_hms(2022, 11, 10, 0, 0, 0).unwrap(),
+                ..Event::example()
+            },
+            Event {
+                ends_at: Utc.with_ymd_and_hms(2022, 11, 1, 0, 0, 0).unwrap(),
+                ..Event::example()
+            },
+            Event {
+                timezone: "Eastern".to_string(),
+                ..Event::example()
+            },
+            Event {
+                join_mode: "open".to_string(),
+                ..Event::example()
+            },
+            Event {
+                capacity: Some(0),
+                ..Event::example()
+            },
+            Event {
+                rrule: Some("FREQ=FORTNIGHTLY".to_string()),
+                ..Event::example()
+            },
+        ];
+        for event in invalid {
+            assert!(event.validate(now).is_err(), "{event:?}");
+        }
+
+        let later = Utc.with_ymd_and_hms(2022, 12, 15, 0, 0, 0).unwrap();
+        assert!(Event::example().validate(later).is_err());
+        assert!(Event {
+            rrule: Some("FREQ=WEEKLY".to_string()),
+            ..Event::example()
+        }
+        .validate(later)
+        .is_ok());
+    }
+
+    #[test]
+    fn expiry() {
+        let cutoff = Utc.with_ymd_and_hms(2022, 12, 2, 3, 0, 0).unwrap();
+        let completed = Event {
+            status: EventStatus::Completed,
+            updated_at: Utc
+                .with_ymd_and_hms(2022, 11, 2, 3, 0, 0)
+                .unwrap()
+                .naive_utc(),
+            ..Event::example()
+        };
+        assert!(completed.is_expired(cutoff));
+        assert!(Event {
+            status: EventStatus::Cancelled,
+            ..completed.clone()
+        }
+        .is_expired(cutoff));
+
+        // Published events are completed before they expire.
+        assert!(!Event {
+            status: EventStatus::Published,
+            ..completed.clone()
+        }
+        .is_expired(cutoff));
+        // The event has to end before the cutoff, not at it.
+        assert!(!Event {
+            ends_at: cutoff,
+            ..completed.clone()
+        }
+        .is_expired(cutoff));
+        // Changes after the cutoff keep the event around.
+        assert!(!Event {
+            updated_at: cutoff.naive_utc(),
+            ..completed.clone()
+        }
+        .is_expired(cutoff));
+    }
+
+    #[test]
+    fn accepts_joins() {
+        let now = Utc.with_ymd_and_hms(2022, 11, 1, 0, 0, 0).unwrap();
+        assert!(Event::example().accepts_joins(None, now));
+        assert!(!Event {
+            join_mode: "invite".to_string(),
+            ..Event::example()
+        }
+        .accepts_joins(None, now));
+
+        assert!(!Event {
+            status: EventStatus::Cancelled,
+            ..Event::example()
+        }
+        .accepts_joins(None, now));
+        assert!(!Event {
+            status: EventStatus::Draft,
+            ..Event::example()
+        }
+        .accepts_joins(None, now));
+
+        let after = Utc.with_ymd_and_hms(2022, 11, 2, 3, 0, 0).unwrap();
+        assert!(!Event::example().accepts_joins(None, after));
+
+        // Recurring events stay open until their last occurrence ends.
+        let weekly = Event {
+            rrule: Some("FREQ=WEEKLY;COUNT=2".to_string()),
+            ..Event::example()
+        };
+        assert!(weekly.accepts_joins(None, after));
+        let first = weekly.occurrences().next().unwrap();
+        assert!(!weekly.accepts_joins(Some(&first), after));
+        let last = Utc.with_ymd_and_hms(2022, 11, 9, 4, 0, 0).unwrap();
+        assert!(!weekly.accepts_joins(None, last));
+    }
+
+    #[test]
+    fn status_transitions() {
+        use EventStatus::*;
+
+        for status in [Draft, Published, Postponed, Cancelled, Completed] {
+            assert_eq!(status.as_str().parse::<EventStatus>().unwrap(), status);
+            assert!(!status.can_become(status));
+            assert!(!status.can_become(Draft));
+        }
+        assert!("confirmed".parse::<EventStatus>().is_err());
+
+        assert!(Draft.can_become(Published));
+        assert!(!Draft.can_become(Postponed));
+        assert!(Published.can_become(Postponed));
+        assert!(Postponed.can_become(Published));
+        assert!(Published.can_become(Completed));
+        assert!(!Postponed.can_become(Completed));
+        assert!(Cancelled.transitions().is_empty());
+        assert!(Completed.transitions().is_empty());
+
+        assert_eq!(Postponed.ical_status(), "TENTATIVE");
+        assert_eq!(Completed.ical_status(), "CONFIRMED");
+        assert_eq!(
+            Cancelled.schema_status(),
+            "https://schema.org/EventCancelled"
+        );
+    }
+
+    #[test]
+    fn occurrences() {
+        let now = Utc.with_ymd_and_hms(2022, 11, 10, 0, 0, 0).unwrap();
+        let weekly = Event {
+            rrule: Some("FREQ=WEEKLY".to_string()),
+            exdates: vec![Utc.with_ymd_and_hms(2022, 11, 16, 1, 0, 0).unwrap()],
+            ..Event::example()
+        };
+        // 8pm in New York, before and after the end of DST.
+        let next: Vec<String> = weekly
+            .next_occurrences(now, 2)
+            .iter()
+            .map(|occurrence| occurrence.id())
+            .collect();
+        assert_eq!(next, vec!["20221123T010000Z", "20221130T010000Z"]);
+
+        assert_eq!(
+            weekly
+                .occurrence("20221109T010000Z")
+                .map(|occurrence| occurrence.ends_at),
+            Some(Utc.with_ymd_and_hms(2022, 11, 9, 4, 0, 0).unwrap())
+        );
+        assert_eq!(weekly.occurrence("20221116T010000Z"), None);
+        assert_eq!(weekly.occurrence("20221109T000000Z"), None);
+        assert_eq!(weekly.occurrence("tomorrow"), None);
+
+        assert_eq!(Event::example().next_occurrences(now, 2), vec![]);
+    }
+
+    #[test]
+    fn unicode_lengths() {
+        let now = Utc.with_ymd_and_hms(2022, 11, 1, 0, 0, 0).unwrap();
+        let event = Event {
+            title: "🎉".repeat(63),
+            ..Event::example()
+        };
+        assert!(event.validate(now).is_ok());
+    }
+}
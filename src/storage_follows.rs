@@ -0,0 +1,169 @@
+use sha2::{Digest, Sha256};
+use url::Url;
+
+use crate::{error::ApEventsError, state::MyStateHandle};
+
+/// Records a follow request. Following again replaces the previous request and waits for a new
+/// accept.
+pub async fn create_follow(
+    app_state: &MyStateHandle,
+    follower_ap_id: &str,
+    followee_ap_id: &str,
+    activity_ap_id: &str,
+) -> Result<(), ApEventsError> {
+    sqlx::query(
+        "INSERT INTO follow_activities (follower_ap_id, followee_ap_id, activity_ap_id) VALUES ($1, $2, $3) ON CONFLICT ON CONSTRAINT follow_activities_pkey DO UPDATE SET activity_ap_id = $3, accepted_at = NULL, accept_activity_id = NULL, updated_at = now()",
+    )
+    .bind(follower_ap_id)
+    .bind(followee_ap_id)
+    .bind(activity_ap_id)
+    .execute(&app_state.pool)
+    .await?;
+    Ok(())
+}
+
+pub async fn accept_follow(
+    app_state: &MyStateHandle,
+    activity_ap_id: &str,
+    accept_activity_id: &str,
+) -> Result<(), ApEventsError> {
+    sqlx::query(
+        "UPDATE follow_activities SET accepted_at = now(), accept_activity_id = $2, updated_at = now() WHERE activity_ap_id = $1",
+    )
+    .bind(activity_ap_id)
+    .bind(accept_activity_id)
+    .execute(&app_state.pool)
+    .await?;
+    Ok(())
+}
+
+pub async fn remove_follow(
+    app_state: &MyStateHandle,
+    follower_ap_id: &str,
+    followee_ap_id: &str,
+) -> Result<(), ApEventsError> {
+    sqlx::query("DELETE FROM follow_activities WHERE follower_ap_id = $1 AND followee_ap_id = $2")
+        .bind(follower_ap_id)
+        .bind(followee_ap_id)
+        .execute(&app_state.pool)
+        .await?;
+    Ok(())
+}
+
+/// Removes a follow by the id of the Follow activity, as long as it was sent by the given actor.
+pub async fn remove_follow_activity(
+    app_state: &MyStateHandle,
+    follower_ap_id: &str,
+    activity_ap_id: &str,
+) -> Result<(), ApEventsError> {
+    sqlx::query("DELETE FROM follow_activities WHERE follower_ap_id = $1 AND activity_ap_id = $2")
+        .bind(follower_ap_id)
+        .bind(activity_ap_id)
+        .execute(&app_state.pool)
+        .await?;
+    Ok(())
+}
+
+/// Removes a follow that the followee rejected, by the id of the Follow activity.
+pub async fn reject_follow(
+    app_state: &MyStateHandle,
+    followee_ap_id: &str,
+    activity_ap_id: &str,
+) -> Result<(), ApEventsError> {
+    sqlx::query("DELETE FROM follow_activities WHERE followee_ap_id = $1 AND activity_ap_id = $2")
+        .bind(followee_ap_id)
+        .bind(activity_ap_id)
+        .execute(&app_state.pool)
+        .await?;
+    Ok(())
+}
+
+/// Every accepted follower of an actor.
+pub async fn followers(
+    app_state: &MyStateHandle,
+    followee_ap_id: &str,
+) -> Result<Vec<String>, ApEventsError> {
+    let followers: Vec<(String,)> = sqlx::query_as(
+        "SELECT follower_ap_id FROM follow_activities WHERE followee_ap_id = $1 AND accepted_at IS NOT NULL ORDER BY follower_ap_id",
+    )
+    .bind(followee_ap_id)
+    .fetch_all(&app_state.pool)
+    .await?;
+
+    Ok(followers.into_iter().map(|(follower,)| follower).collect())
+}
+
+/// The local actors that follow an actor, whether or not the follow was accepted.
+pub async fn local_followers(
+    app_state: &MyStateHandle,
+    followee_ap_id: &str,
+) -> Result<Vec<String>, ApEventsError> {
+    let followers: Vec<(String,)> = sqlx::query_as(
+        "SELECT follower_ap_id FROM follow_activities JOIN actors ON actors.ap_id = follower_ap_id WHERE followee_ap_id = $1 AND actors.is_local AND actors.deleted_at IS NULL ORDER BY follower_ap_id",
+    )
+    .bind(followee_ap_id)
+    .fetch_all(&app_state.pool)
+    .await?;
+
+    Ok(followers.into_iter().map(|(follower,)| follower).collect())
+}
+
+/// The accepted followers whose ids are on the same origin as the given url.
+pub async fn followers_on_origin(
+    app_state: &MyStateHandle,
+    followee_ap_id: &str,
+    origin: &Url,
+) -> Result<Vec<String>, ApEventsError> {
+    let prefix = format!("{}/", origin.origin().ascii_serialization());
+
+    let followers: Vec<(String,)> = sqlx::query_as(
+        "SELECT follower_ap_id FROM follow_activities WHERE followee_ap_id = $1 AND accepted_at IS NOT NULL AND starts_with(follower_ap_id, $2) ORDER BY follower_ap_id",
+    )
+    .bind(followee_ap_id)
+    .bind(prefix)
+    .fetch_all(&app_state.pool)
+    .await?;
+
+    Ok(followers.into_iter().map(|(follower,)| follower).collect())
+}
+
+/// The partial followers digest used by the `Collection-Synchronization` header: the hex encoded
+/// XOR of the SHA-256 digests of each follower id.
+pub fn followers_digest(followers: &[String]) -> String {
+    let mut digest = [0u8; 32];
+    for follower in followers {
+        for (byte, other) in digest.iter_mut().zip(Sha256::digest(follower.as_bytes())) {
+            *byte ^= other;
+        }
+    }
+    digest.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn empty_digest() {
+        assert_eq!(followers_digest(&[]), "0".repeat(64));
+    }
+
+    #[test]
+    fn digest_is_order_independent() {
+        let nick = "https://thegem.city/users/nick".to_string();
+        let mattie = "https://thegem.city/users/mattie".to_string();
+
+        assert_eq!(
+            followers_digest(std::slice::from_ref(&nick)),
+            "ade6be354be9508cdc09e394e9ae93ce5b97d84d3832ac7462e4b20345f2625c"
+        );
+        assert_eq!(
+            followers_digest(&[nick.clone(), mattie.clone()]),
+            followers_digest(&[mattie.clone(), nick.clone()])
+        );
+        assert_eq!(
+            followers_digest(&[nick.clone(), mattie.clone(), mattie]),
+            followers_digest(&[nick])
+        );
+    }
+}
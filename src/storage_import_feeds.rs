@@ -0,0 +1,28 @@
+use crate::{error::ApEventsError, state::MyStateHandle};
+
+/// Registers a calendar feed to be imported again on every sync.
+pub async fn add_import_feed(app_state: &MyStateHandle, url: &str) -> Result<(), ApEventsError> {
+    sqlx::query("INSERT INTO import_feeds (url) VALUES ($1) ON CONFLICT DO NOTHING")
+        .bind(url)
+        .execute(&app_state.pool)
+        .await?;
+    Ok(())
+}
+
+pub async fn import_feeds(app_state: &MyStateHandle) -> Result<Vec<String>, ApEventsError> {
+    let feeds: Vec<(String,)> = sqlx::query_as("SELECT url FROM import_feeds ORDER BY url")
+        .fetch_all(&app_state.pool)
+        .await?;
+    Ok(feeds.into_iter().map(|(url,)| url).collect())
+}
+
+pub async fn mark_import_feed_synced(
+    app_state: &MyStateHandle,
+    url: &str,
+) -> Result<(), ApEventsError> {
+    sqlx::query("UPDATE import_feeds SET last_synced_at = now() WHERE url = $1")
+        .bind(url)
+        .execute(&app_state.pool)
+        .await?;
+    Ok(())
+}
@@ -0,0 +1,176 @@
+use chrono::NaiveDateTime;
+use serde_json::Value;
+use sqlx::{postgres::PgRow, FromRow, Row};
+
+use crate::{
+    ap::{ids::parse_object_id, normalize::is_public_addressed},
+    error::ApEventsError,
+    state::MyStateHandle,
+};
+
+/// The kinds of public activities that are listed in an actor's outbox.
+pub const OUTBOX_KINDS: [&str; 4] = ["Create", "Update", "Announce", "Delete"];
+
+pub struct StoredObject {
+    pub ap_id: String,
+    pub kind: String,
+    pub actor_ap_id: String,
+    pub payload: Value,
+    pub is_public: bool,
+    pub deleted_at: Option<NaiveDateTime>,
+}
+
+impl StoredObject {
+    /// Whether an actor is one of the addressees of the object, or of the object it wraps.
+    pub fn addressed_to(&self, actor_ap_id: &str) -> bool {
+        let addressees = |value: &Value| {
+            ["to", "cc", "bto", "bcc", "audience"]
+                .iter()
+                .filter_map(|field| match &value[*field] {
+                    Value::String(single) => Some(vec![single.clone()]),
+                    Value::Array(many) => Some(
+                        many.iter()
+                            .filter_map(|item| item.as_str().map(str::to_string))
+                            .collect(),
+                    ),
+                    _ => None,
+                })
+                .flatten()
+                .any(|addressee| addressee == actor_ap_id)
+        };
+        addressees(&self.payload) || addressees(&self.payload["object"])
+    }
+
+    /// Whether the object can be shown to whoever signed the request for it. Objects that are
+    /// not public, such as direct messages, are only shown to their addressees.
+    pub fn readable_by(&self, signer_ap_id: Option<&str>) -> bool {
+        self.is_public || signer_ap_id.is_some_and(|signer| self.addressed_to(signer))
+    }
+}
+
+impl FromRow<'_, PgRow> for StoredObject {
+    fn from_row(row: &PgRow) -> sqlx::Result<Self> {
+        Ok(Self {
+            ap_id: row.try_get("ap_id")?,
+            kind: row.try_get("kind")?,
+            actor_ap_id: row.try_get("actor_ap_id")?,
+            payload: row.try_get("payload")?,
+            is_public: row.try_get("is_public")?,
+            deleted_at: row.try_get("deleted_at")?,
+        })
+    }
+}
+
+/// Stores an activity or object that was generated by a local actor so that it can be served at
+/// its id. Storing the same id again replaces the payload.
+pub async fn store_object(
+    app_state: &MyStateHandle,
+    actor_ap_id: &str,
+    payload: &Value,
+) -> Result<(), ApEventsError> {
+    let ap_id = payload["id"]
+        .as_str()
+        .ok_or_else(|| ApEventsError::new("object id missing".to_string()))?;
+    let kind = payload["type"]
+        .as_str()
+        .ok_or_else(|| ApEventsError::new("object type missing".to_string()))?;
+
+    sqlx::query(
+        "INSERT INTO objects (ap_id, kind, actor_ap_id, payload, is_public) VALUES ($1, $2, $3, $4, $5) ON CONFLICT ON CONSTRAINT objects_pkey DO UPDATE SET payload = $4, is_public = $5, updated_at = now()",
+    )
+    .bind(ap_id)
+    .bind(kind)
+    .bind(actor_ap_id)
+    .bind(payload)
+    .bind(is_public_addressed(payload))
+    .execute(&app_state.pool)
+    .await?;
+    Ok(())
+}
+
+pub async fn get_object(
+    app_state: &MyStateHandle,
+    ap_id: &str,
+) -> Result<Option<StoredObject>, ApEventsError> {
+    sqlx::query_as("SELECT * FROM objects WHERE ap_id = $1")
+        .bind(ap_id)
+        .fetch_optional(&app_state.pool)
+        .await
+        .map_err(|err| err.into())
+}
+
+/// Finds a generated object by the last path segment of its id, using the kind encoded in the id
+/// to make sure the stored object is of the expected type.
+pub async fn get_generated_object(
+    app_state: &MyStateHandle,
+    generated_id: &str,
+) -> Result<StoredObject, ApEventsError> {
+    let kind = parse_object_id(generated_id)?;
+    let ap_id = format!(
+        "{}/{}/{}",
+        app_state.external_base,
+        kind.object_prefix(),
+        generated_id
+    );
+
+    get_object(app_state, &ap_id)
+        .await?
+        .filter(|found| found.kind == kind.name())
+        .ok_or(ApEventsError::ObjectNotFound(ap_id))
+}
+
+/// Marks an object as deleted. The row is kept so that the id keeps answering with a Tombstone.
+pub async fn delete_object(app_state: &MyStateHandle, ap_id: &str) -> Result<bool, ApEventsError> {
+    let result = sqlx::query(
+        "UPDATE objects SET deleted_at = now(), updated_at = now() WHERE ap_id = $1 AND deleted_at IS NULL",
+    )
+    .bind(ap_id)
+    .execute(&app_state.pool)
+    .await?;
+    Ok(result.rows_affected() > 0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn direct_message() -> StoredObject {
+        let payload = json!({
+            "id": "https://events.example/activity/AXBhdWV3aS0xMjM0NTY3ODkwYWI",
+            "type": "Create",
+            "actor": "https://events.example/actor/readily-splendid-mule",
+            "to": ["https://thegem.city/users/nick"],
+            "object": {
+                "id": "https://events.example/object/BXBhdWV3aS0xMjM0NTY3ODkwYWI",
+                "type": "Note",
+                "to": ["https://thegem.city/users/nick"],
+                "cc": ["https://thegem.city/users/dana"],
+                "content": "<p>Manage Swing night at https://events.example/events/readily-splendid-mule/admin?password=abcd1234</p>"
+            }
+        });
+        StoredObject {
+            ap_id: payload["id"].as_str().unwrap().to_string(),
+            kind: "Create".to_string(),
+            actor_ap_id: payload["actor"].as_str().unwrap().to_string(),
+            is_public: is_public_addressed(&payload),
+            payload,
+            deleted_at: None,
+        }
+    }
+
+    #[test]
+    fn direct_messages_are_only_read_by_addressees() {
+        let found = direct_message();
+        assert!(!found.is_public);
+        assert!(!found.readable_by(None));
+        assert!(!found.readable_by(Some("https://mastodon.social/users/mallory")));
+        assert!(found.readable_by(Some("https://thegem.city/users/nick")));
+        assert!(found.readable_by(Some("https://thegem.city/users/dana")));
+
+        let mut public = direct_message();
+        public.payload["cc"] = json!(["https://www.w3.org/ns/activitystreams#Public"]);
+        public.is_public = is_public_addressed(&public.payload);
+        assert!(public.readable_by(None));
+    }
+}
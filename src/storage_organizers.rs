@@ -0,0 +1,153 @@
+use std::{fmt, str::FromStr};
+
+use crate::{error::ApEventsError, state::MyStateHandle, storage_events::Event};
+
+/// What an organizer may do with an event. The owner is the event's `owner_ap_id`, and co-hosts
+/// and moderators are added by the owner.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Role {
+    /// Runs the event and chooses its other organizers.
+    Owner,
+    /// Runs the event alongside the owner: changes its status and details.
+    CoHost,
+    /// Looks after the attendee list.
+    Moderator,
+}
+
+impl Role {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Role::Owner => "owner",
+            Role::CoHost => "co-host",
+            Role::Moderator => "moderator",
+        }
+    }
+
+    /// Whether the role can change the event's status, details and reminders.
+    pub fn can_manage_event(&self) -> bool {
+        matches!(self, Role::Owner | Role::CoHost)
+    }
+
+    /// Whether the role can add and remove other organizers.
+    pub fn can_manage_organizers(&self) -> bool {
+        matches!(self, Role::Owner)
+    }
+}
+
+impl fmt::Display for Role {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for Role {
+    type Err = ApEventsError;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "owner" => Ok(Role::Owner),
+            "co-host" => Ok(Role::CoHost),
+            "moderator" => Ok(Role::Moderator),
+            _ => Err(ApEventsError::InvalidQuery(format!(
+                "unknown organizer role {value}"
+            ))),
+        }
+    }
+}
+
+/// The organizers of an event and their roles, owner first.
+pub async fn organizers(
+    app_state: &MyStateHandle,
+    event: &Event,
+) -> Result<Vec<(String, Role)>, ApEventsError> {
+    let found: Vec<(String, String)> = sqlx::query_as(
+        "SELECT organizer_ap_id, role FROM organizers WHERE event_ap_id = $1 ORDER BY created_at, organizer_ap_id",
+    )
+    .bind(&event.actor_ap_id)
+    .fetch_all(&app_state.pool)
+    .await?;
+
+    let mut organizers: Vec<(String, Role)> = event
+        .owner_ap_id
+        .iter()
+        .map(|owner_ap_id| (owner_ap_id.clone(), Role::Owner))
+        .collect();
+    for (organizer_ap_id, role) in found {
+        if !organizers
+            .iter()
+            .any(|(found, _)| *found == organizer_ap_id)
+        {
+            organizers.push((organizer_ap_id, role.parse()?));
+        }
+    }
+    Ok(organizers)
+}
+
+/// The role of an actor in an event, if they organize it.
+pub async fn organizer_role(
+    app_state: &MyStateHandle,
+    event: &Event,
+    actor_ap_id: &str,
+) -> Result<Option<Role>, ApEventsError> {
+    Ok(organizers(app_state, event)
+        .await?
+        .into_iter()
+        .find(|(organizer_ap_id, _)| organizer_ap_id == actor_ap_id)
+        .map(|(_, role)| role))
+}
+
+/// Adds a co-host or moderator to an event, or changes the role of an existing one.
+pub async fn set_organizer(
+    app_state: &MyStateHandle,
+    event: &Event,
+    organizer_ap_id: &str,
+    role: Role,
+) -> Result<(), ApEventsError> {
+    if role == Role::Owner || event.owner_ap_id.as_deref() == Some(organizer_ap_id) {
+        return Err(ApEventsError::InvalidEvent(
+            "the owner of an event cannot be changed here".to_string(),
+        ));
+    }
+    sqlx::query(
+        "INSERT INTO organizers (event_ap_id, organizer_ap_id, role) VALUES ($1, $2, $3) ON CONFLICT ON CONSTRAINT organizers_pkey DO UPDATE SET role = $3",
+    )
+    .bind(&event.actor_ap_id)
+    .bind(organizer_ap_id)
+    .bind(role.as_str())
+    .execute(&app_state.pool)
+    .await?;
+    Ok(())
+}
+
+/// Removes a co-host or moderator from an event. Returns false if they were not one.
+pub async fn remove_organizer(
+    app_state: &MyStateHandle,
+    event_ap_id: &str,
+    organizer_ap_id: &str,
+) -> Result<bool, ApEventsError> {
+    let result =
+        sqlx::query("DELETE FROM organizers WHERE event_ap_id = $1 AND organizer_ap_id = $2")
+            .bind(event_ap_id)
+            .bind(organizer_ap_id)
+            .execute(&app_state.pool)
+            .await?;
+    Ok(result.rows_affected() > 0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn roles() {
+        for role in [Role::Owner, Role::CoHost, Role::Moderator] {
+            assert_eq!(role.as_str().parse::<Role>().unwrap(), role);
+        }
+        assert!("cohost".parse::<Role>().is_err());
+
+        assert!(Role::Owner.can_manage_organizers());
+        assert!(!Role::CoHost.can_manage_organizers());
+        assert!(Role::CoHost.can_manage_event());
+        assert!(!Role::Moderator.can_manage_event());
+    }
+}
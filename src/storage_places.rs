@@ -0,0 +1,173 @@
+use chrono::{DateTime, Utc};
+use sqlx::{postgres::PgRow, FromRow, Row};
+
+use crate::{error::ApEventsError, state::MyStateHandle, storage_events::Event};
+
+/// A venue that events are held at. Places are actors of their own, so that people can follow a
+/// venue and hear about its events.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Place {
+    pub actor_ap_id: String,
+    pub name: String,
+    pub address: Option<String>,
+    pub latitude: Option<f64>,
+    pub longitude: Option<f64>,
+
+    /// Notes on getting in and around, such as step-free access or quiet rooms.
+    pub accessibility: Option<String>,
+}
+
+impl FromRow<'_, PgRow> for Place {
+    fn from_row(row: &PgRow) -> sqlx::Result<Self> {
+        Ok(Self {
+            actor_ap_id: row.try_get("actor_ap_id")?,
+            name: row.try_get("name")?,
+            address: row.try_get("address")?,
+            latitude: row.try_get("latitude")?,
+            longitude: row.try_get("longitude")?,
+            accessibility: row.try_get("accessibility")?,
+        })
+    }
+}
+
+impl Place {
+    /// The location text of events held at the place.
+    pub fn location(&self) -> String {
+        match &self.address {
+            Some(address) => format!("{}, {}", self.name, address),
+            None => self.name.clone(),
+        }
+    }
+
+    pub fn validate(&self) -> Result<(), ApEventsError> {
+        let invalid = |message: &str| Err(ApEventsError::InvalidPlace(message.to_string()));
+
+        if self.name.trim().is_empty() {
+            return invalid("name is required");
+        }
+        if self.name.chars().count() >= 100 {
+            return invalid("name must be under 100 characters");
+        }
+        if let Some(address) = &self.address {
+            // Leaves room for the name in the location of events.
+            if address.chars().count() >= 190 {
+                return invalid("address must be under 190 characters");
+            }
+        }
+        if let Some(accessibility) = &self.accessibility {
+            if accessibility.chars().count() >= 1000 {
+                return invalid("accessibility must be under 1000 characters");
+            }
+        }
+        match (self.latitude, self.longitude) {
+            (None, None) => {}
+            (Some(latitude), Some(longitude))
+                if (-90.0..=90.0).contains(&latitude) && (-180.0..=180.0).contains(&longitude) => {}
+            (Some(_), Some(_)) => return invalid("latitude or longitude is out of range"),
+            _ => return invalid("latitude and longitude are given together"),
+        }
+        Ok(())
+    }
+}
+
+pub async fn get_place(
+    app_state: &MyStateHandle,
+    actor_ap_id: &str,
+) -> Result<Option<Place>, ApEventsError> {
+    sqlx::query_as("SELECT * FROM places WHERE actor_ap_id = $1")
+        .bind(actor_ap_id)
+        .fetch_optional(&app_state.pool)
+        .await
+        .map_err(|err| err.into())
+}
+
+pub async fn all_places(app_state: &MyStateHandle) -> Result<Vec<Place>, ApEventsError> {
+    sqlx::query_as("SELECT * FROM places ORDER BY name, actor_ap_id")
+        .fetch_all(&app_state.pool)
+        .await
+        .map_err(|err| err.into())
+}
+
+/// Creates or updates a place. The location of events held at it is updated to match.
+pub async fn upsert_place(app_state: &MyStateHandle, place: &Place) -> Result<(), ApEventsError> {
+    place.validate()?;
+
+    let mut tx = app_state.pool.begin().await?;
+    sqlx::query(
+        "INSERT INTO places (actor_ap_id, name, address, latitude, longitude, accessibility) VALUES ($1, $2, $3, $4, $5, $6) ON CONFLICT ON CONSTRAINT places_pkey DO UPDATE SET name = $2, address = $3, latitude = $4, longitude = $5, accessibility = $6, updated_at = now()",
+    )
+    .bind(&place.actor_ap_id)
+    .bind(&place.name)
+    .bind(&place.address)
+    .bind(place.latitude)
+    .bind(place.longitude)
+    .bind(&place.accessibility)
+    .execute(&mut tx)
+    .await?;
+    sqlx::query(
+        "UPDATE events SET location = $2, sequence = sequence + 1, updated_at = now() WHERE place_ap_id = $1 AND location IS DISTINCT FROM $2",
+    )
+    .bind(&place.actor_ap_id)
+    .bind(place.location())
+    .execute(&mut tx)
+    .await?;
+    tx.commit().await?;
+    Ok(())
+}
+
+/// The published events held at a place that have not ended yet.
+pub async fn place_events(
+    app_state: &MyStateHandle,
+    place_ap_id: &str,
+    now: DateTime<Utc>,
+) -> Result<Vec<Event>, ApEventsError> {
+    let mut events: Vec<Event> = sqlx::query_as(
+        "SELECT * FROM events WHERE place_ap_id = $1 AND status != 'draft' AND (ends_at > $2 OR rrule IS NOT NULL) ORDER BY starts_at, actor_ap_id",
+    )
+    .bind(place_ap_id)
+    .bind(now)
+    .fetch_all(&app_state.pool)
+    .await?;
+    events.retain(|event| !event.next_occurrences(now, 1).is_empty());
+    Ok(events)
+}
+
+/// Merges duplicate places into one. Events held at the duplicates move to the kept place, their
+/// followers follow it instead, and the duplicates are deleted so that they answer with a
+/// Tombstone.
+pub async fn merge_places(
+    app_state: &MyStateHandle,
+    into: &Place,
+    duplicates: &[String],
+) -> Result<(), ApEventsError> {
+    let mut tx = app_state.pool.begin().await?;
+    sqlx::query(
+        "UPDATE events SET place_ap_id = $1, location = $3, sequence = sequence + 1, updated_at = now() WHERE place_ap_id = ANY($2)",
+    )
+    .bind(&into.actor_ap_id)
+    .bind(duplicates)
+    .bind(into.location())
+    .execute(&mut tx)
+    .await?;
+    sqlx::query(
+        "INSERT INTO follow_activities (follower_ap_id, followee_ap_id, activity_ap_id, accepted_at, accept_activity_id) SELECT DISTINCT ON (follower_ap_id) follower_ap_id, $1, activity_ap_id, accepted_at, accept_activity_id FROM follow_activities WHERE followee_ap_id = ANY($2) AND follower_ap_id != $1 ORDER BY follower_ap_id, created_at ON CONFLICT ON CONSTRAINT follow_activities_pkey DO NOTHING",
+    )
+    .bind(&into.actor_ap_id)
+    .bind(duplicates)
+    .execute(&mut tx)
+    .await?;
+    sqlx::query("DELETE FROM follow_activities WHERE followee_ap_id = ANY($1)")
+        .bind(duplicates)
+        .execute(&mut tx)
+        .await?;
+    sqlx::query("DELETE FROM places WHERE actor_ap_id = ANY($1)")
+        .bind(duplicates)
+        .execute(&mut tx)
+        .await?;
+    sqlx::query("UPDATE actors SET deleted_at = now() WHERE ap_id = ANY($1)")
+        .bind(duplicates)
+        .execute(&mut tx)
+        .await?;
+    tx.commit().await?;
+    Ok(())
+}
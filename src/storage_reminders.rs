@@ -0,0 +1,50 @@
+use crate::{error::ApEventsError, state::MyStateHandle};
+
+/// The reminder offsets of an event in minutes before it starts, or None if the event uses the
+/// instance's default offsets.
+pub async fn event_reminders(
+    app_state: &MyStateHandle,
+    actor_ap_id: &str,
+) -> Result<Option<Vec<i32>>, ApEventsError> {
+    let found: Option<(Option<Vec<i32>>,)> =
+        sqlx::query_as("SELECT reminders FROM events WHERE actor_ap_id = $1")
+            .bind(actor_ap_id)
+            .fetch_optional(&app_state.pool)
+            .await?;
+    Ok(found.and_then(|(reminders,)| reminders))
+}
+
+/// Replaces the reminder offsets of an event. No offsets turns reminders off.
+pub async fn set_event_reminders(
+    app_state: &MyStateHandle,
+    actor_ap_id: &str,
+    reminders: &[i32],
+) -> Result<(), ApEventsError> {
+    sqlx::query("UPDATE events SET reminders = $2 WHERE actor_ap_id = $1")
+        .bind(actor_ap_id)
+        .bind(reminders)
+        .execute(&app_state.pool)
+        .await?;
+    Ok(())
+}
+
+/// Records that a reminder is being sent. Returns false if it was already sent, so that each
+/// reminder is sent at most once even when the sender restarts.
+pub async fn claim_reminder(
+    app_state: &MyStateHandle,
+    actor_ap_id: &str,
+    occurrence: &str,
+    attendee_ap_id: &str,
+    minutes_before: i32,
+) -> Result<bool, ApEventsError> {
+    let result = sqlx::query(
+        "INSERT INTO sent_reminders (actor_ap_id, occurrence, attendee_ap_id, minutes_before) VALUES ($1, $2, $3, $4) ON CONFLICT ON CONSTRAINT sent_reminders_pkey DO NOTHING",
+    )
+    .bind(actor_ap_id)
+    .bind(occurrence)
+    .bind(attendee_ap_id)
+    .bind(minutes_before)
+    .execute(&app_state.pool)
+    .await?;
+    Ok(result.rows_affected() > 0)
+}
@@ -0,0 +1,719 @@
+use chrono::NaiveDateTime;
+use sqlx::{Postgres, Transaction};
+use std::str::FromStr;
+
+use crate::{error::ApEventsError, state::MyStateHandle};
+
+/// An attendee's answer to an event. Join and `#rsvp going` map to going, or to waitlisted when
+/// the event is full, TentativeAccept to maybe, and Leave, Reject and `#rsvp not going` to not
+/// going.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RsvpStatus {
+    Going,
+    Maybe,
+    NotGoing,
+    Waitlisted,
+}
+
+impl RsvpStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            RsvpStatus::Going => "going",
+            RsvpStatus::Maybe => "maybe",
+            RsvpStatus::NotGoing => "not going",
+            RsvpStatus::Waitlisted => "waitlisted",
+        }
+    }
+}
+
+impl FromStr for RsvpStatus {
+    type Err = ApEventsError;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "going" => Ok(RsvpStatus::Going),
+            "maybe" => Ok(RsvpStatus::Maybe),
+            "not going" => Ok(RsvpStatus::NotGoing),
+            "waitlisted" => Ok(RsvpStatus::Waitlisted),
+            _ => Err(ApEventsError::new(format!("unknown rsvp status {value}"))),
+        }
+    }
+}
+
+/// Answers to a whole event, rather than to one occurrence of a recurring event, use this
+/// occurrence id.
+pub const ALL_OCCURRENCES: &str = "";
+
+const SET_RSVP: &str = "INSERT INTO rsvps (actor_ap_id, occurrence, attendee_ap_id, status, activity_ap_id, hidden) VALUES ($1, $2, $3, $4, $5, COALESCE($6, false)) ON CONFLICT ON CONSTRAINT rsvps_pkey DO UPDATE SET status = $4, activity_ap_id = $5, hidden = COALESCE($6, rsvps.hidden), updated_at = now()";
+
+/// Records an attendee's answer, replacing any previous answer to the same event or occurrence.
+/// Hidden answers are only shown to the organizer. When `hidden` is None, a previous answer's
+/// choice is kept and new answers are public.
+pub async fn set_rsvp(
+    app_state: &MyStateHandle,
+    actor_ap_id: &str,
+    occurrence: &str,
+    attendee_ap_id: &str,
+    status: RsvpStatus,
+    activity_ap_id: Option<&str>,
+    hidden: Option<bool>,
+) -> Result<(), ApEventsError> {
+    sqlx::query(SET_RSVP)
+        .bind(actor_ap_id)
+        .bind(occurrence)
+        .bind(attendee_ap_id)
+        .bind(status.as_str())
+        .bind(activity_ap_id)
+        .bind(hidden)
+        .execute(&app_state.pool)
+        .await?;
+    Ok(())
+}
+
+/// Where an answer of going leaves an attendee.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Seat {
+    Going,
+    Waitlisted,
+    /// Already on the waitlist, and keeps their place in line.
+    KeepPlace,
+}
+
+/// Gives an answer of going a seat if one is left out of the capacity, not counting the
+/// attendee's own seat.
+pub fn seat(capacity: Option<i32>, taken: i64, previous: Option<RsvpStatus>) -> Seat {
+    match capacity {
+        Some(capacity) if taken >= i64::from(capacity) => match previous {
+            Some(RsvpStatus::Waitlisted) => Seat::KeepPlace,
+            _ => Seat::Waitlisted,
+        },
+        _ => Seat::Going,
+    }
+}
+
+/// Counts the seats taken at an occurrence by everyone other than the given attendee. These are
+/// the people `going_attendees` lists. Answers to the whole event hold a seat at every occurrence,
+/// so the whole event counts them along with its busiest occurrence from `since` on.
+async fn seats_taken(
+    tx: &mut Transaction<'_, Postgres>,
+    actor_ap_id: &str,
+    occurrence: &str,
+    since: &str,
+    attendee_ap_id: &str,
+) -> Result<i64, ApEventsError> {
+    let (taken,): (i64,) = if occurrence == ALL_OCCURRENCES {
+        sqlx::query_as(
+            "SELECT (SELECT COUNT(*) FROM rsvps WHERE actor_ap_id = $1 AND occurrence = '' AND status = 'going' AND attendee_ap_id != $3) + COALESCE((SELECT MAX(going) FROM (SELECT COUNT(*) AS going FROM rsvps single WHERE single.actor_ap_id = $1 AND single.occurrence >= $2 AND single.occurrence != '' AND single.status = 'going' AND single.attendee_ap_id != $3 AND NOT EXISTS (SELECT 1 FROM rsvps whole WHERE whole.actor_ap_id = $1 AND whole.occurrence = '' AND whole.attendee_ap_id = single.attendee_ap_id AND whole.status = 'going') GROUP BY single.occurrence) AS occurrences), 0)",
+        )
+        .bind(actor_ap_id)
+        .bind(since)
+        .bind(attendee_ap_id)
+        .fetch_one(&mut *tx)
+        .await?
+    } else {
+        sqlx::query_as(
+            "SELECT COUNT(DISTINCT attendee_ap_id) FROM rsvps WHERE actor_ap_id = $1 AND status = 'going' AND attendee_ap_id != $3 AND (occurrence = $2 OR (occurrence = '' AND NOT EXISTS (SELECT 1 FROM rsvps answered WHERE answered.actor_ap_id = rsvps.actor_ap_id AND answered.attendee_ap_id = rsvps.attendee_ap_id AND answered.occurrence = $2 AND answered.status != 'going')))",
+        )
+        .bind(actor_ap_id)
+        .bind(occurrence)
+        .bind(attendee_ap_id)
+        .fetch_one(&mut *tx)
+        .await?
+    };
+    Ok(taken)
+}
+
+/// The seats taken at an occurrence, or for the whole event, counted the same way as when
+/// someone answers.
+pub async fn count_seats(
+    app_state: &MyStateHandle,
+    actor_ap_id: &str,
+    occurrence: &str,
+    since: &str,
+) -> Result<i64, ApEventsError> {
+    let mut tx = app_state.pool.begin().await?;
+    // Nobody is left out of the count, since nobody is answering.
+    let taken = seats_taken(&mut tx, actor_ap_id, occurrence, since, "").await?;
+    tx.commit().await?;
+    Ok(taken)
+}
+
+/// Locks the event for the rest of the transaction, so that seats are counted and taken one
+/// answer at a time, and returns its capacity.
+async fn lock_capacity(
+    tx: &mut Transaction<'_, Postgres>,
+    actor_ap_id: &str,
+) -> Result<Option<i32>, ApEventsError> {
+    let (capacity,): (Option<i32>,) =
+        sqlx::query_as("SELECT capacity FROM events WHERE actor_ap_id = $1 FOR UPDATE")
+            .bind(actor_ap_id)
+            .fetch_one(&mut *tx)
+            .await?;
+    Ok(capacity)
+}
+
+/// Records an answer of going, or of waitlisted when the occurrence is full. Returns the
+/// recorded answer. Someone already on the waitlist keeps their place in line, and only changes
+/// whether their answer is hidden.
+pub async fn claim_seat(
+    app_state: &MyStateHandle,
+    actor_ap_id: &str,
+    occurrence: &str,
+    since: &str,
+    attendee_ap_id: &str,
+    activity_ap_id: Option<&str>,
+    hidden: Option<bool>,
+) -> Result<RsvpStatus, ApEventsError> {
+    let mut tx = app_state.pool.begin().await?;
+    let capacity = lock_capacity(&mut tx, actor_ap_id).await?;
+    let taken = seats_taken(&mut tx, actor_ap_id, occurrence, since, attendee_ap_id).await?;
+    let previous: Option<(String,)> = sqlx::query_as(
+        "SELECT status FROM rsvps WHERE actor_ap_id = $1 AND occurrence = $2 AND attendee_ap_id = $3",
+    )
+    .bind(actor_ap_id)
+    .bind(occurrence)
+    .bind(attendee_ap_id)
+    .fetch_optional(&mut tx)
+    .await?;
+    let previous = previous.map(|(status,)| status.parse()).transpose()?;
+
+    let recorded = match seat(capacity, taken, previous) {
+        Seat::KeepPlace => {
+            sqlx::query(
+                "UPDATE rsvps SET hidden = COALESCE($4, hidden) WHERE actor_ap_id = $1 AND occurrence = $2 AND attendee_ap_id = $3",
+            )
+            .bind(actor_ap_id)
+            .bind(occurrence)
+            .bind(attendee_ap_id)
+            .bind(hidden)
+            .execute(&mut tx)
+            .await?;
+            RsvpStatus::Waitlisted
+        }
+        seat => {
+            let status = match seat {
+                Seat::Going => RsvpStatus::Going,
+                _ => RsvpStatus::Waitlisted,
+            };
+            sqlx::query(SET_RSVP)
+                .bind(actor_ap_id)
+                .bind(occurrence)
+                .bind(attendee_ap_id)
+                .bind(status.as_str())
+                .bind(activity_ap_id)
+                .bind(hidden)
+                .execute(&mut tx)
+                .await?;
+            status
+        }
+    };
+    tx.commit().await?;
+    Ok(recorded)
+}
+
+/// Gives a seat that opened up at an occurrence to whoever has waited longest for it. Returns
+/// them with the activity they asked to join with, or None when there is no seat or nobody
+/// waiting.
+pub async fn promote_waitlisted(
+    app_state: &MyStateHandle,
+    actor_ap_id: &str,
+    occurrence: &str,
+    since: &str,
+) -> Result<Option<(String, Option<String>)>, ApEventsError> {
+    let mut tx = app_state.pool.begin().await?;
+    let capacity = lock_capacity(&mut tx, actor_ap_id).await?;
+    let taken = seats_taken(&mut tx, actor_ap_id, occurrence, since, "").await?;
+    if seat(capacity, taken, None) != Seat::Going {
+        return Ok(None);
+    }
+
+    let next: Option<(String, Option<String>)> = sqlx::query_as(
+        "SELECT attendee_ap_id, activity_ap_id FROM rsvps WHERE actor_ap_id = $1 AND occurrence = $2 AND status = 'waitlisted' ORDER BY updated_at, attendee_ap_id LIMIT 1",
+    )
+    .bind(actor_ap_id)
+    .bind(occurrence)
+    .fetch_optional(&mut tx)
+    .await?;
+    if let Some((attendee_ap_id, _)) = &next {
+        sqlx::query(
+            "UPDATE rsvps SET status = 'going', updated_at = now() WHERE actor_ap_id = $1 AND occurrence = $2 AND attendee_ap_id = $3",
+        )
+        .bind(actor_ap_id)
+        .bind(occurrence)
+        .bind(attendee_ap_id)
+        .execute(&mut tx)
+        .await?;
+    }
+    tx.commit().await?;
+    Ok(next)
+}
+
+pub async fn get_rsvp(
+    app_state: &MyStateHandle,
+    actor_ap_id: &str,
+    occurrence: &str,
+    attendee_ap_id: &str,
+) -> Result<Option<RsvpStatus>, ApEventsError> {
+    let found: Option<(String,)> = sqlx::query_as(
+        "SELECT status FROM rsvps WHERE actor_ap_id = $1 AND occurrence = $2 AND attendee_ap_id = $3",
+    )
+    .bind(actor_ap_id)
+    .bind(occurrence)
+    .bind(attendee_ap_id)
+    .fetch_optional(&app_state.pool)
+    .await?;
+
+    found.map(|(status,)| status.parse()).transpose()
+}
+
+pub async fn count_rsvps(
+    app_state: &MyStateHandle,
+    actor_ap_id: &str,
+    occurrence: &str,
+    status: RsvpStatus,
+) -> Result<i64, ApEventsError> {
+    let (count,): (i64,) = sqlx::query_as(
+        "SELECT COUNT(*) FROM rsvps WHERE actor_ap_id = $1 AND occurrence = $2 AND status = $3",
+    )
+    .bind(actor_ap_id)
+    .bind(occurrence)
+    .bind(status.as_str())
+    .fetch_one(&app_state.pool)
+    .await?;
+    Ok(count)
+}
+
+/// Everyone going to an occurrence, either by answering it or by answering the whole event
+/// without answering the occurrence differently.
+pub async fn going_attendees(
+    app_state: &MyStateHandle,
+    actor_ap_id: &str,
+    occurrence: &str,
+) -> Result<Vec<String>, ApEventsError> {
+    let attendees: Vec<(String,)> = sqlx::query_as(
+        "SELECT attendee_ap_id FROM rsvps WHERE actor_ap_id = $1 AND status = 'going' AND (occurrence = $2 OR (occurrence = '' AND NOT EXISTS (SELECT 1 FROM rsvps answered WHERE answered.actor_ap_id = rsvps.actor_ap_id AND answered.attendee_ap_id = rsvps.attendee_ap_id AND answered.occurrence = $2 AND answered.status != 'going'))) GROUP BY attendee_ap_id ORDER BY attendee_ap_id",
+    )
+    .bind(actor_ap_id)
+    .bind(occurrence)
+    .fetch_all(&app_state.pool)
+    .await?;
+    Ok(attendees
+        .into_iter()
+        .map(|(attendee_ap_id,)| attendee_ap_id)
+        .collect())
+}
+
+/// Everyone going to an event or occurrence, with whether they asked to be hidden. Like
+/// `going_attendees`, people going to every occurrence are going to each one unless they
+/// answered otherwise, and their answer to the occurrence decides whether they are hidden.
+pub async fn going_with_privacy(
+    app_state: &MyStateHandle,
+    actor_ap_id: &str,
+    occurrence: &str,
+) -> Result<Vec<(String, bool)>, ApEventsError> {
+    sqlx::query_as(
+        "SELECT attendee_ap_id, hidden FROM (SELECT DISTINCT ON (attendee_ap_id) attendee_ap_id, hidden, created_at FROM rsvps WHERE actor_ap_id = $1 AND status = 'going' AND (occurrence = $2 OR (occurrence = '' AND NOT EXISTS (SELECT 1 FROM rsvps answered WHERE answered.actor_ap_id = rsvps.actor_ap_id AND answered.attendee_ap_id = rsvps.attendee_ap_id AND answered.occurrence = $2 AND answered.status != 'going'))) ORDER BY attendee_ap_id, occurrence DESC) AS going ORDER BY created_at, attendee_ap_id",
+    )
+    .bind(actor_ap_id)
+    .bind(occurrence)
+    .fetch_all(&app_state.pool)
+    .await
+    .map_err(|err| err.into())
+}
+
+/// Whether the organizer hid the whole attendee list of an event.
+pub async fn attendees_hidden(
+    app_state: &MyStateHandle,
+    actor_ap_id: &str,
+) -> Result<bool, ApEventsError> {
+    let found: Option<(bool,)> =
+        sqlx::query_as("SELECT hide_attendees FROM events WHERE actor_ap_id = $1")
+            .bind(actor_ap_id)
+            .fetch_optional(&app_state.pool)
+            .await?;
+    Ok(found.is_some_and(|(hidden,)| hidden))
+}
+
+pub async fn set_attendees_hidden(
+    app_state: &MyStateHandle,
+    actor_ap_id: &str,
+    hidden: bool,
+) -> Result<(), ApEventsError> {
+    sqlx::query("UPDATE events SET hide_attendees = $2 WHERE actor_ap_id = $1")
+        .bind(actor_ap_id)
+        .bind(hidden)
+        .execute(&app_state.pool)
+        .await?;
+    Ok(())
+}
+
+/// An attendee's place on the waitlist of an occurrence, starting at 1.
+pub async fn waitlist_position(
+    app_state: &MyStateHandle,
+    actor_ap_id: &str,
+    occurrence: &str,
+    attendee_ap_id: &str,
+) -> Result<i64, ApEventsError> {
+    let (position,): (i64,) = sqlx::query_as(
+        "SELECT COUNT(*) FROM rsvps waiting, rsvps attendee WHERE attendee.actor_ap_id = $1 AND attendee.occurrence = $2 AND attendee.attendee_ap_id = $3 AND waiting.actor_ap_id = $1 AND waiting.occurrence = $2 AND waiting.status = 'waitlisted' AND (waiting.updated_at, waiting.attendee_ap_id) <= (attendee.updated_at, attendee.attendee_ap_id)",
+    )
+    .bind(actor_ap_id)
+    .bind(occurrence)
+    .bind(attendee_ap_id)
+    .fetch_one(&app_state.pool)
+    .await?;
+    Ok(position)
+}
+
+/// The occurrences of an event that have anyone on their waitlist.
+pub async fn waitlisted_occurrences(
+    app_state: &MyStateHandle,
+    actor_ap_id: &str,
+) -> Result<Vec<String>, ApEventsError> {
+    let occurrences: Vec<(String,)> = sqlx::query_as(
+        "SELECT DISTINCT occurrence FROM rsvps WHERE actor_ap_id = $1 AND status = 'waitlisted' ORDER BY occurrence",
+    )
+    .bind(actor_ap_id)
+    .fetch_all(&app_state.pool)
+    .await?;
+    Ok(occurrences
+        .into_iter()
+        .map(|(occurrence,)| occurrence)
+        .collect())
+}
+
+/// Everyone going to the event or any of its occurrences.
+pub async fn everyone_going(
+    app_state: &MyStateHandle,
+    actor_ap_id: &str,
+) -> Result<Vec<String>, ApEventsError> {
+    let attendees: Vec<(String,)> = sqlx::query_as(
+        "SELECT DISTINCT attendee_ap_id FROM rsvps WHERE actor_ap_id = $1 AND status = 'going' ORDER BY attendee_ap_id",
+    )
+    .bind(actor_ap_id)
+    .fetch_all(&app_state.pool)
+    .await?;
+    Ok(attendees
+        .into_iter()
+        .map(|(attendee_ap_id,)| attendee_ap_id)
+        .collect())
+}
+
+/// Everyone going to, or maybe going to, the event or any of its occurrences.
+pub async fn attendees(
+    app_state: &MyStateHandle,
+    actor_ap_id: &str,
+) -> Result<Vec<String>, ApEventsError> {
+    let attendees: Vec<(String,)> = sqlx::query_as(
+        "SELECT DISTINCT attendee_ap_id FROM rsvps WHERE actor_ap_id = $1 AND status IN ('going', 'maybe') ORDER BY attendee_ap_id",
+    )
+    .bind(actor_ap_id)
+    .fetch_all(&app_state.pool)
+    .await?;
+    Ok(attendees
+        .into_iter()
+        .map(|(attendee_ap_id,)| attendee_ap_id)
+        .collect())
+}
+
+/// The attendees that may be listed publicly, such as in the addressing of the Event object.
+/// Hidden attendees, and everyone when the organizer hid the attendee list, are left out.
+pub async fn listed_attendees(
+    app_state: &MyStateHandle,
+    actor_ap_id: &str,
+) -> Result<Vec<String>, ApEventsError> {
+    let attendees: Vec<(String,)> = sqlx::query_as(
+        "SELECT DISTINCT attendee_ap_id FROM rsvps WHERE actor_ap_id = $1 AND status IN ('going', 'maybe') AND NOT hidden AND NOT EXISTS (SELECT 1 FROM events WHERE events.actor_ap_id = rsvps.actor_ap_id AND events.hide_attendees) ORDER BY attendee_ap_id",
+    )
+    .bind(actor_ap_id)
+    .fetch_all(&app_state.pool)
+    .await?;
+    Ok(attendees
+        .into_iter()
+        .map(|(attendee_ap_id,)| attendee_ap_id)
+        .collect())
+}
+
+/// Every answer to an event: the occurrence, the attendee, their answer and whether it is hidden.
+pub async fn all_rsvps(
+    app_state: &MyStateHandle,
+    actor_ap_id: &str,
+) -> Result<Vec<(String, String, RsvpStatus, bool)>, ApEventsError> {
+    let found: Vec<(String, String, String, bool)> = sqlx::query_as(
+        "SELECT occurrence, attendee_ap_id, status, hidden FROM rsvps WHERE actor_ap_id = $1 ORDER BY created_at, attendee_ap_id",
+    )
+    .bind(actor_ap_id)
+    .fetch_all(&app_state.pool)
+    .await?;
+    found
+        .into_iter()
+        .map(|(occurrence, attendee_ap_id, status, hidden)| {
+            Ok((occurrence, attendee_ap_id, status.parse()?, hidden))
+        })
+        .collect()
+}
+
+/// When any of an attendee's answers last changed.
+pub async fn latest_rsvp_update(
+    app_state: &MyStateHandle,
+    attendee_ap_id: &str,
+) -> Result<Option<NaiveDateTime>, ApEventsError> {
+    let (latest,): (Option<NaiveDateTime>,) =
+        sqlx::query_as("SELECT MAX(updated_at) FROM rsvps WHERE attendee_ap_id = $1")
+            .bind(attendee_ap_id)
+            .fetch_one(&app_state.pool)
+            .await?;
+    Ok(latest)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn status_round_trip() {
+        for status in [
+            RsvpStatus::Going,
+            RsvpStatus::Maybe,
+            RsvpStatus::NotGoing,
+            RsvpStatus::Waitlisted,
+        ] {
+            assert_eq!(status.as_str().parse::<RsvpStatus>().unwrap(), status);
+        }
+        assert!("nope".parse::<RsvpStatus>().is_err());
+    }
+
+    #[test]
+    fn seats() {
+        assert_eq!(seat(None, 100, None), Seat::Going);
+        assert_eq!(seat(Some(2), 1, None), Seat::Going);
+        assert_eq!(seat(Some(2), 2, None), Seat::Waitlisted);
+        assert_eq!(seat(Some(2), 2, Some(RsvpStatus::Going)), Seat::Waitlisted);
+        assert_eq!(
+            seat(Some(2), 2, Some(RsvpStatus::Waitlisted)),
+            Seat::KeepPlace
+        );
+        assert_eq!(seat(Some(2), 1, Some(RsvpStatus::Waitlisted)), Seat::Going);
+    }
+
+    /// Adds an event with the given capacity, and returns its actor id.
+    async fn event_with_capacity(app_state: &MyStateHandle, capacity: i32) -> String {
+        let actor_ap_id = format!("https://events.example/actor/{}", rand::random::<u64>());
+        sqlx::query(
+            "INSERT INTO events (actor_ap_id, title, starts_at, ends_at, capacity) VALUES ($1, 'Swing night', now() + interval '1 day', now() + interval '1 day 3 hours', $2)",
+        )
+        .bind(&actor_ap_id)
+        .bind(capacity)
+        .execute(&app_state.pool)
+        .await
+        .unwrap();
+        actor_ap_id
+    }
+
+    #[actix_web::test]
+    async fn waitlist_order_and_promotion() {
+        let Some(app_state) = crate::state::test_state().await else {
+            return;
+        };
+        let event = event_with_capacity(&app_state, 2).await;
+        let claim = |attendee: &'static str| {
+            let app_state = app_state.clone();
+            let event = event.clone();
+            async move {
+                claim_seat(&app_state, &event, "", "", attendee, None, None)
+                    .await
+                    .unwrap()
+            }
+        };
+
+        assert_eq!(claim("alice").await, RsvpStatus::Going);
+        assert_eq!(claim("bob").await, RsvpStatus::Going);
+        assert_eq!(claim("carol").await, RsvpStatus::Waitlisted);
+        assert_eq!(claim("dana").await, RsvpStatus::Waitlisted);
+        // Answering again keeps everyone's seat or place in line.
+        assert_eq!(claim("alice").await, RsvpStatus::Going);
+        assert_eq!(claim("carol").await, RsvpStatus::Waitlisted);
+        assert_eq!(
+            waitlist_position(&app_state, &event, "", "carol")
+                .await
+                .unwrap(),
+            1
+        );
+        assert_eq!(
+            waitlist_position(&app_state, &event, "", "dana")
+                .await
+                .unwrap(),
+            2
+        );
+
+        // Nobody is promoted while the event is full.
+        assert_eq!(
+            promote_waitlisted(&app_state, &event, "", "")
+                .await
+                .unwrap(),
+            None
+        );
+        set_rsvp(
+            &app_state,
+            &event,
+            "",
+            "alice",
+            RsvpStatus::NotGoing,
+            None,
+            None,
+        )
+        .await
+        .unwrap();
+        assert_eq!(
+            promote_waitlisted(&app_state, &event, "", "")
+                .await
+                .unwrap(),
+            Some(("carol".to_string(), None))
+        );
+        assert_eq!(
+            promote_waitlisted(&app_state, &event, "", "")
+                .await
+                .unwrap(),
+            None
+        );
+        assert_eq!(
+            waitlist_position(&app_state, &event, "", "dana")
+                .await
+                .unwrap(),
+            1
+        );
+    }
+
+    #[actix_web::test]
+    async fn whole_event_answers_hold_seats() {
+        let Some(app_state) = crate::state::test_state().await else {
+            return;
+        };
+        let event = event_with_capacity(&app_state, 1).await;
+        let first = "20301105T000000Z";
+        let second = "20301112T000000Z";
+
+        assert_eq!(
+            claim_seat(&app_state, &event, "", first, "alice", None, None)
+                .await
+                .unwrap(),
+            RsvpStatus::Going
+        );
+        // Alice holds the seat at every occurrence.
+        assert_eq!(
+            claim_seat(&app_state, &event, second, first, "bob", None, None)
+                .await
+                .unwrap(),
+            RsvpStatus::Waitlisted
+        );
+
+        // Once she skips an occurrence, her seat there is free.
+        set_rsvp(
+            &app_state,
+            &event,
+            second,
+            "alice",
+            RsvpStatus::NotGoing,
+            None,
+            None,
+        )
+        .await
+        .unwrap();
+        assert_eq!(
+            promote_waitlisted(&app_state, &event, second, first)
+                .await
+                .unwrap(),
+            Some(("bob".to_string(), None))
+        );
+        // Bob now holds a seat at one occurrence, so nobody else can go to all of them.
+        assert_eq!(
+            claim_seat(&app_state, &event, "", first, "carol", None, None)
+                .await
+                .unwrap(),
+            RsvpStatus::Waitlisted
+        );
+    }
+
+    #[actix_web::test]
+    async fn occurrences_count_whole_event_answers() {
+        let Some(app_state) = crate::state::test_state().await else {
+            return;
+        };
+        let event = event_with_capacity(&app_state, 10).await;
+        let first = "20301105T000000Z";
+        let second = "20301112T000000Z";
+
+        for (occurrence, attendee, status, hidden) in [
+            ("", "alice", RsvpStatus::Going, None),
+            ("", "bob", RsvpStatus::Going, None),
+            (first, "bob", RsvpStatus::NotGoing, None),
+            (first, "carol", RsvpStatus::Going, Some(true)),
+            (second, "dana", RsvpStatus::Going, None),
+        ] {
+            set_rsvp(
+                &app_state, &event, occurrence, attendee, status, None, hidden,
+            )
+            .await
+            .unwrap();
+        }
+
+        let mut going = going_with_privacy(&app_state, &event, first).await.unwrap();
+        going.sort();
+        assert_eq!(
+            going,
+            vec![("alice".to_string(), false), ("carol".to_string(), true)]
+        );
+        assert_eq!(
+            count_seats(&app_state, &event, first, first).await.unwrap(),
+            going.len() as i64
+        );
+        assert_eq!(
+            count_seats(&app_state, &event, second, first)
+                .await
+                .unwrap(),
+            3
+        );
+        // Two people go to every occurrence, and at most one more to any one of them.
+        assert_eq!(count_seats(&app_state, &event, "", first).await.unwrap(), 3);
+    }
+
+    #[actix_web::test]
+    async fn concurrent_answers_do_not_overbook() {
+        let Some(app_state) = crate::state::test_state().await else {
+            return;
+        };
+        let event = event_with_capacity(&app_state, 1).await;
+        let answers: Vec<_> = (0..5)
+            .map(|attendee| {
+                let app_state = app_state.clone();
+                let event = event.clone();
+                actix_web::rt::spawn(async move {
+                    claim_seat(
+                        &app_state,
+                        &event,
+                        "",
+                        "",
+                        &format!("attendee-{attendee}"),
+                        None,
+                        None,
+                    )
+                    .await
+                    .unwrap()
+                })
+            })
+            .collect();
+        let mut going = 0;
+        for answer in answers {
+            if answer.await.unwrap() == RsvpStatus::Going {
+                going += 1;
+            }
+        }
+        assert_eq!(going, 1);
+        assert_eq!(
+            count_rsvps(&app_state, &event, "", RsvpStatus::Going)
+                .await
+                .unwrap(),
+            1
+        );
+    }
+}
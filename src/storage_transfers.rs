@@ -0,0 +1,100 @@
+use crate::{error::ApEventsError, state::MyStateHandle, storage_events::Event};
+
+/// Asks someone to take over an event. Ownership only moves once they confirm, and a new
+/// nomination replaces any earlier one.
+pub async fn nominate_owner(
+    app_state: &MyStateHandle,
+    event: &Event,
+    nominee_ap_id: &str,
+) -> Result<(), ApEventsError> {
+    if event.owner_ap_id.as_deref() == Some(nominee_ap_id) {
+        return Err(ApEventsError::InvalidEvent(
+            "they already own it".to_string(),
+        ));
+    }
+    sqlx::query(
+        "INSERT INTO ownership_transfers (event_ap_id, nominee_ap_id) VALUES ($1, $2) ON CONFLICT ON CONSTRAINT ownership_transfers_pkey DO UPDATE SET nominee_ap_id = $2, created_at = now()",
+    )
+    .bind(&event.actor_ap_id)
+    .bind(nominee_ap_id)
+    .execute(&app_state.pool)
+    .await?;
+    Ok(())
+}
+
+/// Who has been asked to take over an event, if anyone.
+pub async fn pending_transfer(
+    app_state: &MyStateHandle,
+    event_ap_id: &str,
+) -> Result<Option<String>, ApEventsError> {
+    let found: Option<(String,)> =
+        sqlx::query_as("SELECT nominee_ap_id FROM ownership_transfers WHERE event_ap_id = $1")
+            .bind(event_ap_id)
+            .fetch_optional(&app_state.pool)
+            .await?;
+    Ok(found.map(|(nominee_ap_id,)| nominee_ap_id))
+}
+
+/// Withdraws the nomination for an event. Returns false if there was none.
+pub async fn cancel_transfer(
+    app_state: &MyStateHandle,
+    event_ap_id: &str,
+) -> Result<bool, ApEventsError> {
+    let result = sqlx::query("DELETE FROM ownership_transfers WHERE event_ap_id = $1")
+        .bind(event_ap_id)
+        .execute(&app_state.pool)
+        .await?;
+    Ok(result.rows_affected() > 0)
+}
+
+/// Makes the nominee the owner of an event. Returns false if they were not nominated. The nominee stops being a co-host or moderator, and admin links issued to the
+/// previous owner stop working.
+pub async fn complete_transfer(
+    app_state: &MyStateHandle,
+    event_ap_id: &str,
+    nominee_ap_id: &str,
+) -> Result<bool, ApEventsError> {
+    let mut tx = app_state.pool.begin().await?;
+
+    let result = sqlx::query(
+        "DELETE FROM ownership_transfers WHERE event_ap_id = $1 AND nominee_ap_id = $2",
+    )
+    .bind(event_ap_id)
+    .bind(nominee_ap_id)
+    .execute(&mut tx)
+    .await?;
+    if result.rows_affected() == 0 {
+        return Ok(false);
+    }
+
+    sqlx::query("UPDATE events SET owner_ap_id = $2 WHERE actor_ap_id = $1")
+        .bind(event_ap_id)
+        .bind(nominee_ap_id)
+        .execute(&mut tx)
+        .await?;
+    sqlx::query("DELETE FROM organizers WHERE event_ap_id = $1 AND organizer_ap_id = $2")
+        .bind(event_ap_id)
+        .bind(nominee_ap_id)
+        .execute(&mut tx)
+        .await?;
+    sqlx::query("DELETE FROM admin_tokens WHERE actor_ap_id = $1 AND role = 'owner'")
+        .bind(event_ap_id)
+        .execute(&mut tx)
+        .await?;
+
+    tx.commit().await?;
+    Ok(true)
+}
+
+/// The events owned by an actor.
+pub async fn owned_events(
+    app_state: &MyStateHandle,
+    owner_ap_id: &str,
+) -> Result<Vec<Event>, ApEventsError> {
+    Ok(
+        sqlx::query_as("SELECT * FROM events WHERE owner_ap_id = $1 ORDER BY starts_at")
+            .bind(owner_ap_id)
+            .fetch_all(&app_state.pool)
+            .await?,
+    )
+}
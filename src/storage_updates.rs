@@ -0,0 +1,38 @@
+use chrono::{DateTime, Utc};
+use serde_json::Value;
+
+use crate::{error::ApEventsError, state::MyStateHandle};
+
+/// Queues a notification about an edited event. The details from before the first of several
+/// edits in a row are kept, and each edit pushes the notification back.
+pub async fn queue_event_update(
+    app_state: &MyStateHandle,
+    actor_ap_id: &str,
+    previous: &Value,
+    notify_at: DateTime<Utc>,
+) -> Result<(), ApEventsError> {
+    sqlx::query(
+        "INSERT INTO pending_updates (actor_ap_id, previous, notify_at) VALUES ($1, $2, $3) ON CONFLICT ON CONSTRAINT pending_updates_pkey DO UPDATE SET notify_at = $3",
+    )
+    .bind(actor_ap_id)
+    .bind(previous)
+    .bind(notify_at)
+    .execute(&app_state.pool)
+    .await?;
+    Ok(())
+}
+
+/// Removes and returns the queued notifications that are due, with the details from before the
+/// edits.
+pub async fn take_due_event_updates(
+    app_state: &MyStateHandle,
+    now: DateTime<Utc>,
+) -> Result<Vec<(String, Value)>, ApEventsError> {
+    sqlx::query_as(
+        "DELETE FROM pending_updates WHERE notify_at <= $1 RETURNING actor_ap_id, previous",
+    )
+    .bind(now)
+    .fetch_all(&app_state.pool)
+    .await
+    .map_err(|err| err.into())
+}
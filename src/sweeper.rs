@@ -0,0 +1,153 @@
+use activitypub_federation::{core::object_id::ObjectId, traits::Actor};
+use chrono::{DateTime, Utc};
+use log::{info, warn};
+use serde::Serialize;
+use url::Url;
+
+use crate::{
+    activities::delete::Delete,
+    ap::ids::{generate_object_id, KindType},
+    error::ApEventsError,
+    objects::actor::EventActor,
+    state::MyStateHandle,
+    storage_actor::get_actors,
+    storage_archive::{archive_event, ArchivedEvent},
+    storage_events::{expired_events, Event},
+    storage_follows::followers,
+    storage_rsvps::attendees,
+};
+
+#[derive(Serialize, Debug)]
+pub struct SweepReport {
+    pub dry_run: bool,
+    pub retention_days: i64,
+    pub expired: Vec<ArchivedEvent>,
+}
+
+/// Deletes completed and cancelled events once the retention period has passed since they
+/// ended. Followers and attendees are sent a `Delete` of the event actor, a summary of the event
+/// is archived and everything else about it is removed. A dry run only reports the events that
+/// would be deleted.
+pub async fn sweep_events(
+    app_state: &MyStateHandle,
+    now: DateTime<Utc>,
+    dry_run: bool,
+) -> Result<SweepReport, ApEventsError> {
+    let mut report = SweepReport {
+        dry_run,
+        retention_days: app_state.event_retention.num_days(),
+        expired: vec![],
+    };
+
+    for event in expired_events(app_state, now - app_state.event_retention).await? {
+        let followers = followers(app_state, &event.actor_ap_id).await?;
+        let attendees = attendees(app_state, &event.actor_ap_id).await?;
+        let archived = summarize(&event, &followers, &attendees);
+
+        if !dry_run {
+            let recipients: Vec<String> = followers.into_iter().chain(attendees).collect();
+            if let Err(err) = expire_event(app_state, &archived, &recipients).await {
+                warn!("cannot expire event {}: {}", archived.actor_ap_id, err);
+                continue;
+            }
+        }
+        report.expired.push(archived);
+    }
+    Ok(report)
+}
+
+/// What is archived of an expired event.
+fn summarize(event: &Event, followers: &[String], attendees: &[String]) -> ArchivedEvent {
+    ArchivedEvent {
+        actor_ap_id: event.actor_ap_id.clone(),
+        title: event.title.clone(),
+        starts_at: event.starts_at,
+        ends_at: event.ends_at,
+        timezone: event.timezone.clone(),
+        status: event.status.to_string(),
+        follower_count: followers.len() as i64,
+        attendee_count: attendees.len() as i64,
+    }
+}
+
+async fn expire_event(
+    app_state: &MyStateHandle,
+    archived: &ArchivedEvent,
+    recipients: &[String],
+) -> Result<(), ApEventsError> {
+    let actor = ObjectId::<EventActor>::new(Url::parse(&archived.actor_ap_id)?)
+        .dereference_local(app_state)
+        .await?;
+    let inboxes = get_actors(app_state, recipients)
+        .await?
+        .iter()
+        .map(|recipient| recipient.shared_inbox_or_inbox())
+        .collect();
+
+    let delete = Delete::new(
+        &actor,
+        generate_object_id(&app_state.external_base, KindType::Delete)?,
+    )?;
+    actor.send(delete, inboxes, app_state).await?;
+    archive_event(app_state, archived).await
+}
+
+pub async fn run_sweeper(app_state: MyStateHandle) -> Result<(), ApEventsError> {
+    let report = sweep_events(&app_state, Utc::now(), false).await?;
+    for archived in &report.expired {
+        info!("expired event {}", archived.actor_ap_id);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use chrono::TimeZone;
+    use serde_json::json;
+
+    use crate::storage_events::EventStatus;
+
+    #[test]
+    fn dry_run_report() {
+        let event = Event {
+            location: None,
+            status: EventStatus::Completed,
+            updated_at: Utc
+                .with_ymd_and_hms(2022, 11, 2, 3, 0, 0)
+                .unwrap()
+                .naive_utc(),
+            ..Event::example()
+        };
+        let report = SweepReport {
+            dry_run: true,
+            retention_days: 30,
+            expired: vec![summarize(
+                &event,
+                &["https://thegem.city/users/nick".to_string()],
+                &[
+                    "https://thegem.city/users/nick".to_string(),
+                    "https://mastodon.social/users/dana".to_string(),
+                ],
+            )],
+        };
+        assert_eq!(
+            serde_json::to_value(&report).unwrap(),
+            json!({
+                "dry_run": true,
+                "retention_days": 30,
+                "expired": [{
+                    "actor_ap_id": "https://events.thegem.city/actor/readily-splendid-mule",
+                    "title": "Swing night",
+                    "starts_at": "2022-11-02T00:00:00Z",
+                    "ends_at": "2022-11-02T03:00:00Z",
+                    "timezone": "America/New_York",
+                    "status": "completed",
+                    "follower_count": 1,
+                    "attendee_count": 2
+                }]
+            })
+        );
+    }
+}
@@ -0,0 +1,27 @@
+use std::{future::Future, time::Duration};
+
+use log::warn;
+
+use crate::{error::ApEventsError, state::MyStateHandle};
+
+/// Runs a task every `every`, starting immediately. Failures are logged and the task runs again
+/// at the next tick.
+pub fn spawn_periodic<Task, Fut>(
+    name: &'static str,
+    app_state: MyStateHandle,
+    every: Duration,
+    task: Task,
+) where
+    Task: Fn(MyStateHandle) -> Fut + 'static,
+    Fut: Future<Output = Result<(), ApEventsError>>,
+{
+    actix_web::rt::spawn(async move {
+        let mut interval = actix_web::rt::time::interval(every);
+        loop {
+            interval.tick().await;
+            if let Err(err) = task(app_state.clone()).await {
+                warn!("{} failed: {}", name, err);
+            }
+        }
+    });
+}
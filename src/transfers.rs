@@ -0,0 +1,76 @@
+use activitypub_federation::core::object_id::ObjectId;
+use askama::{Html, MarkupDisplay};
+use url::Url;
+
+use crate::{
+    error::ApEventsError,
+    messages::send_note,
+    objects::actor::EventActor,
+    state::MyStateHandle,
+    storage_actor::get_actors,
+    storage_events::Event,
+    storage_transfers::{nominate_owner, owned_events},
+};
+
+/// Asks someone to take over an event, and tells them how to accept.
+pub async fn offer_event(
+    app_state: &MyStateHandle,
+    actor: &EventActor,
+    event: &Event,
+    nominee: &EventActor,
+) -> Result<(), ApEventsError> {
+    nominate_owner(app_state, event, nominee.ap_id.inner().as_str()).await?;
+    let title = MarkupDisplay::new_unsafe(&event.title, Html);
+    send_note(
+        app_state,
+        actor,
+        std::slice::from_ref(nominee),
+        false,
+        format!("<p>You have been asked to take over {title}. Reply #transfer accept to become its owner.</p>"),
+        None,
+    )
+    .await
+}
+
+/// Handles an owner moving to another account, once the move is verified. Each event they own
+/// asks the new account to take it over.
+pub async fn offer_moved_events(
+    app_state: &MyStateHandle,
+    moved_from: &Url,
+    moved_to: &Url,
+    request_counter: &mut i32,
+) -> Result<(), ApEventsError> {
+    let events = owned_events(app_state, moved_from.as_str()).await?;
+    if events.is_empty() {
+        return Ok(());
+    }
+
+    let nominee = ObjectId::<EventActor>::new(moved_to.clone())
+        .dereference(app_state, &app_state.local_instance, request_counter)
+        .await?;
+    let moved_from_ref = get_actors(app_state, &[moved_from.to_string()])
+        .await?
+        .into_iter()
+        .next()
+        .map(|found| found.actor_ref)
+        .unwrap_or_else(|| moved_from.to_string());
+    let moved_from_ref = MarkupDisplay::new_unsafe(&moved_from_ref, Html);
+
+    for event in &events {
+        let actor = ObjectId::<EventActor>::new(Url::parse(&event.actor_ap_id)?)
+            .dereference_local(app_state)
+            .await?;
+        nominate_owner(app_state, event, moved_to.as_str()).await?;
+        let title = MarkupDisplay::new_unsafe(&event.title, Html);
+        send_note(
+            app_state,
+            &actor,
+            std::slice::from_ref(&nominee),
+            false,
+            format!("<p>{moved_from_ref} moved to this account. Reply #transfer accept to become the owner of {title}.</p>"),
+            None,
+        )
+        .await?;
+    }
+    Ok(())
+}
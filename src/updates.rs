@@ -0,0 +1,264 @@
+use activitypub_federation::{
+    core::object_id::ObjectId,
+    traits::{Actor, ApubObject},
+};
+use askama::{Html, MarkupDisplay};
+use chrono::{DateTime, Utc};
+use chrono_tz::Tz;
+use log::warn;
+use serde::{Deserialize, Serialize};
+use url::Url;
+
+use crate::{
+    activities::update::{Update, UpdateObject},
+    ap::{
+        event::event_context,
+        ids::{generate_object_id, KindType},
+    },
+    error::ApEventsError,
+    handler_events::format_when,
+    lifecycle::{event_audience, send_event_object},
+    messages::send_note_to_each,
+    objects::actor::EventActor,
+    recurrence::Occurrence,
+    state::MyStateHandle,
+    storage_actor::get_actors,
+    storage_events::{get_event, Event, EventStatus},
+    storage_rsvps::everyone_going,
+    storage_updates::{queue_event_update, take_due_event_updates},
+};
+
+/// The details of an event that people are told about when they change.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct EventDetails {
+    pub title: String,
+    pub summary: String,
+    pub starts_at: DateTime<Utc>,
+    pub ends_at: DateTime<Utc>,
+    pub timezone: String,
+    pub location: Option<String>,
+    pub rrule: Option<String>,
+    /// Only people going are told about changes, so private links are included.
+    #[serde(default)]
+    pub online_url: Option<String>,
+}
+
+impl From<&Event> for EventDetails {
+    fn from(event: &Event) -> Self {
+        EventDetails {
+            title: event.title.clone(),
+            summary: event.summary.clone(),
+            starts_at: event.starts_at,
+            ends_at: event.ends_at,
+            timezone: event.timezone.clone(),
+            location: event.location.clone(),
+            rrule: event.rrule.clone(),
+            online_url: event.online_url.clone(),
+        }
+    }
+}
+
+impl EventDetails {
+    fn when(&self) -> String {
+        format_when(
+            &self.timezone.parse().unwrap_or(Tz::UTC),
+            &Occurrence {
+                starts_at: self.starts_at,
+                ends_at: self.ends_at,
+            },
+        )
+    }
+}
+
+/// Describes what changed between two versions of an event, one sentence of HTML per change.
+pub fn describe_changes(before: &EventDetails, after: &EventDetails) -> Vec<String> {
+    let escape = |value: &str| MarkupDisplay::new_unsafe(value, Html).to_string();
+    let or_none = |value: &Option<String>, none: &str| match value {
+        Some(value) => escape(value),
+        None => none.to_string(),
+    };
+
+    let mut changes = vec![];
+    if before.title != after.title {
+        changes.push(format!(
+            "Title changed from {} to {}.",
+            escape(&before.title),
+            escape(&after.title)
+        ));
+    }
+    let (before_when, after_when) = (before.when(), after.when());
+    if before_when != after_when {
+        changes.push(format!("Time changed from {before_when} to {after_when}."));
+    }
+    if before.rrule != after.rrule {
+        changes.push(format!(
+            "Repeats changed from {} to {}.",
+            or_none(&before.rrule, "not repeating"),
+            or_none(&after.rrule, "not repeating")
+        ));
+    }
+    if before.location != after.location {
+        changes.push(format!(
+            "Location changed from {} to {}.",
+            or_none(&before.location, "no location"),
+            or_none(&after.location, "no location")
+        ));
+    }
+    if before.online_url != after.online_url {
+        changes.push(format!(
+            "Online link changed from {} to {}.",
+            or_none(&before.online_url, "no link"),
+            or_none(&after.online_url, "no link")
+        ));
+    }
+    if before.summary != after.summary {
+        changes.push("Description changed.".to_string());
+    }
+    changes
+}
+
+/// Queues a notification about an edit, unless nothing changed or the event is still a draft.
+pub async fn event_edited(
+    app_state: &MyStateHandle,
+    previous: &Event,
+    stored: &Event,
+) -> Result<(), ApEventsError> {
+    if previous.sequence == stored.sequence || stored.is_draft() {
+        return Ok(());
+    }
+    queue_event_update(
+        app_state,
+        &stored.actor_ap_id,
+        &serde_json::to_value(EventDetails::from(previous))?,
+        Utc::now() + app_state.update_debounce,
+    )
+    .await
+}
+
+/// Tells followers and attendees about edits once no more edits have been made for a while.
+/// Followers and attendees are sent an `Update` of the event actor and Event object, and people
+/// going are sent a message describing the changes.
+pub async fn run_update_notifications(app_state: MyStateHandle) -> Result<(), ApEventsError> {
+    for (actor_ap_id, previous) in take_due_event_updates(&app_state, Utc::now()).await? {
+        if let Err(err) = notify_edit(&app_state, &actor_ap_id, previous).await {
+            warn!("cannot send update of {}: {}", actor_ap_id, err);
+        }
+    }
+    Ok(())
+}
+
+async fn notify_edit(
+    app_state: &MyStateHandle,
+    actor_ap_id: &str,
+    previous: serde_json::Value,
+) -> Result<(), ApEventsError> {
+    let event = match get_event(app_state, actor_ap_id).await? {
+        Some(event)
+            if matches!(
+                event.status,
+                EventStatus::Published | EventStatus::Postponed
+            ) =>
+        {
+            event
+        }
+        _ => return Ok(()),
+    };
+    let previous: EventDetails = serde_json::from_value(previous)?;
+    let changes = describe_changes(&previous, &EventDetails::from(&event));
+    if changes.is_empty() {
+        return Ok(());
+    }
+
+    let actor = ObjectId::<EventActor>::new(Url::parse(actor_ap_id)?)
+        .dereference_local(app_state)
+        .await?;
+    let (followers, attendees) = event_audience(app_state, &actor).await?;
+    let inboxes = followers
+        .iter()
+        .chain(attendees.iter())
+        .map(|recipient| recipient.shared_inbox_or_inbox())
+        .collect();
+    let update = Update::new(
+        actor.ap_id.clone(),
+        UpdateObject::Actor(Box::new(actor.clone().into_apub(app_state).await?)),
+        generate_object_id(&app_state.external_base, KindType::Update)?,
+    );
+    actor
+        .send_with_context(update, Some(event_context()), inboxes, app_state)
+        .await?;
+    send_event_object(app_state, &actor, &event, false).await?;
+
+    let going = get_actors(app_state, &everyone_going(app_state, actor_ap_id).await?).await?;
+    if going.is_empty() {
+        return Ok(());
+    }
+    let content = format!(
+        "<p>{} has changed.</p><p>{}</p>",
+        MarkupDisplay::new_unsafe(&event.title, Html),
+        changes.join("<br />")
+    );
+    send_note_to_each(app_state, &actor, going, &content).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use chrono::TimeZone;
+
+    fn details() -> EventDetails {
+        EventDetails {
+            title: "Swing night".to_string(),
+            summary: "Dancing at the bar.".to_string(),
+            starts_at: Utc.with_ymd_and_hms(2022, 11, 2, 0, 0, 0).unwrap(),
+            ends_at: Utc.with_ymd_and_hms(2022, 11, 2, 3, 0, 0).unwrap(),
+            timezone: "America/New_York".to_string(),
+            location: Some("The bar".to_string()),
+            rrule: None,
+            online_url: None,
+        }
+    }
+
+    #[test]
+    fn no_changes() {
+        assert!(describe_changes(&details(), &details()).is_empty());
+    }
+
+    #[test]
+    fn changes() {
+        let after = EventDetails {
+            title: "Swing & blues night".to_string(),
+            summary: "Dancing at the bar, with a lesson first.".to_string(),
+            starts_at: Utc.with_ymd_and_hms(2022, 11, 2, 1, 0, 0).unwrap(),
+            ends_at: Utc.with_ymd_and_hms(2022, 11, 2, 4, 0, 0).unwrap(),
+            location: None,
+            rrule: Some("FREQ=WEEKLY".to_string()),
+            online_url: Some("https://meet.example.com/swing".to_string()),
+            ..details()
+        };
+        assert_eq!(
+            describe_changes(&details(), &after),
+            vec![
+                "Title changed from Swing night to Swing &amp; blues night.",
+                "Time changed from 11/1/2022 at 8:00 PM EDT (UTC-04:00) until 11/1/2022 at 11:00 PM EDT (UTC-04:00) to 11/1/2022 at 9:00 PM EDT (UTC-04:00) until 11/2/2022 at 12:00 AM EDT (UTC-04:00).",
+                "Repeats changed from not repeating to FREQ=WEEKLY.",
+                "Location changed from The bar to no location.",
+                "Online link changed from no link to https://meet.example.com/swing.",
+                "Description changed.",
+            ]
+        );
+    }
+
+    #[test]
+    fn timezone_changes() {
+        // The same instant in another timezone reads differently to attendees.
+        let after = EventDetails {
+            timezone: "America/Chicago".to_string(),
+            ..details()
+        };
+        assert_eq!(
+            describe_changes(&details(), &after),
+            vec!["Time changed from 11/1/2022 at 8:00 PM EDT (UTC-04:00) until 11/1/2022 at 11:00 PM EDT (UTC-04:00) to 11/1/2022 at 7:00 PM CDT (UTC-05:00) until 11/1/2022 at 10:00 PM CDT (UTC-05:00)."]
+        );
+    }
+}
@@ -1,5 +1,8 @@
 use activitypub_federation::{Error, APUB_JSON_CONTENT_TYPE};
-use actix_web::guard::{Guard, GuardContext};
+use actix_web::{
+    guard::{Guard, GuardContext},
+    HttpRequest,
+};
 use http::{header::HeaderName, HeaderMap, HeaderValue};
 use http_signature_normalization_reqwest::{prelude::SignExt, Config};
 use httpdate::fmt_http_date;
@@ -8,6 +11,7 @@ use openssl::{hash::MessageDigest, pkey::PKey, sign::Signer};
 use reqwest::header;
 use reqwest::Client;
 use reqwest::Request;
+use reqwest::StatusCode;
 use reqwest_middleware::ClientWithMiddleware;
 use reqwest_middleware::RequestBuilder;
 use serde::de::DeserializeOwned;
@@ -63,6 +67,55 @@ pub async fn fetch_object_http<Kind: DeserializeOwned>(
     res.json().await.map_err(Error::conv)
 }
 
+/// Signs and posts an activity to an inbox, and returns the response status. The
+/// `Collection-Synchronization` header is not part of the signature.
+pub async fn deliver_activity(
+    inbox: &Url,
+    activity: String,
+    public_key_id: String,
+    private_key: String,
+    collection_synchronization: Option<String>,
+) -> Result<StatusCode, anyhow::Error> {
+    info!("Delivering activity to {}", inbox.to_string());
+
+    let client: ClientWithMiddleware = Client::default().into();
+    let request_timeout = Duration::from_secs(10);
+
+    let mut headers = generate_object_request_headers(inbox);
+    headers.insert(
+        HeaderName::from_static("content-type"),
+        HeaderValue::from_static(APUB_JSON_CONTENT_TYPE),
+    );
+    if let Some(collection_synchronization) = collection_synchronization {
+        headers.insert(
+            HeaderName::from_static("collection-synchronization"),
+            HeaderValue::from_str(&collection_synchronization)?,
+        );
+    }
+
+    let request_builder = client
+        .post(inbox.to_string())
+        .timeout(request_timeout)
+        .headers(headers);
+
+    let request = sign_request(request_builder, activity, public_key_id, private_key).await?;
+    let res = client.execute(request).await?;
+    Ok(res.status())
+}
+
+/// Reads the `keyId` parameter of a request's HTTP signature without verifying it.
+pub fn signature_key_id(request: &HttpRequest) -> Option<String> {
+    request
+        .headers()
+        .get("signature")?
+        .to_str()
+        .ok()?
+        .split(',')
+        .filter_map(|param| param.trim().split_once('='))
+        .find(|(name, _)| *name == "keyId")
+        .map(|(_, value)| value.trim_matches('"').to_string())
+}
+
 fn generate_object_request_headers(inbox_url: &Url) -> HeaderMap {
     let mut host = inbox_url.domain().expect("read inbox domain").to_string();
     if let Some(port) = inbox_url.port() {
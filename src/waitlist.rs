@@ -0,0 +1,210 @@
+use activitypub_federation::traits::Actor;
+use askama::{Html, MarkupDisplay};
+use chrono::Utc;
+use url::Url;
+
+use crate::{
+    activities::accept::Accept,
+    ap::{
+        ids::{generate_object_id, KindType},
+        object_or_id::ObjectOrId,
+    },
+    error::ApEventsError,
+    messages::send_note,
+    objects::{actor::EventActor, event::LocalEvent},
+    state::MyStateHandle,
+    storage_actor::get_actors,
+    storage_events::Event,
+    storage_rsvps::{
+        claim_seat, get_rsvp, promote_waitlisted, set_rsvp, waitlist_position,
+        waitlisted_occurrences, RsvpStatus, ALL_OCCURRENCES,
+    },
+};
+
+/// Records an attendee's answer to an event. Answers of going are put on the waitlist once the
+/// event is full, and a seat that is given up goes to the next person on the waitlist. Returns
+/// the recorded answer, or None when the event is not taking answers of going.
+pub async fn answer_event(
+    app_state: &MyStateHandle,
+    found_event: &LocalEvent,
+    attendee_ap_id: &str,
+    status: RsvpStatus,
+    activity_ap_id: Option<&str>,
+    hidden: Option<bool>,
+) -> Result<Option<RsvpStatus>, ApEventsError> {
+    let now = Utc::now();
+    let actor_ap_id = found_event.actor.ap_id.inner().as_str();
+    let occurrence_id = found_event.occurrence_id();
+    let previous = get_rsvp(app_state, actor_ap_id, &occurrence_id, attendee_ap_id).await?;
+
+    let recorded = match status {
+        RsvpStatus::Going | RsvpStatus::Waitlisted => {
+            if !found_event
+                .event
+                .accepts_joins(found_event.occurrence.as_ref(), now)
+            {
+                return Ok(None);
+            }
+            let recorded = claim_seat(
+                app_state,
+                actor_ap_id,
+                &occurrence_id,
+                &found_event.seats_since(now),
+                attendee_ap_id,
+                activity_ap_id,
+                hidden,
+            )
+            .await?;
+            if previous == Some(RsvpStatus::Waitlisted) && recorded == RsvpStatus::Waitlisted {
+                return Ok(Some(recorded));
+            }
+            recorded
+        }
+        status => {
+            set_rsvp(
+                app_state,
+                actor_ap_id,
+                &occurrence_id,
+                attendee_ap_id,
+                status,
+                activity_ap_id,
+                hidden,
+            )
+            .await?;
+            status
+        }
+    };
+
+    if previous == Some(RsvpStatus::Going) && recorded != RsvpStatus::Going {
+        fill_waitlist(app_state, found_event).await?;
+    }
+    if recorded == RsvpStatus::Going && previous != Some(RsvpStatus::Going) {
+        send_private_online_link(app_state, found_event, attendee_ap_id).await?;
+    }
+    Ok(Some(recorded))
+}
+
+/// Sends an attendee who is now going the online link that only attendees may see.
+async fn send_private_online_link(
+    app_state: &MyStateHandle,
+    found_event: &LocalEvent,
+    attendee_ap_id: &str,
+) -> Result<(), ApEventsError> {
+    let event = &found_event.event;
+    let link = match event.private_online_url().and(event.online_link_html()) {
+        Some(link) => link,
+        None => return Ok(()),
+    };
+    let attendees = get_actors(app_state, &[attendee_ap_id.to_string()]).await?;
+    let content = format!(
+        "<p>You are going to {}. {link}</p>",
+        MarkupDisplay::new_unsafe(&event.title, Html)
+    );
+    send_note(
+        app_state,
+        &found_event.actor,
+        &attendees,
+        false,
+        content,
+        None,
+    )
+    .await
+}
+
+/// The message sent to someone who was put on the waitlist.
+pub async fn waitlisted_notice(
+    app_state: &MyStateHandle,
+    found_event: &LocalEvent,
+    attendee_ap_id: &str,
+) -> Result<String, ApEventsError> {
+    let position = waitlist_position(
+        app_state,
+        found_event.actor.ap_id.inner().as_str(),
+        &found_event.occurrence_id(),
+        attendee_ap_id,
+    )
+    .await?;
+    Ok(format!(
+        "<p>{} is full, so you are number {position} on the waitlist. You will hear from us if a spot opens up.</p>",
+        MarkupDisplay::new_unsafe(&found_event.event.title, Html)
+    ))
+}
+
+/// Gives open seats to the people who have waited longest. People who asked to join with a
+/// Join are sent an Accept of it, and everyone promoted is sent a message.
+pub async fn fill_waitlist(
+    app_state: &MyStateHandle,
+    found_event: &LocalEvent,
+) -> Result<(), ApEventsError> {
+    let actor = &found_event.actor;
+    let actor_ap_id = actor.ap_id.inner().as_str();
+    let occurrence_id = found_event.occurrence_id();
+
+    loop {
+        let now = Utc::now();
+        if !found_event
+            .event
+            .accepts_joins(found_event.occurrence.as_ref(), now)
+        {
+            break;
+        }
+        let (attendee_ap_id, activity_ap_id) = match promote_waitlisted(
+            app_state,
+            actor_ap_id,
+            &occurrence_id,
+            &found_event.seats_since(now),
+        )
+        .await?
+        {
+            Some(next) => next,
+            None => break,
+        };
+
+        let attendees = get_actors(app_state, &[attendee_ap_id]).await?;
+        if let (Some(attendee), Some(activity_ap_id)) = (attendees.first(), activity_ap_id) {
+            let accept = Accept::new(
+                actor.ap_id.clone(),
+                ObjectOrId::Id(Url::parse(&activity_ap_id)?),
+                generate_object_id(&app_state.external_base, KindType::Accept)?,
+            );
+            actor
+                .send(accept, vec![attendee.shared_inbox_or_inbox()], app_state)
+                .await?;
+        }
+        let event = &found_event.event;
+        let link = match event.private_online_url().and(event.online_link_html()) {
+            Some(link) => format!(" {link}"),
+            None => String::new(),
+        };
+        let content = format!(
+            "<p>A spot opened up at {}, so you are now going.{link}</p>",
+            MarkupDisplay::new_unsafe(&event.title, Html)
+        );
+        send_note(app_state, actor, &attendees, false, content, None).await?;
+    }
+    Ok(())
+}
+
+/// Fills every waitlist of an event, such as after its capacity was raised.
+pub async fn fill_waitlists(
+    app_state: &MyStateHandle,
+    actor: &EventActor,
+    event: &Event,
+) -> Result<(), ApEventsError> {
+    for occurrence_id in waitlisted_occurrences(app_state, &event.actor_ap_id).await? {
+        let occurrence = match occurrence_id.as_str() {
+            ALL_OCCURRENCES => None,
+            occurrence_id => match event.occurrence(occurrence_id) {
+                Some(occurrence) => Some(occurrence),
+                None => continue,
+            },
+        };
+        let found_event = LocalEvent {
+            actor: actor.clone(),
+            event: event.clone(),
+            occurrence,
+        };
+        fill_waitlist(app_state, &found_event).await?;
+    }
+    Ok(())
+}
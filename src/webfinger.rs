@@ -50,11 +50,13 @@ pub async fn handle_webfinger(
         return HttpResponse::NotFound().finish();
     }
 
-    let found_actor_res: Result<Option<EventActor>, sqlx::Error> =
-        sqlx::query_as("SELECT * FROM actors WHERE $1 = ANY (resources)")
-            .bind(query_resource.unwrap())
-            .fetch_optional(&app_state.pool)
-            .await;
+    // Drafts cannot be found until they are published, and expired events not at all.
+    let found_actor_res: Result<Option<EventActor>, sqlx::Error> = sqlx::query_as(
+        "SELECT * FROM actors WHERE $1 = ANY (resources) AND deleted_at IS NULL AND NOT EXISTS (SELECT 1 FROM events WHERE events.actor_ap_id = actors.ap_id AND events.status = 'draft')",
+    )
+    .bind(query_resource.unwrap())
+    .fetch_optional(&app_state.pool)
+    .await;
 
     if found_actor_res.is_err() {
         return HttpResponse::NotFound().finish();